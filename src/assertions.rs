@@ -0,0 +1,261 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::cell::RefCell;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+
+use crate::SubTestResult;
+use crate::TestResult;
+
+/// Name of the sub test the failures without a sub test are reported in
+/// when the test returns `TestResult::SubTests`.
+const ASSERTIONS_SUB_TEST_NAME: &str = "assertions";
+
+thread_local! {
+  static CURRENT_ASSERTIONS: RefCell<Option<TestAssertions>> =
+    const { RefCell::new(None) };
+}
+
+#[derive(Debug)]
+struct AssertionFailure {
+  sub_test: Option<String>,
+  message: String,
+}
+
+/// Non-fatal assertion failures of the running test, which are merged into
+/// its result once it finishes. This allows a test to report every
+/// mismatch (ex. each mismatched output file of a case) instead of
+/// stopping at the first.
+///
+/// ```rs
+/// let assertions = TestAssertions::current();
+/// for (file, expected) in expected_files {
+///   if actual(file) != expected {
+///     assertions.fail_in(file, format!("{} differs", file));
+///   }
+/// }
+/// TestResult::Passed
+/// ```
+///
+/// Clones share the failures, so they may be moved to other threads.
+#[derive(Debug, Clone, Default)]
+pub struct TestAssertions(Arc<Mutex<Vec<AssertionFailure>>>);
+
+impl TestAssertions {
+  /// Gets the assertions of the test running on the current thread.
+  ///
+  /// Panics when not called from a test.
+  pub fn current() -> TestAssertions {
+    CURRENT_ASSERTIONS.with(|assertions| {
+      assertions
+        .borrow()
+        .clone()
+        .expect("Test assertions can only be accessed in a test.")
+    })
+  }
+
+  /// Records a failure of the test.
+  pub fn fail(&self, message: impl Into<String>) {
+    self.0.lock().push(AssertionFailure {
+      sub_test: None,
+      message: message.into(),
+    });
+  }
+
+  /// Records a failure of a sub test (ex. a file of the case), which is
+  /// reported as a failed sub test.
+  pub fn fail_in(
+    &self,
+    sub_test: impl Into<String>,
+    message: impl Into<String>,
+  ) {
+    self.0.lock().push(AssertionFailure {
+      sub_test: Some(sub_test.into()),
+      message: message.into(),
+    });
+  }
+
+  /// Records a failure with the message when the condition is false,
+  /// returning the condition.
+  pub fn check(
+    &self,
+    condition: bool,
+    message: impl FnOnce() -> String,
+  ) -> bool {
+    if !condition {
+      self.fail(message());
+    }
+    condition
+  }
+
+  /// Records a failure when the values differ, returning whether they're
+  /// equal.
+  pub fn check_eq<T: PartialEq + std::fmt::Debug>(
+    &self,
+    actual: T,
+    expected: T,
+    description: &str,
+  ) -> bool {
+    self.check(actual == expected, || {
+      format!(
+        "{}\n  expected: {:?}\n    actual: {:?}",
+        description, expected, actual
+      )
+    })
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.0.lock().is_empty()
+  }
+
+  /// Merges the recorded failures into the test's result.
+  fn merge_into(&self, result: TestResult) -> TestResult {
+    let failures = std::mem::take(&mut *self.0.lock());
+    if failures.is_empty() {
+      return result;
+    }
+    match result {
+      TestResult::SubTests(mut sub_tests) => {
+        for failure in failures {
+          let name = failure
+            .sub_test
+            .unwrap_or_else(|| ASSERTIONS_SUB_TEST_NAME.to_string());
+          let index = match sub_tests.iter().position(|s| s.name == name) {
+            Some(index) => index,
+            None => {
+              sub_tests.push(SubTestResult {
+                name,
+                result: TestResult::Passed,
+              });
+              sub_tests.len() - 1
+            }
+          };
+          let sub_test = &mut sub_tests[index];
+          sub_test.result = merge_messages(
+            std::mem::replace(&mut sub_test.result, TestResult::Passed),
+            vec![failure.message],
+          );
+        }
+        TestResult::SubTests(sub_tests)
+      }
+      TestResult::Passed | TestResult::Ignored
+        if failures.iter().all(|f| f.sub_test.is_some()) =>
+      {
+        self.0.lock().extend(failures);
+        self.merge_into(TestResult::SubTests(Vec::new()))
+      }
+      result => {
+        let messages = failures
+          .into_iter()
+          .map(|failure| match failure.sub_test {
+            Some(sub_test) => format!("{}: {}", sub_test, failure.message),
+            None => failure.message,
+          })
+          .collect();
+        merge_messages(result, messages)
+      }
+    }
+  }
+}
+
+/// Makes the result a failure with the messages appended to its output.
+fn merge_messages(mut result: TestResult, messages: Vec<String>) -> TestResult {
+  if result.output_mut().is_none() {
+    result = TestResult::failed(Vec::new());
+  }
+  let output = result.output_mut().unwrap();
+  if !output.is_empty() && !output.ends_with(b"\n") {
+    output.push(b'\n');
+  }
+  if !output.is_empty() {
+    output.push(b'\n');
+  }
+  if messages.len() > 1 {
+    output
+      .extend(format!("{} assertions failed:\n\n", messages.len()).as_bytes());
+  }
+  output.extend(messages.join("\n\n").as_bytes());
+  result
+}
+
+/// Runs the test with assertions set for the current thread, merging the
+/// failures into its result.
+pub(crate) fn with_test_assertions(
+  func: impl FnOnce() -> TestResult,
+) -> TestResult {
+  let assertions = TestAssertions::default();
+  let previous = CURRENT_ASSERTIONS
+    .with(|current| current.replace(Some(assertions.clone())));
+  let result = func();
+  CURRENT_ASSERTIONS.with(|current| current.replace(previous));
+  assertions.merge_into(result)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn output(result: &TestResult) -> String {
+    match result {
+      TestResult::Failed { output, .. } => {
+        String::from_utf8(output.clone()).unwrap()
+      }
+      _ => unreachable!(),
+    }
+  }
+
+  #[test]
+  fn merges_failures() {
+    let result = with_test_assertions(|| {
+      let assertions = TestAssertions::current();
+      assert!(assertions.check_eq(1, 1, "same"));
+      assert!(!assertions.check_eq(1, 2, "value"));
+      assertions.fail("other");
+      TestResult::Passed
+    });
+    assert_eq!(
+      output(&result),
+      "2 assertions failed:\n\nvalue\n  expected: 2\n    actual: 1\n\nother"
+    );
+
+    let result = with_test_assertions(|| TestResult::Passed);
+    assert!(matches!(result, TestResult::Passed));
+
+    let result = with_test_assertions(|| {
+      TestAssertions::current().fail("mismatch");
+      TestResult::failed(b"panicked".to_vec())
+    });
+    assert_eq!(output(&result), "panicked\n\nmismatch");
+
+    // sub test failures are reported as failed sub tests
+    let result = with_test_assertions(|| {
+      let assertions = TestAssertions::current();
+      assertions.fail_in("a.out", "differs");
+      assertions.fail_in("b.out", "differs");
+      assertions.fail_in("a.out", "missing line");
+      TestResult::Passed
+    });
+    let TestResult::SubTests(sub_tests) = &result else {
+      unreachable!();
+    };
+    assert_eq!(sub_tests.len(), 2);
+    assert_eq!(sub_tests[0].name, "a.out");
+    assert_eq!(output(&sub_tests[0].result), "differs\n\nmissing line");
+    assert_eq!(output(&sub_tests[1].result), "differs");
+
+    let result = with_test_assertions(|| {
+      TestAssertions::current().fail("unnamed");
+      TestResult::SubTests(vec![SubTestResult {
+        name: "step".to_string(),
+        result: TestResult::Passed,
+      }])
+    });
+    let TestResult::SubTests(sub_tests) = &result else {
+      unreachable!();
+    };
+    assert!(matches!(sub_tests[0].result, TestResult::Passed));
+    assert_eq!(sub_tests[1].name, ASSERTIONS_SUB_TEST_NAME);
+    assert_eq!(output(&sub_tests[1].result), "unnamed");
+  }
+}
@@ -0,0 +1,148 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+//! Support for running under `bazel test`.
+//!
+//! See https://bazel.build/reference/test-encyclopedia for the environment
+//! variables Bazel provides to test binaries.
+
+use std::path::PathBuf;
+
+use crate::collection::CollectedCategoryOrTest;
+use crate::collection::CollectedTestCategory;
+use crate::PathedIoError;
+
+#[derive(Debug, Default, Clone)]
+pub(crate) struct BazelEnv {
+  pub shard: Option<Shard>,
+  pub shard_status_file: Option<PathBuf>,
+  pub xml_output_file: Option<PathBuf>,
+}
+
+impl BazelEnv {
+  pub fn from_env() -> Self {
+    fn var(name: &str) -> Option<String> {
+      std::env::var(name).ok().filter(|v| !v.is_empty())
+    }
+
+    let shard = match (var("TEST_TOTAL_SHARDS"), var("TEST_SHARD_INDEX")) {
+      (Some(total), Some(index)) => {
+        match (total.parse::<usize>(), index.parse::<usize>()) {
+          (Ok(total), Ok(index)) if total > 0 && index < total => {
+            Some(Shard { index, total })
+          }
+          _ => None,
+        }
+      }
+      _ => None,
+    };
+    Self {
+      shard,
+      shard_status_file: var("TEST_SHARD_STATUS_FILE").map(PathBuf::from),
+      xml_output_file: var("XML_OUTPUT_FILE").map(PathBuf::from),
+    }
+  }
+
+  /// Bazel requires the test runner to touch the shard status file in
+  /// order to indicate that it supports sharding.
+  pub fn touch_shard_status_file(&self) -> Result<(), PathedIoError> {
+    if let Some(path) = &self.shard_status_file {
+      std::fs::write(path, []).map_err(|err| PathedIoError::new(path, err))?;
+    }
+    Ok(())
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Shard {
+  pub index: usize,
+  pub total: usize,
+}
+
+impl Shard {
+  /// Keeps every test whose position in the tree falls in this shard
+  /// using a round-robin assignment.
+  pub fn apply<TData: Clone>(
+    &self,
+    category: &CollectedTestCategory<TData>,
+  ) -> CollectedTestCategory<TData> {
+    fn filter<TData: Clone>(
+      category: &CollectedTestCategory<TData>,
+      shard: &Shard,
+      position: &mut usize,
+    ) -> CollectedTestCategory<TData> {
+      let mut children = Vec::new();
+      for child in &category.children {
+        match child {
+          CollectedCategoryOrTest::Category(c) => {
+            let c = filter(c, shard, position);
+            if !c.is_empty() {
+              children.push(CollectedCategoryOrTest::Category(c));
+            }
+          }
+          CollectedCategoryOrTest::Test(t) => {
+            if *position % shard.total == shard.index {
+              children.push(CollectedCategoryOrTest::Test(t.clone()));
+            }
+            *position += 1;
+          }
+        }
+      }
+      CollectedTestCategory {
+        name: category.name.clone(),
+        path: category.path.clone(),
+        description: None,
+        children,
+      }
+    }
+
+    filter(category, self, &mut 0)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::path::PathBuf;
+
+  use super::*;
+  use crate::collection::CollectedTest;
+
+  fn test(name: &str) -> CollectedCategoryOrTest {
+    CollectedCategoryOrTest::Test(CollectedTest::new(name, name, ()))
+  }
+
+  fn test_names(category: &CollectedTestCategory) -> Vec<String> {
+    let mut names = Vec::new();
+    for child in &category.children {
+      match child {
+        CollectedCategoryOrTest::Category(c) => names.extend(test_names(c)),
+        CollectedCategoryOrTest::Test(t) => names.push(t.name.clone()),
+      }
+    }
+    names
+  }
+
+  #[test]
+  fn shard_apply() {
+    let category = CollectedTestCategory {
+      name: "specs".to_string(),
+      path: PathBuf::from("specs"),
+      description: None,
+      children: vec![
+        test("a"),
+        CollectedCategoryOrTest::Category(CollectedTestCategory {
+          name: "specs::sub".to_string(),
+          path: PathBuf::from("specs/sub"),
+          description: None,
+          children: vec![test("b"), test("c")],
+        }),
+        test("d"),
+      ],
+    };
+    let shard = Shard { index: 0, total: 2 };
+    assert_eq!(test_names(&shard.apply(&category)), vec!["a", "c"]);
+    let shard = Shard { index: 1, total: 2 };
+    assert_eq!(test_names(&shard.apply(&category)), vec!["b", "d"]);
+    let shard = Shard { index: 2, total: 3 };
+    assert_eq!(test_names(&shard.apply(&category)), vec!["c"]);
+  }
+}
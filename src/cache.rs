@@ -0,0 +1,220 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::collection::CollectedTest;
+use crate::state::StateStore;
+use crate::PathedIoError;
+use crate::TestResult;
+
+const CACHE_FILE_NAME: &str = "cache.json";
+
+#[derive(Default, Serialize, Deserialize)]
+struct CacheFile {
+  binary: String,
+  tests: HashMap<String, u64>,
+}
+
+/// Cache of the hashes of test files that passed, which allows skipping
+/// tests whose input hasn't changed since they last passed.
+pub(crate) struct ResultCache {
+  store: StateStore,
+  binary: String,
+  passed: HashMap<String, u64>,
+  /// Hashes of the tests currently running.
+  pending: HashMap<String, u64>,
+}
+
+impl ResultCache {
+  pub fn load(store: StateStore) -> Self {
+    let binary = binary_fingerprint();
+    let passed = store
+      .read(CACHE_FILE_NAME)
+      .ok()
+      .flatten()
+      .and_then(|text| serde_json::from_str::<CacheFile>(&text).ok())
+      // the results are no longer valid when the test binary changes
+      .filter(|file| file.binary == binary)
+      .map(|file| file.tests)
+      .unwrap_or_default();
+    Self {
+      store,
+      binary,
+      passed,
+      pending: HashMap::new(),
+    }
+  }
+
+  /// Gets if the test passed previously with the same input, which
+  /// includes the provided additional input files.
+  pub fn is_cached<TData>(
+    &mut self,
+    test: &CollectedTest<TData>,
+    input_files: &[PathBuf],
+  ) -> bool {
+    let Some(hash) = hash_test(test, input_files) else {
+      return false;
+    };
+    if self.passed.get(&test.name) == Some(&hash) {
+      return true;
+    }
+    self.passed.remove(&test.name);
+    self.pending.insert(test.name.clone(), hash);
+    false
+  }
+
+  pub fn record_result<TData>(
+    &mut self,
+    test: &CollectedTest<TData>,
+    result: &TestResult,
+  ) {
+    if let Some(hash) = self.pending.remove(&test.name) {
+      if !result.is_failed() && !matches!(result, TestResult::Ignored) {
+        self.passed.insert(test.name.clone(), hash);
+      }
+    }
+  }
+
+  pub fn save(&self) -> Result<(), PathedIoError> {
+    let file = CacheFile {
+      binary: self.binary.clone(),
+      tests: self.passed.clone(),
+    };
+    self.store.write(
+      CACHE_FILE_NAME,
+      serde_json::to_string(&file).unwrap().as_bytes(),
+    )
+  }
+}
+
+fn hash_test<TData>(
+  test: &CollectedTest<TData>,
+  input_files: &[PathBuf],
+) -> Option<u64> {
+  let mut hasher = Fnv1a::default();
+  let bytes = test.read_to_bytes().ok()?;
+  hasher.write(&(bytes.len() as u64).to_le_bytes());
+  hasher.write(&bytes);
+  for path in input_files {
+    hash_path(&mut hasher, path);
+  }
+  Some(hasher.0)
+}
+
+/// Hashes the file or the files in the directory, including their paths
+/// so that adding, removing, or renaming files changes the hash.
+fn hash_path(hasher: &mut Fnv1a, path: &Path) {
+  let path_bytes = path.to_string_lossy();
+  hasher.write(&(path_bytes.len() as u64).to_le_bytes());
+  hasher.write(path_bytes.as_bytes());
+  if path.is_dir() {
+    let mut entries = std::fs::read_dir(path)
+      .map(|entries| {
+        entries
+          .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+          .collect::<Vec<_>>()
+      })
+      .unwrap_or_default();
+    entries.sort();
+    for entry in entries {
+      hash_path(hasher, &entry);
+    }
+  } else {
+    match std::fs::read(path) {
+      Ok(bytes) => {
+        hasher.write(&(bytes.len() as u64).to_le_bytes());
+        hasher.write(&bytes);
+      }
+      // the file may be created later
+      Err(_) => hasher.write(&u64::MAX.to_le_bytes()),
+    }
+  }
+}
+
+/// Identifies the current test binary so that results are invalidated
+/// when the code running the tests changes.
+fn binary_fingerprint() -> String {
+  let metadata = std::env::current_exe()
+    .and_then(|exe| std::fs::metadata(&exe).map(|m| (exe, m)));
+  match metadata {
+    Ok((exe, metadata)) => {
+      let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|m| m.duration_since(std::time::SystemTime::UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+      format!("{}:{}:{}", exe.display(), metadata.len(), modified)
+    }
+    Err(_) => String::new(),
+  }
+}
+
+/// FNV-1a hash, which is used instead of the standard library's hasher
+/// because it needs to be stable across Rust versions.
+pub(crate) struct Fnv1a(pub u64);
+
+impl Default for Fnv1a {
+  fn default() -> Self {
+    Self(0xcbf29ce484222325)
+  }
+}
+
+impl Fnv1a {
+  pub fn write(&mut self, bytes: &[u8]) {
+    for byte in bytes {
+      self.0 ^= *byte as u64;
+      self.0 = self.0.wrapping_mul(0x100000001b3);
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn caches_passed_tests() {
+    let dir = std::env::temp_dir()
+      .join(format!("file_test_runner_cache_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let test = CollectedTest::new("specs::test", dir.join("test.txt"), ());
+    std::fs::write(&test.path, "1").unwrap();
+    let store = StateStore::new(dir.join("state"));
+
+    let mut cache = ResultCache::load(store.clone());
+    assert!(!cache.is_cached(&test, &[]));
+    cache.record_result(&test, &TestResult::failed(Vec::new()));
+    assert!(!cache.is_cached(&test, &[]));
+    cache.record_result(&test, &TestResult::Passed);
+    cache.save().unwrap();
+
+    let mut cache = ResultCache::load(store.clone());
+    assert!(cache.is_cached(&test, &[]));
+    std::fs::write(&test.path, "2").unwrap();
+    assert!(!cache.is_cached(&test, &[]));
+
+    // additional input files
+    let fixtures = dir.join("fixtures");
+    std::fs::create_dir_all(&fixtures).unwrap();
+    std::fs::write(fixtures.join("a.json"), "{}").unwrap();
+    let input_files = [fixtures.clone()];
+    let mut cache = ResultCache::load(store.clone());
+    assert!(!cache.is_cached(&test, &input_files));
+    cache.record_result(&test, &TestResult::Passed);
+    assert!(cache.is_cached(&test, &input_files));
+    std::fs::write(fixtures.join("b.json"), "{}").unwrap();
+    assert!(!cache.is_cached(&test, &input_files));
+    cache.record_result(&test, &TestResult::Passed);
+    assert!(cache.is_cached(&test, &input_files));
+    std::fs::write(fixtures.join("a.json"), "[]").unwrap();
+    assert!(!cache.is_cached(&test, &input_files));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -0,0 +1,193 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::process::Command;
+use std::process::Stdio;
+use std::sync::Arc;
+
+use crate::collection::CollectedCategoryOrTest;
+use crate::collection::CollectedTest;
+use crate::collection::CollectedTestCategory;
+
+type CapabilityProbe = Arc<dyn Fn() -> bool + Send + Sync>;
+
+/// Capabilities that tests may require via `CollectedTest::requires`
+/// (ex. `"docker"`), along with how to check if each is available.
+///
+/// The probes of the required capabilities are evaluated once when the
+/// run starts. Tests that require a capability that isn't available or
+/// has no probe are reported as ignored without running them.
+///
+/// ```rust
+/// use file_test_runner::Capabilities;
+///
+/// let capabilities = Capabilities::default()
+///   .command("docker", "docker", &["info"])
+///   .probe("network", || std::env::var_os("OFFLINE").is_none());
+/// ```
+#[derive(Clone, Default)]
+pub struct Capabilities {
+  probes: HashMap<String, CapabilityProbe>,
+}
+
+impl std::fmt::Debug for Capabilities {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let mut names = self.probes.keys().collect::<Vec<_>>();
+    names.sort();
+    f.debug_struct("Capabilities")
+      .field("probes", &names)
+      .finish()
+  }
+}
+
+impl Capabilities {
+  /// Registers a capability that's available when the probe returns true.
+  pub fn probe(
+    mut self,
+    name: impl Into<String>,
+    probe: impl Fn() -> bool + Send + Sync + 'static,
+  ) -> Self {
+    self.probes.insert(name.into(), Arc::new(probe));
+    self
+  }
+
+  /// Registers a capability that's available when the command runs
+  /// successfully (ex. `docker info` for a running Docker daemon).
+  pub fn command(
+    self,
+    name: impl Into<String>,
+    program: impl Into<String>,
+    args: &[&str],
+  ) -> Self {
+    let program = program.into();
+    let args = args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+    self.probe(name, move || {
+      Command::new(&program)
+        .args(&args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+    })
+  }
+
+  /// Registers a capability that's always available.
+  pub fn available(self, name: impl Into<String>) -> Self {
+    self.probe(name, || true)
+  }
+
+  /// Evaluates the probes of the capabilities the tests require, running
+  /// them at the same time since some are slow (ex. starting a process).
+  pub(crate) fn evaluate<TData>(
+    &self,
+    category: &CollectedTestCategory<TData>,
+  ) -> EvaluatedCapabilities {
+    let mut required = BTreeSet::new();
+    collect_required(category, &mut required);
+    let available = std::thread::scope(|scope| {
+      let handles = required
+        .into_iter()
+        .map(|name| {
+          let probe = self.probes.get(name).cloned();
+          let handle = scope.spawn(move || probe.map(|probe| probe()));
+          (name, handle)
+        })
+        .collect::<Vec<_>>();
+      handles
+        .into_iter()
+        .map(|(name, handle)| {
+          // a probe that panics means the capability isn't available
+          (name.to_string(), handle.join().unwrap_or(Some(false)))
+        })
+        .collect()
+    });
+    EvaluatedCapabilities { available }
+  }
+}
+
+fn collect_required<'a, TData>(
+  category: &'a CollectedTestCategory<TData>,
+  required: &mut BTreeSet<&'a str>,
+) {
+  for child in &category.children {
+    match child {
+      CollectedCategoryOrTest::Category(category) => {
+        collect_required(category, required)
+      }
+      CollectedCategoryOrTest::Test(test) => {
+        required
+          .extend(test.settings.requires.iter().map(|name| name.as_str()));
+      }
+    }
+  }
+}
+
+/// Availability of the required capabilities, where `None` is a
+/// capability without a probe.
+#[derive(Debug, Default)]
+pub(crate) struct EvaluatedCapabilities {
+  available: HashMap<String, Option<bool>>,
+}
+
+impl EvaluatedCapabilities {
+  /// Gets the reason to ignore the test because of its unmet
+  /// requirements.
+  pub fn ignore_reason<TData>(
+    &self,
+    test: &CollectedTest<TData>,
+  ) -> Option<String> {
+    let unmet = test
+      .settings
+      .requires
+      .iter()
+      .filter_map(|name| match self.available.get(name).copied().flatten() {
+        Some(true) => None,
+        Some(false) => Some(name.clone()),
+        None => Some(format!("{} (unknown capability)", name)),
+      })
+      .collect::<Vec<_>>();
+    (!unmet.is_empty()).then(|| format!("requires: {}", unmet.join(", ")))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::testing::CategoryBuilder;
+
+  #[test]
+  fn ignores_tests_with_unmet_requirements() {
+    let requires = |names: &[&str]| {
+      let requires = names
+        .iter()
+        .map(|name| name.to_string())
+        .collect::<Vec<_>>();
+      move |mut test: CollectedTest<()>| {
+        test.settings.requires = requires;
+        test
+      }
+    };
+    let category = CategoryBuilder::<()>::new("specs")
+      .test_with("docker", (), requires(&["docker"]))
+      .test_with("both", (), requires(&["node", "docker"]))
+      .test_with("unknown", (), requires(&["gpu"]))
+      .test("none")
+      .build();
+    let capabilities = Capabilities::default()
+      .probe("docker", || false)
+      .available("node")
+      .probe("unused", || panic!("not required"));
+    let evaluated = capabilities.evaluate(&category);
+    let reason =
+      |name: &str| evaluated.ignore_reason(category.find_test(name).unwrap());
+    assert_eq!(reason("specs::docker").as_deref(), Some("requires: docker"));
+    assert_eq!(reason("specs::both").as_deref(), Some("requires: docker"));
+    assert_eq!(
+      reason("specs::unknown").as_deref(),
+      Some("requires: gpu (unknown capability)")
+    );
+    assert_eq!(reason("specs::none"), None);
+  }
+}
@@ -0,0 +1,211 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashSet;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Stdio;
+
+use deno_terminal::colors;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::PathedIoError;
+
+use super::CollectFilterFunc;
+use super::CollectedTest;
+
+/// Source files and the names of the tests that use them, which an
+/// external tool provides (ex. from the coverage of a previous run).
+///
+/// The JSON format maps each source file path to the test names:
+///
+/// ```json
+/// { "cli/tools/fmt.rs": ["specs::fmt::basic", "specs::fmt::check"] }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct CoverageMap {
+  tests_by_source: BTreeMap<PathBuf, BTreeSet<String>>,
+}
+
+impl CoverageMap {
+  pub fn from_file(path: &Path) -> Result<Self, anyhow::Error> {
+    let text = std::fs::read_to_string(path)
+      .map_err(|err| PathedIoError::new(path, err))?;
+    serde_json::from_str(&text).map_err(|err| {
+      anyhow::anyhow!("Failed to parse '{}': {:#}", path.display(), err)
+    })
+  }
+
+  /// Records that the test uses the source file.
+  pub fn add(&mut self, source: impl Into<PathBuf>, test_name: &str) {
+    self
+      .tests_by_source
+      .entry(source.into())
+      .or_default()
+      .insert(test_name.to_string());
+  }
+}
+
+/// Tests affected by a set of changed files according to a
+/// `CoverageMap`.
+///
+/// A test is affected when it uses a changed source file or its own file
+/// changed. Tests the coverage map doesn't mention (ex. new tests) are
+/// always affected since nothing is known about them.
+#[derive(Debug, Clone)]
+pub struct AffectedTests {
+  affected_names: HashSet<String>,
+  covered_names: HashSet<String>,
+  changed_files: HashSet<PathBuf>,
+}
+
+impl AffectedTests {
+  /// Creates the selection, where relative paths of the coverage map and
+  /// changed files are resolved against `root`.
+  pub fn new(
+    root: &Path,
+    coverage: &CoverageMap,
+    changed_files: impl IntoIterator<Item = PathBuf>,
+  ) -> Self {
+    let changed_files = changed_files
+      .into_iter()
+      .map(|path| root.join(path))
+      .collect::<HashSet<_>>();
+    let mut affected_names = HashSet::new();
+    let mut covered_names = HashSet::new();
+    for (source, names) in &coverage.tests_by_source {
+      let is_changed = changed_files.contains(&root.join(source));
+      for name in names {
+        if is_changed {
+          affected_names.insert(name.clone());
+        }
+        covered_names.insert(name.clone());
+      }
+    }
+    Self {
+      affected_names,
+      covered_names,
+      changed_files,
+    }
+  }
+
+  /// Selects the tests affected by the files that differ from the git
+  /// revision (ex. `origin/main`), including uncommitted and untracked
+  /// files. Paths in the coverage map are relative to the repository root.
+  pub fn from_git_diff(
+    coverage: &CoverageMap,
+    base_revision: &str,
+  ) -> Result<Self, anyhow::Error> {
+    let root = PathBuf::from(git_output(&["rev-parse", "--show-toplevel"])?);
+    let diff = git_output(&["diff", "--name-only", base_revision])?;
+    let untracked = git_output(&[
+      "ls-files",
+      "--others",
+      "--exclude-standard",
+      "--full-name",
+    ])?;
+    let changed_files = diff
+      .lines()
+      .chain(untracked.lines())
+      .map(PathBuf::from)
+      .collect::<Vec<_>>();
+    Ok(Self::new(&root, coverage, changed_files))
+  }
+
+  pub fn is_affected<TData>(&self, test: &CollectedTest<TData>) -> bool {
+    if !self.covered_names.contains(&test.name)
+      || self.affected_names.contains(&test.name)
+    {
+      return true;
+    }
+    std::path::absolute(&test.path)
+      .is_ok_and(|path| self.changed_files.contains(&path))
+  }
+
+  /// Gets a `CollectOptions::filter_fn` that keeps the affected tests.
+  pub fn into_filter_fn<TData>(self) -> CollectFilterFunc<TData> {
+    Box::new(move |test| self.is_affected(test))
+  }
+}
+
+/// Gets a `CollectOptions::filter_fn` that only keeps the tests affected
+/// by the changes since the git revision according to the coverage map
+/// file. When the coverage map or the changes can't be read, a warning is
+/// printed and `None` is returned so the full suite is run.
+pub fn affected_tests_filter<TData>(
+  coverage_path: &Path,
+  base_revision: &str,
+) -> Option<CollectFilterFunc<TData>> {
+  let result = CoverageMap::from_file(coverage_path).and_then(|coverage| {
+    AffectedTests::from_git_diff(&coverage, base_revision)
+  });
+  match result {
+    Ok(affected) => Some(affected.into_filter_fn()),
+    Err(err) => {
+      eprintln!(
+        "{}: running all tests because the affected tests are unknown: {:#}",
+        colors::yellow_bold("warning"),
+        err
+      );
+      None
+    }
+  }
+}
+
+fn git_output(args: &[&str]) -> Result<String, anyhow::Error> {
+  let output = Command::new("git")
+    .args(args)
+    .stdin(Stdio::null())
+    .output()
+    .map_err(|err| anyhow::anyhow!("Failed to run git: {:#}", err))?;
+  if !output.status.success() {
+    anyhow::bail!(
+      "git {} failed: {}",
+      args.join(" "),
+      String::from_utf8_lossy(&output.stderr).trim()
+    );
+  }
+  Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn affected_tests() {
+    let root = std::env::temp_dir().join("repo");
+    let coverage: CoverageMap = serde_json::from_str(
+      r#"{
+        "src/fmt.rs": ["specs::fmt", "specs::fmt_check"],
+        "src/lint.rs": ["specs::lint"],
+        "src/run.rs": ["specs::run"]
+      }"#,
+    )
+    .unwrap();
+    let affected = AffectedTests::new(
+      &root,
+      &coverage,
+      vec![PathBuf::from("src/fmt.rs"), PathBuf::from("tests/run.txt")],
+    );
+    let test =
+      |name: &str, path: &str| CollectedTest::new(name, root.join(path), ());
+    assert!(affected.is_affected(&test("specs::fmt", "tests/fmt.txt")));
+    assert!(affected.is_affected(&test("specs::fmt_check", "tests/check.txt")));
+    assert!(!affected.is_affected(&test("specs::lint", "tests/lint.txt")));
+    // its own file changed
+    assert!(affected.is_affected(&test("specs::run", "tests/run.txt")));
+    // not in the coverage map
+    assert!(affected.is_affected(&test("specs::new", "tests/new.txt")));
+
+    let mut added = CoverageMap::default();
+    added.add("src/lint.rs", "specs::lint");
+    let affected =
+      AffectedTests::new(&root, &added, vec![PathBuf::from("src/lint.rs")]);
+    assert!(affected.is_affected(&test("specs::lint", "tests/lint.txt")));
+  }
+}
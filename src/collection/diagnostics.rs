@@ -0,0 +1,60 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::cell::RefCell;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Non-fatal problem found while collecting tests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CollectWarning {
+  pub path: PathBuf,
+  pub message: String,
+}
+
+impl std::fmt::Display for CollectWarning {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{} ({})", self.message, self.path.display())
+  }
+}
+
+thread_local! {
+  static WARNINGS: RefCell<Option<Vec<CollectWarning>>> =
+    const { RefCell::new(None) };
+}
+
+/// Records a warning from a `TestCollectionStrategy`, which is returned
+/// by `collect_tests_with_warnings` and displayed by the reporter when
+/// using `collect_and_run_tests`.
+///
+/// This does nothing when not called while collecting tests.
+pub fn report_collect_warning(path: &Path, message: impl Into<String>) {
+  WARNINGS.with(|warnings| {
+    if let Some(warnings) = warnings.borrow_mut().as_mut() {
+      warnings.push(CollectWarning {
+        path: path.to_path_buf(),
+        message: message.into(),
+      });
+    }
+  });
+}
+
+/// Records the warnings reported on the current thread until the
+/// returned guard is taken or dropped.
+pub(crate) fn track_warnings() -> WarningsGuard {
+  WARNINGS.with(|warnings| *warnings.borrow_mut() = Some(Vec::new()));
+  WarningsGuard
+}
+
+pub(crate) struct WarningsGuard;
+
+impl WarningsGuard {
+  pub fn take(self) -> Vec<CollectWarning> {
+    WARNINGS.with(|warnings| warnings.borrow_mut().take().unwrap_or_default())
+  }
+}
+
+impl Drop for WarningsGuard {
+  fn drop(&mut self) {
+    WARNINGS.with(|warnings| warnings.borrow_mut().take());
+  }
+}
@@ -0,0 +1,133 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::cell::RefCell;
+use std::panic::RefUnwindSafe;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::LazyLock;
+
+use super::ContentProvider;
+use super::FileMetadata;
+
+/// Kind of a file system entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+  File,
+  Dir,
+  Symlink,
+}
+
+impl EntryKind {
+  pub fn is_file(&self) -> bool {
+    *self == EntryKind::File
+  }
+
+  pub fn is_dir(&self) -> bool {
+    *self == EntryKind::Dir
+  }
+}
+
+/// File system the built-in strategies collect tests from, which can be
+/// replaced while collecting via `with_file_system` (ex. with
+/// `file_test_runner::testing::InMemoryFileSystem` in unit tests).
+pub trait FileSystem: Send + Sync + RefUnwindSafe {
+  /// Gets the paths of the entries in the directory in any order.
+  fn read_dir(&self, path: &Path) -> std::io::Result<Vec<PathBuf>>;
+  /// Gets the kind of the entry without following symlinks.
+  fn entry_kind(&self, path: &Path) -> std::io::Result<EntryKind>;
+  /// Gets the path with all the symlinks resolved.
+  fn canonicalize(&self, path: &Path) -> std::io::Result<PathBuf>;
+  /// Gets the metadata of the file, following symlinks.
+  fn metadata(&self, path: &Path) -> std::io::Result<FileMetadata>;
+  fn read(&self, path: &Path) -> std::io::Result<Vec<u8>>;
+
+  fn read_to_string(&self, path: &Path) -> std::io::Result<String> {
+    String::from_utf8(self.read(path)?).map_err(|err| {
+      std::io::Error::new(std::io::ErrorKind::InvalidData, err.utf8_error())
+    })
+  }
+
+  fn exists(&self, path: &Path) -> bool {
+    self.entry_kind(path).is_ok()
+  }
+}
+
+/// The file system of the machine.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RealFileSystem;
+
+impl FileSystem for RealFileSystem {
+  fn read_dir(&self, path: &Path) -> std::io::Result<Vec<PathBuf>> {
+    std::fs::read_dir(path)?
+      .map(|entry| entry.map(|entry| entry.path()))
+      .collect()
+  }
+
+  fn entry_kind(&self, path: &Path) -> std::io::Result<EntryKind> {
+    let file_type = std::fs::symlink_metadata(path)?.file_type();
+    Ok(if file_type.is_symlink() {
+      EntryKind::Symlink
+    } else if file_type.is_dir() {
+      EntryKind::Dir
+    } else {
+      EntryKind::File
+    })
+  }
+
+  fn canonicalize(&self, path: &Path) -> std::io::Result<PathBuf> {
+    std::fs::canonicalize(path)
+  }
+
+  fn metadata(&self, path: &Path) -> std::io::Result<FileMetadata> {
+    std::fs::metadata(path).map(|metadata| FileMetadata::from(&metadata))
+  }
+
+  fn read(&self, path: &Path) -> std::io::Result<Vec<u8>> {
+    std::fs::read(path)
+  }
+
+  fn read_to_string(&self, path: &Path) -> std::io::Result<String> {
+    std::fs::read_to_string(path)
+  }
+}
+
+static REAL_FILE_SYSTEM: LazyLock<Arc<dyn FileSystem>> =
+  LazyLock::new(|| Arc::new(RealFileSystem));
+
+thread_local! {
+  static CURRENT_FILE_SYSTEM: RefCell<Option<Arc<dyn FileSystem>>> =
+    const { RefCell::new(None) };
+}
+
+/// Collects the tests on the current thread from the provided file system
+/// instead of the real one (ex. `with_file_system(fs, || collect_tests(options))`).
+///
+/// The tests collected by the built-in strategies read their contents
+/// from the file system via `CollectedTest::content_provider`.
+pub fn with_file_system<R>(
+  file_system: Arc<dyn FileSystem>,
+  func: impl FnOnce() -> R,
+) -> R {
+  let previous =
+    CURRENT_FILE_SYSTEM.with(|current| current.replace(Some(file_system)));
+  let result = func();
+  CURRENT_FILE_SYSTEM.with(|current| current.replace(previous));
+  result
+}
+
+/// Gets the file system to collect tests from.
+pub(crate) fn file_system() -> Arc<dyn FileSystem> {
+  CURRENT_FILE_SYSTEM
+    .with(|current| current.borrow().clone())
+    .unwrap_or_else(|| REAL_FILE_SYSTEM.clone())
+}
+
+/// Gets the content provider of a collected test file, which is only
+/// needed when not collecting from the real file system.
+pub(crate) fn file_content_provider(path: &Path) -> Option<ContentProvider> {
+  let file_system =
+    CURRENT_FILE_SYSTEM.with(|current| current.borrow().clone())?;
+  let path = path.to_path_buf();
+  Some(ContentProvider::new(move || file_system.read(&path)))
+}
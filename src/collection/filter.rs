@@ -0,0 +1,137 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+/// How a filter is matched against the names of the tests.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FilterMode {
+  /// Names containing the filter, which is what `cargo test` does.
+  #[default]
+  Substring,
+  /// Names starting with the filter.
+  Prefix,
+  /// Names equal to the filter (`--exact`).
+  Exact,
+  /// Names matching the filter, where `*` matches any number of
+  /// characters and `?` matches a single character.
+  Glob,
+}
+
+/// Filter for selecting tests by name.
+///
+/// The mode is provided on the command line via `--exact`, `--prefix`, or
+/// `--glob`, and `--ignore-case` makes the match case-insensitive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterSpec {
+  pub pattern: String,
+  pub mode: FilterMode,
+  pub case_insensitive: bool,
+}
+
+impl From<&str> for FilterSpec {
+  fn from(pattern: &str) -> Self {
+    Self {
+      pattern: pattern.to_string(),
+      mode: FilterMode::default(),
+      case_insensitive: false,
+    }
+  }
+}
+
+impl FilterSpec {
+  /// Creates the filter with the mode provided on the command line.
+  pub fn with_cli_mode(pattern: String) -> Self {
+    Self::with_args_mode(pattern, std::env::args().skip(1))
+  }
+
+  fn with_args_mode(
+    pattern: String,
+    args: impl Iterator<Item = String>,
+  ) -> Self {
+    let mut spec = FilterSpec::from(pattern.as_str());
+    for arg in args {
+      match arg.as_str() {
+        "--exact" => spec.mode = FilterMode::Exact,
+        "--prefix" => spec.mode = FilterMode::Prefix,
+        "--glob" => spec.mode = FilterMode::Glob,
+        "--ignore-case" => spec.case_insensitive = true,
+        _ => {}
+      }
+    }
+    spec
+  }
+
+  /// Gets if the test name matches the filter.
+  pub fn matches(&self, name: &str) -> bool {
+    let (pattern, name) = if self.case_insensitive {
+      (self.pattern.to_lowercase(), name.to_lowercase())
+    } else {
+      (self.pattern.clone(), name.to_string())
+    };
+    match self.mode {
+      FilterMode::Substring => name.contains(&pattern),
+      FilterMode::Prefix => name.starts_with(&pattern),
+      FilterMode::Exact => name == pattern,
+      FilterMode::Glob => glob_match(
+        &pattern.chars().collect::<Vec<_>>(),
+        &name.chars().collect::<Vec<_>>(),
+      ),
+    }
+  }
+}
+
+fn glob_match(pattern: &[char], text: &[char]) -> bool {
+  let mut p = 0;
+  let mut t = 0;
+  // position of the last `*` and the text position it was tried at
+  let mut backtrack = None;
+  while t < text.len() {
+    match pattern.get(p) {
+      Some('*') => {
+        backtrack = Some((p, t));
+        p += 1;
+      }
+      Some(c) if *c == '?' || *c == text[t] => {
+        p += 1;
+        t += 1;
+      }
+      _ => match backtrack {
+        Some((star_p, star_t)) => {
+          // let the `*` match one more character
+          backtrack = Some((star_p, star_t + 1));
+          p = star_p + 1;
+          t = star_t + 1;
+        }
+        None => return false,
+      },
+    }
+  }
+  pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn spec(pattern: &str, args: &[&str]) -> FilterSpec {
+    FilterSpec::with_args_mode(
+      pattern.to_string(),
+      args.iter().map(|arg| arg.to_string()),
+    )
+  }
+
+  #[test]
+  fn matches() {
+    let name = "specs::lsp::Completions";
+    assert!(spec("lsp", &[]).matches(name));
+    assert!(!spec("completions", &[]).matches(name));
+    assert!(spec("completions", &["--ignore-case"]).matches(name));
+    assert!(spec("specs::lsp", &["--prefix"]).matches(name));
+    assert!(!spec("lsp", &["--prefix"]).matches(name));
+    assert!(!spec("specs::lsp", &["--exact"]).matches(name));
+    assert!(spec(name, &["--exact"]).matches(name));
+    assert!(spec("specs::*::Comp*", &["--glob"]).matches(name));
+    assert!(spec("specs::ls?::*", &["--glob"]).matches(name));
+    assert!(!spec("specs::*::comp*", &["--glob"]).matches(name));
+    assert!(spec("*::comp*", &["--glob", "--ignore-case"]).matches(name));
+    assert!(!spec("*::lsp", &["--glob"]).matches(name));
+  }
+}
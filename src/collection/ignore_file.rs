@@ -0,0 +1,316 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::cell::RefCell;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use regex::Regex;
+
+use super::file_system::file_system;
+use super::CollectTestsError;
+use crate::PathedIoError;
+
+/// Name of the file at the collection base with the paths to exclude
+/// from collection, in gitignore syntax.
+pub const IGNORE_FILE_NAME: &str = ".ftrignore";
+
+#[derive(Debug, Clone)]
+struct IgnorePattern {
+  regex: Regex,
+  negated: bool,
+  dir_only: bool,
+}
+
+/// Patterns of a `.ftrignore` file, which exclude the matching files and
+/// directories below the collection base from every strategy.
+///
+/// This supports the gitignore syntax: `#` comments, `!` to re-include a
+/// path, a trailing `/` to only match directories, a leading or inner `/`
+/// to anchor the pattern to the base, and the `*`, `?`, `[...]`, and `**`
+/// wildcards. The last matching pattern wins.
+#[derive(Debug, Clone)]
+pub struct IgnoreFile {
+  base: PathBuf,
+  patterns: Vec<IgnorePattern>,
+}
+
+impl IgnoreFile {
+  /// Parses the patterns, which are relative to `base`.
+  pub fn parse(base: &Path, text: &str) -> Result<Self, CollectTestsError> {
+    let mut patterns = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+      let line = line.trim_end_matches('\r');
+      let line = trim_unescaped_trailing_spaces(line);
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+      let (negated, line) = match line.strip_prefix('!') {
+        Some(line) => (true, line),
+        None => (false, line.strip_prefix('\\').unwrap_or(line)),
+      };
+      let (dir_only, line) = match line.strip_suffix('/') {
+        Some(line) => (true, line),
+        None => (false, line),
+      };
+      if line.is_empty() {
+        continue;
+      }
+      // a slash anywhere but the end anchors the pattern to the base
+      let anchored = line.contains('/');
+      let line = line.strip_prefix('/').unwrap_or(line);
+      let prefix = if anchored { "" } else { "(?:.*/)?" };
+      let regex = Regex::new(&format!("^{}{}$", prefix, glob_to_regex(line)))
+        .map_err(|err| CollectTestsError::MetadataParse {
+        path: base.join(IGNORE_FILE_NAME),
+        message: format!("invalid pattern on line {}: {}", index + 1, err),
+      })?;
+      patterns.push(IgnorePattern {
+        regex,
+        negated,
+        dir_only,
+      });
+    }
+    Ok(Self {
+      base: base.to_path_buf(),
+      patterns,
+    })
+  }
+
+  /// Reads the `.ftrignore` file in the directory when it exists.
+  pub fn read(base: &Path) -> Result<Option<Self>, CollectTestsError> {
+    let path = base.join(IGNORE_FILE_NAME);
+    let file_system = file_system();
+    if !file_system.exists(&path) {
+      return Ok(None);
+    }
+    let text = file_system
+      .read_to_string(&path)
+      .map_err(|err| PathedIoError::new(&path, err))?;
+    Self::parse(base, &text).map(Some)
+  }
+
+  /// Gets if the path below the base is excluded, where directories
+  /// are only excluded by their own patterns since the strategies
+  /// don't traverse the contents of an excluded directory.
+  pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+    let Ok(relative) = path.strip_prefix(&self.base) else {
+      return false;
+    };
+    let relative = relative
+      .components()
+      .map(|c| c.as_os_str().to_string_lossy())
+      .collect::<Vec<_>>()
+      .join("/");
+    if relative.is_empty() {
+      return false;
+    }
+    let mut ignored = false;
+    for pattern in &self.patterns {
+      if pattern.negated == ignored
+        && (is_dir || !pattern.dir_only)
+        && pattern.regex.is_match(&relative)
+      {
+        ignored = !pattern.negated;
+      }
+    }
+    ignored
+  }
+
+  fn path(&self) -> PathBuf {
+    self.base.join(IGNORE_FILE_NAME)
+  }
+}
+
+fn trim_unescaped_trailing_spaces(line: &str) -> &str {
+  let trimmed = line.trim_end_matches(' ');
+  if trimmed.ends_with('\\') && trimmed.len() < line.len() {
+    &line[..trimmed.len() + 1]
+  } else {
+    trimmed
+  }
+}
+
+fn glob_to_regex(glob: &str) -> String {
+  let chars = glob.chars().collect::<Vec<_>>();
+  let mut regex = String::new();
+  let mut i = 0;
+  while i < chars.len() {
+    match chars[i] {
+      '*' if chars.get(i + 1) == Some(&'*') => {
+        let at_start = i == 0 || chars[i - 1] == '/';
+        let at_end = i + 2 == chars.len();
+        let before_slash = chars.get(i + 2) == Some(&'/');
+        if at_start && before_slash {
+          // `**/` matches zero or more directories
+          regex.push_str("(?:.*/)?");
+          i += 3;
+        } else if at_start && at_end {
+          regex.push_str(".*");
+          i += 2;
+        } else {
+          regex.push_str("[^/]*");
+          i += 2;
+        }
+        continue;
+      }
+      '*' => regex.push_str("[^/]*"),
+      '?' => regex.push_str("[^/]"),
+      '[' => match chars[i..].iter().position(|c| *c == ']') {
+        Some(end) if end > 1 => {
+          let class = chars[i + 1..i + end].iter().collect::<String>();
+          let class = match class.strip_prefix('!') {
+            Some(class) => format!("^{}", class),
+            None => class,
+          };
+          regex.push('[');
+          regex.push_str(&class.replace('\\', "\\\\").replace('[', "\\["));
+          regex.push(']');
+          i += end + 1;
+          continue;
+        }
+        _ => regex.push_str("\\["),
+      },
+      '\\' if i + 1 < chars.len() => {
+        i += 1;
+        regex.push_str(&regex::escape(&chars[i].to_string()));
+      }
+      c => regex.push_str(&regex::escape(&c.to_string())),
+    }
+    i += 1;
+  }
+  regex
+}
+
+thread_local! {
+  static CURRENT_IGNORE_FILE: RefCell<Option<Arc<IgnoreFile>>> =
+    const { RefCell::new(None) };
+}
+
+/// Excludes the paths matched by the `.ftrignore` file at the base from
+/// the strategies running on the current thread until the returned guard
+/// is dropped.
+pub(crate) fn track_ignore_file(
+  base: &Path,
+) -> Result<IgnoreFileGuard, CollectTestsError> {
+  let ignore_file = IgnoreFile::read(base)?.map(Arc::new);
+  CURRENT_IGNORE_FILE.with(|current| *current.borrow_mut() = ignore_file);
+  Ok(IgnoreFileGuard)
+}
+
+pub(crate) struct IgnoreFileGuard;
+
+impl Drop for IgnoreFileGuard {
+  fn drop(&mut self) {
+    CURRENT_IGNORE_FILE.with(|current| current.borrow_mut().take());
+  }
+}
+
+/// Gets if the entry is excluded by the current `.ftrignore` file,
+/// including the ignore file itself.
+pub(crate) fn is_ignored_entry(path: &Path) -> bool {
+  let Some(ignore_file) =
+    CURRENT_IGNORE_FILE.with(|current| current.borrow().clone())
+  else {
+    return false;
+  };
+  if path == ignore_file.path() {
+    return true;
+  }
+  let is_dir = file_system()
+    .entry_kind(path)
+    .is_ok_and(|kind| kind.is_dir());
+  ignore_file.is_ignored(path, is_dir)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::collection::collect_tests_with_warnings;
+  use crate::collection::strategies::EntryFilter;
+  use crate::collection::strategies::TestPerFileCollectionStrategy;
+  use crate::collection::with_file_system;
+  use crate::collection::CollectOptions;
+  use crate::testing::InMemoryFileSystem;
+
+  #[test]
+  fn matches_gitignore_patterns() {
+    let base = Path::new("specs");
+    let ignore_file = IgnoreFile::parse(
+      base,
+      r#"
+# generated output
+generated/
+*.wip.txt
+/root_only.txt
+docs/**/draft
+fixtures/*/tmp
+[ab].snap
+!keep.wip.txt
+\#hash.txt
+"#,
+    )
+    .unwrap();
+    let is_ignored = |path: &str, is_dir: bool| {
+      ignore_file.is_ignored(&base.join(path), is_dir)
+    };
+    assert!(is_ignored("generated", true));
+    assert!(is_ignored("sub/generated", true));
+    assert!(!is_ignored("generated", false));
+    assert!(is_ignored("a.wip.txt", false));
+    assert!(is_ignored("sub/b.wip.txt", false));
+    assert!(!is_ignored("keep.wip.txt", false));
+    assert!(is_ignored("root_only.txt", false));
+    assert!(!is_ignored("sub/root_only.txt", false));
+    assert!(is_ignored("docs/draft", true));
+    assert!(is_ignored("docs/a/b/draft", false));
+    assert!(is_ignored("fixtures/one/tmp", true));
+    assert!(!is_ignored("fixtures/one/two/tmp", true));
+    assert!(is_ignored("a.snap", false));
+    assert!(!is_ignored("c.snap", false));
+    assert!(is_ignored("#hash.txt", false));
+    assert!(!is_ignored("other.txt", false));
+    assert!(!ignore_file.is_ignored(Path::new("other/a.wip.txt"), false));
+
+    let err = IgnoreFile::parse(base, "a\n[z-a]").unwrap_err();
+    let CollectTestsError::MetadataParse { message, .. } = err else {
+      unreachable!();
+    };
+    assert!(
+      message.starts_with("invalid pattern on line 2:"),
+      "{}",
+      message
+    );
+  }
+
+  #[test]
+  fn excludes_from_collection() {
+    let fs = InMemoryFileSystem::default()
+      .file("specs/.ftrignore", "generated/\n*.wip.txt\n")
+      .file("specs/a.txt", "")
+      .file("specs/b.wip.txt", "")
+      .file("specs/generated/c.txt", "")
+      .file("specs/sub/d.txt", "")
+      .file("specs/sub/e.wip.txt", "");
+    let output = with_file_system(Arc::new(fs), || {
+      collect_tests_with_warnings(CollectOptions {
+        filter_override: Some(String::new()),
+        ..CollectOptions::new(
+          PathBuf::from("specs"),
+          TestPerFileCollectionStrategy {
+            entry_filter: EntryFilter {
+              skip_hidden: false,
+              ..Default::default()
+            },
+            ..Default::default()
+          },
+        )
+      })
+    })
+    .unwrap();
+    assert_eq!(output.category.test_count(), 2);
+    assert!(output.category.find_test("specs::a").is_some());
+    assert!(output.category.find_test("specs::sub::d").is_some());
+    assert!(output.warnings.is_empty());
+  }
+}
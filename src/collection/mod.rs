@@ -1,16 +1,49 @@
 // Copyright 2018-2024 the Deno authors. MIT license.
 
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::panic::RefUnwindSafe;
+use std::path::Path;
 use std::path::PathBuf;
+use std::process::Command;
+use std::sync::Arc;
+use std::sync::OnceLock;
+use std::time::Duration;
+use std::time::SystemTime;
 
 use deno_terminal::colors;
+use regex::Regex;
 use thiserror::Error;
 
+use crate::exit_code::RunExitCode;
 use crate::PathedIoError;
 
 use self::strategies::TestCollectionStrategy;
 
+mod affected;
+mod diagnostics;
+mod file_system;
+mod filter;
+mod ignore_file;
+mod progress;
 pub mod strategies;
 
+pub use affected::affected_tests_filter;
+pub use affected::AffectedTests;
+pub use affected::CoverageMap;
+pub use diagnostics::report_collect_warning;
+pub use diagnostics::CollectWarning;
+pub use file_system::with_file_system;
+pub use file_system::EntryKind;
+pub use file_system::FileSystem;
+pub use file_system::RealFileSystem;
+pub use filter::FilterMode;
+pub use filter::FilterSpec;
+pub use ignore_file::IgnoreFile;
+pub use ignore_file::IGNORE_FILE_NAME;
+pub use progress::CollectProgress;
+pub use progress::CollectProgressFunc;
+
 #[derive(Debug, Clone)]
 pub enum CollectedCategoryOrTest<T = ()> {
   Category(CollectedTestCategory<T>),
@@ -24,6 +57,9 @@ pub struct CollectedTestCategory<T = ()> {
   /// Path to the test category. May be a file or directory
   /// depending on how the test strategy collects tests.
   pub path: PathBuf,
+  /// Human readable description of the category, which reporters show
+  /// along with its name. See `TestCollectionStrategy::describe_categories`.
+  pub description: Option<String>,
   /// Children of the category.
   pub children: Vec<CollectedCategoryOrTest<T>>,
 }
@@ -40,13 +76,115 @@ impl<T> CollectedTestCategory<T> {
       .sum()
   }
 
-  pub fn filter_children(&mut self, filter: &str) {
-    self.children.retain_mut(|mut child| match &mut child {
+  /// Finds a test in this category or its sub categories by its
+  /// fully resolved name.
+  pub fn find_test(&self, name: &str) -> Option<&CollectedTest<T>> {
+    self.children.iter().find_map(|child| match child {
+      CollectedCategoryOrTest::Category(c) => c.find_test(name),
+      CollectedCategoryOrTest::Test(t) => (t.name == name).then_some(t),
+    })
+  }
+
+  /// Adds a test to this category, creating the sub categories in its
+  /// name as necessary. This allows tests that aren't collected from files
+  /// (ex. tests written in Rust) to be run alongside the collected tests.
+  ///
+  /// Panics if the test's name is not within this category.
+  pub fn push_test(&mut self, test: CollectedTest<T>) {
+    let Some(relative_name) = test
+      .name
+      .strip_prefix(&self.name)
+      .and_then(|name| name.strip_prefix("::"))
+    else {
+      panic!("Test '{}' is not in category '{}'.", test.name, self.name);
+    };
+    let mut category_names = relative_name
+      .split("::")
+      .map(|name| name.to_string())
+      .collect::<Vec<_>>();
+    category_names.pop();
+
+    let mut current = self;
+    for category_name in category_names {
+      let name = format!("{}::{}", current.name, category_name);
+      let index = match current.children.iter().position(|child| {
+        matches!(child, CollectedCategoryOrTest::Category(c) if c.name == name)
+      }) {
+        Some(index) => index,
+        None => {
+          let path = current.path.join(&category_name);
+          current.children.push(CollectedCategoryOrTest::Category(
+            CollectedTestCategory {
+              name,
+              path,
+              description: None,
+              children: Vec::new(),
+            },
+          ));
+          current.children.len() - 1
+        }
+      };
+      let CollectedCategoryOrTest::Category(category) =
+        &mut current.children[index]
+      else {
+        unreachable!();
+      };
+      current = category;
+    }
+    current.children.push(CollectedCategoryOrTest::Test(test));
+  }
+
+  /// Keeps only the tests whose names match the filter, which is a
+  /// substring match when providing a `&str`.
+  pub fn filter_children(&mut self, filter: impl Into<FilterSpec>) {
+    let filter = filter.into();
+    self.retain_tests(&mut |test| filter.matches(&test.name));
+  }
+
+  /// Marks the tests whose names don't match the filter as filtered out
+  /// (see `CollectedTest::filtered_out`) instead of removing them,
+  /// returning the number of tests marked.
+  pub fn ignore_filtered_out(
+    &mut self,
+    filter: impl Into<FilterSpec>,
+  ) -> usize {
+    let filter = filter.into();
+    self.ignore_unselected(&|test| filter.matches(&test.name))
+  }
+
+  fn ignore_unselected(
+    &mut self,
+    is_selected: &impl Fn(&CollectedTest<T>) -> bool,
+  ) -> usize {
+    let mut count = 0;
+    for child in &mut self.children {
+      match child {
+        CollectedCategoryOrTest::Category(c) => {
+          count += c.ignore_unselected(is_selected);
+        }
+        CollectedCategoryOrTest::Test(t) => {
+          if !is_selected(t) {
+            t.filtered_out = true;
+            count += 1;
+          }
+        }
+      }
+    }
+    count
+  }
+
+  /// Keeps only the tests that match the predicate, removing any
+  /// categories that become empty.
+  pub fn retain_tests(
+    &mut self,
+    predicate: &mut impl FnMut(&CollectedTest<T>) -> bool,
+  ) {
+    self.children.retain_mut(|child| match child {
       CollectedCategoryOrTest::Category(c) => {
-        c.filter_children(filter);
+        c.retain_tests(predicate);
         !c.is_empty()
       }
-      CollectedCategoryOrTest::Test(t) => t.name.contains(filter),
+      CollectedCategoryOrTest::Test(t) => predicate(t),
     });
   }
 
@@ -74,19 +212,319 @@ pub struct CollectedTest<T = ()> {
   pub name: String,
   /// Path to the test file.
   pub path: PathBuf,
+  /// One-based line and column of the test within the test file, if
+  /// the test is not the entire file.
+  pub line_and_column: Option<(u32, u32)>,
+  /// Stable ID of the test that machine readable output includes along
+  /// with its name, so external dashboards and quarantine lists keyed by
+  /// it keep working when the test is renamed.
+  pub alias: Option<String>,
+  /// Human readable name reporters show for the test (ex. the original
+  /// file name or a title from the test's front matter), while `name`
+  /// remains the identifier used for filtering.
+  pub display_name: Option<String>,
+  /// Environment variables to run the test with, which are usually set by
+  /// a mapper from the test's configuration (ex. an `"envs"` section).
+  ///
+  /// These are set on the worker process when using worker processes and on
+  /// commands created via `CollectedTest::command`. They are not set when
+  /// running tests on threads because the environment is shared.
+  pub env_vars: HashMap<String, String>,
+  /// Reason to ignore the test on all platforms (ex. `"requires network"`),
+  /// which the runner reports without calling the run function.
+  pub ignored: Option<String>,
+  /// Whether the test was kept in the tree after being filtered out (see
+  /// `CollectOptions::keep_filtered_out`), which the runner reports as
+  /// ignored with the `FILTERED_OUT_REASON`.
+  pub filtered_out: bool,
+  /// Priority of the test (ex. from `__config__.jsonc` files or set by a
+  /// strategy from the test's data), where higher priority tests are run
+  /// before the rest so their failures are reported first.
+  pub priority: TestPriority,
+  /// Settings that most tests leave as their defaults.
+  pub settings: Box<TestSettings>,
+  cached_content: ContentCache,
   /// Data associated with the test that may have been
   /// set by the collection strategy.
   pub data: T,
 }
 
+/// Settings of a `CollectedTest` that most tests leave as their defaults,
+/// which are boxed to keep the tree of collected tests small.
+#[derive(Debug, Clone, Default)]
+pub struct TestSettings {
+  /// Platforms to ignore the test on, which may be an operating system
+  /// (ex. `"windows"`, `"macos"`, `"linux"`), an operating system family
+  /// (`"unix"` or `"windows"`), or an architecture (ex. `"aarch64"`).
+  pub ignore_on: Vec<String>,
+  /// Labels of the test (ex. from `__config__.jsonc` files), which
+  /// `CollectOptions::filter_fn` may select tests by.
+  pub tags: Vec<String>,
+  /// Capabilities the test needs (ex. `"docker"` or `"network"`), where
+  /// the runner ignores the test when one isn't available according to
+  /// `RunOptions::capabilities`.
+  pub requires: Vec<String>,
+  /// Timeout of the test, which takes precedence over
+  /// `RunOptions::timeout` but not `RunOptions::timeout_overrides`.
+  pub timeout: Option<Duration>,
+  /// Metadata of the test file, when recorded by the strategy.
+  pub metadata: Option<FileMetadata>,
+  /// Provides the contents of the test instead of reading the file at
+  /// `path` (ex. for tests extracted from a larger file or an archive).
+  pub content_provider: Option<ContentProvider>,
+}
+
+/// Text of the test file once it's read by `CollectedTest::content`,
+/// which is shared by the clones of the test. The text is stored along
+/// with where it was read from, so a clone whose path or content provider
+/// was changed reads its own text instead of the original's.
+#[derive(Debug, Clone, Default)]
+struct ContentCache(Arc<ContentCacheSlot>);
+
+#[derive(Debug, Default)]
+struct ContentCacheSlot(OnceLock<CachedContent>);
+
+#[derive(Debug)]
+struct CachedContent {
+  path: PathBuf,
+  content_provider: Option<ContentProvider>,
+  text: String,
+  /// Slot for the text of a clone read from somewhere else.
+  next: Box<ContentCacheSlot>,
+}
+
+impl CachedContent {
+  fn is_for<T>(&self, test: &CollectedTest<T>) -> bool {
+    self.path == test.path
+      && match (&self.content_provider, &test.settings.content_provider) {
+        (Some(a), Some(b)) => Arc::ptr_eq(&a.0, &b.0),
+        (None, None) => true,
+        _ => false,
+      }
+  }
+}
+
+type ContentProviderFunc =
+  dyn Fn() -> Result<Vec<u8>, std::io::Error> + Send + Sync + RefUnwindSafe;
+
+/// Provides the contents of a test, which are used by
+/// `CollectedTest::read_to_string`, `read_to_bytes`, and `content`.
+#[derive(Clone)]
+pub struct ContentProvider(Arc<ContentProviderFunc>);
+
+impl std::fmt::Debug for ContentProvider {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("ContentProvider").finish_non_exhaustive()
+  }
+}
+
+impl ContentProvider {
+  pub fn new(
+    provide: impl Fn() -> Result<Vec<u8>, std::io::Error>
+      + Send
+      + Sync
+      + RefUnwindSafe
+      + 'static,
+  ) -> Self {
+    Self(Arc::new(provide))
+  }
+}
+
+/// Metadata of a test file that's recorded while collecting, so that it
+/// doesn't need to be read from the file system again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileMetadata {
+  pub size: u64,
+  pub modified: Option<SystemTime>,
+}
+
+/// Priority of a test. The runner dispatches the high priority tests of
+/// every category before the normal priority ones, followed by the low
+/// priority ones.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TestPriority {
+  High,
+  #[default]
+  Normal,
+  Low,
+}
+
+impl TestPriority {
+  pub fn parse(text: &str) -> Option<Self> {
+    match text {
+      "high" => Some(Self::High),
+      "normal" => Some(Self::Normal),
+      "low" => Some(Self::Low),
+      _ => None,
+    }
+  }
+
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Self::High => "high",
+      Self::Normal => "normal",
+      Self::Low => "low",
+    }
+  }
+
+  /// Gets the next lower priority.
+  pub(crate) fn lower(&self) -> Option<Self> {
+    match self {
+      Self::High => Some(Self::Normal),
+      Self::Normal => Some(Self::Low),
+      Self::Low => None,
+    }
+  }
+}
+
+impl From<&std::fs::Metadata> for FileMetadata {
+  fn from(metadata: &std::fs::Metadata) -> Self {
+    Self {
+      size: metadata.len(),
+      modified: metadata.modified().ok(),
+    }
+  }
+}
+
 impl<T> CollectedTest<T> {
+  /// Creates a test that's read from the file at the path, with the
+  /// rest of its settings defaulted.
+  pub fn new(
+    name: impl Into<String>,
+    path: impl Into<PathBuf>,
+    data: T,
+  ) -> Self {
+    Self {
+      name: name.into(),
+      path: path.into(),
+      line_and_column: None,
+      alias: None,
+      display_name: None,
+      env_vars: Default::default(),
+      ignored: None,
+      filtered_out: false,
+      priority: Default::default(),
+      settings: Default::default(),
+      cached_content: Default::default(),
+      data,
+    }
+  }
+
+  /// Converts the test's data, keeping the rest of the test as is.
+  pub fn map_data<U>(self, map: impl FnOnce(T) -> U) -> CollectedTest<U> {
+    CollectedTest {
+      name: self.name,
+      path: self.path,
+      line_and_column: self.line_and_column,
+      alias: self.alias,
+      display_name: self.display_name,
+      env_vars: self.env_vars,
+      ignored: self.ignored,
+      filtered_out: self.filtered_out,
+      priority: self.priority,
+      settings: self.settings,
+      cached_content: self.cached_content,
+      data: map(self.data),
+    }
+  }
+
+  /// Gets the name reporters show for the test, which is the display
+  /// name when set.
+  pub fn label(&self) -> &str {
+    self.display_name.as_deref().unwrap_or(&self.name)
+  }
+
   /// Helper to read the test file to a string.
   pub fn read_to_string(&self) -> Result<String, PathedIoError> {
-    std::fs::read_to_string(&self.path)
-      .map_err(|err| PathedIoError::new(&self.path, err))
+    let result = match &self.settings.content_provider {
+      Some(provider) => (provider.0)().and_then(|bytes| {
+        String::from_utf8(bytes).map_err(|err| {
+          std::io::Error::new(std::io::ErrorKind::InvalidData, err)
+        })
+      }),
+      None => std::fs::read_to_string(&self.path),
+    };
+    result.map_err(|err| PathedIoError::new(&self.path, err))
+  }
+
+  /// Helper to read the test file's bytes.
+  pub fn read_to_bytes(&self) -> Result<Vec<u8>, PathedIoError> {
+    let result = match &self.settings.content_provider {
+      Some(provider) => (provider.0)(),
+      None => std::fs::read(&self.path),
+    };
+    result.map_err(|err| PathedIoError::new(&self.path, err))
+  }
+
+  /// Gets the text of the test file, reading it the first time this
+  /// is called.
+  pub fn content(&self) -> Result<&str, PathedIoError> {
+    let mut slot = &*self.cached_content.0;
+    loop {
+      let cached = match slot.0.get() {
+        Some(cached) => cached,
+        None => {
+          let text = self.read_to_string()?;
+          slot.0.get_or_init(|| CachedContent {
+            path: self.path.clone(),
+            content_provider: self.settings.content_provider.clone(),
+            text,
+            next: Default::default(),
+          })
+        }
+      };
+      if cached.is_for(self) {
+        return Ok(&cached.text);
+      }
+      slot = &cached.next;
+    }
+  }
+
+  /// Whether the test was kept in the tree after being filtered out
+  /// (see `CollectOptions::keep_filtered_out`).
+  pub fn is_filtered_out(&self) -> bool {
+    self.filtered_out
+  }
+
+  /// Gets the reason the test should be ignored on the current platform.
+  pub fn ignore_reason(&self) -> Option<String> {
+    if self.filtered_out {
+      return Some(FILTERED_OUT_REASON.to_string());
+    }
+    if let Some(reason) = &self.ignored {
+      return Some(reason.clone());
+    }
+    self
+      .settings
+      .ignore_on
+      .iter()
+      .find(|platform| {
+        let platform = platform.as_str();
+        platform == std::env::consts::OS
+          || platform == std::env::consts::FAMILY
+          || platform == std::env::consts::ARCH
+      })
+      .map(|platform| format!("ignore_on: {}", platform))
+  }
+
+  /// Creates a command with the test's environment variables and the
+  /// `FILE_TEST_RUNNER_TEST_SEED` environment variable set.
+  pub fn command(&self, program: impl AsRef<std::ffi::OsStr>) -> Command {
+    let mut command = Command::new(program);
+    command.envs(&self.env_vars);
+    if let Some(seed) = crate::seed::current_seed() {
+      command.env(crate::TEST_SEED_ENV_VAR, seed.to_string());
+    }
+    command
   }
 }
 
+/// Reason the tests kept via `CollectOptions::keep_filtered_out` are
+/// reported as ignored with.
+pub const FILTERED_OUT_REASON: &str = "filtered out";
+
+pub type CollectFilterFunc<TData> = Box<dyn Fn(&CollectedTest<TData>) -> bool>;
+
 pub struct CollectOptions<TData> {
   /// Base path to start from when searching for tests.
   pub base: PathBuf,
@@ -96,17 +534,104 @@ pub struct CollectOptions<TData> {
   ///
   /// Generally, just provide `None` here.
   pub filter_override: Option<String>,
+  /// Only keep the tests matching the predicate in addition to the
+  /// filter, which allows selecting tests based on their data (ex. tags
+  /// or platforms) instead of only their names.
+  pub filter_fn: Option<CollectFilterFunc<TData>>,
+  /// Keep the tests that don't match the filter in the collected tree,
+  /// marked as `CollectedTest::filtered_out`, instead of removing them.
+  /// This allows listing the full suite and reporting the filtered out
+  /// tests as skipped (ex. in JUnit reports).
+  pub keep_filtered_out: bool,
+  /// Error when test files or directories differ only by case, which
+  /// collide when checked out on case-insensitive file systems
+  /// (ex. macOS and Windows).
+  pub check_case_collisions: bool,
+  /// What to do with test names containing characters that can't be
+  /// used to filter tests via the command line.
+  pub name_policy: TestNamePolicy,
+  /// Called periodically with the progress of collecting the tests, which
+  /// is useful for large trees that take a while to collect.
+  ///
+  /// When using `collect_and_run_tests`, this defaults to reporting
+  /// the progress to the reporter.
+  pub on_progress: Option<CollectProgressFunc>,
+}
+
+impl<TData> CollectOptions<TData> {
+  /// Creates options to collect the tests in the base path with the
+  /// strategy, where the other options can be set with the struct update
+  /// syntax (ex. `CollectOptions { keep_filtered_out: true, ..options }`).
+  pub fn new(
+    base: impl Into<PathBuf>,
+    strategy: impl TestCollectionStrategy<TData> + 'static,
+  ) -> Self {
+    Self {
+      base: base.into(),
+      strategy: Box::new(strategy),
+      filter_override: None,
+      filter_fn: None,
+      keep_filtered_out: false,
+      check_case_collisions: false,
+      name_policy: Default::default(),
+      on_progress: None,
+    }
+  }
+}
+
+/// How to handle test names with characters other than ASCII alphanumeric
+/// characters and underscores, which are usually derived from file names.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TestNamePolicy {
+  /// Error with an `InvalidTestNameError`.
+  #[default]
+  Error,
+  /// Hex-encode the code point of each character that `Error` rejects
+  /// (ex. `-` becomes `_u2d_`), keeping the names that are already valid
+  /// as they are. Errors with a `TestNameCollisionError` when two tests
+  /// or categories end up with the same name.
+  Encode,
+}
+
+impl TestNamePolicy {
+  fn apply<TData>(
+    &self,
+    category: &mut CollectedTestCategory<TData>,
+  ) -> Result<(), TestNameCollisionError> {
+    match self {
+      TestNamePolicy::Error => Ok(()),
+      TestNamePolicy::Encode => {
+        let mut names = HashMap::new();
+        encode_test_names(category, &mut names)
+      }
+    }
+  }
 }
 
 /// Collect all the tests or exit if there are any errors.
+///
+/// Warnings are printed to stderr.
 pub fn collect_tests_or_exit<TData>(
   options: CollectOptions<TData>,
 ) -> CollectedTestCategory<TData> {
-  match collect_tests(options) {
-    Ok(category) => category,
+  let output = collect_tests_with_warnings_or_exit(options);
+  for warning in &output.warnings {
+    eprintln!("{}: {}", colors::yellow_bold("warning"), warning);
+  }
+  output.category
+}
+
+pub(crate) fn collect_tests_with_warnings_or_exit<TData>(
+  options: CollectOptions<TData>,
+) -> CollectOutput<TData> {
+  match collect_tests_with_warnings(options) {
+    Ok(output) => output,
     Err(err) => {
       eprintln!("{}: {}", colors::red_bold("error"), err);
-      std::process::exit(1);
+      if let Some(hint) = err.hint() {
+        eprintln!("  {} {}", colors::gray("hint:"), hint);
+      }
+      RunExitCode::CollectionError.exit()
     }
   }
 }
@@ -116,17 +641,121 @@ pub enum CollectTestsError {
   #[error(transparent)]
   InvalidTestName(#[from] InvalidTestNameError),
   #[error(transparent)]
+  TestNameCollision(#[from] TestNameCollisionError),
+  #[error(transparent)]
+  CaseCollision(#[from] CaseCollisionError),
+  #[error(transparent)]
   Io(#[from] PathedIoError),
+  #[error(transparent)]
+  DirectoryConfig(#[from] strategies::DirectoryConfigError),
+  /// A pattern of the strategy (ex. `file_pattern`) is not a valid regex.
+  #[error("Invalid pattern '{pattern}': {source}")]
+  InvalidPattern {
+    pattern: String,
+    source: regex::Error,
+  },
+  /// A path the strategy can't collect tests from (ex. a symlink that's
+  /// not allowed by the symlink policy).
+  #[error("Unsupported path '{}': {}", path.display(), reason)]
+  UnsupportedPath { path: PathBuf, reason: String },
+  /// A directory tree that doesn't contain the test file of a
+  /// `TestPerDirectoryCollectionStrategy`.
+  #[error("Could not find '{}' in directory tree '{}'. Perhaps the file is named incorrectly?", path.display(), dir.display())]
+  MissingTestFile { path: PathBuf, dir: PathBuf },
+  /// The metadata of a test or the file containing the tests (ex. the
+  /// headers of an archive) could not be parsed.
+  #[error("Failed to parse '{}': {}", path.display(), message)]
+  MetadataParse { path: PathBuf, message: String },
   #[error("No tests found")]
   NoTestsFound,
   #[error(transparent)]
   Other(#[from] anyhow::Error),
 }
 
+impl CollectTestsError {
+  /// Creates an `InvalidPattern` error when the pattern isn't a valid regex.
+  pub fn compile_pattern(pattern: &str) -> Result<Regex, CollectTestsError> {
+    Regex::new(pattern).map_err(|source| CollectTestsError::InvalidPattern {
+      pattern: pattern.to_string(),
+      source,
+    })
+  }
+
+  /// Gets a hint on how to fix the error, which `collect_tests_or_exit`
+  /// prints along with the error.
+  pub fn hint(&self) -> Option<&'static str> {
+    match self {
+      CollectTestsError::InvalidTestName(_) => Some(
+        "Set `CollectOptions::name_policy` to `TestNamePolicy::Encode` to encode the characters instead.",
+      ),
+      CollectTestsError::TestNameCollision(_) => {
+        Some("Rename one of them so their names differ once encoded.")
+      }
+      CollectTestsError::CaseCollision(_) => {
+        Some("Rename one of the paths so they differ by more than case.")
+      }
+      CollectTestsError::DirectoryConfig(_) => {
+        Some("See `schemas/config.v1.json` for the supported settings.")
+      }
+      CollectTestsError::InvalidPattern { .. } => Some(
+        "Patterns use the syntax of the `regex` crate, so escape characters such as `.` and `(` to match them literally.",
+      ),
+      CollectTestsError::NoTestsFound => {
+        Some("Check that `CollectOptions::base` is the directory of the tests.")
+      }
+      CollectTestsError::Io(_)
+      | CollectTestsError::UnsupportedPath { .. }
+      | CollectTestsError::MissingTestFile { .. }
+      | CollectTestsError::MetadataParse { .. }
+      | CollectTestsError::Other(_) => None,
+    }
+  }
+}
+
 pub fn collect_tests<TData>(
   options: CollectOptions<TData>,
 ) -> Result<CollectedTestCategory<TData>, CollectTestsError> {
+  collect_tests_with_warnings(options).map(|output| output.category)
+}
+
+#[derive(Debug, Clone)]
+pub struct CollectOutput<TData = ()> {
+  pub category: CollectedTestCategory<TData>,
+  /// Non-fatal problems found while collecting (ex. empty directories).
+  pub warnings: Vec<CollectWarning>,
+  pub stats: CollectStats,
+}
+
+/// Number of tests that were collected and how many of them the filter
+/// removed.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CollectStats {
+  /// Number of tests collected before filtering.
+  pub total: usize,
+  pub filtered_out: usize,
+  /// Filter provided on the command line or via
+  /// `CollectOptions::filter_override`.
+  pub filter: Option<String>,
+}
+
+impl CollectStats {
+  /// Number of tests that will be run.
+  pub fn selected(&self) -> usize {
+    self.total - self.filtered_out
+  }
+}
+
+/// Collects the tests along with any warnings reported by the strategy.
+pub fn collect_tests_with_warnings<TData>(
+  options: CollectOptions<TData>,
+) -> Result<CollectOutput<TData>, CollectTestsError> {
+  let progress_guard = progress::track_progress(options.on_progress);
+  let warnings_guard = diagnostics::track_warnings();
+  let ignore_file_guard = ignore_file::track_ignore_file(&options.base)?;
   let mut category = options.strategy.collect_tests(&options.base)?;
+  let warnings = warnings_guard.take();
+  drop(ignore_file_guard);
+  drop(progress_guard);
 
   // error when no tests are found before filtering
   if category.is_empty() {
@@ -134,15 +763,47 @@ pub fn collect_tests<TData>(
   }
 
   // ensure all test names are valid
+  options.name_policy.apply(&mut category)?;
   ensure_valid_test_names(&category)?;
 
+  if options.check_case_collisions {
+    ensure_no_case_collisions(&category)?;
+  }
+
   // filter
-  let maybe_filter = options.filter_override.or_else(parse_cli_arg_filter);
-  if let Some(filter) = &maybe_filter {
-    category.filter_children(filter);
+  let total = category.test_count();
+  let maybe_filter = options
+    .filter_override
+    .or_else(parse_cli_arg_filter)
+    .filter(|filter| !filter.is_empty());
+  let filter_spec = maybe_filter.clone().map(FilterSpec::with_cli_mode);
+  let filter_fn = options.filter_fn;
+  let is_selected = |test: &CollectedTest<TData>| {
+    filter_spec
+      .as_ref()
+      .is_none_or(|filter| filter.matches(&test.name))
+      && filter_fn.as_ref().is_none_or(|filter_fn| filter_fn(test))
+  };
+  let mut filtered_out = 0;
+  if filter_spec.is_some() || filter_fn.is_some() {
+    if options.keep_filtered_out {
+      filtered_out = category.ignore_unselected(&is_selected);
+    } else {
+      category.retain_tests(&mut |test| is_selected(test));
+      filtered_out = total - category.test_count();
+    }
   }
+  let stats = CollectStats {
+    total,
+    filtered_out,
+    filter: maybe_filter,
+  };
 
-  Ok(category)
+  Ok(CollectOutput {
+    category,
+    warnings,
+    stats,
+  })
 }
 
 fn ensure_valid_test_names<TData>(
@@ -154,12 +815,7 @@ fn ensure_valid_test_names<TData>(
         ensure_valid_test_names(category)?;
       }
       CollectedCategoryOrTest::Test(test) => {
-        // only support characters that work with filtering with `cargo test`
-        if !test
-          .name
-          .chars()
-          .all(|c| c.is_alphanumeric() || matches!(c, '_' | ':'))
-        {
+        if !test.name.chars().all(is_valid_test_name_char) {
           return Err(InvalidTestNameError(test.name.clone()));
         }
       }
@@ -169,12 +825,469 @@ fn ensure_valid_test_names<TData>(
 }
 
 #[derive(Debug, Error)]
-#[error("Invalid test name ({0}). Use only alphanumeric and underscore characters so tests can be filtered via the command line.")]
+#[error("Invalid test name ({0}). Use only ASCII alphanumeric and underscore characters so tests can be filtered via the command line.")]
 pub struct InvalidTestNameError(String);
 
+// only support characters that work with filtering with `cargo test`
+fn is_valid_test_name_char(c: char) -> bool {
+  c.is_ascii_alphanumeric() || matches!(c, '_' | ':')
+}
+
+#[derive(Debug, Error)]
+#[error("'{0}' and '{1}' are both named '{2}' once encoded.")]
+pub struct TestNameCollisionError(String, String, String);
+
+/// Encodes the names, where `names` maps the encoded test and category
+/// names to the original ones to find the names that collide.
+fn encode_test_names<TData>(
+  category: &mut CollectedTestCategory<TData>,
+  names: &mut HashMap<String, String>,
+) -> Result<(), TestNameCollisionError> {
+  category.name = encode_unique_name(&category.name, names)?;
+  for child in &mut category.children {
+    match child {
+      CollectedCategoryOrTest::Category(category) => {
+        encode_test_names(category, names)?;
+      }
+      CollectedCategoryOrTest::Test(test) => {
+        test.name = encode_unique_name(&test.name, names)?;
+      }
+    }
+  }
+  Ok(())
+}
+
+fn encode_unique_name(
+  name: &str,
+  names: &mut HashMap<String, String>,
+) -> Result<String, TestNameCollisionError> {
+  let encoded = encode_test_name(name);
+  match names.get(&encoded) {
+    // tests in the same file may share a name
+    Some(original) if original != name => Err(TestNameCollisionError(
+      original.clone(),
+      name.to_string(),
+      encoded,
+    )),
+    Some(_) => Ok(encoded),
+    None => {
+      names.insert(encoded.clone(), name.to_string());
+      Ok(encoded)
+    }
+  }
+}
+
+fn encode_test_name(name: &str) -> String {
+  let mut encoded = String::with_capacity(name.len());
+  for c in name.chars() {
+    if is_valid_test_name_char(c) {
+      encoded.push(c);
+    } else {
+      encoded.push_str(&format!("_u{:x}_", c as u32));
+    }
+  }
+  encoded
+}
+
+fn ensure_no_case_collisions<TData>(
+  category: &CollectedTestCategory<TData>,
+) -> Result<(), CaseCollisionError> {
+  fn collect_paths<'a, TData>(
+    category: &'a CollectedTestCategory<TData>,
+    paths: &mut Vec<&'a Path>,
+  ) {
+    paths.push(&category.path);
+    for child in &category.children {
+      match child {
+        CollectedCategoryOrTest::Category(category) => {
+          collect_paths(category, paths);
+        }
+        CollectedCategoryOrTest::Test(test) => paths.push(&test.path),
+      }
+    }
+  }
+
+  let mut paths = Vec::new();
+  collect_paths(category, &mut paths);
+  let mut seen = HashSet::new();
+  let mut lowercase_paths = HashMap::new();
+  for path in paths {
+    // multiple tests may be in the same file
+    if !seen.insert(path) {
+      continue;
+    }
+    let key = path.to_string_lossy().to_lowercase();
+    if let Some(other) = lowercase_paths.insert(key, path) {
+      return Err(CaseCollisionError(other.to_path_buf(), path.to_path_buf()));
+    }
+  }
+  Ok(())
+}
+
+#[derive(Debug, Error)]
+#[error("Test paths differ only by case, which collide on case-insensitive file systems:\n  {}\n  {}", .0.display(), .1.display())]
+pub struct CaseCollisionError(PathBuf, PathBuf);
+
 fn parse_cli_arg_filter() -> Option<String> {
   let args: Vec<String> = std::env::args().collect();
   let maybe_filter =
     args.get(1).filter(|s| !s.starts_with('-') && !s.is_empty());
   maybe_filter.cloned()
 }
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn typed_errors() {
+    let err = CollectTestsError::compile_pattern("(").unwrap_err();
+    assert!(matches!(
+      &err,
+      CollectTestsError::InvalidPattern { pattern, .. } if pattern == "("
+    ));
+    assert!(err.to_string().starts_with("Invalid pattern '(': "));
+    assert!(err.hint().is_some());
+    assert!(CollectTestsError::compile_pattern("\\.ts$").is_ok());
+    let err = CollectTestsError::MetadataParse {
+      path: PathBuf::from("specs.tar"),
+      message: "invalid size".to_string(),
+    };
+    assert_eq!(err.to_string(), "Failed to parse 'specs.tar': invalid size");
+    assert_eq!(err.hint(), None);
+  }
+
+  #[test]
+  fn encodes_test_names() {
+    assert_eq!(encode_test_name("specs::a_1"), "specs::a_1");
+    // non-ascii letters are encoded too
+    assert_eq!(encode_test_name("specs::caf\u{e9}"), "specs::caf_ue9_");
+    assert_eq!(
+      encode_test_name("specs::\u{65e5}-x.y"),
+      "specs::_u65e5__u2d_x_u2e_y"
+    );
+
+    let mut category = CollectedTestCategory {
+      name: "specs".to_string(),
+      path: PathBuf::from("specs"),
+      description: None,
+      children: Vec::new(),
+    };
+    category.push_test(CollectedTest::new("specs::a-b", "a-b", ()));
+    category.push_test(CollectedTest::new("specs::c", "c", ()));
+    category.push_test(CollectedTest::new("specs::c", "c", ()));
+    assert!(TestNamePolicy::Encode.apply(&mut category.clone()).is_ok());
+    category.push_test(CollectedTest::new("specs::a_u2d_b", "a_u2d_b", ()));
+    let err = TestNamePolicy::Encode.apply(&mut category).unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "'specs::a-b' and 'specs::a_u2d_b' are both named 'specs::a_u2d_b' once encoded."
+    );
+
+    let mut category = CollectedTestCategory::<()> {
+      name: "specs".to_string(),
+      path: PathBuf::from("specs"),
+      description: None,
+      children: Vec::new(),
+    };
+    for name in ["specs::x-y", "specs::x_u2d_y"] {
+      category.children.push(CollectedCategoryOrTest::Category(
+        CollectedTestCategory {
+          name: name.to_string(),
+          path: PathBuf::from(name),
+          description: None,
+          children: Vec::new(),
+        },
+      ));
+    }
+    let err = TestNamePolicy::Encode.apply(&mut category).unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "'specs::x-y' and 'specs::x_u2d_y' are both named 'specs::x_u2d_y' once encoded."
+    );
+  }
+
+  #[test]
+  fn push_test() {
+    let test = |name: &str| CollectedTest::new(name, "lib.rs", ());
+    let mut category = CollectedTestCategory {
+      name: "specs".to_string(),
+      path: PathBuf::from("specs"),
+      description: None,
+      children: Vec::new(),
+    };
+    category.push_test(test("specs::a"));
+    category.push_test(test("specs::rust::b"));
+    category.push_test(test("specs::rust::c"));
+    assert_eq!(category.test_count(), 3);
+    assert_eq!(category.children.len(), 2);
+    let CollectedCategoryOrTest::Category(sub) = &category.children[1] else {
+      unreachable!();
+    };
+    assert_eq!(sub.name, "specs::rust");
+    assert_eq!(sub.path, PathBuf::from("specs/rust"));
+    assert!(category.find_test("specs::rust::c").is_some());
+
+    let result = std::panic::catch_unwind(move || {
+      category.push_test(test("other::d"));
+    });
+    assert!(result.is_err());
+  }
+
+  #[test]
+  fn collect_warnings() {
+    let dir = std::env::temp_dir()
+      .join(format!("file_test_runner_warnings_{}", std::process::id()))
+      .join("specs");
+    std::fs::create_dir_all(dir.join("empty")).unwrap();
+    std::fs::write(dir.join("a.txt"), "").unwrap();
+    let collect = |warn_empty_dirs| {
+      collect_tests_with_warnings(CollectOptions {
+        filter_override: Some(String::new()),
+        ..CollectOptions::new(
+          dir.clone(),
+          strategies::TestPerFileCollectionStrategy {
+            entry_filter: strategies::EntryFilter {
+              warn_empty_dirs,
+              ..Default::default()
+            },
+            ..Default::default()
+          },
+        )
+      })
+      .unwrap()
+    };
+    assert_eq!(collect(false).warnings, Vec::new());
+    let output = collect(true);
+    assert_eq!(output.category.test_count(), 1);
+    assert_eq!(
+      output.warnings,
+      vec![CollectWarning {
+        path: dir.join("empty"),
+        message: "Directory is empty.".to_string(),
+      }]
+    );
+    // not recorded outside of collecting
+    report_collect_warning(&dir, "other");
+
+    std::fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+  }
+
+  #[test]
+  fn collect_stats() {
+    let dir = std::env::temp_dir()
+      .join(format!("file_test_runner_stats_{}", std::process::id()))
+      .join("specs");
+    std::fs::create_dir_all(&dir).unwrap();
+    for name in ["lsp_a.txt", "lsp_b.txt", "run.txt"] {
+      std::fs::write(dir.join(name), "").unwrap();
+    }
+    let collect = |filter: &str, keep_filtered_out: bool| {
+      collect_tests_with_warnings(CollectOptions {
+        filter_override: Some(filter.to_string()),
+        keep_filtered_out,
+        ..CollectOptions::new(
+          dir.clone(),
+          strategies::TestPerFileCollectionStrategy::default(),
+        )
+      })
+      .unwrap()
+    };
+    let stats = collect("lsp", false).stats;
+    assert_eq!(
+      stats,
+      CollectStats {
+        total: 3,
+        filtered_out: 1,
+        filter: Some("lsp".to_string()),
+      }
+    );
+    assert_eq!(stats.selected(), 2);
+    assert_eq!(
+      collect("", false).stats,
+      CollectStats {
+        total: 3,
+        filtered_out: 0,
+        filter: None,
+      }
+    );
+
+    let output = collect("lsp", true);
+    assert_eq!(output.stats.filtered_out, 1);
+    assert_eq!(output.category.test_count(), 3);
+    let run = output.category.find_test("specs::run").unwrap();
+    assert!(run.is_filtered_out());
+    assert_eq!(run.ignore_reason().as_deref(), Some(FILTERED_OUT_REASON));
+    assert_eq!(run.ignored, None);
+    assert!(!output
+      .category
+      .find_test("specs::lsp_a")
+      .unwrap()
+      .is_filtered_out());
+
+    let output = collect_tests_with_warnings(CollectOptions {
+      filter_override: Some("lsp".to_string()),
+      filter_fn: Some(Box::new(|test| test.name.ends_with("_b"))),
+      ..CollectOptions::new(
+        dir.clone(),
+        strategies::TestPerFileCollectionStrategy::default(),
+      )
+    })
+    .unwrap();
+    assert_eq!(output.stats.filtered_out, 2);
+    assert!(output.category.find_test("specs::lsp_b").is_some());
+
+    std::fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+  }
+
+  #[test]
+  fn filter_fn() {
+    let fs = crate::testing::InMemoryFileSystem::default()
+      .file("specs/a.txt", "")
+      .file("specs/sub/b.txt", "")
+      .file("specs/sub/c.txt", "");
+    let collect = |keep_filtered_out| {
+      with_file_system(std::sync::Arc::new(fs.clone()), || {
+        collect_tests_with_warnings(CollectOptions {
+          filter_override: Some(String::new()),
+          filter_fn: Some(Box::new(|test| test.path.starts_with("specs/sub"))),
+          keep_filtered_out,
+          ..CollectOptions::new(
+            PathBuf::from("specs"),
+            strategies::TestPerFileCollectionStrategy::default(),
+          )
+        })
+      })
+      .unwrap()
+    };
+    let output = collect(false);
+    assert_eq!(output.stats.filtered_out, 1);
+    assert_eq!(output.category.test_count(), 2);
+    assert!(output.category.find_test("specs::a").is_none());
+    assert!(output.category.find_test("specs::sub::b").is_some());
+
+    let output = collect(true);
+    assert_eq!(output.stats.filtered_out, 1);
+    assert!(output
+      .category
+      .find_test("specs::a")
+      .unwrap()
+      .is_filtered_out());
+    assert!(!output
+      .category
+      .find_test("specs::sub::c")
+      .unwrap()
+      .is_filtered_out());
+  }
+
+  #[test]
+  fn ignore_filtered_out() {
+    let mut category = CollectedTestCategory {
+      name: "specs".to_string(),
+      path: PathBuf::from("specs"),
+      description: None,
+      children: Vec::new(),
+    };
+    let mut skipped = CollectedTest::new("specs::skipped", "skipped", ());
+    skipped.ignored = Some("requires network".to_string());
+    category.push_test(skipped);
+    let mut reason = CollectedTest::new("specs::reason", "reason", ());
+    reason.ignored = Some(FILTERED_OUT_REASON.to_string());
+    category.push_test(reason);
+    assert_eq!(category.ignore_filtered_out("reason"), 1);
+
+    let skipped = category.find_test("specs::skipped").unwrap();
+    assert!(skipped.is_filtered_out());
+    assert_eq!(skipped.ignored.as_deref(), Some("requires network"));
+    // a strategy's reason that happens to match isn't a filtered out test
+    assert!(!category
+      .find_test("specs::reason")
+      .unwrap()
+      .is_filtered_out());
+  }
+
+  #[test]
+  fn cached_content() {
+    let dir = std::env::temp_dir()
+      .join(format!("file_test_runner_content_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("a.txt");
+    std::fs::write(&path, "a").unwrap();
+    let test = CollectedTest::new("specs::a", path.clone(), ());
+    let clone = test.clone();
+    assert_eq!(test.content().unwrap(), "a");
+    std::fs::write(&path, "b").unwrap();
+    // read once and shared by clones
+    assert_eq!(clone.content().unwrap(), "a");
+    assert_eq!(test.read_to_bytes().unwrap(), b"b");
+
+    // clones read from elsewhere don't get the original's text
+    std::fs::write(dir.join("b.txt"), "other").unwrap();
+    let mut other = test.clone();
+    other.path = dir.join("b.txt");
+    assert_eq!(other.content().unwrap(), "other");
+    let mut provided = test.clone();
+    provided.settings.content_provider =
+      Some(ContentProvider::new(|| Ok(b"provided".to_vec())));
+    assert_eq!(provided.content().unwrap(), "provided");
+    assert_eq!(test.content().unwrap(), "a");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn content_provider() {
+    // the path doesn't need to exist
+    let mut test = CollectedTest::new("specs::a", "specs/missing.txt", ());
+    test.settings.content_provider =
+      Some(ContentProvider::new(|| Ok(b"provided".to_vec())));
+    assert_eq!(test.read_to_string().unwrap(), "provided");
+    assert_eq!(test.read_to_bytes().unwrap(), b"provided");
+    assert_eq!(test.content().unwrap(), "provided");
+
+    test.settings.content_provider =
+      Some(ContentProvider::new(|| Ok(vec![0xff])));
+    let err = test.read_to_string().unwrap_err();
+    assert_eq!(err.path, PathBuf::from("specs/missing.txt"));
+    assert_eq!(err.err.kind(), std::io::ErrorKind::InvalidData);
+
+    // tests collected from another file system read from it
+    let fs = crate::testing::InMemoryFileSystem::default()
+      .file("specs/a.txt", "in memory");
+    let category = with_file_system(std::sync::Arc::new(fs), || {
+      strategies::TestPerFileCollectionStrategy::default()
+        .collect_tests(Path::new("specs"))
+    })
+    .unwrap();
+    let test = category.find_test("specs::a").unwrap();
+    assert_eq!(test.read_to_string().unwrap(), "in memory");
+  }
+
+  #[test]
+  fn case_collisions() {
+    let test = |path: &str| {
+      CollectedCategoryOrTest::Test(CollectedTest::new("specs::test", path, ()))
+    };
+    let mut category = CollectedTestCategory {
+      name: "specs".to_string(),
+      path: PathBuf::from("specs"),
+      description: None,
+      children: vec![
+        test("specs/a.ts"),
+        test("specs/a.ts"),
+        CollectedCategoryOrTest::Category(CollectedTestCategory {
+          name: "specs::sub".to_string(),
+          path: PathBuf::from("specs/sub"),
+          description: None,
+          children: vec![test("specs/sub/b.ts")],
+        }),
+      ],
+    };
+    assert!(ensure_no_case_collisions(&category).is_ok());
+
+    category.children.push(test("specs/Sub/b.ts"));
+    let err = ensure_no_case_collisions(&category).unwrap_err();
+    assert_eq!(err.0, PathBuf::from("specs/sub/b.ts"));
+    assert_eq!(err.1, PathBuf::from("specs/Sub/b.ts"));
+  }
+}
@@ -0,0 +1,111 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::cell::RefCell;
+use std::time::Duration;
+use std::time::Instant;
+
+/// How often the progress callback is called.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+pub type CollectProgressFunc = Box<dyn Fn(&CollectProgress)>;
+
+/// Progress of collecting tests, which is provided to
+/// `CollectOptions::on_progress` periodically.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CollectProgress {
+  pub directories_scanned: usize,
+  pub tests_found: usize,
+}
+
+struct ProgressTracker {
+  progress: CollectProgress,
+  last_report: Instant,
+  on_progress: CollectProgressFunc,
+}
+
+thread_local! {
+  static TRACKER: RefCell<Option<ProgressTracker>> = const { RefCell::new(None) };
+}
+
+/// Tracks the progress of the strategies running on the current thread
+/// until the returned guard is dropped.
+pub(crate) fn track_progress(
+  on_progress: Option<CollectProgressFunc>,
+) -> ProgressTrackerGuard {
+  TRACKER.with(|tracker| {
+    *tracker.borrow_mut() = on_progress.map(|on_progress| ProgressTracker {
+      progress: CollectProgress::default(),
+      last_report: Instant::now(),
+      on_progress,
+    });
+  });
+  ProgressTrackerGuard
+}
+
+pub(crate) struct ProgressTrackerGuard;
+
+impl Drop for ProgressTrackerGuard {
+  fn drop(&mut self) {
+    TRACKER.with(|tracker| tracker.borrow_mut().take());
+  }
+}
+
+pub(crate) fn report_directory_scanned() {
+  update(|progress| progress.directories_scanned += 1);
+}
+
+pub(crate) fn report_test_found() {
+  update(|progress| progress.tests_found += 1);
+}
+
+fn update(func: impl FnOnce(&mut CollectProgress)) {
+  TRACKER.with(|tracker| {
+    let mut tracker = tracker.borrow_mut();
+    let Some(tracker) = tracker.as_mut() else {
+      return;
+    };
+    func(&mut tracker.progress);
+    if tracker.last_report.elapsed() >= PROGRESS_INTERVAL {
+      tracker.last_report = Instant::now();
+      (tracker.on_progress)(&tracker.progress);
+    }
+  });
+}
+
+#[cfg(test)]
+mod test {
+  use std::rc::Rc;
+
+  use super::*;
+
+  #[test]
+  fn reports_progress_periodically() {
+    let reported = Rc::new(RefCell::new(Vec::new()));
+    let guard = track_progress(Some(Box::new({
+      let reported = reported.clone();
+      move |progress| reported.borrow_mut().push(*progress)
+    })));
+    report_directory_scanned();
+    report_test_found();
+    // not reported until the interval elapses
+    assert!(reported.borrow().is_empty());
+    TRACKER.with(|tracker| {
+      let mut tracker = tracker.borrow_mut();
+      let tracker = tracker.as_mut().unwrap();
+      tracker.last_report = Instant::now() - PROGRESS_INTERVAL;
+    });
+    report_test_found();
+    assert_eq!(
+      *reported.borrow(),
+      vec![CollectProgress {
+        directories_scanned: 1,
+        tests_found: 2,
+      }]
+    );
+
+    drop(guard);
+    TRACKER.with(|tracker| assert!(tracker.borrow().is_none()));
+    report_test_found();
+    assert_eq!(reported.borrow().len(), 1);
+  }
+}
@@ -0,0 +1,309 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::path::Path;
+
+use crate::collection::file_system::file_system;
+use crate::collection::CollectTestsError;
+use crate::collection::CollectedCategoryOrTest;
+use crate::collection::CollectedTestCategory;
+
+use super::TestCollectionStrategy;
+
+/// Removes the categories without any tests. See
+/// `TestCollectionStrategy::prune_empty`.
+#[derive(Debug, Clone)]
+pub struct PruneEmptyStrategy<TStrategy> {
+  pub strategy: TStrategy,
+}
+
+impl<TData, TStrategy: TestCollectionStrategy<TData>>
+  TestCollectionStrategy<TData> for PruneEmptyStrategy<TStrategy>
+{
+  fn collect_tests(
+    &self,
+    base: &Path,
+  ) -> Result<CollectedTestCategory<TData>, CollectTestsError> {
+    let mut category = self.strategy.collect_tests(base)?;
+    prune_empty(&mut category);
+    Ok(category)
+  }
+}
+
+fn prune_empty<TData>(category: &mut CollectedTestCategory<TData>) {
+  category.children.retain_mut(|child| match child {
+    CollectedCategoryOrTest::Category(c) => {
+      prune_empty(c);
+      !c.is_empty()
+    }
+    CollectedCategoryOrTest::Test(_) => true,
+  });
+}
+
+/// Renames the categories along with the tests within them. See
+/// `TestCollectionStrategy::rename_categories`.
+#[derive(Debug, Clone)]
+pub struct RenameCategoriesStrategy<TStrategy, TRename> {
+  pub strategy: TStrategy,
+  pub rename: TRename,
+}
+
+impl<
+    TData,
+    TStrategy: TestCollectionStrategy<TData>,
+    TRename: Fn(&str) -> String,
+  > TestCollectionStrategy<TData>
+  for RenameCategoriesStrategy<TStrategy, TRename>
+{
+  fn collect_tests(
+    &self,
+    base: &Path,
+  ) -> Result<CollectedTestCategory<TData>, CollectTestsError> {
+    let mut category = self.strategy.collect_tests(base)?;
+    rename_categories(&mut category, &self.rename);
+    Ok(category)
+  }
+}
+
+fn rename_categories<TData>(
+  category: &mut CollectedTestCategory<TData>,
+  rename: &impl Fn(&str) -> String,
+) {
+  let new_name = rename(&category.name);
+  if new_name != category.name {
+    let old_name = std::mem::replace(&mut category.name, new_name.clone());
+    replace_name_prefixes(category, &old_name, &new_name);
+  }
+  for child in &mut category.children {
+    if let CollectedCategoryOrTest::Category(c) = child {
+      rename_categories(c, rename);
+    }
+  }
+}
+
+/// Replaces the prefix of the names of the descendants of the category.
+fn replace_name_prefixes<TData>(
+  category: &mut CollectedTestCategory<TData>,
+  old_prefix: &str,
+  new_prefix: &str,
+) {
+  let replace = |name: &mut String| {
+    if let Some(rest) = name
+      .strip_prefix(old_prefix)
+      .filter(|rest| rest.starts_with("::"))
+    {
+      *name = format!("{}{}", new_prefix, rest);
+    }
+  };
+  for child in &mut category.children {
+    match child {
+      CollectedCategoryOrTest::Category(c) => {
+        replace(&mut c.name);
+        replace_name_prefixes(c, old_prefix, new_prefix);
+      }
+      CollectedCategoryOrTest::Test(t) => replace(&mut t.name),
+    }
+  }
+}
+
+/// Prefixes the names of all the categories and tests. See
+/// `TestCollectionStrategy::prefix_names`.
+#[derive(Debug, Clone)]
+pub struct PrefixNamesStrategy<TStrategy> {
+  pub strategy: TStrategy,
+  pub prefix: String,
+}
+
+impl<TData, TStrategy: TestCollectionStrategy<TData>>
+  TestCollectionStrategy<TData> for PrefixNamesStrategy<TStrategy>
+{
+  fn collect_tests(
+    &self,
+    base: &Path,
+  ) -> Result<CollectedTestCategory<TData>, CollectTestsError> {
+    let mut category = self.strategy.collect_tests(base)?;
+    let new_name = format!("{}::{}", self.prefix, category.name);
+    let old_name = std::mem::replace(&mut category.name, new_name.clone());
+    replace_name_prefixes(&mut category, &old_name, &new_name);
+    Ok(category)
+  }
+}
+
+/// Sets the descriptions of the categories. See
+/// `TestCollectionStrategy::describe_categories`.
+#[derive(Debug, Clone)]
+pub struct DescribeCategoriesStrategy<TStrategy, TDescribe> {
+  pub strategy: TStrategy,
+  pub describe: TDescribe,
+}
+
+impl<
+    TData,
+    TStrategy: TestCollectionStrategy<TData>,
+    TDescribe: Fn(&Path) -> Option<String>,
+  > TestCollectionStrategy<TData>
+  for DescribeCategoriesStrategy<TStrategy, TDescribe>
+{
+  fn collect_tests(
+    &self,
+    base: &Path,
+  ) -> Result<CollectedTestCategory<TData>, CollectTestsError> {
+    let mut category = self.strategy.collect_tests(base)?;
+    describe_categories(&mut category, &self.describe);
+    Ok(category)
+  }
+}
+
+fn describe_categories<TData>(
+  category: &mut CollectedTestCategory<TData>,
+  describe: &impl Fn(&Path) -> Option<String>,
+) {
+  if let Some(description) = describe(&category.path) {
+    category.description = Some(description);
+  }
+  for child in &mut category.children {
+    if let CollectedCategoryOrTest::Category(c) = child {
+      describe_categories(c, describe);
+    }
+  }
+}
+
+/// Gets the first non-empty line of the `README.md` in the category's
+/// directory without any leading `#`, for use with
+/// `TestCollectionStrategy::describe_categories`.
+pub fn readme_description(path: &Path) -> Option<String> {
+  let text = file_system().read_to_string(&path.join("README.md")).ok()?;
+  text
+    .lines()
+    .map(|line| line.trim_start_matches('#').trim())
+    .find(|line| !line.is_empty())
+    .map(|line| line.to_string())
+}
+
+#[cfg(test)]
+mod test {
+  use std::path::PathBuf;
+
+  use super::*;
+  use crate::collection::CollectedTest;
+
+  struct FixedStrategy;
+
+  impl TestCollectionStrategy for FixedStrategy {
+    fn collect_tests(
+      &self,
+      _base: &Path,
+    ) -> Result<CollectedTestCategory, CollectTestsError> {
+      let mut category = CollectedTestCategory {
+        name: "specs".to_string(),
+        path: PathBuf::from("specs"),
+        description: None,
+        children: vec![CollectedCategoryOrTest::Category(
+          CollectedTestCategory {
+            name: "specs::empty".to_string(),
+            path: PathBuf::from("specs/empty"),
+            description: None,
+            children: Vec::new(),
+          },
+        )],
+      };
+      for name in ["specs::a", "specs::node::b", "specs::node::compat::c"] {
+        category.push_test(CollectedTest::new(name, name, ()));
+      }
+      Ok(category)
+    }
+  }
+
+  fn names(category: &CollectedTestCategory) -> Vec<String> {
+    let mut result = vec![category.name.clone()];
+    for child in &category.children {
+      match child {
+        CollectedCategoryOrTest::Category(c) => result.extend(names(c)),
+        CollectedCategoryOrTest::Test(t) => result.push(t.name.clone()),
+      }
+    }
+    result
+  }
+
+  #[test]
+  fn adapters() {
+    let base = Path::new("specs");
+    assert_eq!(
+      names(&FixedStrategy.prune_empty().collect_tests(base).unwrap()),
+      vec![
+        "specs",
+        "specs::a",
+        "specs::node",
+        "specs::node::b",
+        "specs::node::compat",
+        "specs::node::compat::c",
+      ]
+    );
+    // sub categories are renamed along with their parent
+    let strategy =
+      FixedStrategy
+        .prune_empty()
+        .rename_categories(|name| match name {
+          "specs::node" => "specs::nodejs".to_string(),
+          _ => name.to_string(),
+        });
+    assert_eq!(
+      names(&strategy.collect_tests(base).unwrap()),
+      vec![
+        "specs",
+        "specs::a",
+        "specs::nodejs",
+        "specs::nodejs::b",
+        "specs::nodejs::compat",
+        "specs::nodejs::compat::c",
+      ]
+    );
+    let strategy = FixedStrategy.prune_empty().prefix_names("unit");
+    assert_eq!(
+      names(&strategy.collect_tests(base).unwrap()),
+      vec![
+        "unit::specs",
+        "unit::specs::a",
+        "unit::specs::node",
+        "unit::specs::node::b",
+        "unit::specs::node::compat",
+        "unit::specs::node::compat::c",
+      ]
+    );
+  }
+
+  #[test]
+  fn describe_categories() {
+    let dir = std::env::temp_dir()
+      .join(format!("file_test_runner_describe_{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("specs/node")).unwrap();
+    std::fs::write(
+      dir.join("specs/node/README.md"),
+      "\n# Node compat\n\nMore.",
+    )
+    .unwrap();
+    let category = FixedStrategy
+      .prefix_names("unit")
+      .describe_categories(|path| readme_description(&dir.join(path)))
+      .collect_tests(Path::new("specs"))
+      .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    let descriptions = category
+      .children
+      .iter()
+      .filter_map(|child| match child {
+        CollectedCategoryOrTest::Category(c) => {
+          Some((c.name.as_str(), c.description.as_deref()))
+        }
+        CollectedCategoryOrTest::Test(_) => None,
+      })
+      .collect::<Vec<_>>();
+    assert_eq!(category.description, None);
+    assert_eq!(
+      descriptions,
+      vec![
+        ("unit::specs::empty", None),
+        ("unit::specs::node", Some("Node compat")),
+      ]
+    );
+  }
+}
@@ -0,0 +1,533 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::collection::file_system::file_system;
+use crate::collection::CollectTestsError;
+use crate::collection::CollectedCategoryOrTest;
+use crate::collection::CollectedTest;
+use crate::collection::CollectedTestCategory;
+use crate::collection::TestPriority;
+use crate::PathedIoError;
+
+use super::TestCollectionStrategy;
+
+/// Name of the files whose settings apply to the tests in their directory
+/// and its sub directories.
+pub const DIRECTORY_CONFIG_FILE_NAME: &str = "__config__.jsonc";
+
+/// Settings of a `__config__.jsonc` file, which follows
+/// `schemas/config.v1.json`.
+///
+/// ```jsonc
+/// {
+///   // added to the tags of the parent directories
+///   "tags": ["npm"],
+///   // added to the requirements of the parent directories
+///   "requires": ["network"],
+///   // seconds
+///   "timeout": 300,
+///   "env": { "NO_COLOR": "1" },
+///   // `high`, `normal`, or `low`
+///   "priority": "high",
+///   // `true`, a reason, or the platforms to ignore the tests on
+///   "ignore": ["windows"]
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DirectoryConfig {
+  pub tags: Vec<String>,
+  pub requires: Vec<String>,
+  pub timeout: Option<Duration>,
+  pub env: HashMap<String, String>,
+  pub priority: Option<TestPriority>,
+  pub ignore: Option<DirectoryIgnore>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DirectoryIgnore {
+  /// Ignore the tests (`true`) or stop ignoring the tests a farther
+  /// config ignores (`false`).
+  All(bool),
+  /// Ignore the tests for the provided reason.
+  Reason(String),
+  /// Ignore the tests on the provided platforms (see
+  /// `CollectedTest::ignore_on`).
+  Platforms(Vec<String>),
+}
+
+#[derive(Debug, Error)]
+#[error("Invalid config '{}' at {}: {}", path.display(), location, message)]
+pub struct DirectoryConfigError {
+  pub path: PathBuf,
+  /// Location of the problem (ex. `$.env.NO_COLOR` or `line 3, column 5`).
+  pub location: String,
+  pub message: String,
+}
+
+impl DirectoryConfig {
+  /// Parses and validates the text of a config file, which may contain
+  /// comments and trailing commas.
+  pub fn parse(path: &Path, text: &str) -> Result<Self, DirectoryConfigError> {
+    let error = |location: String, message: String| DirectoryConfigError {
+      path: path.to_path_buf(),
+      location,
+      message,
+    };
+    let value: Value =
+      serde_json::from_str(&strip_jsonc(text)).map_err(|err| {
+        // the location is provided separately
+        let message = err.to_string();
+        let message = message.split(" at line ").next().unwrap_or_default();
+        error(
+          format!("line {}, column {}", err.line(), err.column()),
+          message.to_string(),
+        )
+      })?;
+    let Value::Object(properties) = value else {
+      return Err(error("$".to_string(), "expected an object".to_string()));
+    };
+    let mut config = Self::default();
+    for (key, value) in properties {
+      let location = format!("$.{}", key);
+      match key.as_str() {
+        "$schema" => {}
+        "tags" => config.tags = string_array(&value, &location, &error)?,
+        "requires" => {
+          config.requires = string_array(&value, &location, &error)?
+        }
+        "timeout" => {
+          let seconds = value.as_u64().ok_or_else(|| {
+            error(location, "expected a number of seconds".to_string())
+          })?;
+          config.timeout = Some(Duration::from_secs(seconds));
+        }
+        "env" => {
+          let Value::Object(vars) = value else {
+            return Err(error(location, "expected an object".to_string()));
+          };
+          for (name, value) in vars {
+            let Value::String(value) = value else {
+              return Err(error(
+                format!("{}.{}", location, name),
+                "expected a string".to_string(),
+              ));
+            };
+            config.env.insert(name, value);
+          }
+        }
+        "priority" => {
+          let priority = value.as_str().and_then(TestPriority::parse);
+          config.priority = Some(priority.ok_or_else(|| {
+            error(
+              location,
+              "expected `high`, `normal`, or `low`".to_string(),
+            )
+          })?);
+        }
+        "ignore" => {
+          config.ignore = Some(match &value {
+            Value::Bool(ignore) => DirectoryIgnore::All(*ignore),
+            Value::String(reason) => DirectoryIgnore::Reason(reason.clone()),
+            Value::Array(_) => DirectoryIgnore::Platforms(string_array(
+              &value, &location, &error,
+            )?),
+            _ => {
+              return Err(error(
+                location,
+                "expected a boolean, a reason, or a list of platforms"
+                  .to_string(),
+              ))
+            }
+          });
+        }
+        _ => {
+          return Err(error(
+            location,
+            "unknown property, expected one of `tags`, `requires`, `timeout`, `env`, `priority`, or `ignore`"
+              .to_string(),
+          ))
+        }
+      }
+    }
+    Ok(config)
+  }
+
+  /// Reads the config file in the directory, if it exists.
+  pub fn read(dir: &Path) -> Result<Option<Self>, CollectTestsError> {
+    let path = dir.join(DIRECTORY_CONFIG_FILE_NAME);
+    let text = match file_system().read_to_string(&path) {
+      Ok(text) => text,
+      Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+        return Ok(None)
+      }
+      Err(err) => return Err(PathedIoError::new(&path, err).into()),
+    };
+    Ok(Some(Self::parse(&path, &text)?))
+  }
+
+  /// Gets the config of a directory whose parent has this config, where
+  /// the settings of the nearer config take precedence. Tags and
+  /// requirements accumulate and environment variables are merged.
+  pub fn inherit(&self, nearer: &DirectoryConfig) -> DirectoryConfig {
+    let mut config = self.clone();
+    for tag in &nearer.tags {
+      if !config.tags.contains(tag) {
+        config.tags.push(tag.clone());
+      }
+    }
+    for capability in &nearer.requires {
+      if !config.requires.contains(capability) {
+        config.requires.push(capability.clone());
+      }
+    }
+    if nearer.timeout.is_some() {
+      config.timeout = nearer.timeout;
+    }
+    config.env.extend(nearer.env.clone());
+    if nearer.priority.is_some() {
+      config.priority = nearer.priority;
+    }
+    if nearer.ignore.is_some() {
+      config.ignore.clone_from(&nearer.ignore);
+    }
+    config
+  }
+
+  /// Applies the settings to a test, keeping the environment variables
+  /// and ignore reason the test already has.
+  fn apply<TData>(&self, test: &mut CollectedTest<TData>) {
+    for tag in &self.tags {
+      if !test.settings.tags.contains(tag) {
+        test.settings.tags.push(tag.clone());
+      }
+    }
+    for capability in &self.requires {
+      if !test.settings.requires.contains(capability) {
+        test.settings.requires.push(capability.clone());
+      }
+    }
+    if self.timeout.is_some() {
+      test.settings.timeout = self.timeout;
+    }
+    if let Some(priority) = self.priority {
+      test.priority = priority;
+    }
+    for (name, value) in &self.env {
+      test
+        .env_vars
+        .entry(name.clone())
+        .or_insert_with(|| value.clone());
+    }
+    match &self.ignore {
+      Some(DirectoryIgnore::All(true)) if test.ignored.is_none() => {
+        test.ignored = Some(DIRECTORY_CONFIG_FILE_NAME.to_string());
+      }
+      Some(DirectoryIgnore::Reason(reason)) if test.ignored.is_none() => {
+        test.ignored = Some(reason.clone());
+      }
+      Some(DirectoryIgnore::Platforms(platforms)) => {
+        test.settings.ignore_on.extend(platforms.iter().cloned());
+      }
+      _ => {}
+    }
+  }
+}
+
+fn string_array(
+  value: &Value,
+  location: &str,
+  error: &impl Fn(String, String) -> DirectoryConfigError,
+) -> Result<Vec<String>, DirectoryConfigError> {
+  let Value::Array(items) = value else {
+    return Err(error(location.to_string(), "expected an array".to_string()));
+  };
+  items
+    .iter()
+    .enumerate()
+    .map(|(index, item)| match item {
+      Value::String(text) => Ok(text.clone()),
+      _ => Err(error(
+        format!("{}[{}]", location, index),
+        "expected a string".to_string(),
+      )),
+    })
+    .collect()
+}
+
+/// Replaces the comments and trailing commas with spaces, which keeps the
+/// line and column numbers of parse errors accurate.
+fn strip_jsonc(text: &str) -> String {
+  let chars = text.chars().collect::<Vec<_>>();
+  let mut output = String::with_capacity(text.len());
+  let mut in_string = false;
+  let mut index = 0;
+  while index < chars.len() {
+    let c = chars[index];
+    let next = chars.get(index + 1).copied();
+    if in_string {
+      output.push(c);
+      if c == '\\' {
+        if let Some(next) = next {
+          output.push(next);
+          index += 1;
+        }
+      } else if c == '"' {
+        in_string = false;
+      }
+    } else if c == '/' && next == Some('/') {
+      while index < chars.len() && chars[index] != '\n' {
+        output.push(' ');
+        index += 1;
+      }
+      continue;
+    } else if c == '/' && next == Some('*') {
+      let end = (index + 2..chars.len().saturating_sub(1))
+        .find(|&i| chars[i] == '*' && chars[i + 1] == '/')
+        .map(|i| i + 2)
+        .unwrap_or(chars.len());
+      for c in &chars[index..end] {
+        output.push(if *c == '\n' { '\n' } else { ' ' });
+      }
+      index = end;
+      continue;
+    } else if c == ',' && is_trailing_comma(&chars[index + 1..]) {
+      output.push(' ');
+    } else {
+      in_string = c == '"';
+      output.push(c);
+    }
+    index += 1;
+  }
+  output
+}
+
+fn is_trailing_comma(rest: &[char]) -> bool {
+  let mut index = 0;
+  while index < rest.len() {
+    match (rest[index], rest.get(index + 1)) {
+      (c, _) if c.is_whitespace() => index += 1,
+      ('/', Some('/')) => {
+        while index < rest.len() && rest[index] != '\n' {
+          index += 1;
+        }
+      }
+      ('/', Some('*')) => {
+        index += 2;
+        while index < rest.len()
+          && !(rest[index] == '*' && rest.get(index + 1) == Some(&'/'))
+        {
+          index += 1;
+        }
+        index += 2;
+      }
+      (c, _) => return c == ']' || c == '}',
+    }
+  }
+  false
+}
+
+/// Applies the `__config__.jsonc` files to the tests. See
+/// `TestCollectionStrategy::with_directory_config`.
+#[derive(Debug, Clone)]
+pub struct DirectoryConfigStrategy<TStrategy> {
+  pub strategy: TStrategy,
+}
+
+impl<TData, TStrategy: TestCollectionStrategy<TData>>
+  TestCollectionStrategy<TData> for DirectoryConfigStrategy<TStrategy>
+{
+  fn collect_tests(
+    &self,
+    base: &Path,
+  ) -> Result<CollectedTestCategory<TData>, CollectTestsError> {
+    let mut category = self.strategy.collect_tests(base)?;
+    let mut configs = DirectoryConfigs {
+      base,
+      configs: Default::default(),
+    };
+    apply_directory_configs(&mut category, &mut configs)?;
+    Ok(category)
+  }
+}
+
+/// Resolved configs by directory.
+struct DirectoryConfigs<'a> {
+  base: &'a Path,
+  configs: BTreeMap<PathBuf, DirectoryConfig>,
+}
+
+impl DirectoryConfigs<'_> {
+  fn get(&mut self, dir: &Path) -> Result<DirectoryConfig, CollectTestsError> {
+    if let Some(config) = self.configs.get(dir) {
+      return Ok(config.clone());
+    }
+    // configs above the base directory don't apply
+    let inherited = match dir.parent() {
+      Some(parent) if dir != self.base && parent.starts_with(self.base) => {
+        self.get(parent)?
+      }
+      _ => DirectoryConfig::default(),
+    };
+    let config = match DirectoryConfig::read(dir)? {
+      Some(nearer) => inherited.inherit(&nearer),
+      None => inherited,
+    };
+    self.configs.insert(dir.to_path_buf(), config.clone());
+    Ok(config)
+  }
+}
+
+fn apply_directory_configs<TData>(
+  category: &mut CollectedTestCategory<TData>,
+  configs: &mut DirectoryConfigs,
+) -> Result<(), CollectTestsError> {
+  // the config files aren't tests when collecting every file
+  category.children.retain(|child| match child {
+    CollectedCategoryOrTest::Test(test) => {
+      test.path.file_name() != Some(DIRECTORY_CONFIG_FILE_NAME.as_ref())
+    }
+    CollectedCategoryOrTest::Category(_) => true,
+  });
+  for child in &mut category.children {
+    match child {
+      CollectedCategoryOrTest::Category(c) => {
+        apply_directory_configs(c, configs)?;
+      }
+      CollectedCategoryOrTest::Test(test) => {
+        let Some(dir) = test.path.parent() else {
+          continue;
+        };
+        if dir.starts_with(configs.base) {
+          configs.get(dir)?.apply(test);
+        }
+      }
+    }
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::collection::strategies::TestPerFileCollectionStrategy;
+
+  #[test]
+  fn strips_jsonc() {
+    let text = "{\n  // comment\n  \"a\": \"//not a comment\", /* b */\n  \"c\": [1, 2,],\n}";
+    let value: Value = serde_json::from_str(&strip_jsonc(text)).unwrap();
+    assert_eq!(
+      value,
+      serde_json::json!({ "a": "//not a comment", "c": [1, 2] })
+    );
+    assert_eq!(strip_jsonc(text).lines().count(), text.lines().count());
+  }
+
+  #[test]
+  fn validates_config() {
+    let path = Path::new("specs/__config__.jsonc");
+    let error =
+      |text: &str| DirectoryConfig::parse(path, text).unwrap_err().to_string();
+    assert_eq!(
+      error(r#"{ "tags": ["a", 1] }"#),
+      "Invalid config 'specs/__config__.jsonc' at $.tags[1]: expected a string"
+    );
+    assert_eq!(
+      error(r#"{ "env": { "A": true } }"#),
+      "Invalid config 'specs/__config__.jsonc' at $.env.A: expected a string"
+    );
+    assert!(error(r#"{ "timeouts": 1 }"#).contains("at $.timeouts: unknown"));
+    assert!(error("{\n  \"tags\": [\n}").contains("at line 3, column 1"));
+    assert!(error(r#"{ "priority": "urgent" }"#)
+      .contains("at $.priority: expected `high`, `normal`, or `low`"));
+    assert_eq!(
+      DirectoryConfig::parse(
+        path,
+        r#"{ "tags": ["a"], "timeout": 5, "priority": "low", "ignore": ["windows"] }"#
+      )
+      .unwrap(),
+      DirectoryConfig {
+        tags: vec!["a".to_string()],
+        requires: Vec::new(),
+        timeout: Some(Duration::from_secs(5)),
+        env: Default::default(),
+        priority: Some(TestPriority::Low),
+        ignore: Some(DirectoryIgnore::Platforms(vec!["windows".to_string()])),
+      }
+    );
+  }
+
+  #[test]
+  fn applies_directory_configs() {
+    let dir = std::env::temp_dir()
+      .join(format!(
+        "file_test_runner_dir_config_{}",
+        std::process::id()
+      ))
+      .join("specs");
+    std::fs::create_dir_all(dir.join("npm/slow")).unwrap();
+    std::fs::write(
+      dir.join(DIRECTORY_CONFIG_FILE_NAME),
+      r#"{
+        // applies to every test
+        "tags": ["spec"],
+        "env": { "A": "1", "B": "1" },
+        "ignore": true,
+      }"#,
+    )
+    .unwrap();
+    std::fs::write(
+      dir.join("npm").join(DIRECTORY_CONFIG_FILE_NAME),
+      r#"{ "tags": ["npm"], "requires": ["network"], "timeout": 300, "env": { "B": "2" }, "ignore": false }"#,
+    )
+    .unwrap();
+    std::fs::write(
+      dir.join("npm/slow").join(DIRECTORY_CONFIG_FILE_NAME),
+      r#"{ "timeout": 600, "priority": "high" }"#,
+    )
+    .unwrap();
+    std::fs::write(dir.join("a.txt"), "").unwrap();
+    std::fs::write(dir.join("npm/b.txt"), "").unwrap();
+    std::fs::write(dir.join("npm/slow/c.txt"), "").unwrap();
+
+    let category = TestPerFileCollectionStrategy::default()
+      .with_directory_config()
+      .collect_tests(&dir)
+      .unwrap();
+    assert_eq!(category.test_count(), 3);
+    let a = category.find_test("specs::a").unwrap();
+    assert_eq!(a.settings.tags, vec!["spec"]);
+    assert_eq!(a.settings.timeout, None);
+    assert_eq!(a.ignored.as_deref(), Some(DIRECTORY_CONFIG_FILE_NAME));
+    let b = category.find_test("specs::npm::b").unwrap();
+    assert_eq!(b.settings.tags, vec!["spec", "npm"]);
+    assert_eq!(b.settings.timeout, Some(Duration::from_secs(300)));
+    assert_eq!(b.env_vars["A"], "1");
+    assert_eq!(b.env_vars["B"], "2");
+    assert_eq!(b.ignored, None);
+    let c = category.find_test("specs::npm::slow::c").unwrap();
+    assert_eq!(c.settings.tags, vec!["spec", "npm"]);
+    assert_eq!(c.settings.requires, vec!["network"]);
+    assert!(a.settings.requires.is_empty());
+    assert_eq!(c.settings.timeout, Some(Duration::from_secs(600)));
+    assert_eq!(c.priority, TestPriority::High);
+    assert_eq!(b.priority, TestPriority::Normal);
+
+    std::fs::write(dir.join("npm").join(DIRECTORY_CONFIG_FILE_NAME), "[]")
+      .unwrap();
+    let err = TestPerFileCollectionStrategy::default()
+      .with_directory_config()
+      .collect_tests(&dir)
+      .unwrap_err();
+    assert!(err.to_string().contains("at $: expected an object"));
+
+    std::fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+  }
+}
@@ -52,6 +52,7 @@ impl<
     Ok(CollectedTestCategory {
       name: category.name,
       path: category.path,
+      description: None,
       children: new_children,
     })
   }
@@ -1,24 +1,117 @@
 // Copyright 2018-2024 the Deno authors. MIT license.
 
 use std::path::Path;
+use std::path::PathBuf;
 
+use crate::collection::file_system::file_system;
+use crate::collection::ignore_file::is_ignored_entry;
+use crate::collection::progress::report_directory_scanned;
+use crate::collection::report_collect_warning;
+use crate::collection::CollectTestsError;
+use crate::collection::EntryKind;
+use crate::collection::FileMetadata;
 use crate::PathedIoError;
 
+use super::EntryFilter;
+use super::SymlinkPolicy;
+
+/// Gets the paths of the included entries of the directory sorted by
+/// their file names, excluding the ones matched by the `.ftrignore` file.
+/// Empty directories are reported as a warning when the filter's
+/// `warn_empty_dirs` is set.
 pub(crate) fn read_dir_entries(
   dir_path: &Path,
-) -> Result<Vec<std::fs::DirEntry>, PathedIoError> {
-  let mut entries = std::fs::read_dir(dir_path)
-    .map_err(|err| PathedIoError::new(dir_path, err))?
-    .collect::<Result<Vec<_>, _>>()
+  filter: &EntryFilter,
+) -> Result<Vec<PathBuf>, PathedIoError> {
+  let mut entries = file_system()
+    .read_dir(dir_path)
     .map_err(|err| PathedIoError::new(dir_path, err))?;
-  entries.retain(|e| {
-    !e.file_name().to_string_lossy().starts_with('.')
-      && e.file_name().to_ascii_lowercase() != "readme.md"
+  entries.retain(|path| {
+    path
+      .file_name()
+      .is_some_and(|name| filter.is_included(&name.to_string_lossy()))
+      && !is_ignored_entry(path)
   });
-  entries.sort_by_key(|a| a.file_name());
+  entries.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+  report_directory_scanned();
+  if entries.is_empty() && filter.warn_empty_dirs {
+    report_collect_warning(dir_path, "Directory is empty.");
+  }
   Ok(entries)
 }
 
+/// Gets the file type of the entry, resolving symlinks according to the
+/// filter's `SymlinkPolicy`. Returns `None` when the entry should be
+/// skipped.
+pub(crate) fn entry_file_type(
+  path: &Path,
+  filter: &EntryFilter,
+) -> Result<Option<EntryKind>, CollectTestsError> {
+  let file_system = file_system();
+  let file_type = match file_system.entry_kind(path) {
+    Ok(file_type) => file_type,
+    Err(err) => {
+      report_collect_warning(
+        path,
+        format!("Skipped because its metadata could not be read: {:#}", err),
+      );
+      return Ok(None);
+    }
+  };
+  if file_type != EntryKind::Symlink {
+    return Ok(Some(file_type));
+  }
+  match filter.symlinks {
+    SymlinkPolicy::Skip => Ok(None),
+    SymlinkPolicy::Warn => {
+      report_collect_warning(path, "Skipped symlink.");
+      Ok(None)
+    }
+    SymlinkPolicy::Error => Err(CollectTestsError::UnsupportedPath {
+      path: path.to_path_buf(),
+      reason: "symlinks are not allowed by the symlink policy".to_string(),
+    }),
+    SymlinkPolicy::Follow => {
+      let (target, kind) =
+        match file_system.canonicalize(path).and_then(|target| {
+          file_system.entry_kind(&target).map(|kind| (target, kind))
+        }) {
+          Ok(target) => target,
+          Err(err) => {
+            report_collect_warning(
+              path,
+              format!("Skipped symlink that could not be resolved: {:#}", err),
+            );
+            return Ok(None);
+          }
+        };
+      if kind.is_dir() && is_ancestor_target(path, &target) {
+        report_collect_warning(path, "Skipped symlink that forms a cycle.");
+        return Ok(None);
+      }
+      Ok(Some(kind))
+    }
+  }
+}
+
+/// Gets if following the symlink would revisit a directory that's
+/// already being traversed.
+fn is_ancestor_target(symlink_path: &Path, target: &Path) -> bool {
+  symlink_path
+    .ancestors()
+    .skip(1)
+    .filter_map(|dir| file_system().canonicalize(dir).ok())
+    .any(|dir| dir == target)
+}
+
+/// Reads the metadata of a test file when the strategy records it.
+pub(crate) fn file_metadata(path: &Path, record: bool) -> Option<FileMetadata> {
+  if !record {
+    return None;
+  }
+  file_system().metadata(path).ok()
+}
+
 pub(crate) fn append_to_category_name(
   category_name: &str,
   new_part: &str,
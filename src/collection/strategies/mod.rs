@@ -2,18 +2,84 @@
 
 use std::path::Path;
 
+mod adapters;
+mod directory_config;
 mod file_test_mapper;
 mod helpers;
+mod parameter_matrix;
+mod test_per_archive_entry;
 mod test_per_directory;
 mod test_per_file;
+mod test_per_stem;
 
+pub use adapters::*;
+pub use directory_config::*;
 pub use file_test_mapper::*;
+pub use parameter_matrix::*;
+pub use test_per_archive_entry::*;
 pub use test_per_directory::*;
 pub use test_per_file::*;
+pub use test_per_stem::*;
 
 use crate::collection::CollectTestsError;
 use crate::collection::CollectedTestCategory;
 
+/// Files and directories to skip when searching for tests.
+#[derive(Debug, Clone)]
+pub struct EntryFilter {
+  /// Skip files and directories whose names start with a period.
+  pub skip_hidden: bool,
+  /// Names of files and directories to skip, which are compared
+  /// case-insensitively (ex. `node_modules`).
+  pub skip_names: Vec<String>,
+  /// Names of files and directories to include even when they would
+  /// otherwise be skipped (ex. `.cargo`).
+  pub include_names: Vec<String>,
+  /// How to handle symlinks.
+  pub symlinks: SymlinkPolicy,
+  /// Report a warning for each directory without any included entries.
+  pub warn_empty_dirs: bool,
+}
+
+/// How strategies handle symlinked files and directories.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+  /// Skip symlinks.
+  #[default]
+  Skip,
+  /// Skip symlinks, reporting a warning for each one.
+  Warn,
+  /// Collect from the targets of symlinks. Symlinks to a directory that's
+  /// already being traversed are skipped with a warning.
+  Follow,
+  /// Error when finding a symlink.
+  Error,
+}
+
+impl Default for EntryFilter {
+  fn default() -> Self {
+    Self {
+      skip_hidden: true,
+      skip_names: vec!["readme.md".to_string()],
+      include_names: Vec::new(),
+      symlinks: SymlinkPolicy::default(),
+      warn_empty_dirs: false,
+    }
+  }
+}
+
+impl EntryFilter {
+  pub fn is_included(&self, name: &str) -> bool {
+    if self.include_names.iter().any(|n| n == name) {
+      return true;
+    }
+    if self.skip_hidden && name.starts_with('.') {
+      return false;
+    }
+    !self.skip_names.iter().any(|n| n.eq_ignore_ascii_case(name))
+  }
+}
+
 /// Strategy for collecting tests.
 pub trait TestCollectionStrategy<TData = ()> {
   /// Return a list of tests found in the provided base path.
@@ -25,4 +91,145 @@ pub trait TestCollectionStrategy<TData = ()> {
     &self,
     base: &Path,
   ) -> Result<CollectedTestCategory<TData>, CollectTestsError>;
+
+  /// Removes the categories that don't contain any tests.
+  fn prune_empty(self) -> PruneEmptyStrategy<Self>
+  where
+    Self: Sized,
+  {
+    PruneEmptyStrategy { strategy: self }
+  }
+
+  /// Renames the categories, which also renames the categories and tests
+  /// within them. Sub categories are provided to the function with the
+  /// new name of their parent.
+  fn rename_categories<TRename: Fn(&str) -> String>(
+    self,
+    rename: TRename,
+  ) -> RenameCategoriesStrategy<Self, TRename>
+  where
+    Self: Sized,
+  {
+    RenameCategoriesStrategy {
+      strategy: self,
+      rename,
+    }
+  }
+
+  /// Prefixes the names of all the categories and tests
+  /// (ex. `specs::a` becomes `node::specs::a` with a `node` prefix).
+  fn prefix_names(self, prefix: impl Into<String>) -> PrefixNamesStrategy<Self>
+  where
+    Self: Sized,
+  {
+    PrefixNamesStrategy {
+      strategy: self,
+      prefix: prefix.into(),
+    }
+  }
+
+  /// Sets the descriptions of the categories from their paths (ex. with
+  /// `readme_description`). Descriptions already set are kept when the
+  /// function returns `None`.
+  fn describe_categories<TDescribe: Fn(&Path) -> Option<String>>(
+    self,
+    describe: TDescribe,
+  ) -> DescribeCategoriesStrategy<Self, TDescribe>
+  where
+    Self: Sized,
+  {
+    DescribeCategoriesStrategy {
+      strategy: self,
+      describe,
+    }
+  }
+
+  /// Applies the `__config__.jsonc` files in the test directories to the
+  /// tests within them, with nearer files overriding farther ones. See
+  /// `DirectoryConfig` for the settings.
+  fn with_directory_config(self) -> DirectoryConfigStrategy<Self>
+  where
+    Self: Sized,
+  {
+    DirectoryConfigStrategy { strategy: self }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn entry_filter() {
+    let filter = EntryFilter::default();
+    assert!(filter.is_included("a.ts"));
+    assert!(!filter.is_included(".git"));
+    assert!(!filter.is_included("README.md"));
+
+    let filter = EntryFilter {
+      skip_names: vec!["node_modules".to_string(), "target".to_string()],
+      include_names: vec![".cargo".to_string()],
+      ..Default::default()
+    };
+    assert!(filter.is_included(".cargo"));
+    assert!(!filter.is_included(".git"));
+    assert!(!filter.is_included("node_modules"));
+    assert!(filter.is_included("readme.md"));
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn symlink_policy() {
+    let dir = std::env::temp_dir()
+      .join(format!("file_test_runner_symlinks_{}", std::process::id()))
+      .join("specs");
+    std::fs::create_dir_all(dir.join("real")).unwrap();
+    std::fs::write(dir.join("real/a.txt"), "").unwrap();
+    std::os::unix::fs::symlink(dir.join("real"), dir.join("linked")).unwrap();
+    // cycle back to the base directory
+    std::os::unix::fs::symlink(&dir, dir.join("real/cycle")).unwrap();
+
+    let strategy = |symlinks| TestPerFileCollectionStrategy {
+      entry_filter: EntryFilter {
+        symlinks,
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    let category = strategy(SymlinkPolicy::Skip).collect_tests(&dir).unwrap();
+    assert_eq!(category.test_count(), 1);
+    let category = strategy(SymlinkPolicy::Follow).collect_tests(&dir).unwrap();
+    assert_eq!(category.test_count(), 2);
+    assert!(category.find_test("specs::linked::a").is_some());
+    assert!(matches!(
+      strategy(SymlinkPolicy::Error).collect_tests(&dir),
+      Err(CollectTestsError::UnsupportedPath { .. })
+    ));
+    let warnings = |symlinks| {
+      crate::collection::collect_tests_with_warnings(
+        crate::collection::CollectOptions {
+          filter_override: Some(String::new()),
+          ..crate::collection::CollectOptions::new(
+            dir.clone(),
+            strategy(symlinks),
+          )
+        },
+      )
+      .unwrap()
+      .warnings
+      .into_iter()
+      .map(|warning| warning.path)
+      .collect::<Vec<_>>()
+    };
+    assert_eq!(
+      warnings(SymlinkPolicy::Skip),
+      Vec::<std::path::PathBuf>::new()
+    );
+    assert_eq!(
+      warnings(SymlinkPolicy::Warn),
+      vec![dir.join("linked"), dir.join("real/cycle")]
+    );
+
+    std::fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+  }
 }
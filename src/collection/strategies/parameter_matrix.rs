@@ -0,0 +1,233 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::path::Path;
+
+use crate::collection::CollectTestsError;
+use crate::collection::CollectedCategoryOrTest;
+use crate::collection::CollectedTest;
+use crate::collection::CollectedTestCategory;
+
+use super::TestCollectionStrategy;
+
+/// Dimensions whose combinations each collected test is run with
+/// (ex. target operating system × a config flag).
+#[derive(Debug, Clone, Default)]
+pub struct ParameterMatrix {
+  dimensions: Vec<(String, Vec<String>)>,
+}
+
+impl ParameterMatrix {
+  /// Adds a dimension with the values of the parameter.
+  pub fn dimension(
+    mut self,
+    name: impl Into<String>,
+    values: impl IntoIterator<Item = impl Into<String>>,
+  ) -> Self {
+    self.dimensions.push((
+      name.into(),
+      values.into_iter().map(|value| value.into()).collect(),
+    ));
+    self
+  }
+
+  /// Gets every combination of the parameters, where the values of the
+  /// last dimension change the fastest.
+  pub fn combinations(&self) -> Vec<TestParameters> {
+    let mut combinations = vec![TestParameters::default()];
+    for (name, values) in &self.dimensions {
+      combinations = combinations
+        .iter()
+        .flat_map(|parameters| {
+          values.iter().map(move |value| {
+            let mut parameters = parameters.clone();
+            parameters.0.push((name.clone(), value.clone()));
+            parameters
+          })
+        })
+        .collect();
+    }
+    combinations
+  }
+}
+
+/// Parameters of a test expanded by `ParameterMatrixStrategy`, in the
+/// order of the matrix's dimensions.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TestParameters(Vec<(String, String)>);
+
+impl TestParameters {
+  /// Gets the value of a parameter.
+  pub fn get(&self, name: &str) -> Option<&str> {
+    self
+      .0
+      .iter()
+      .find(|(n, _)| n == name)
+      .map(|(_, value)| value.as_str())
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+    self
+      .0
+      .iter()
+      .map(|(name, value)| (name.as_str(), value.as_str()))
+  }
+
+  /// Gets the part of the test name encoding the parameters
+  /// (ex. `os_linux__flag_on`).
+  pub fn name_suffix(&self) -> String {
+    self
+      .iter()
+      .map(|(name, value)| format!("{}_{}", name, value))
+      .collect::<Vec<_>>()
+      .join("__")
+  }
+}
+
+/// Expands each collected test into a category with a test per
+/// combination of the matrix's parameters, which are provided as the
+/// tests' data and encoded in their names (ex. `specs::a::os_linux__flag_on`).
+///
+/// This allows running the same test files in several configurations
+/// without duplicating them.
+#[derive(Debug, Clone)]
+pub struct ParameterMatrixStrategy<TBaseStrategy: TestCollectionStrategy<()>> {
+  /// Base strategy to use for collecting files.
+  pub base_strategy: TBaseStrategy,
+  pub matrix: ParameterMatrix,
+}
+
+fn expand_category(
+  category: CollectedTestCategory<()>,
+  combinations: &[TestParameters],
+) -> CollectedTestCategory<TestParameters> {
+  let children = category
+    .children
+    .into_iter()
+    .map(|child| match child {
+      CollectedCategoryOrTest::Category(c) => {
+        CollectedCategoryOrTest::Category(expand_category(c, combinations))
+      }
+      CollectedCategoryOrTest::Test(t) => expand_test(t, combinations),
+    })
+    .collect();
+  CollectedTestCategory {
+    name: category.name,
+    path: category.path,
+    description: category.description,
+    children,
+  }
+}
+
+fn expand_test(
+  test: CollectedTest<()>,
+  combinations: &[TestParameters],
+) -> CollectedCategoryOrTest<TestParameters> {
+  let with_parameters = |parameters: &TestParameters| {
+    test.clone().map_data(|()| parameters.clone())
+  };
+  if let [parameters] = combinations {
+    if parameters.0.is_empty() {
+      // no dimensions, so keep the test as is
+      return CollectedCategoryOrTest::Test(with_parameters(parameters));
+    }
+  }
+  let children = combinations
+    .iter()
+    .map(|parameters| {
+      let mut expanded = with_parameters(parameters);
+      let suffix = parameters.name_suffix();
+      expanded.name = format!("{}::{}", test.name, suffix);
+      expanded.alias = test
+        .alias
+        .as_ref()
+        .map(|alias| format!("{}::{}", alias, suffix));
+      expanded.display_name = test
+        .display_name
+        .as_ref()
+        .map(|display_name| format!("{} ({})", display_name, suffix));
+      CollectedCategoryOrTest::Test(expanded)
+    })
+    .collect();
+  CollectedCategoryOrTest::Category(CollectedTestCategory {
+    name: test.name,
+    path: test.path,
+    description: None,
+    children,
+  })
+}
+
+impl<TBaseStrategy: TestCollectionStrategy<()>>
+  TestCollectionStrategy<TestParameters>
+  for ParameterMatrixStrategy<TBaseStrategy>
+{
+  fn collect_tests(
+    &self,
+    base: &Path,
+  ) -> Result<CollectedTestCategory<TestParameters>, CollectTestsError> {
+    let category = self.base_strategy.collect_tests(base)?;
+    Ok(expand_category(category, &self.matrix.combinations()))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::collection::strategies::TestPerFileCollectionStrategy;
+
+  #[test]
+  fn expands_matrix() {
+    let dir = std::env::temp_dir()
+      .join(format!("file_test_runner_matrix_{}", std::process::id()))
+      .join("specs");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("a.txt"), "").unwrap();
+
+    let matrix = ParameterMatrix::default()
+      .dimension("os", ["linux", "windows"])
+      .dimension("flag", ["on", "off"]);
+    let category = ParameterMatrixStrategy {
+      base_strategy: TestPerFileCollectionStrategy::default(),
+      matrix,
+    }
+    .collect_tests(&dir)
+    .unwrap();
+    assert_eq!(category.test_count(), 4);
+    let test = category.find_test("specs::a::os_windows__flag_on").unwrap();
+    assert_eq!(test.data.get("os"), Some("windows"));
+    assert_eq!(test.data.get("flag"), Some("on"));
+    assert_eq!(test.path, dir.join("a.txt"));
+    let CollectedCategoryOrTest::Category(file_category) =
+      &category.children[0]
+    else {
+      unreachable!();
+    };
+    assert_eq!(file_category.name, "specs::a");
+    let names = file_category
+      .children
+      .iter()
+      .map(|child| match child {
+        CollectedCategoryOrTest::Test(t) => t.name.as_str(),
+        CollectedCategoryOrTest::Category(c) => c.name.as_str(),
+      })
+      .collect::<Vec<_>>();
+    assert_eq!(
+      names,
+      vec![
+        "specs::a::os_linux__flag_on",
+        "specs::a::os_linux__flag_off",
+        "specs::a::os_windows__flag_on",
+        "specs::a::os_windows__flag_off",
+      ]
+    );
+
+    let category = ParameterMatrixStrategy {
+      base_strategy: TestPerFileCollectionStrategy::default(),
+      matrix: ParameterMatrix::default(),
+    }
+    .collect_tests(&dir)
+    .unwrap();
+    assert!(category.find_test("specs::a").is_some());
+
+    std::fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+  }
+}
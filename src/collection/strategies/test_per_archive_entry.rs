@@ -0,0 +1,261 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::collection::file_system::file_system;
+use crate::collection::progress::report_test_found;
+use crate::collection::CollectTestsError;
+use crate::collection::CollectedTest;
+use crate::collection::CollectedTestCategory;
+use crate::collection::ContentProvider;
+use crate::collection::FileMetadata;
+use crate::PathedIoError;
+
+use super::helpers::append_to_category_name;
+use super::TestCollectionStrategy;
+
+/// File read out of an archive.
+#[derive(Debug, Clone)]
+pub struct ArchiveEntry {
+  /// Path of the file within the archive.
+  pub path: PathBuf,
+  pub contents: Arc<[u8]>,
+}
+
+/// Collects a test per file in an uncompressed tar archive, which allows
+/// large test suites to be stored as a single file. The base path is the
+/// path to the archive.
+///
+/// The directories in the archive are collected as categories. The path of
+/// each test is the archive's path joined with the file's path within the
+/// archive, which doesn't exist on the file system, so the tests read
+/// their contents from the archive entry instead (ex. with
+/// `CollectedTest::read_to_string`).
+#[derive(Debug, Clone, Default)]
+pub struct TestPerArchiveEntryCollectionStrategy {
+  /// Pattern that the paths within the archive must match.
+  ///
+  /// Provide `None` to match all files.
+  pub file_pattern: Option<String>,
+}
+
+impl TestCollectionStrategy<ArchiveEntry>
+  for TestPerArchiveEntryCollectionStrategy
+{
+  fn collect_tests(
+    &self,
+    base: &Path,
+  ) -> Result<CollectedTestCategory<ArchiveEntry>, CollectTestsError> {
+    let pattern = match self.file_pattern.as_ref() {
+      Some(pattern) => Some(CollectTestsError::compile_pattern(pattern)?),
+      None => None,
+    };
+    let bytes = file_system()
+      .read(base)
+      .map_err(|err| PathedIoError::new(base, err))?;
+    let mut entries = read_tar_entries(&bytes).map_err(|err| {
+      CollectTestsError::MetadataParse {
+        path: base.to_path_buf(),
+        message: format!("{:#}", err),
+      }
+    })?;
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let file_name = base.file_name().unwrap().to_string_lossy();
+    // remove all the extensions (ex. `.tar`)
+    let category_name = file_name.split('.').next().unwrap();
+    let mut category = CollectedTestCategory {
+      name: category_name.to_string(),
+      path: base.to_path_buf(),
+      description: None,
+      children: Vec::new(),
+    };
+    for entry in entries {
+      if let Some(pattern) = &pattern {
+        if !pattern.is_match(&entry.path.to_string_lossy()) {
+          continue;
+        }
+      }
+      insert_entry(&mut category, entry);
+    }
+    Ok(category)
+  }
+}
+
+fn insert_entry(
+  category: &mut CollectedTestCategory<ArchiveEntry>,
+  entry: ArchiveEntry,
+) {
+  let mut name = category.name.clone();
+  for component in entry.path.parent().iter().flat_map(|p| p.components()) {
+    if let Component::Normal(dir_name) = component {
+      name = append_to_category_name(&name, &dir_name.to_string_lossy());
+    }
+  }
+  let Some(stem) = entry.path.file_stem() else {
+    return;
+  };
+  let mut test = CollectedTest::new(
+    append_to_category_name(&name, &stem.to_string_lossy()),
+    category.path.join(&entry.path),
+    entry,
+  );
+  test.settings.metadata = Some(FileMetadata {
+    size: test.data.contents.len() as u64,
+    modified: None,
+  });
+  test.settings.content_provider = Some(ContentProvider::new({
+    let contents = test.data.contents.clone();
+    move || Ok(contents.to_vec())
+  }));
+  report_test_found();
+  category.push_test(test);
+}
+
+const BLOCK_SIZE: usize = 512;
+
+/// Reads the regular files out of a tar archive, supporting the long
+/// path extensions of GNU and pax archives.
+fn read_tar_entries(bytes: &[u8]) -> Result<Vec<ArchiveEntry>, anyhow::Error> {
+  let mut entries = Vec::new();
+  let mut offset = 0;
+  let mut long_path = None;
+  while offset + BLOCK_SIZE <= bytes.len() {
+    let header = &bytes[offset..offset + BLOCK_SIZE];
+    if header.iter().all(|b| *b == 0) {
+      break;
+    }
+    let size = parse_octal(&header[124..136])?;
+    let data_start = offset + BLOCK_SIZE;
+    let Some(data) = bytes.get(data_start..data_start + size) else {
+      anyhow::bail!("entry at offset {} was truncated", offset);
+    };
+    offset = data_start + size.div_ceil(BLOCK_SIZE) * BLOCK_SIZE;
+    match header[156] {
+      b'0' | 0 => {
+        let path = match long_path.take() {
+          Some(path) => path,
+          None => header_path(header),
+        };
+        entries.push(ArchiveEntry {
+          path: PathBuf::from(path),
+          contents: Arc::from(data),
+        });
+      }
+      // GNU long path
+      b'L' => long_path = Some(c_str(data)),
+      // pax extended header
+      b'x' => long_path = pax_path(data),
+      // directories, links, etc.
+      _ => long_path = None,
+    }
+  }
+  Ok(entries)
+}
+
+fn header_path(header: &[u8]) -> String {
+  let name = c_str(&header[0..100]);
+  let is_ustar = &header[257..262] == b"ustar";
+  let prefix = c_str(&header[345..500]);
+  if is_ustar && !prefix.is_empty() {
+    format!("{}/{}", prefix, name)
+  } else {
+    name
+  }
+}
+
+fn c_str(bytes: &[u8]) -> String {
+  let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+  String::from_utf8_lossy(&bytes[..end]).to_string()
+}
+
+fn parse_octal(bytes: &[u8]) -> Result<usize, anyhow::Error> {
+  if bytes[0] & 0x80 != 0 {
+    anyhow::bail!("base-256 encoded sizes are not supported");
+  }
+  let text = c_str(bytes);
+  let text = text.trim();
+  if text.is_empty() {
+    return Ok(0);
+  }
+  usize::from_str_radix(text, 8)
+    .map_err(|_| anyhow::anyhow!("invalid size '{}'", text))
+}
+
+/// Gets the `path` record from pax extended header records, which are
+/// formatted as `<length> <key>=<value>\n`.
+fn pax_path(data: &[u8]) -> Option<String> {
+  let mut rest = data;
+  while !rest.is_empty() {
+    let space = rest.iter().position(|b| *b == b' ')?;
+    let len = std::str::from_utf8(&rest[..space]).ok()?.parse().ok()?;
+    let record = rest.get(space + 1..len)?;
+    let record = record.strip_suffix(b"\n").unwrap_or(record);
+    if let Some(path) = record.strip_prefix(b"path=") {
+      return Some(String::from_utf8_lossy(path).to_string());
+    }
+    rest = &rest[len..];
+  }
+  None
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn tar_entry(path: &str, type_flag: u8, data: &[u8]) -> Vec<u8> {
+    let mut header = vec![0; BLOCK_SIZE];
+    header[..path.len()].copy_from_slice(path.as_bytes());
+    let size = format!("{:011o}", data.len());
+    header[124..135].copy_from_slice(size.as_bytes());
+    header[156] = type_flag;
+    header[257..262].copy_from_slice(b"ustar");
+    let mut entry = header;
+    entry.extend(data);
+    entry.resize(entry.len().div_ceil(BLOCK_SIZE) * BLOCK_SIZE, 0);
+    entry
+  }
+
+  #[test]
+  fn collects_archive_entries() {
+    let long_path = format!("sub/{}.js", "a".repeat(120));
+    let pax_record = format!(" path={}\n", long_path);
+    let pax_record =
+      format!("{}{}", pax_record.len() + 3, pax_record).into_bytes();
+    let mut archive = Vec::new();
+    archive.extend(tar_entry("sub/", b'5', &[]));
+    archive.extend(tar_entry("sub/b.js", b'0', b"b"));
+    archive.extend(tar_entry("a.js", b'0', b"a"));
+    archive.extend(tar_entry("a.md", b'0', b"readme"));
+    archive.extend(tar_entry("PaxHeader", b'x', &pax_record));
+    archive.extend(tar_entry("truncated", b'0', b"long"));
+    archive.extend(vec![0; BLOCK_SIZE * 2]);
+
+    let dir = std::env::temp_dir()
+      .join(format!("file_test_runner_archive_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let archive_path = dir.join("test262.tar");
+    std::fs::write(&archive_path, &archive).unwrap();
+
+    let category = TestPerArchiveEntryCollectionStrategy {
+      file_pattern: Some(r"\.js$".to_string()),
+    }
+    .collect_tests(&archive_path)
+    .unwrap();
+    assert_eq!(category.name, "test262");
+    assert_eq!(category.test_count(), 3);
+    let test = category.find_test("test262::a").unwrap();
+    assert_eq!(test.path, archive_path.join("a.js"));
+    assert_eq!(&*test.data.contents, b"a");
+    assert_eq!(test.content().unwrap(), "a");
+    let test = category.find_test("test262::sub::b").unwrap();
+    assert_eq!(test.data.path, PathBuf::from("sub/b.js"));
+    let name = format!("test262::sub::{}", "a".repeat(120));
+    assert_eq!(&*category.find_test(&name).unwrap().data.contents, b"long");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -1,15 +1,21 @@
 // Copyright 2018-2024 the Deno authors. MIT license.
 
 use std::path::Path;
+use std::path::PathBuf;
 
+use crate::collection::file_system::file_content_provider;
+use crate::collection::file_system::file_system;
+use crate::collection::progress::report_test_found;
 use crate::collection::CollectTestsError;
 use crate::collection::CollectedCategoryOrTest;
 use crate::collection::CollectedTest;
 use crate::collection::CollectedTestCategory;
-use crate::PathedIoError;
 
 use super::helpers::append_to_category_name;
+use super::helpers::entry_file_type;
+use super::helpers::file_metadata;
 use super::helpers::read_dir_entries;
+use super::EntryFilter;
 use super::TestCollectionStrategy;
 
 /// Recursively searches directories finding the provided
@@ -17,13 +23,47 @@ use super::TestCollectionStrategy;
 /// then an error is raised. Once a matching test file is found
 /// in a directory, traversing will stop.
 ///
-/// Note: This ignores hidden directories starting with a period.
-#[derive(Debug, Clone)]
+/// Note: By default, this ignores hidden directories starting with a
+/// period (see `entry_filter`).
+#[derive(Debug, Clone, Default)]
 pub struct TestPerDirectoryCollectionStrategy {
   /// The file name to search for in each directory.
   ///
   /// Example: `__test__.jsonc`
   pub file_name: String,
+  /// Name of a file that marks a directory as a test that's ignored,
+  /// even when the directory doesn't contain the test file.
+  ///
+  /// Example: `.skip`
+  pub skip_marker_file: Option<String>,
+  /// Prefix of the names of directories that are tests that are ignored,
+  /// even when the directory doesn't contain the test file.
+  ///
+  /// Example: `_`
+  pub skip_dir_prefix: Option<String>,
+  /// Files and directories to skip.
+  pub entry_filter: EntryFilter,
+  /// Record the size and modified time of each test file in
+  /// `CollectedTest::metadata`.
+  pub record_metadata: bool,
+}
+
+impl TestPerDirectoryCollectionStrategy {
+  /// Gets the reason a test directory is marked as skipped.
+  fn skip_reason(&self, dir_path: &Path) -> Option<String> {
+    if let Some(marker) = &self.skip_marker_file {
+      if file_system().exists(&dir_path.join(marker)) {
+        return Some(format!("skip marker '{}'", marker));
+      }
+    }
+    if let Some(prefix) = &self.skip_dir_prefix {
+      let dir_name = dir_path.file_name().unwrap().to_string_lossy();
+      if dir_name.starts_with(prefix.as_str()) {
+        return Some(format!("skip prefix '{}'", prefix));
+      }
+    }
+    None
+  }
 }
 
 impl TestCollectionStrategy<()> for TestPerDirectoryCollectionStrategy {
@@ -32,48 +72,54 @@ impl TestCollectionStrategy<()> for TestPerDirectoryCollectionStrategy {
     base: &Path,
   ) -> Result<CollectedTestCategory<()>, CollectTestsError> {
     fn collect_test_per_directory(
+      strategy: &TestPerDirectoryCollectionStrategy,
       category_name: &str,
       dir_path: &Path,
-      dir_test_file_name: &str,
     ) -> Result<Vec<CollectedCategoryOrTest<()>>, CollectTestsError> {
+      let dir_test_file_name = strategy.file_name.as_str();
       let mut tests = vec![];
 
       let mut found_dir = false;
       let mut is_dir_empty = true;
-      for entry in read_dir_entries(dir_path)? {
+      for path in read_dir_entries(dir_path, &strategy.entry_filter)? {
         is_dir_empty = false;
-        let path = entry.path();
-        let file_type = entry
-          .file_type()
-          .map_err(|err| PathedIoError::new(&path, err))?;
+        let Some(file_type) = entry_file_type(&path, &strategy.entry_filter)?
+        else {
+          continue;
+        };
         if file_type.is_dir() {
           found_dir = true;
           let test_file_path = path.join(dir_test_file_name);
-          if test_file_path.exists() {
-            let test = CollectedTest {
-              name: append_to_category_name(
+          let skip_reason = strategy.skip_reason(&path);
+          if file_system().exists(&test_file_path) || skip_reason.is_some() {
+            let mut test = CollectedTest::new(
+              append_to_category_name(
                 category_name,
                 &path.file_name().unwrap().to_string_lossy(),
               ),
-              path: test_file_path,
-              data: (),
-            };
+              test_file_path.clone(),
+              (),
+            );
+            test.ignored = skip_reason;
+            test.settings.metadata =
+              file_metadata(&test_file_path, strategy.record_metadata);
+            test.settings.content_provider =
+              file_content_provider(&test_file_path);
+            report_test_found();
             tests.push(CollectedCategoryOrTest::Test(test));
           } else {
             let category_name = append_to_category_name(
               category_name,
               &path.file_name().unwrap().to_string_lossy(),
             );
-            let children = collect_test_per_directory(
-              &category_name,
-              &path,
-              dir_test_file_name,
-            )?;
+            let children =
+              collect_test_per_directory(strategy, &category_name, &path)?;
             if !children.is_empty() {
               tests.push(CollectedCategoryOrTest::Category(
                 CollectedTestCategory {
                   name: category_name,
                   path,
+                  description: None,
                   children,
                 },
               ));
@@ -86,19 +132,82 @@ impl TestCollectionStrategy<()> for TestPerDirectoryCollectionStrategy {
       // accidentally not naming the test file correctly
       // (ex. `__test__.json` instead of `__test__.jsonc` in Deno's case)
       if !found_dir && !is_dir_empty {
-        return Err(anyhow::anyhow!("Could not find '{}' in directory tree '{}'. Perhaps the file is named incorrectly?", dir_test_file_name, dir_path.display()).into());
+        return Err(CollectTestsError::MissingTestFile {
+          path: PathBuf::from(dir_test_file_name),
+          dir: dir_path.to_path_buf(),
+        });
       }
 
       Ok(tests)
     }
 
     let category_name = base.file_name().unwrap().to_string_lossy();
-    let children =
-      collect_test_per_directory(&category_name, base, &self.file_name)?;
+    let children = collect_test_per_directory(self, &category_name, base)?;
     Ok(CollectedTestCategory {
       name: category_name.to_string(),
       path: base.to_path_buf(),
+      description: None,
       children,
     })
   }
 }
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn skip_markers() {
+    let dir = std::env::temp_dir()
+      .join(format!("file_test_runner_skip_{}", std::process::id()))
+      .join("specs");
+    for sub_dir in ["a", "b", "_c"] {
+      std::fs::create_dir_all(dir.join(sub_dir)).unwrap();
+    }
+    std::fs::write(dir.join("a/__test__.json"), "").unwrap();
+    std::fs::write(dir.join("b/.skip"), "").unwrap();
+    std::fs::write(dir.join("_c/other.txt"), "").unwrap();
+
+    let mut strategy = TestPerDirectoryCollectionStrategy {
+      file_name: "__test__.json".to_string(),
+      ..Default::default()
+    };
+    let err = strategy.collect_tests(&dir).unwrap_err();
+    assert!(
+      matches!(&err, CollectTestsError::MissingTestFile { dir: err_dir, .. } if *err_dir == dir.join("_c")),
+      "{:?}",
+      err
+    );
+    assert_eq!(
+      err.to_string(),
+      format!(
+        "Could not find '__test__.json' in directory tree '{}'. Perhaps the file is named incorrectly?",
+        dir.join("_c").display()
+      )
+    );
+
+    strategy.skip_marker_file = Some(".skip".to_string());
+    strategy.skip_dir_prefix = Some("_".to_string());
+    let category = strategy.collect_tests(&dir).unwrap();
+    assert_eq!(category.test_count(), 3);
+    assert_eq!(category.find_test("specs::a").unwrap().ignored, None);
+    assert_eq!(
+      category
+        .find_test("specs::b")
+        .unwrap()
+        .ignore_reason()
+        .unwrap(),
+      "skip marker '.skip'"
+    );
+    assert_eq!(
+      category
+        .find_test("specs::_c")
+        .unwrap()
+        .ignore_reason()
+        .unwrap(),
+      "skip prefix '_'"
+    );
+
+    std::fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+  }
+}
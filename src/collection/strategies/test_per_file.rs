@@ -4,14 +4,18 @@ use std::path::Path;
 
 use regex::Regex;
 
+use crate::collection::file_system::file_content_provider;
+use crate::collection::progress::report_test_found;
 use crate::collection::CollectTestsError;
 use crate::collection::CollectedCategoryOrTest;
 use crate::collection::CollectedTest;
 use crate::collection::CollectedTestCategory;
-use crate::PathedIoError;
 
 use super::helpers::append_to_category_name;
+use super::helpers::entry_file_type;
+use super::helpers::file_metadata;
 use super::helpers::read_dir_entries;
+use super::EntryFilter;
 use super::TestCollectionStrategy;
 
 /// All the files in every sub directory will be traversed
@@ -19,11 +23,16 @@ use super::TestCollectionStrategy;
 ///
 /// Provide `None` to match all files.
 ///
-/// Note: This ignores readme.md files and hidden directories
-/// starting with a period.
+/// Note: By default, this ignores readme.md files and hidden directories
+/// starting with a period (see `entry_filter`).
 #[derive(Debug, Clone, Default)]
 pub struct TestPerFileCollectionStrategy {
   pub file_pattern: Option<String>,
+  /// Files and directories to skip.
+  pub entry_filter: EntryFilter,
+  /// Record the size and modified time of each test file in
+  /// `CollectedTest::metadata`.
+  pub record_metadata: bool,
 }
 
 impl TestCollectionStrategy<()> for TestPerFileCollectionStrategy {
@@ -32,28 +41,31 @@ impl TestCollectionStrategy<()> for TestPerFileCollectionStrategy {
     base: &Path,
   ) -> Result<CollectedTestCategory<()>, CollectTestsError> {
     fn collect_test_per_file(
+      strategy: &TestPerFileCollectionStrategy,
       category_name: &str,
       dir_path: &Path,
       pattern: Option<&Regex>,
     ) -> Result<Vec<CollectedCategoryOrTest<()>>, CollectTestsError> {
       let mut tests = vec![];
 
-      for entry in read_dir_entries(dir_path)? {
-        let path = entry.path();
-        let file_type = entry
-          .file_type()
-          .map_err(|err| PathedIoError::new(&path, err))?;
+      for path in read_dir_entries(dir_path, &strategy.entry_filter)? {
+        let Some(file_type) = entry_file_type(&path, &strategy.entry_filter)?
+        else {
+          continue;
+        };
         if file_type.is_dir() {
           let category_name = append_to_category_name(
             category_name,
             &path.file_name().unwrap().to_string_lossy(),
           );
-          let children = collect_test_per_file(&category_name, &path, pattern)?;
+          let children =
+            collect_test_per_file(strategy, &category_name, &path, pattern)?;
           if !children.is_empty() {
             tests.push(CollectedCategoryOrTest::Category(
               CollectedTestCategory {
                 name: category_name,
                 path,
+                description: None,
                 children,
               },
             ));
@@ -64,14 +76,18 @@ impl TestCollectionStrategy<()> for TestPerFileCollectionStrategy {
               continue;
             }
           }
-          let test = CollectedTest {
-            name: append_to_category_name(
+          let mut test = CollectedTest::new(
+            append_to_category_name(
               category_name,
               &path.file_stem().unwrap().to_string_lossy(),
             ),
-            path,
-            data: (),
-          };
+            path.clone(),
+            (),
+          );
+          test.settings.metadata =
+            file_metadata(&path, strategy.record_metadata);
+          test.settings.content_provider = file_content_provider(&path);
+          report_test_found();
           tests.push(CollectedCategoryOrTest::Test(test));
         }
       }
@@ -80,16 +96,54 @@ impl TestCollectionStrategy<()> for TestPerFileCollectionStrategy {
     }
 
     let pattern = match self.file_pattern.as_ref() {
-      Some(pattern) => Some(Regex::new(pattern).map_err(anyhow::Error::from)?),
+      Some(pattern) => Some(CollectTestsError::compile_pattern(pattern)?),
       None => None,
     };
     let category_name = base.file_name().unwrap().to_string_lossy();
     let children =
-      collect_test_per_file(&category_name, base, pattern.as_ref())?;
+      collect_test_per_file(self, &category_name, base, pattern.as_ref())?;
     Ok(CollectedTestCategory {
       name: category_name.to_string(),
       path: base.to_path_buf(),
+      description: None,
       children,
     })
   }
 }
+
+#[cfg(test)]
+mod test {
+  use std::path::PathBuf;
+  use std::sync::Arc;
+
+  use super::*;
+  use crate::collection::with_file_system;
+  use crate::testing::InMemoryFileSystem;
+
+  #[test]
+  fn records_metadata() {
+    let fs = InMemoryFileSystem::default()
+      .file("specs/a.txt", "hello")
+      .file("specs/sub/b.txt", "");
+    let collect = |record_metadata| {
+      with_file_system(Arc::new(fs.clone()), || {
+        TestPerFileCollectionStrategy {
+          record_metadata,
+          ..Default::default()
+        }
+        .collect_tests(&PathBuf::from("specs"))
+      })
+      .unwrap()
+    };
+    let category = collect(false);
+    assert_eq!(
+      category.find_test("specs::a").unwrap().settings.metadata,
+      None
+    );
+    let category = collect(true);
+    let metadata =
+      |name: &str| category.find_test(name).unwrap().settings.metadata.unwrap();
+    assert_eq!(metadata("specs::a").size, 5);
+    assert_eq!(metadata("specs::sub::b").size, 0);
+  }
+}
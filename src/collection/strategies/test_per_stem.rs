@@ -0,0 +1,184 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use regex::Regex;
+
+use crate::collection::file_system::file_content_provider;
+use crate::collection::progress::report_test_found;
+use crate::collection::report_collect_warning;
+use crate::collection::CollectTestsError;
+use crate::collection::CollectedCategoryOrTest;
+use crate::collection::CollectedTest;
+use crate::collection::CollectedTestCategory;
+
+use super::helpers::append_to_category_name;
+use super::helpers::entry_file_type;
+use super::helpers::file_metadata;
+use super::helpers::read_dir_entries;
+use super::EntryFilter;
+use super::TestCollectionStrategy;
+
+/// Collects a test per file matching the pattern, grouping the other files
+/// in the same directory that share its stem (the file name up to the first
+/// period) into the test's data.
+///
+/// For example, with a pattern of `\.ts$`, the files `case1.ts`, `case1.out`,
+/// and `case1.stderr` are collected as a single test for `case1.ts` with the
+/// data `vec!["case1.out", "case1.stderr"]` (as full paths). Files that don't
+/// share a stem with a test file are not collected.
+///
+/// Note: By default, this ignores readme.md files and hidden directories
+/// starting with a period (see `entry_filter`).
+#[derive(Debug, Clone, Default)]
+pub struct TestPerStemCollectionStrategy {
+  /// Pattern of the file names of the test files (ex. `\.ts$`).
+  pub file_pattern: String,
+  /// Files and directories to skip.
+  pub entry_filter: EntryFilter,
+  /// Record the size and modified time of each test file in
+  /// `CollectedTest::metadata`.
+  pub record_metadata: bool,
+}
+
+impl TestCollectionStrategy<Vec<PathBuf>> for TestPerStemCollectionStrategy {
+  fn collect_tests(
+    &self,
+    base: &Path,
+  ) -> Result<CollectedTestCategory<Vec<PathBuf>>, CollectTestsError> {
+    fn collect_test_per_stem(
+      strategy: &TestPerStemCollectionStrategy,
+      category_name: &str,
+      dir_path: &Path,
+      pattern: &Regex,
+    ) -> Result<Vec<CollectedCategoryOrTest<Vec<PathBuf>>>, CollectTestsError>
+    {
+      let mut tests = vec![];
+      let mut files = vec![];
+
+      for path in read_dir_entries(dir_path, &strategy.entry_filter)? {
+        let Some(file_type) = entry_file_type(&path, &strategy.entry_filter)?
+        else {
+          continue;
+        };
+        if file_type.is_dir() {
+          let category_name = append_to_category_name(
+            category_name,
+            &path.file_name().unwrap().to_string_lossy(),
+          );
+          let children =
+            collect_test_per_stem(strategy, &category_name, &path, pattern)?;
+          if !children.is_empty() {
+            tests.push(CollectedCategoryOrTest::Category(
+              CollectedTestCategory {
+                name: category_name,
+                path,
+                description: None,
+                children,
+              },
+            ));
+          }
+        } else if file_type.is_file() {
+          files.push(path);
+        }
+      }
+
+      let (test_files, other_files): (Vec<_>, Vec<_>) =
+        files.into_iter().partition(|path| {
+          pattern.is_match(&path.file_name().unwrap().to_string_lossy())
+        });
+      for (i, path) in test_files.iter().enumerate() {
+        let name = stem(path);
+        if let Some(other) = test_files[..i].iter().find(|p| stem(p) == name) {
+          return Err(CollectTestsError::UnsupportedPath {
+            path: path.clone(),
+            reason: format!(
+              "test file has the same stem as '{}'",
+              other.display()
+            ),
+          });
+        }
+        let related_files = other_files
+          .iter()
+          .filter(|p| stem(p) == name)
+          .cloned()
+          .collect();
+        report_test_found();
+        let mut test = CollectedTest::new(
+          append_to_category_name(category_name, &name),
+          path.clone(),
+          related_files,
+        );
+        test.settings.metadata = file_metadata(path, strategy.record_metadata);
+        test.settings.content_provider = file_content_provider(path);
+        tests.push(CollectedCategoryOrTest::Test(test));
+      }
+
+      for path in &other_files {
+        let name = stem(path);
+        if !test_files.iter().any(|p| stem(p) == name) {
+          report_collect_warning(
+            path,
+            "Skipped because no test file shares its stem.",
+          );
+        }
+      }
+
+      Ok(tests)
+    }
+
+    let pattern = CollectTestsError::compile_pattern(&self.file_pattern)?;
+    let category_name = base.file_name().unwrap().to_string_lossy();
+    let children = collect_test_per_stem(self, &category_name, base, &pattern)?;
+    Ok(CollectedTestCategory {
+      name: category_name.to_string(),
+      path: base.to_path_buf(),
+      description: None,
+      children,
+    })
+  }
+}
+
+fn stem(path: &Path) -> String {
+  let file_name = path.file_name().unwrap().to_string_lossy();
+  match file_name.split_once('.') {
+    Some((stem, _)) => stem.to_string(),
+    None => file_name.to_string(),
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn groups_files_by_stem() {
+    let dir = std::env::temp_dir()
+      .join(format!("file_test_runner_stem_{}", std::process::id()))
+      .join("specs");
+    std::fs::create_dir_all(dir.join("sub")).unwrap();
+    for file in ["a.ts", "a.out", "a.stderr", "b.ts", "c.out", "sub/d.ts"] {
+      std::fs::write(dir.join(file), "").unwrap();
+    }
+
+    let strategy = TestPerStemCollectionStrategy {
+      file_pattern: r"\.ts$".to_string(),
+      record_metadata: true,
+      ..Default::default()
+    };
+    let category = strategy.collect_tests(&dir).unwrap();
+    assert_eq!(category.test_count(), 3);
+    let test = category.find_test("specs::a").unwrap();
+    assert_eq!(test.path, dir.join("a.ts"));
+    assert_eq!(test.data, vec![dir.join("a.out"), dir.join("a.stderr")]);
+    assert_eq!(test.settings.metadata.unwrap().size, 0);
+    assert!(category.find_test("specs::b").unwrap().data.is_empty());
+    assert!(category.find_test("specs::sub::d").is_some());
+
+    std::fs::write(dir.join("a.test.ts"), "").unwrap();
+    assert!(strategy.collect_tests(&dir).is_err());
+
+    std::fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+  }
+}
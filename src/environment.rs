@@ -0,0 +1,118 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::collections::BTreeMap;
+use std::process::Command;
+use std::process::Stdio;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Environment variables recorded in addition to the ones starting with
+/// `FILE_TEST_RUNNER_`.
+const RECORDED_ENV_VARS: [&str; 3] = ["CI", "RUST_BACKTRACE", "RUST_LOG"];
+
+/// Conditions the tests were run in, which helps when investigating
+/// failures that only happen on CI.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunEnvironment {
+  pub os: String,
+  pub arch: String,
+  pub cpu_count: usize,
+  /// Maximum number of tests run at the same time.
+  pub parallelism: usize,
+  /// Commit being tested, when known.
+  pub git_commit: Option<String>,
+  /// Values of the recorded environment variables that are set. See
+  /// `RunOptions::recorded_env_vars`.
+  pub env_vars: BTreeMap<String, String>,
+}
+
+impl RunEnvironment {
+  pub(crate) fn capture(parallelism: usize, extra_env_vars: &[String]) -> Self {
+    let env_vars = std::env::vars()
+      .filter(|(name, _)| {
+        name.starts_with("FILE_TEST_RUNNER_")
+          || RECORDED_ENV_VARS.contains(&name.as_str())
+          || extra_env_vars.contains(name)
+      })
+      .collect();
+    Self {
+      os: std::env::consts::OS.to_string(),
+      arch: std::env::consts::ARCH.to_string(),
+      cpu_count: std::thread::available_parallelism()
+        .map(|count| count.get())
+        .unwrap_or(1),
+      parallelism,
+      git_commit: git_commit(),
+      env_vars,
+    }
+  }
+
+  /// Gets the environment as name and value pairs, where environment
+  /// variables are prefixed with `env.`.
+  pub fn properties(&self) -> Vec<(String, String)> {
+    let mut properties = vec![
+      ("os".to_string(), self.os.clone()),
+      ("arch".to_string(), self.arch.clone()),
+      ("cpu_count".to_string(), self.cpu_count.to_string()),
+      ("parallelism".to_string(), self.parallelism.to_string()),
+    ];
+    if let Some(commit) = &self.git_commit {
+      properties.push(("git_commit".to_string(), commit.clone()));
+    }
+    for (name, value) in &self.env_vars {
+      properties.push((format!("env.{}", name), value.clone()));
+    }
+    properties
+  }
+}
+
+fn git_commit() -> Option<String> {
+  // prefer what CI providers set since the checkout may be shallow
+  // or not have git installed
+  for name in ["GITHUB_SHA", "CI_COMMIT_SHA", "BUILDKITE_COMMIT"] {
+    if let Some(commit) = std::env::var(name).ok().filter(|c| !c.is_empty()) {
+      return Some(commit);
+    }
+  }
+  let output = Command::new("git")
+    .args(["rev-parse", "HEAD"])
+    .stdin(Stdio::null())
+    .stderr(Stdio::null())
+    .output()
+    .ok()?;
+  let commit = String::from_utf8(output.stdout).ok()?;
+  let commit = commit.trim();
+  (output.status.success() && !commit.is_empty()).then(|| commit.to_string())
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn properties() {
+    let environment = RunEnvironment {
+      os: "linux".to_string(),
+      arch: "x86_64".to_string(),
+      cpu_count: 8,
+      parallelism: 7,
+      git_commit: Some("abc123".to_string()),
+      env_vars: BTreeMap::from([("CI".to_string(), "true".to_string())]),
+    };
+    assert_eq!(
+      environment.properties(),
+      vec![
+        ("os".to_string(), "linux".to_string()),
+        ("arch".to_string(), "x86_64".to_string()),
+        ("cpu_count".to_string(), "8".to_string()),
+        ("parallelism".to_string(), "7".to_string()),
+        ("git_commit".to_string(), "abc123".to_string()),
+        ("env.CI".to_string(), "true".to_string()),
+      ]
+    );
+    let environment = RunEnvironment::capture(2, &["PATH".to_string()]);
+    assert_eq!(environment.parallelism, 2);
+    assert!(environment.env_vars.contains_key("PATH"));
+  }
+}
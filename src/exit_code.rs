@@ -0,0 +1,40 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use deno_terminal::colors;
+
+/// Exit codes used when the process is exited by the runner.
+///
+/// These are a stable contract, so CI scripts may rely on them to react
+/// differently to each outcome.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunExitCode {
+  /// Tests failed or, with `RunOptions::fail_over_budget`, categories
+  /// exceeded their budget.
+  TestsFailed = 1,
+  /// No tests matched the filter with
+  /// `RunOptions::fail_when_no_tests_match`.
+  NoTestsMatched = 2,
+  /// Collecting the tests failed (ex. no tests were found).
+  CollectionError = 3,
+  /// The runner itself failed (ex. writing the results failed).
+  InternalError = 4,
+  /// Tests weren't run because they would have missed
+  /// `RunOptions::deadline`.
+  TestsNotRun = 5,
+}
+
+impl RunExitCode {
+  pub fn code(self) -> i32 {
+    self as i32
+  }
+
+  pub(crate) fn exit(self) -> ! {
+    std::process::exit(self.code())
+  }
+}
+
+/// Prints the error and exits with `RunExitCode::InternalError`.
+pub(crate) fn exit_with_internal_error(err: impl std::fmt::Display) -> ! {
+  eprintln!("{}: {:#}", colors::red_bold("error"), err);
+  RunExitCode::InternalError.exit()
+}
@@ -0,0 +1,364 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+//! Fixtures that are shared between tests.
+
+use std::any::Any;
+use std::any::TypeId;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::OnceLock;
+
+use parking_lot::Mutex;
+
+type SetupFunc = Arc<dyn Fn() -> Box<dyn Any> + Send + Sync>;
+type TeardownFunc = Arc<dyn Fn(Box<dyn Any>) + Send + Sync>;
+
+type FixtureCell = Arc<OnceLock<Arc<dyn Any + Send + Sync>>>;
+
+thread_local! {
+  static WORKER_FIXTURE: RefCell<Option<Box<dyn Any>>> = RefCell::new(None);
+  static CURRENT_FIXTURES: RefCell<Option<Arc<Fixtures>>> =
+    const { RefCell::new(None) };
+  static CURRENT_CATEGORY_FIXTURES: RefCell<Option<Arc<Fixtures>>> =
+    const { RefCell::new(None) };
+}
+
+/// Fixtures that are lazily initialized once per run and shared by all
+/// the tests in the run (or in the worker process when using worker
+/// processes).
+///
+/// The fixtures are dropped in the reverse order they were initialized
+/// after all the tests finish, even when tests fail, so teardown can be
+/// done in their `Drop` implementations.
+#[derive(Default)]
+pub struct Fixtures {
+  cells: Mutex<HashMap<TypeId, FixtureCell>>,
+  /// Initialized fixtures in the order they were initialized.
+  initialized: Mutex<Vec<Arc<dyn Any + Send + Sync>>>,
+}
+
+impl std::fmt::Debug for Fixtures {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("Fixtures")
+      .field("len", &self.initialized.lock().len())
+      .finish()
+  }
+}
+
+impl Fixtures {
+  /// Gets the fixtures of the run on the current thread.
+  ///
+  /// Panics when not called from a test or a `TestHooks` method.
+  pub fn current() -> Arc<Fixtures> {
+    CURRENT_FIXTURES.with(|fixtures| {
+      fixtures
+        .borrow()
+        .clone()
+        .expect("Fixtures can only be accessed while running tests.")
+    })
+  }
+
+  /// Gets the fixtures of the category of the test running on the
+  /// current thread, which are dropped when the category finishes.
+  ///
+  /// Panics when not called from a test.
+  pub fn current_category() -> Arc<Fixtures> {
+    CURRENT_CATEGORY_FIXTURES.with(|fixtures| {
+      fixtures
+        .borrow()
+        .clone()
+        .expect("Category fixtures can only be accessed while running tests.")
+    })
+  }
+
+  /// Gets the fixture of type `T`, initializing it if this is the first
+  /// time it's accessed in the run or category.
+  pub fn get_or_init<T: Send + Sync + 'static>(
+    &self,
+    init: impl FnOnce() -> T,
+  ) -> Arc<T> {
+    let cell = self
+      .cells
+      .lock()
+      .entry(TypeId::of::<T>())
+      .or_default()
+      .clone();
+    // don't hold the lock while initializing because it may
+    // take a while or initialize other fixtures
+    let value = cell.get_or_init(|| {
+      let value: Arc<dyn Any + Send + Sync> = Arc::new(init());
+      self.initialized.lock().push(value.clone());
+      value
+    });
+    value.clone().downcast::<T>().unwrap()
+  }
+
+  /// Drops the fixtures in the reverse order they were initialized.
+  pub(crate) fn teardown(&self) {
+    self.cells.lock().clear();
+    let initialized = std::mem::take(&mut *self.initialized.lock());
+    for value in initialized.into_iter().rev() {
+      drop(value);
+    }
+  }
+
+  /// Sets the fixtures of the run on the current thread.
+  pub(crate) fn set_current(fixtures: Option<Arc<Fixtures>>) {
+    CURRENT_FIXTURES.with(|current| *current.borrow_mut() = fixtures);
+  }
+
+  /// Sets the fixtures of the category of the test running on the
+  /// current thread.
+  pub(crate) fn set_current_category(fixtures: Option<Arc<Fixtures>>) {
+    CURRENT_CATEGORY_FIXTURES.with(|current| *current.borrow_mut() = fixtures);
+  }
+}
+
+/// Tears down the fixtures when dropped, which happens even when
+/// the run panics.
+pub(crate) struct FixturesTeardownGuard(pub Arc<Fixtures>);
+
+impl Drop for FixturesTeardownGuard {
+  fn drop(&mut self) {
+    Fixtures::set_current(None);
+    self.0.teardown();
+  }
+}
+
+/// Fixture that's set up once per worker thread or worker process, which
+/// is useful for resources that can't be shared between tests running
+/// in parallel, but are too expensive to create for each test (ex. a
+/// database container).
+///
+/// The fixture is set up on the worker before it runs its first test and
+/// torn down after the worker finishes, before the failures are reported.
+#[derive(Clone)]
+pub struct WorkerFixture {
+  setup: SetupFunc,
+  teardown: TeardownFunc,
+}
+
+impl std::fmt::Debug for WorkerFixture {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("WorkerFixture").finish_non_exhaustive()
+  }
+}
+
+impl WorkerFixture {
+  pub fn new<W: 'static>(
+    setup: impl Fn() -> W + Send + Sync + 'static,
+    teardown: impl Fn(W) + Send + Sync + 'static,
+  ) -> Self {
+    Self {
+      setup: Arc::new(move || Box::new(setup())),
+      teardown: Arc::new(move |value| {
+        teardown(*value.downcast::<W>().unwrap());
+      }),
+    }
+  }
+
+  /// Calls the closure with the fixture of the worker running the
+  /// current test.
+  ///
+  /// Panics when not called from a test or when the fixture is not of
+  /// type `W`.
+  pub fn with<W: 'static, R>(func: impl FnOnce(&W) -> R) -> R {
+    WORKER_FIXTURE.with(|fixture| {
+      let fixture = fixture.borrow();
+      let Some(fixture) = fixture.as_ref() else {
+        panic!("No worker fixture was set up for the current thread.");
+      };
+      let Some(fixture) = fixture.downcast_ref::<W>() else {
+        panic!(
+          "Worker fixture was not of type '{}'.",
+          std::any::type_name::<W>()
+        );
+      };
+      func(fixture)
+    })
+  }
+
+  /// Sets up the fixture for the current thread, which is torn down
+  /// when the returned guard is dropped.
+  pub(crate) fn install(&self) -> WorkerFixtureGuard {
+    let value = (self.setup)();
+    WORKER_FIXTURE.with(|fixture| {
+      *fixture.borrow_mut() = Some(value);
+    });
+    WorkerFixtureGuard {
+      teardown: self.teardown.clone(),
+    }
+  }
+}
+
+pub(crate) struct WorkerFixtureGuard {
+  teardown: TeardownFunc,
+}
+
+impl Drop for WorkerFixtureGuard {
+  fn drop(&mut self) {
+    let value = WORKER_FIXTURE.with(|fixture| fixture.borrow_mut().take());
+    if let Some(value) = value {
+      (self.teardown)(value);
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::path::PathBuf;
+
+  use parking_lot::Mutex;
+
+  use super::*;
+  use crate::collection::CollectedCategoryOrTest;
+  use crate::collection::CollectedTest;
+  use crate::collection::CollectedTestCategory;
+  use crate::run_tests;
+  use crate::RunOptions;
+  use crate::TestResult;
+
+  #[test]
+  fn run_fixtures() {
+    struct Server(Arc<Mutex<Vec<&'static str>>>);
+
+    impl Drop for Server {
+      fn drop(&mut self) {
+        self.0.lock().push("teardown");
+      }
+    }
+
+    let category = CollectedTestCategory {
+      name: "specs".to_string(),
+      path: PathBuf::from("specs"),
+      description: None,
+      children: (0..4)
+        .map(|i| {
+          CollectedCategoryOrTest::Test(CollectedTest::new(
+            format!("specs::{}", i),
+            i.to_string(),
+            (),
+          ))
+        })
+        .collect(),
+    };
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe({
+      let events = events.clone();
+      move || {
+        run_tests(
+          &category,
+          RunOptions {
+            parallel: true,
+            ..Default::default()
+          },
+          move |_| {
+            Fixtures::current().get_or_init(|| {
+              events.lock().push("setup");
+              Server(events.clone())
+            });
+            TestResult::failed(Vec::new())
+          },
+        )
+      }
+    }));
+    assert!(result.is_err());
+    assert_eq!(*events.lock(), vec!["setup", "teardown"]);
+  }
+
+  #[test]
+  fn category_fixtures() {
+    let test = |name: &str| {
+      CollectedCategoryOrTest::Test(CollectedTest::new(name, name, ()))
+    };
+    let category = |name: &str, children| {
+      CollectedCategoryOrTest::Category(CollectedTestCategory {
+        name: name.to_string(),
+        path: PathBuf::from(name),
+        description: None,
+        children,
+      })
+    };
+    let CollectedCategoryOrTest::Category(root) = category(
+      "specs",
+      vec![
+        category("specs::a", vec![test("specs::a::1"), test("specs::a::2")]),
+        category("specs::b", vec![test("specs::b::1")]),
+      ],
+    ) else {
+      unreachable!();
+    };
+    let created = Arc::new(Mutex::new(Vec::new()));
+    run_tests(&root, RunOptions::default(), {
+      let created = created.clone();
+      move |test| {
+        let category = test.name.rsplit_once("::").unwrap().0.to_string();
+        let value = Fixtures::current_category().get_or_init(|| {
+          created.lock().push(category.clone());
+          category.clone()
+        });
+        assert_eq!(*value, category);
+        TestResult::Passed
+      }
+    });
+    assert_eq!(*created.lock(), vec!["specs::a", "specs::b"]);
+  }
+
+  #[test]
+  fn worker_fixture() {
+    let category = CollectedTestCategory {
+      name: "specs".to_string(),
+      path: PathBuf::from("specs"),
+      description: None,
+      children: (0..10)
+        .map(|i| {
+          CollectedCategoryOrTest::Test(CollectedTest::new(
+            format!("specs::{}", i),
+            i.to_string(),
+            (),
+          ))
+        })
+        .collect(),
+    };
+    let events = Arc::new(Mutex::new(Vec::new()));
+    for parallel in [false, true] {
+      events.lock().clear();
+      run_tests(
+        &category,
+        RunOptions {
+          parallel,
+          worker_fixture: Some(WorkerFixture::new(
+            {
+              let events = events.clone();
+              move || {
+                let id = std::thread::current().id();
+                events.lock().push(format!("setup {:?}", id));
+                id
+              }
+            },
+            {
+              let events = events.clone();
+              move |id| events.lock().push(format!("teardown {:?}", id))
+            },
+          )),
+          ..Default::default()
+        },
+        |_| {
+          let id = WorkerFixture::with(|id: &std::thread::ThreadId| *id);
+          assert_eq!(id, std::thread::current().id());
+          TestResult::Passed
+        },
+      );
+      let events = events.lock();
+      let setups = events.iter().filter(|e| e.starts_with("setup")).count();
+      let teardowns =
+        events.iter().filter(|e| e.starts_with("teardown")).count();
+      assert!(setups > 0);
+      assert_eq!(setups, teardowns);
+      if !parallel {
+        assert_eq!(setups, 1);
+      }
+    }
+  }
+}
@@ -0,0 +1,239 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use deno_terminal::colors;
+use parking_lot::Condvar;
+use parking_lot::Mutex;
+
+use crate::collection::CollectedTest;
+use crate::collection::CollectedTestCategory;
+use crate::runner::run_tests_inner;
+use crate::RunOptions;
+use crate::RunSummary;
+use crate::TestResult;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RunState {
+  Running,
+  Paused,
+  Cancelled,
+}
+
+/// Gates the dispatching of tests.
+#[derive(Debug)]
+pub(crate) struct RunControl {
+  state: Mutex<RunState>,
+  changed: Condvar,
+  /// Maximum number of tests to run at the same time.
+  parallelism: AtomicUsize,
+}
+
+impl Default for RunControl {
+  fn default() -> Self {
+    Self {
+      state: Mutex::new(RunState::Running),
+      changed: Condvar::new(),
+      parallelism: AtomicUsize::new(usize::MAX),
+    }
+  }
+}
+
+impl RunControl {
+  fn set_state(&self, state: RunState) {
+    let mut current = self.state.lock();
+    // a cancelled run can't be resumed
+    if *current != RunState::Cancelled {
+      *current = state;
+      self.changed.notify_all();
+    }
+  }
+
+  pub fn is_paused(&self) -> bool {
+    *self.state.lock() == RunState::Paused
+  }
+
+  pub fn is_cancelled(&self) -> bool {
+    *self.state.lock() == RunState::Cancelled
+  }
+
+  pub fn parallelism(&self) -> usize {
+    self.parallelism.load(Ordering::Relaxed)
+  }
+
+  /// Blocks while the run is paused, returning false when the
+  /// run was cancelled.
+  pub fn wait_while_paused(&self) -> bool {
+    let mut state = self.state.lock();
+    while *state == RunState::Paused {
+      self.changed.wait(&mut state);
+    }
+    *state != RunState::Cancelled
+  }
+}
+
+/// Handle to a run started by `spawn_tests`.
+#[derive(Debug)]
+pub struct RunHandle {
+  control: Arc<RunControl>,
+  thread: JoinHandle<RunSummary>,
+}
+
+impl RunHandle {
+  /// Stops dispatching new tests. Tests that are already running
+  /// continue until they finish.
+  pub fn pause(&self) {
+    self.control.set_state(RunState::Paused);
+  }
+
+  /// Resumes dispatching tests after a pause.
+  pub fn resume(&self) {
+    self.control.set_state(RunState::Running);
+  }
+
+  /// Stops dispatching new tests and ends the run once the tests
+  /// that are already running finish. The remaining tests are not run.
+  pub fn cancel(&self) {
+    self.control.set_state(RunState::Cancelled);
+  }
+
+  /// Changes the maximum number of tests run at the same time, which is
+  /// capped by the number of threads or worker processes in the run.
+  ///
+  /// At least one test is always allowed to run so that the run can't
+  /// stall. Use `pause()` to stop running tests instead.
+  pub fn set_parallelism(&self, parallelism: usize) {
+    if parallelism == 0 {
+      eprintln!(
+        "{}: parallelism must be at least 1, using 1",
+        colors::yellow_bold("warning")
+      );
+    }
+    self
+      .control
+      .parallelism
+      .store(parallelism.max(1), Ordering::Relaxed);
+  }
+
+  pub fn is_finished(&self) -> bool {
+    self.thread.is_finished()
+  }
+
+  /// Waits for the run to finish.
+  ///
+  /// Unlike `run_tests`, this does not panic when tests fail.
+  pub fn join(self) -> RunSummary {
+    match self.thread.join() {
+      Ok(summary) => summary,
+      Err(err) => std::panic::resume_unwind(err),
+    }
+  }
+}
+
+/// Runs the tests on a background thread, returning a handle that can
+/// be used to pause, resume, or cancel the run.
+pub fn spawn_tests<TData: Clone + Send + 'static>(
+  category: CollectedTestCategory<TData>,
+  options: RunOptions<TData>,
+  run_test: impl (Fn(&CollectedTest<TData>) -> TestResult) + Send + Sync + 'static,
+) -> RunHandle {
+  let control = Arc::new(RunControl::default());
+  let thread = std::thread::spawn({
+    let control = control.clone();
+    move || run_tests_inner(&category, options, run_test, Some(control))
+  });
+  RunHandle { control, thread }
+}
+
+#[cfg(test)]
+mod test {
+  use std::path::PathBuf;
+  use std::time::Duration;
+
+  use super::*;
+  use crate::collection::CollectedCategoryOrTest;
+
+  #[test]
+  fn pause_resume_cancel() {
+    let category = CollectedTestCategory {
+      name: "specs".to_string(),
+      path: PathBuf::from("specs"),
+      description: None,
+      children: (0..20)
+        .map(|i| {
+          CollectedCategoryOrTest::Test(CollectedTest::new(
+            format!("specs::{}", i),
+            i.to_string(),
+            (),
+          ))
+        })
+        .collect(),
+    };
+    let (started_tx, started_rx) = crossbeam_channel::unbounded();
+    let (proceed_tx, proceed_rx) = crossbeam_channel::unbounded::<()>();
+    let handle = spawn_tests(category, RunOptions::default(), move |test| {
+      started_tx.send(test.name.clone()).unwrap();
+      proceed_rx.recv().unwrap();
+      if test.name == "specs::1" {
+        TestResult::failed(Vec::new())
+      } else {
+        TestResult::Passed
+      }
+    });
+    assert_eq!(started_rx.recv().unwrap(), "specs::0");
+    handle.pause();
+    proceed_tx.send(()).unwrap();
+    // no test is started while paused
+    assert!(started_rx.recv_timeout(Duration::from_millis(100)).is_err());
+    handle.resume();
+    assert_eq!(started_rx.recv().unwrap(), "specs::1");
+    handle.cancel();
+    proceed_tx.send(()).unwrap();
+    let summary = handle.join();
+    assert!(summary.cancelled);
+    assert_eq!(summary.total_tests, 20);
+    assert_eq!(summary.failed_tests, vec!["specs::1".to_string()]);
+    assert!(started_rx.try_recv().is_err());
+  }
+
+  #[test]
+  fn set_parallelism_floor() {
+    let category = CollectedTestCategory {
+      name: "specs".to_string(),
+      path: PathBuf::from("specs"),
+      description: None,
+      children: (0..8)
+        .map(|i| {
+          CollectedCategoryOrTest::Test(CollectedTest::new(
+            format!("specs::{}", i),
+            i.to_string(),
+            (),
+          ))
+        })
+        .collect(),
+    };
+    let (proceed_tx, proceed_rx) = crossbeam_channel::unbounded::<()>();
+    let handle = spawn_tests(
+      category,
+      RunOptions {
+        parallel: true,
+        ..Default::default()
+      },
+      move |_| {
+        proceed_rx.recv().unwrap();
+        TestResult::Passed
+      },
+    );
+    // setting the parallelism to zero should not stall the run
+    handle.set_parallelism(0);
+    for _ in 0..8 {
+      proceed_tx.send(()).unwrap();
+    }
+    let summary = handle.join();
+    assert_eq!(summary.total_tests, 8);
+    assert!(summary.failed_tests.is_empty());
+  }
+}
@@ -0,0 +1,493 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+//! Append-only history of test results across runs, which can be used
+//! to find flaky tests.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use parking_lot::Mutex;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::collection::CollectedTest;
+use crate::collection::CollectedTestCategory;
+use crate::exit_code::exit_with_internal_error;
+use crate::reporter::Reporter;
+use crate::reporter::ReporterContext;
+use crate::reporter::ReporterFailure;
+use crate::reporter::TimingRegression;
+use crate::state::StateStore;
+use crate::PathedIoError;
+use crate::TestResult;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryOutcome {
+  Passed,
+  Failed,
+  Ignored,
+}
+
+impl HistoryOutcome {
+  fn from_result(result: &TestResult) -> Self {
+    if result.is_failed() {
+      HistoryOutcome::Failed
+    } else if matches!(result, TestResult::Ignored) {
+      HistoryOutcome::Ignored
+    } else {
+      HistoryOutcome::Passed
+    }
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryTestRecord {
+  pub name: String,
+  pub outcome: HistoryOutcome,
+  pub duration_ms: u64,
+}
+
+/// Results of all the tests in a single run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryRun {
+  /// Unix timestamp in milliseconds of when the run finished.
+  pub timestamp_ms: u64,
+  pub tests: Vec<HistoryTestRecord>,
+}
+
+impl HistoryRun {
+  /// Loads the last run of the provided history file (ex. one saved from
+  /// a previous CI run) to compare a run against.
+  pub fn load_last(path: &Path) -> Result<Option<Self>, PathedIoError> {
+    let text = std::fs::read_to_string(path)
+      .map_err(|err| PathedIoError::new(path, err))?;
+    Ok(TestHistory::parse(&text).runs.pop())
+  }
+
+  /// Classifies the failures of a run against this run.
+  pub fn compare(
+    &self,
+    failed_tests: &[String],
+    passed_tests: &HashSet<String>,
+  ) -> BaselineComparison {
+    let baseline_failures = self
+      .tests
+      .iter()
+      .filter(|test| test.outcome == HistoryOutcome::Failed)
+      .map(|test| test.name.as_str())
+      .collect::<HashSet<_>>();
+    let (still_failing, new_failures) = failed_tests
+      .iter()
+      .cloned()
+      .partition(|name| baseline_failures.contains(name.as_str()));
+    let mut fixed = baseline_failures
+      .into_iter()
+      .filter(|name| passed_tests.contains(*name))
+      .map(|name| name.to_string())
+      .collect::<Vec<_>>();
+    fixed.sort();
+    BaselineComparison {
+      new_failures,
+      still_failing,
+      fixed,
+    }
+  }
+
+  /// Gets the tests that slowed down beyond the threshold compared to
+  /// this run, with the largest slow down first.
+  pub fn timing_regressions(
+    &self,
+    durations: &HashMap<String, Duration>,
+    threshold: TimingThreshold,
+  ) -> Vec<TimingRegression> {
+    let mut regressions = self
+      .tests
+      .iter()
+      .filter(|test| test.outcome == HistoryOutcome::Passed)
+      .filter_map(|test| {
+        let elapsed = *durations.get(&test.name)?;
+        let baseline = Duration::from_millis(test.duration_ms);
+        threshold
+          .is_exceeded(baseline, elapsed)
+          .then(|| TimingRegression {
+            name: test.name.clone(),
+            elapsed,
+            baseline,
+          })
+      })
+      .collect::<Vec<_>>();
+    regressions.sort_by_key(|r| std::cmp::Reverse(r.elapsed - r.baseline));
+    regressions
+  }
+}
+
+/// How much slower than in the baseline run a test may get before it's
+/// reported as a timing regression.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimingThreshold {
+  /// Slower by more than this duration.
+  Absolute(Duration),
+  /// Slower by more than this percentage (ex. `50.0` for 50% slower).
+  Percentage(f64),
+}
+
+impl TimingThreshold {
+  fn is_exceeded(&self, baseline: Duration, elapsed: Duration) -> bool {
+    match *self {
+      TimingThreshold::Absolute(threshold) => {
+        elapsed.saturating_sub(baseline) > threshold
+      }
+      TimingThreshold::Percentage(percentage) => {
+        elapsed.as_secs_f64()
+          > baseline.as_secs_f64() * (1.0 + percentage / 100.0)
+      }
+    }
+  }
+}
+
+/// Failures of a run classified against a previous run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BaselineComparison {
+  /// Tests that failed, but didn't fail in the baseline.
+  pub new_failures: Vec<String>,
+  /// Tests that failed in both runs.
+  pub still_failing: Vec<String>,
+  /// Tests that failed in the baseline, but passed.
+  pub fixed: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestFailureRate {
+  pub name: String,
+  /// Number of runs the test was run in (excluding when ignored).
+  pub runs: usize,
+  pub failures: usize,
+}
+
+impl TestFailureRate {
+  pub fn rate(&self) -> f64 {
+    if self.runs == 0 {
+      0.0
+    } else {
+      self.failures as f64 / self.runs as f64
+    }
+  }
+}
+
+/// Name of the history file in the `StateStore`.
+pub const HISTORY_FILE_NAME: &str = "history.jsonl";
+
+/// Test results of previous runs, oldest first.
+#[derive(Debug, Default, Clone)]
+pub struct TestHistory {
+  pub runs: Vec<HistoryRun>,
+}
+
+impl TestHistory {
+  /// Default location of the history file.
+  pub fn default_path() -> PathBuf {
+    StateStore::default().path(HISTORY_FILE_NAME)
+  }
+
+  /// Loads the history from the provided state store.
+  pub fn load_from_store(store: &StateStore) -> Result<Self, PathedIoError> {
+    Ok(
+      store
+        .read(HISTORY_FILE_NAME)?
+        .map(|text| Self::parse(&text))
+        .unwrap_or_default(),
+    )
+  }
+
+  /// Loads the history from the provided file, returning an empty
+  /// history if it doesn't exist.
+  pub fn load(path: &Path) -> Result<Self, PathedIoError> {
+    match std::fs::read_to_string(path) {
+      Ok(text) => Ok(Self::parse(&text)),
+      Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+        Ok(Self::default())
+      }
+      Err(err) => Err(PathedIoError::new(path, err)),
+    }
+  }
+
+  fn parse(text: &str) -> Self {
+    Self {
+      runs: text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        // skip lines that fail to parse, which may happen if
+        // a previous run was killed while writing
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect(),
+    }
+  }
+
+  /// Appends a run to the history file in the provided state store.
+  pub fn append(
+    store: &StateStore,
+    run: &HistoryRun,
+  ) -> Result<(), PathedIoError> {
+    let mut line = serde_json::to_string(run).unwrap();
+    line.push('\n');
+    store.append(HISTORY_FILE_NAME, line.as_bytes())
+  }
+
+  /// Gets the duration of each test in the last run it wasn't ignored in,
+  /// which predicts how long it will take to run.
+  pub fn last_durations(&self) -> HashMap<String, Duration> {
+    let mut durations = HashMap::new();
+    for run in &self.runs {
+      for test in &run.tests {
+        if test.outcome != HistoryOutcome::Ignored {
+          durations
+            .insert(test.name.clone(), Duration::from_millis(test.duration_ms));
+        }
+      }
+    }
+    durations
+  }
+
+  /// Gets the failure rate of each test over the last `last_runs` runs,
+  /// sorted with the highest failure rate first.
+  pub fn failure_rates(&self, last_runs: usize) -> Vec<TestFailureRate> {
+    let start = self.runs.len().saturating_sub(last_runs);
+    let mut rates: HashMap<&str, TestFailureRate> = HashMap::new();
+    for run in &self.runs[start..] {
+      for test in &run.tests {
+        if test.outcome == HistoryOutcome::Ignored {
+          continue;
+        }
+        let rate = rates.entry(&test.name).or_insert_with(|| TestFailureRate {
+          name: test.name.clone(),
+          runs: 0,
+          failures: 0,
+        });
+        rate.runs += 1;
+        if test.outcome == HistoryOutcome::Failed {
+          rate.failures += 1;
+        }
+      }
+    }
+    let mut rates = rates.into_values().collect::<Vec<_>>();
+    rates.sort_by(|a, b| {
+      b.rate()
+        .total_cmp(&a.rate())
+        .then_with(|| a.name.cmp(&b.name))
+    });
+    rates
+  }
+
+  /// Builds a human readable report of the tests that failed at least
+  /// once in the last `last_runs` runs.
+  pub fn flakiness_report(&self, last_runs: usize) -> String {
+    let rates = self
+      .failure_rates(last_runs)
+      .into_iter()
+      .filter(|r| r.failures > 0)
+      .collect::<Vec<_>>();
+    let mut text = String::new();
+    if rates.is_empty() {
+      writeln!(
+        text,
+        "No failures in the last {} runs.",
+        last_runs.min(self.runs.len())
+      )
+      .unwrap();
+      return text;
+    }
+    for rate in rates {
+      writeln!(
+        text,
+        "{:>5.1}% ({}/{}) {}",
+        rate.rate() * 100.0,
+        rate.failures,
+        rate.runs,
+        rate.name
+      )
+      .unwrap();
+    }
+    text
+  }
+}
+
+/// Reporter that appends the results of the run to the history file.
+pub(crate) struct HistoryReporter {
+  store: StateStore,
+  tests: Mutex<Vec<HistoryTestRecord>>,
+}
+
+impl HistoryReporter {
+  pub fn new(store: StateStore) -> Self {
+    Self {
+      store,
+      tests: Default::default(),
+    }
+  }
+}
+
+impl<TData> Reporter<TData> for HistoryReporter {
+  fn report_category_start(
+    &self,
+    _category: &CollectedTestCategory<TData>,
+    _context: &ReporterContext,
+  ) {
+  }
+
+  fn report_category_end(
+    &self,
+    _category: &CollectedTestCategory<TData>,
+    _context: &ReporterContext,
+  ) {
+  }
+
+  fn report_test_start(
+    &self,
+    _test: &CollectedTest<TData>,
+    _context: &ReporterContext,
+  ) {
+  }
+
+  fn report_test_end(
+    &self,
+    test: &CollectedTest<TData>,
+    duration: Duration,
+    result: &TestResult,
+    _context: &ReporterContext,
+  ) {
+    self.tests.lock().push(HistoryTestRecord {
+      name: test.name.clone(),
+      outcome: HistoryOutcome::from_result(result),
+      duration_ms: duration.as_millis() as u64,
+    });
+  }
+
+  fn report_long_running_test(&self, _test_name: &str, _elapsed: Duration) {}
+
+  fn report_failures(
+    &self,
+    _failures: &[ReporterFailure<TData>],
+    _total_tests: usize,
+  ) {
+    let run = HistoryRun {
+      timestamp_ms: SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0),
+      tests: std::mem::take(&mut *self.tests.lock()),
+    };
+    if let Err(err) = TestHistory::append(&self.store, &run) {
+      exit_with_internal_error(err);
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn failure_rates() {
+    let history = TestHistory::parse(concat!(
+      r#"{"timestamp_ms":1,"tests":[{"name":"a","outcome":"failed","duration_ms":1},{"name":"b","outcome":"passed","duration_ms":1}]}"#,
+      "\n",
+      "{\"timestamp_ms\":2,\"tests\":[{\"name\":\"a\"\n",
+      r#"{"timestamp_ms":3,"tests":[{"name":"a","outcome":"passed","duration_ms":1},{"name":"b","outcome":"failed","duration_ms":1},{"name":"c","outcome":"ignored","duration_ms":0}]}"#,
+      "\n",
+      r#"{"timestamp_ms":4,"tests":[{"name":"a","outcome":"passed","duration_ms":1},{"name":"b","outcome":"failed","duration_ms":1}]}"#,
+      "\n",
+    ));
+    assert_eq!(history.runs.len(), 3);
+    assert_eq!(
+      history.failure_rates(3),
+      vec![
+        TestFailureRate {
+          name: "b".to_string(),
+          runs: 3,
+          failures: 2,
+        },
+        TestFailureRate {
+          name: "a".to_string(),
+          runs: 3,
+          failures: 1,
+        },
+      ]
+    );
+    assert_eq!(
+      history.failure_rates(2),
+      vec![
+        TestFailureRate {
+          name: "b".to_string(),
+          runs: 2,
+          failures: 2,
+        },
+        TestFailureRate {
+          name: "a".to_string(),
+          runs: 2,
+          failures: 0,
+        },
+      ]
+    );
+    assert_eq!(
+      history.flakiness_report(3),
+      " 66.7% (2/3) b\n 33.3% (1/3) a\n"
+    );
+  }
+
+  #[test]
+  fn compare_to_baseline() {
+    let baseline = TestHistory::parse(concat!(
+      r#"{"timestamp_ms":1,"tests":[{"name":"a","outcome":"failed","duration_ms":1},{"name":"b","outcome":"failed","duration_ms":1},{"name":"c","outcome":"failed","duration_ms":1},{"name":"d","outcome":"passed","duration_ms":1}]}"#,
+      "\n",
+    ))
+    .runs
+    .remove(0);
+    let comparison = baseline.compare(
+      &["d".to_string(), "a".to_string()],
+      &HashSet::from(["b".to_string()]),
+    );
+    assert_eq!(
+      comparison,
+      BaselineComparison {
+        new_failures: vec!["d".to_string()],
+        still_failing: vec!["a".to_string()],
+        fixed: vec!["b".to_string()],
+      }
+    );
+  }
+
+  #[test]
+  fn timing_regressions() {
+    let baseline = TestHistory::parse(concat!(
+      r#"{"timestamp_ms":1,"tests":[{"name":"a","outcome":"passed","duration_ms":100},{"name":"b","outcome":"passed","duration_ms":100},{"name":"c","outcome":"passed","duration_ms":1000},{"name":"d","outcome":"failed","duration_ms":1}]}"#,
+      "\n",
+    ))
+    .runs
+    .remove(0);
+    let durations = HashMap::from([
+      ("a".to_string(), Duration::from_millis(140)),
+      ("b".to_string(), Duration::from_millis(300)),
+      ("c".to_string(), Duration::from_millis(1180)),
+      ("d".to_string(), Duration::from_millis(500)),
+    ]);
+    let names = |threshold| {
+      baseline
+        .timing_regressions(&durations, threshold)
+        .into_iter()
+        .map(|r| r.name)
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(names(TimingThreshold::Percentage(50.0)), vec!["b"]);
+    assert_eq!(names(TimingThreshold::Percentage(30.0)), vec!["b", "a"]);
+    assert_eq!(
+      names(TimingThreshold::Absolute(Duration::from_millis(150))),
+      vec!["b", "c"]
+    );
+  }
+}
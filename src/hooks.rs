@@ -0,0 +1,99 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use crate::collection::CollectedTest;
+use crate::collection::CollectedTestCategory;
+use crate::TestResult;
+
+/// Hooks that are called around the run and each test.
+///
+/// `before_test` and `after_test` are called on the same thread that runs
+/// the test. When using worker processes, they're called in the worker
+/// process and `on_run_start`/`on_run_end` are called in both the
+/// coordinator and each worker.
+pub trait TestHooks<TData = ()>: Send + Sync {
+  /// Called once before any tests are run.
+  fn on_run_start(&self, _category: &CollectedTestCategory<TData>) {}
+  /// Called once after all the tests have run, before the failures
+  /// are reported.
+  fn on_run_end(&self, _category: &CollectedTestCategory<TData>) {}
+  /// Called before each test is run.
+  fn before_test(&self, _test: &CollectedTest<TData>) {}
+  /// Called after each test is run. The result may be modified, for
+  /// example, to fail the test when a global invariant was broken.
+  fn after_test(&self, _test: &CollectedTest<TData>, _result: &mut TestResult) {
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::path::PathBuf;
+  use std::sync::Arc;
+
+  use parking_lot::Mutex;
+
+  use super::*;
+  use crate::collection::CollectedCategoryOrTest;
+  use crate::run_tests;
+  use crate::RunOptions;
+
+  #[derive(Default)]
+  struct RecordingHooks(Mutex<Vec<String>>);
+
+  impl TestHooks for RecordingHooks {
+    fn on_run_start(&self, category: &CollectedTestCategory) {
+      self.0.lock().push(format!("run_start {}", category.name));
+    }
+
+    fn on_run_end(&self, category: &CollectedTestCategory) {
+      self.0.lock().push(format!("run_end {}", category.name));
+    }
+
+    fn before_test(&self, test: &CollectedTest) {
+      self.0.lock().push(format!("before {}", test.name));
+    }
+
+    fn after_test(&self, test: &CollectedTest, result: &mut TestResult) {
+      self
+        .0
+        .lock()
+        .push(format!("after {} {}", test.name, result.is_failed()));
+    }
+  }
+
+  #[test]
+  fn calls_hooks() {
+    let test = |name: &str| {
+      CollectedCategoryOrTest::Test(CollectedTest::new(
+        format!("specs::{}", name),
+        name,
+        (),
+      ))
+    };
+    let category = CollectedTestCategory {
+      name: "specs".to_string(),
+      path: PathBuf::from("specs"),
+      description: None,
+      children: vec![test("a"), test("b")],
+    };
+    let hooks = Arc::new(RecordingHooks::default());
+    run_tests(
+      &category,
+      RunOptions {
+        hooks: Some(hooks.clone()),
+        ..Default::default()
+      },
+      |_| TestResult::Passed,
+    );
+    assert_eq!(
+      *hooks.0.lock(),
+      vec![
+        "run_start specs",
+        "before specs::a",
+        "after specs::a false",
+        "before specs::b",
+        "after specs::b false",
+        "run_end specs",
+      ]
+    );
+  }
+}
@@ -0,0 +1,176 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use deno_terminal::colors;
+use parking_lot::Mutex;
+
+use crate::collection::CollectedTest;
+use crate::hooks::TestHooks;
+use crate::TestResult;
+
+/// What to do when a test leaks resources.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LeakAction {
+  /// Print a warning to stderr.
+  #[default]
+  Warn,
+  /// Fail the test.
+  Fail,
+}
+
+/// Hooks that detect tests leaking file descriptors or files in a
+/// temp directory by comparing them before and after each test.
+///
+/// File descriptors are counted for the whole process and the temp
+/// directory is shared by every test, so only enable `check_fds` or set
+/// `temp_dir` when tests run sequentially or in single threaded worker
+/// processes. Otherwise, the files of one test are blamed on another.
+/// Counting file descriptors is supported on Linux and macOS.
+#[derive(Debug, Default)]
+pub struct LeakDetector {
+  /// Check the number of open file descriptors.
+  pub check_fds: bool,
+  /// Temp directory that tests must remove their files from. Tests must
+  /// run sequentially when set (see above).
+  pub temp_dir: Option<PathBuf>,
+  pub action: LeakAction,
+  snapshots: Mutex<HashMap<String, Snapshot>>,
+}
+
+#[derive(Debug)]
+struct Snapshot {
+  fd_count: Option<usize>,
+  temp_entries: BTreeSet<OsString>,
+}
+
+impl LeakDetector {
+  fn snapshot(&self) -> Snapshot {
+    Snapshot {
+      fd_count: if self.check_fds {
+        open_fd_count()
+      } else {
+        None
+      },
+      temp_entries: self
+        .temp_dir
+        .as_ref()
+        .map(|dir| dir_entries(dir))
+        .unwrap_or_default(),
+    }
+  }
+
+  fn find_leaks(&self, before: &Snapshot) -> Vec<String> {
+    let after = self.snapshot();
+    let mut leaks = Vec::new();
+    if let (Some(before), Some(after)) = (before.fd_count, after.fd_count) {
+      if after > before {
+        leaks.push(format!("{} file descriptor(s)", after - before));
+      }
+    }
+    if let Some(temp_dir) = &self.temp_dir {
+      for name in after.temp_entries.difference(&before.temp_entries) {
+        leaks.push(format!("temp file {}", temp_dir.join(name).display()));
+      }
+    }
+    leaks
+  }
+}
+
+impl<TData> TestHooks<TData> for LeakDetector {
+  fn before_test(&self, test: &CollectedTest<TData>) {
+    let snapshot = self.snapshot();
+    self.snapshots.lock().insert(test.name.clone(), snapshot);
+  }
+
+  fn after_test(&self, test: &CollectedTest<TData>, result: &mut TestResult) {
+    let Some(before) = self.snapshots.lock().remove(&test.name) else {
+      return;
+    };
+    let leaks = self.find_leaks(&before);
+    if leaks.is_empty() {
+      return;
+    }
+    let message = format!("Test leaked {}.", leaks.join(", "));
+    match self.action {
+      LeakAction::Warn => {
+        eprintln!(
+          "{}: {} ({})",
+          colors::yellow_bold("warning"),
+          message,
+          test.name
+        );
+      }
+      LeakAction::Fail => {
+        if result.output_mut().is_none() {
+          *result = TestResult::failed(Vec::new());
+        }
+        let output = result.output_mut().unwrap();
+        if !output.is_empty() {
+          output.push(b'\n');
+        }
+        output.extend(message.into_bytes());
+      }
+    }
+  }
+}
+
+fn open_fd_count() -> Option<usize> {
+  let dir = if cfg!(target_os = "linux") {
+    "/proc/self/fd"
+  } else if cfg!(target_os = "macos") {
+    "/dev/fd"
+  } else {
+    return None;
+  };
+  Some(std::fs::read_dir(dir).ok()?.count())
+}
+
+fn dir_entries(dir: &std::path::Path) -> BTreeSet<OsString> {
+  match std::fs::read_dir(dir) {
+    Ok(entries) => entries
+      .filter_map(|entry| Some(entry.ok()?.file_name()))
+      .collect(),
+    Err(_) => BTreeSet::new(),
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn detects_leaks() {
+    let dir = std::env::temp_dir()
+      .join(format!("file_test_runner_leaks_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    // file descriptors aren't checked and the temp directory is only used
+    // by this test because other tests run in parallel
+    let detector = LeakDetector {
+      temp_dir: Some(dir.clone()),
+      action: LeakAction::Fail,
+      ..Default::default()
+    };
+    let test = CollectedTest::new("specs::a", "a", ());
+
+    detector.before_test(&test);
+    let mut result = TestResult::Passed;
+    detector.after_test(&test, &mut result);
+    assert!(!result.is_failed());
+
+    detector.before_test(&test);
+    std::fs::write(dir.join("leaked.txt"), "").unwrap();
+    let mut result = TestResult::Passed;
+    detector.after_test(&test, &mut result);
+    let TestResult::Failed { output, .. } = result else {
+      unreachable!();
+    };
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("leaked.txt"), "{}", output);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+}
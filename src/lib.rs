@@ -1,15 +1,65 @@
 // Copyright 2018-2024 the Deno authors. MIT license.
 
+mod assertions;
+mod bazel;
+mod cache;
+mod capabilities;
 pub mod collection;
+mod environment;
+mod exit_code;
+pub mod fixtures;
+mod handle;
+pub mod history;
+mod hooks;
+mod leaks;
+mod list;
+mod middleware;
+mod output;
+mod process;
+mod quarantine;
+pub mod reporter;
 mod runner;
+mod seed;
+pub mod spec;
+mod stacks;
+pub mod state;
+pub mod testing;
+mod timeouts;
+pub mod utils;
+mod verbosity;
+mod worker;
 
+pub use assertions::TestAssertions;
+pub use capabilities::Capabilities;
 use collection::CollectedTest;
+pub use environment::RunEnvironment;
+pub use exit_code::RunExitCode;
+pub use handle::spawn_tests;
+pub use handle::RunHandle;
+pub use hooks::TestHooks;
+pub use leaks::LeakAction;
+pub use leaks::LeakDetector;
+pub use middleware::NextFunc;
+pub use middleware::RerunSubTestFunc;
+pub use middleware::RetryBackoff;
+pub use middleware::RetryMiddleware;
+pub use middleware::RunMiddleware;
+pub use process::ProcessLimits;
+pub use quarantine::Quarantine;
 pub use runner::*;
+pub use seed::test_seed;
+pub use seed::SEED_ENV_VAR;
+pub use seed::TEST_SEED_ENV_VAR;
+pub use timeouts::TimeoutOverrides;
+pub use verbosity::verbosity;
+pub use verbosity::VERBOSE_ENV_VAR;
+pub use worker::WorkerOptions;
+pub use worker::WORKER_ENV_VAR;
 
 use std::path::Path;
 use std::path::PathBuf;
 
-use collection::collect_tests_or_exit;
+use collection::collect_tests_with_warnings_or_exit;
 use collection::CollectOptions;
 use thiserror::Error;
 
@@ -30,11 +80,29 @@ impl PathedIoError {
 }
 
 /// Helper function to collect and run the tests.
+///
+/// Use `run_tests` directly to get the `RunSummary` when using
+/// `OnFailure::Return`.
 pub fn collect_and_run_tests<TData: Clone + Send + 'static>(
   collect_options: CollectOptions<TData>,
-  run_options: RunOptions,
+  run_options: RunOptions<TData>,
   run_test: impl (Fn(&CollectedTest<TData>) -> TestResult) + Send + Sync + 'static,
 ) {
-  let category = collect_tests_or_exit(collect_options);
-  run_tests(&category, run_options, run_test)
+  let mut collect_options = collect_options;
+  if collect_options.on_progress.is_none() && !worker::is_worker_process() {
+    let reporter = run_options.reporter.clone();
+    collect_options.on_progress = Some(Box::new(move |progress| {
+      reporter.report_collect_progress(progress)
+    }));
+  }
+  let output = collect_tests_with_warnings_or_exit(collect_options);
+  if !worker::is_worker_process() {
+    if !output.warnings.is_empty() {
+      run_options
+        .reporter
+        .report_collect_warnings(&output.warnings);
+    }
+    run_options.reporter.report_collect_stats(&output.stats);
+  }
+  run_tests(&output.category, run_options, run_test);
 }
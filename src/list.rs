@@ -0,0 +1,250 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+//! Listing the tests via `--list`, which is what `cargo test -- --list`
+//! passes to test binaries.
+
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use crate::collection::CollectedCategoryOrTest;
+use crate::collection::CollectedTest;
+use crate::collection::CollectedTestCategory;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ListFormat {
+  /// Same format as libtest (`specs::a: test`).
+  Pretty,
+  /// Same as `Pretty` without the summary at the end.
+  Terse,
+  /// JSON array of the tests with their paths and positions, which is
+  /// useful for editor extensions and test selection scripts.
+  Json,
+}
+
+/// Gets the format to list the tests in when `--list` was provided.
+pub(crate) fn list_format_from_args() -> Option<ListFormat> {
+  parse_list_format(std::env::args().skip(1))
+}
+
+fn parse_list_format(
+  mut args: impl Iterator<Item = String>,
+) -> Option<ListFormat> {
+  let mut is_list = false;
+  let mut format = None;
+  while let Some(arg) = args.next() {
+    if arg == "--list" {
+      is_list = true;
+    } else if arg == "--format" {
+      format = args.next();
+    } else if let Some(value) = arg.strip_prefix("--format=") {
+      format = Some(value.to_string());
+    }
+  }
+  if !is_list {
+    return None;
+  }
+  Some(match format.as_deref() {
+    Some("json") => ListFormat::Json,
+    Some("terse") => ListFormat::Terse,
+    _ => ListFormat::Pretty,
+  })
+}
+
+#[derive(Serialize)]
+struct ListEntry<'a> {
+  name: &'a str,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  alias: Option<&'a str>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  display_name: Option<&'a str>,
+  path: PathBuf,
+  line: Option<u32>,
+  column: Option<u32>,
+}
+
+/// Gets the text listing the tests in the provided format.
+pub(crate) fn list_tests<TData>(
+  category: &CollectedTestCategory<TData>,
+  format: ListFormat,
+) -> String {
+  let mut tests = Vec::new();
+  collect_tests(category, &mut tests);
+  match format {
+    ListFormat::Pretty | ListFormat::Terse => {
+      let mut text = String::new();
+      for test in &tests {
+        text.push_str(&format!("{}: test\n", test.name));
+      }
+      if format == ListFormat::Pretty {
+        text.push_str(&format!("\n{} tests, 0 benchmarks\n", tests.len()));
+      }
+      text
+    }
+    ListFormat::Json => {
+      let entries = tests
+        .iter()
+        .map(|test| ListEntry {
+          name: &test.name,
+          alias: test.alias.as_deref(),
+          display_name: test.display_name.as_deref(),
+          path: std::path::absolute(&test.path)
+            .unwrap_or_else(|_| test.path.clone()),
+          line: test.line_and_column.map(|(line, _)| line),
+          column: test.line_and_column.map(|(_, column)| column),
+        })
+        .collect::<Vec<_>>();
+      format!("{}\n", serde_json::to_string(&entries).unwrap())
+    }
+  }
+}
+
+fn collect_tests<'a, TData>(
+  category: &'a CollectedTestCategory<TData>,
+  tests: &mut Vec<&'a CollectedTest<TData>>,
+) {
+  for child in &category.children {
+    match child {
+      CollectedCategoryOrTest::Category(c) => collect_tests(c, tests),
+      CollectedCategoryOrTest::Test(t) => tests.push(t),
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn args(args: &[&str]) -> impl Iterator<Item = String> {
+    args
+      .iter()
+      .map(|arg| arg.to_string())
+      .collect::<Vec<_>>()
+      .into_iter()
+  }
+
+  #[test]
+  fn parses_list_format() {
+    assert_eq!(parse_list_format(args(&["lsp"])), None);
+    assert_eq!(
+      parse_list_format(args(&["--list"])),
+      Some(ListFormat::Pretty)
+    );
+    assert_eq!(
+      parse_list_format(args(&["lsp", "--list", "--format", "json"])),
+      Some(ListFormat::Json)
+    );
+    assert_eq!(
+      parse_list_format(args(&["--format=terse", "--list"])),
+      Some(ListFormat::Terse)
+    );
+  }
+
+  #[test]
+  fn lists_tests() {
+    let test = |name: &str, line_and_column| {
+      let mut test =
+        CollectedTest::new(name, std::env::temp_dir().join("a.rs"), ());
+      test.line_and_column = line_and_column;
+      CollectedCategoryOrTest::Test(test)
+    };
+    let category = CollectedTestCategory {
+      name: "specs".to_string(),
+      path: std::env::temp_dir(),
+      description: None,
+      children: vec![
+        test("specs::a", Some((3, 1))),
+        CollectedCategoryOrTest::Category(CollectedTestCategory {
+          name: "specs::sub".to_string(),
+          path: std::env::temp_dir(),
+          description: None,
+          children: vec![test("specs::sub::b", None)],
+        }),
+      ],
+    };
+    assert_eq!(
+      list_tests(&category, ListFormat::Pretty),
+      "specs::a: test\nspecs::sub::b: test\n\n2 tests, 0 benchmarks\n"
+    );
+    assert_eq!(
+      list_tests(&category, ListFormat::Terse),
+      "specs::a: test\nspecs::sub::b: test\n"
+    );
+    let path = std::env::temp_dir().join("a.rs");
+    assert_eq!(
+      serde_json::from_str::<serde_json::Value>(&list_tests(
+        &category,
+        ListFormat::Json
+      ))
+      .unwrap(),
+      serde_json::json!([
+        { "name": "specs::a", "path": path, "line": 3, "column": 1 },
+        { "name": "specs::sub::b", "path": path, "line": null, "column": null },
+      ])
+    );
+  }
+
+  #[test]
+  fn lists_aliases() {
+    let path = std::env::temp_dir().join("a.rs");
+    let mut aliased = CollectedTest::new("specs::a", path.clone(), ());
+    aliased.alias = Some("spec-42".to_string());
+    let category = CollectedTestCategory {
+      name: "specs".to_string(),
+      path: std::env::temp_dir(),
+      description: None,
+      children: vec![
+        CollectedCategoryOrTest::Test(aliased),
+        CollectedCategoryOrTest::Test(CollectedTest::new(
+          "specs::b",
+          path.clone(),
+          (),
+        )),
+      ],
+    };
+    assert_eq!(
+      serde_json::from_str::<serde_json::Value>(&list_tests(
+        &category,
+        ListFormat::Json
+      ))
+      .unwrap(),
+      serde_json::json!([
+        {
+          "name": "specs::a",
+          "alias": "spec-42",
+          "path": path,
+          "line": null,
+          "column": null,
+        },
+        { "name": "specs::b", "path": path, "line": null, "column": null },
+      ])
+    );
+  }
+
+  #[test]
+  fn lists_display_names() {
+    let path = std::env::temp_dir().join("my-test.rs");
+    let mut test = CollectedTest::new("specs::my_test", path.clone(), ());
+    test.display_name = Some("my-test".to_string());
+    let category = CollectedTestCategory {
+      name: "specs".to_string(),
+      path: std::env::temp_dir(),
+      description: None,
+      children: vec![CollectedCategoryOrTest::Test(test)],
+    };
+    assert_eq!(
+      serde_json::from_str::<serde_json::Value>(&list_tests(
+        &category,
+        ListFormat::Json
+      ))
+      .unwrap(),
+      serde_json::json!([{
+        "name": "specs::my_test",
+        "display_name": "my-test",
+        "path": path,
+        "line": null,
+        "column": null,
+      }])
+    );
+  }
+}
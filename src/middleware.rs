@@ -0,0 +1,332 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::collection::CollectedTest;
+use crate::SubTestResult;
+use crate::TestResult;
+
+/// Runs the rest of the middleware chain and the test.
+pub type NextFunc<'a, TData> = &'a dyn Fn(&CollectedTest<TData>) -> TestResult;
+
+/// Layer that wraps the running of each test, which allows packaging
+/// cross-cutting concerns such as timing, environment setup, or retrying
+/// as reusable middleware.
+///
+/// Closures can be used directly via `RunOptions::wrap`.
+pub trait RunMiddleware<TData = ()>: Send + Sync {
+  /// Runs the test by calling `next` any number of times.
+  fn run(
+    &self,
+    next: NextFunc<'_, TData>,
+    test: &CollectedTest<TData>,
+  ) -> TestResult;
+}
+
+pub(crate) struct FnMiddleware<TFunc>(pub TFunc);
+
+impl<TData, TFunc> RunMiddleware<TData> for FnMiddleware<TFunc>
+where
+  TFunc:
+    Fn(NextFunc<'_, TData>, &CollectedTest<TData>) -> TestResult + Send + Sync,
+{
+  fn run(
+    &self,
+    next: NextFunc<'_, TData>,
+    test: &CollectedTest<TData>,
+  ) -> TestResult {
+    (self.0)(next, test)
+  }
+}
+
+/// Delay before retrying a failed test.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RetryBackoff {
+  /// Retry immediately.
+  #[default]
+  None,
+  /// Wait the same duration before each retry.
+  Fixed(Duration),
+  /// Double the delay after each retry, up to `max`.
+  Exponential { initial: Duration, max: Duration },
+}
+
+impl RetryBackoff {
+  /// Gets the delay before the provided retry, starting at zero.
+  pub fn delay(&self, retry: usize) -> Duration {
+    match *self {
+      RetryBackoff::None => Duration::ZERO,
+      RetryBackoff::Fixed(delay) => delay,
+      RetryBackoff::Exponential { initial, max } => {
+        let factor = 1u32.checked_shl(retry as u32).unwrap_or(u32::MAX);
+        initial.saturating_mul(factor).min(max)
+      }
+    }
+  }
+}
+
+/// Runs a single sub test of a test again. See
+/// `RetryMiddleware::rerun_sub_test`.
+pub type RerunSubTestFunc<TData> =
+  Arc<dyn (Fn(&CollectedTest<TData>, &str) -> TestResult) + Send + Sync>;
+
+/// Middleware that retries failed tests, which is useful for tests that
+/// depend on the network.
+pub struct RetryMiddleware<TData = ()> {
+  /// Number of times to retry a failed test.
+  pub retries: usize,
+  pub backoff: RetryBackoff,
+  /// Randomize each delay between zero and the backoff's delay, which
+  /// spreads out the retries of tests that failed in a burst.
+  pub jitter: bool,
+  /// Runs a sub test again by its name. When set, only the failed sub tests
+  /// of a test with sub tests are retried instead of the whole test, and
+  /// each keeps the result of its last attempt (ex. a sub test may be
+  /// ignored on a retry).
+  pub rerun_sub_test: Option<RerunSubTestFunc<TData>>,
+}
+
+impl<TData> Default for RetryMiddleware<TData> {
+  fn default() -> Self {
+    Self {
+      retries: 0,
+      backoff: RetryBackoff::default(),
+      jitter: false,
+      rerun_sub_test: None,
+    }
+  }
+}
+
+impl<TData> Clone for RetryMiddleware<TData> {
+  fn clone(&self) -> Self {
+    Self {
+      retries: self.retries,
+      backoff: self.backoff,
+      jitter: self.jitter,
+      rerun_sub_test: self.rerun_sub_test.clone(),
+    }
+  }
+}
+
+impl<TData> std::fmt::Debug for RetryMiddleware<TData> {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("RetryMiddleware")
+      .field("retries", &self.retries)
+      .field("backoff", &self.backoff)
+      .field("jitter", &self.jitter)
+      .field("rerun_sub_test", &self.rerun_sub_test.is_some())
+      .finish()
+  }
+}
+
+impl<TData> RetryMiddleware<TData> {
+  fn delay(&self, retry: usize) -> Duration {
+    let delay = self.backoff.delay(retry);
+    if self.jitter && !delay.is_zero() {
+      let random = RandomState::new().hash_one(retry);
+      delay.mul_f64((random % 1000) as f64 / 1000.0)
+    } else {
+      delay
+    }
+  }
+}
+
+impl<TData> RunMiddleware<TData> for RetryMiddleware<TData> {
+  fn run(
+    &self,
+    next: NextFunc<'_, TData>,
+    test: &CollectedTest<TData>,
+  ) -> TestResult {
+    let mut result = next(test);
+    for retry in 0..self.retries {
+      if !result.is_failed() {
+        break;
+      }
+      std::thread::sleep(self.delay(retry));
+      result = match (&self.rerun_sub_test, result) {
+        (Some(rerun), TestResult::SubTests(sub_tests)) => {
+          TestResult::SubTests(rerun_failed_sub_tests(sub_tests, |name| {
+            rerun(test, name)
+          }))
+        }
+        _ => next(test),
+      };
+    }
+    result
+  }
+}
+
+fn rerun_failed_sub_tests(
+  sub_tests: Vec<SubTestResult>,
+  rerun: impl Fn(&str) -> TestResult,
+) -> Vec<SubTestResult> {
+  sub_tests
+    .into_iter()
+    .map(|sub_test| {
+      if sub_test.result.is_failed() {
+        SubTestResult {
+          result: rerun(&sub_test.name),
+          name: sub_test.name,
+        }
+      } else {
+        sub_test
+      }
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+  use std::path::PathBuf;
+
+  use parking_lot::Mutex;
+
+  use super::*;
+  use crate::collection::CollectedCategoryOrTest;
+  use crate::collection::CollectedTestCategory;
+  use crate::run_tests;
+  use crate::RunOptions;
+
+  #[test]
+  fn wraps_in_order() {
+    let category = CollectedTestCategory {
+      name: "specs".to_string(),
+      path: PathBuf::from("specs"),
+      description: None,
+      children: vec![CollectedCategoryOrTest::Test(CollectedTest::new(
+        "specs::a",
+        "a",
+        (),
+      ))],
+    };
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let options = RunOptions::default()
+      .wrap({
+        let calls = calls.clone();
+        move |next, test| {
+          calls.lock().push("inner");
+          // retry once
+          match next(test) {
+            TestResult::Failed { .. } => next(test),
+            result => result,
+          }
+        }
+      })
+      .wrap({
+        let calls = calls.clone();
+        move |next, test| {
+          calls.lock().push("outer");
+          next(test)
+        }
+      });
+    let attempts = Arc::new(Mutex::new(0));
+    run_tests(&category, options, {
+      let calls = calls.clone();
+      let attempts = attempts.clone();
+      move |_| {
+        calls.lock().push("test");
+        let mut attempts = attempts.lock();
+        *attempts += 1;
+        if *attempts == 1 {
+          TestResult::failed(Vec::new())
+        } else {
+          TestResult::Passed
+        }
+      }
+    });
+    assert_eq!(*calls.lock(), vec!["outer", "inner", "test", "test"]);
+  }
+
+  #[test]
+  fn retries_with_backoff() {
+    let backoff = RetryBackoff::Exponential {
+      initial: Duration::from_millis(100),
+      max: Duration::from_millis(500),
+    };
+    let delays = (0..4).map(|retry| backoff.delay(retry).as_millis());
+    assert_eq!(delays.collect::<Vec<_>>(), vec![100, 200, 400, 500]);
+    assert_eq!(backoff.delay(100), Duration::from_millis(500));
+    assert_eq!(RetryBackoff::None.delay(3), Duration::ZERO);
+
+    let middleware = RetryMiddleware {
+      retries: 2,
+      backoff: RetryBackoff::Fixed(Duration::from_millis(10)),
+      jitter: true,
+      rerun_sub_test: None,
+    };
+    for retry in 0..10 {
+      assert!(middleware.delay(retry) <= Duration::from_millis(10));
+    }
+    let test = CollectedTest::new("specs::a", "a", ());
+    let attempts = Mutex::new(0);
+    let result = middleware.run(
+      &|_| {
+        *attempts.lock() += 1;
+        TestResult::failed(Vec::new())
+      },
+      &test,
+    );
+    assert!(result.is_failed());
+    assert_eq!(*attempts.lock(), 3);
+  }
+
+  #[test]
+  fn retries_failed_sub_tests() {
+    let sub_test = |name: &str, result: TestResult| SubTestResult {
+      name: name.to_string(),
+      result,
+    };
+    let failed = || TestResult::failed(Vec::new());
+    let reruns = Arc::new(Mutex::new(Vec::new()));
+    let middleware = RetryMiddleware {
+      retries: 2,
+      rerun_sub_test: Some(Arc::new({
+        let reruns = reruns.clone();
+        move |_: &CollectedTest, name: &str| {
+          let mut reruns = reruns.lock();
+          reruns.push(name.to_string());
+          match name {
+            "step2" => TestResult::Passed,
+            "step3" if reruns.len() < 3 => failed(),
+            _ => TestResult::Ignored,
+          }
+        }
+      })),
+      ..Default::default()
+    };
+    let test = CollectedTest::new("specs::a", "a", ());
+    let attempts = Mutex::new(0);
+    let result = middleware.run(
+      &|_| {
+        *attempts.lock() += 1;
+        TestResult::SubTests(vec![
+          sub_test("step1", TestResult::Passed),
+          sub_test("step2", failed()),
+          sub_test("step3", failed()),
+        ])
+      },
+      &test,
+    );
+    // the whole test only ran once
+    assert_eq!(*attempts.lock(), 1);
+    assert_eq!(*reruns.lock(), vec!["step2", "step3", "step3"]);
+    let TestResult::SubTests(sub_tests) = result else {
+      unreachable!();
+    };
+    let results = sub_tests
+      .iter()
+      .map(|s| (s.name.as_str(), format!("{:?}", s.result)))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      results,
+      vec![
+        ("step1", "Passed".to_string()),
+        ("step2", "Passed".to_string()),
+        ("step3", "Ignored".to_string()),
+      ]
+    );
+  }
+}
@@ -0,0 +1,97 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::collections::VecDeque;
+
+/// Captured output that stops growing once it exceeds its maximum size,
+/// which protects the runner from tests that log gigabytes in a tight
+/// loop. The head and tail of the output are kept because they're
+/// usually the most useful parts.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct CappedOutput {
+  max_size: Option<usize>,
+  head: Vec<u8>,
+  tail: VecDeque<u8>,
+  dropped: u64,
+}
+
+impl CappedOutput {
+  pub fn new(max_size: Option<usize>) -> Self {
+    Self {
+      max_size,
+      ..Default::default()
+    }
+  }
+
+  pub fn extend(&mut self, bytes: &[u8]) {
+    let Some(max_size) = self.max_size else {
+      self.head.extend(bytes);
+      return;
+    };
+    let head_size = max_size / 2;
+    let tail_size = max_size - head_size;
+    let to_head = bytes.len().min(head_size.saturating_sub(self.head.len()));
+    self.head.extend(&bytes[..to_head]);
+    let bytes = &bytes[to_head..];
+    if bytes.is_empty() {
+      return;
+    }
+    // keep the last bytes that fit in the tail
+    let to_tail = &bytes[bytes.len().saturating_sub(tail_size)..];
+    self.dropped += (bytes.len() - to_tail.len()) as u64;
+    let overflow = (self.tail.len() + to_tail.len()).saturating_sub(tail_size);
+    self.dropped += overflow as u64;
+    self.tail.drain(..overflow);
+    self.tail.extend(to_tail);
+  }
+
+  /// Gets the output, with a notice in place of any dropped output.
+  pub fn to_bytes(&self) -> Vec<u8> {
+    let mut bytes = self.head.clone();
+    if self.dropped > 0 {
+      if !bytes.ends_with(b"\n") {
+        bytes.push(b'\n');
+      }
+      bytes.extend(
+        format!(
+          "... {} bytes of output were dropped for exceeding the maximum output size ...\n",
+          self.dropped
+        )
+        .into_bytes(),
+      );
+    }
+    bytes.extend(&self.tail);
+    bytes
+  }
+
+  pub fn into_bytes(self) -> Vec<u8> {
+    if self.dropped == 0 && self.tail.is_empty() {
+      return self.head;
+    }
+    self.to_bytes()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn capped_output() {
+    let mut output = CappedOutput::new(None);
+    output.extend(b"hello ");
+    output.extend(b"world");
+    assert_eq!(output.into_bytes(), b"hello world");
+
+    let mut output = CappedOutput::new(Some(8));
+    output.extend(b"abc");
+    assert_eq!(output.to_bytes(), b"abc");
+    output.extend(b"defgh");
+    assert_eq!(output.to_bytes(), b"abcdefgh");
+    output.extend(b"ijklmnop");
+    output.extend(b"qr");
+    assert_eq!(
+      String::from_utf8(output.into_bytes()).unwrap(),
+      "abcd\n... 10 bytes of output were dropped for exceeding the maximum output size ...\nopqr"
+    );
+  }
+}
@@ -0,0 +1,139 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+//! Settings applied to spawned test processes.
+
+use std::process::Command;
+
+/// Resource limits and scheduling priority applied to spawned test
+/// processes, which prevents a single runaway test from exhausting the
+/// machine and taking out unrelated tests running in parallel.
+///
+/// Only supported on Unix and ignored on other platforms.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProcessLimits {
+  /// Maximum size of the virtual memory in bytes (`RLIMIT_AS`).
+  pub max_memory: Option<u64>,
+  /// Maximum number of open file descriptors (`RLIMIT_NOFILE`).
+  pub max_open_files: Option<u64>,
+  /// Maximum size of core dumps in bytes (`RLIMIT_CORE`), where `0`
+  /// disables them.
+  pub max_core_dump_size: Option<u64>,
+  /// Niceness to run the process with, where higher values have a
+  /// lower priority.
+  pub niceness: Option<i32>,
+}
+
+impl ProcessLimits {
+  /// Applies the limits to the process spawned by the command.
+  pub(crate) fn apply(&self, command: &mut Command) {
+    if *self == Self::default() {
+      return;
+    }
+    #[cfg(unix)]
+    {
+      use std::os::unix::process::CommandExt;
+
+      let limits = [
+        (libc::RLIMIT_AS, self.max_memory),
+        (libc::RLIMIT_NOFILE, self.max_open_files),
+        (libc::RLIMIT_CORE, self.max_core_dump_size),
+      ];
+      let niceness = self.niceness;
+      // SAFETY: only calls setrlimit and setpriority, which are
+      // async-signal-safe
+      unsafe {
+        command.pre_exec(move || {
+          for (resource, value) in limits {
+            let Some(value) = value else {
+              continue;
+            };
+            let limit = libc::rlimit {
+              rlim_cur: value as libc::rlim_t,
+              rlim_max: value as libc::rlim_t,
+            };
+            if libc::setrlimit(resource, &limit) != 0 {
+              return Err(std::io::Error::last_os_error());
+            }
+          }
+          if let Some(niceness) = niceness {
+            if libc::setpriority(libc::PRIO_PROCESS, 0, niceness) != 0 {
+              return Err(std::io::Error::last_os_error());
+            }
+          }
+          Ok(())
+        });
+      }
+    }
+    #[cfg(not(unix))]
+    {
+      let _ = command;
+    }
+  }
+}
+
+/// Restricts the process spawned by the command to the provided CPU
+/// cores (zero-based indexes), which reduces the variance of timing
+/// sensitive tests.
+///
+/// This is only supported on Linux and does nothing on other platforms.
+pub(crate) fn set_cpu_affinity(command: &mut Command, cores: &[usize]) {
+  #[cfg(target_os = "linux")]
+  {
+    use std::os::unix::process::CommandExt;
+
+    // SAFETY: cpu_set_t is a plain bit set, so zeroed is valid
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    let max_cores = std::mem::size_of::<libc::cpu_set_t>() * 8;
+    for core in cores.iter().filter(|core| **core < max_cores) {
+      // SAFETY: the core is within the bounds of the set
+      unsafe { libc::CPU_SET(*core, &mut set) };
+    }
+    // SAFETY: only calls sched_setaffinity, which is async-signal-safe
+    unsafe {
+      command.pre_exec(move || {
+        let size = std::mem::size_of::<libc::cpu_set_t>();
+        if libc::sched_setaffinity(0, size, &set) != 0 {
+          return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+      });
+    }
+  }
+  #[cfg(not(target_os = "linux"))]
+  {
+    let _ = (command, cores);
+  }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod test {
+  use super::*;
+
+  #[test]
+  fn cpu_affinity() {
+    let mut command = Command::new("sh");
+    command.args(["-c", "grep Cpus_allowed_list /proc/self/status"]);
+    set_cpu_affinity(&mut command, &[0]);
+    let output = command.output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.split_whitespace().last(), Some("0"));
+  }
+
+  #[test]
+  fn process_limits() {
+    let mut command = Command::new("sh");
+    command.args(["-c", "ulimit -v; ulimit -n; ulimit -c; nice"]);
+    ProcessLimits {
+      max_memory: Some(1024 * 1024 * 1024),
+      max_open_files: Some(64),
+      max_core_dump_size: Some(0),
+      niceness: Some(5),
+    }
+    .apply(&mut command);
+    let output = command.output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, "1048576\n64\n0\n5\n");
+  }
+}
@@ -0,0 +1,96 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::path::Path;
+
+use regex::Regex;
+
+use crate::PathedIoError;
+
+/// Tests whose failures don't fail the run (ex. known flaky tests or an
+/// imported suite during bring-up). They're still run and reported, but
+/// their failures are absorbed by the first pattern matching their name.
+///
+/// Patterns are glob patterns over the fully resolved test names, where
+/// `*` matches any characters (ex. `specs::node_compat::*`) and a name
+/// without wildcards matches exactly, or regexes between slashes (ex.
+/// `/^specs::npm::.*_flaky$/`).
+#[derive(Debug, Clone, Default)]
+pub struct Quarantine {
+  patterns: Vec<(String, Regex)>,
+}
+
+impl Quarantine {
+  /// Loads the patterns from a JSON file with an array of patterns:
+  ///
+  /// ```json
+  /// ["specs::node_compat::*", "specs::run::flaky_test"]
+  /// ```
+  pub fn from_file(path: &Path) -> Result<Self, anyhow::Error> {
+    let text = std::fs::read_to_string(path)
+      .map_err(|err| PathedIoError::new(path, err))?;
+    let patterns: Vec<String> = serde_json::from_str(&text).map_err(|err| {
+      anyhow::anyhow!("Failed to parse '{}': {:#}", path.display(), err)
+    })?;
+    let mut quarantine = Self::default();
+    for pattern in patterns {
+      quarantine.add(&pattern)?;
+    }
+    Ok(quarantine)
+  }
+
+  /// Adds a glob pattern or a regex between slashes.
+  pub fn add(&mut self, pattern: &str) -> Result<(), regex::Error> {
+    let regex = match pattern
+      .strip_prefix('/')
+      .and_then(|pattern| pattern.strip_suffix('/'))
+    {
+      Some(regex) => Regex::new(regex)?,
+      None => Regex::new(&format!(
+        "^{}$",
+        pattern
+          .split('*')
+          .map(regex::escape)
+          .collect::<Vec<_>>()
+          .join(".*")
+      ))?,
+    };
+    self.patterns.push((pattern.to_string(), regex));
+    Ok(())
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.patterns.is_empty()
+  }
+
+  pub fn patterns(&self) -> impl Iterator<Item = &str> {
+    self.patterns.iter().map(|(pattern, _)| pattern.as_str())
+  }
+
+  /// Gets the index of the first pattern matching the test's name.
+  pub(crate) fn find(&self, test_name: &str) -> Option<usize> {
+    self
+      .patterns
+      .iter()
+      .position(|(_, regex)| regex.is_match(test_name))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn matches_patterns() {
+    let mut quarantine = Quarantine::default();
+    quarantine.add("specs::node_compat::*").unwrap();
+    quarantine.add("specs::run::flaky").unwrap();
+    quarantine.add("/^specs::npm::.*_flaky$/").unwrap();
+    assert_eq!(quarantine.find("specs::node_compat::fs::read"), Some(0));
+    assert_eq!(quarantine.find("specs::run::flaky"), Some(1));
+    assert_eq!(quarantine.find("specs::run::flaky_2"), None);
+    assert_eq!(quarantine.find("specs::npm::install_flaky"), Some(2));
+    assert_eq!(quarantine.find("specs::npm::install"), None);
+    assert_eq!(quarantine.find("specs::node_compat"), None);
+    assert!(quarantine.add("/[z-a]/").is_err());
+  }
+}
@@ -0,0 +1,440 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use crate::collection::CollectedTest;
+use crate::collection::CollectedTestCategory;
+use crate::FailureValue;
+use crate::PathedIoError;
+use crate::RunEnvironment;
+use crate::SubTestResult;
+use crate::TestResult;
+
+use super::CategoryTiming;
+use super::Reporter;
+use super::ReporterContext;
+use super::ReporterFailure;
+
+/// Version of the event stream's schema. This is only bumped when an
+/// event changes in a way that is not backwards compatible.
+pub const EVENT_SCHEMA_VERSION: u32 = 1;
+
+/// JSON schema of the events written by the `EventStreamReporter`.
+pub const EVENT_SCHEMA: &str = include_str!("../../schemas/events.v1.json");
+
+/// Reporter that writes newline delimited JSON events, which is useful for
+/// editor extensions that want to map results back to the test files.
+///
+/// Every test event includes the fully resolved name, the absolute path of
+/// the test file, and the line and column of the test within that file
+/// when known.
+///
+/// This reporter is enabled in addition to the configured reporter when
+/// the `FILE_TEST_RUNNER_EVENTS` environment variable is set to a file path.
+///
+/// Each event has a `"schema_version"` field. The schema is described by
+/// `EVENT_SCHEMA` and is only changed in a backwards compatible way (ex.
+/// adding fields or event types) without bumping `EVENT_SCHEMA_VERSION`.
+pub struct EventStreamReporter {
+  writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl EventStreamReporter {
+  pub fn new(writer: Box<dyn Write + Send>) -> Self {
+    Self {
+      writer: Mutex::new(writer),
+    }
+  }
+
+  /// Creates the reporter from the `FILE_TEST_RUNNER_EVENTS`
+  /// environment variable when it is set.
+  pub fn from_env() -> Result<Option<Self>, PathedIoError> {
+    let Some(path) = std::env::var_os("FILE_TEST_RUNNER_EVENTS")
+      .filter(|v| !v.is_empty())
+      .map(PathBuf::from)
+    else {
+      return Ok(None);
+    };
+    let file = std::fs::File::create(&path)
+      .map_err(|err| PathedIoError::new(&path, err))?;
+    Ok(Some(Self::new(Box::new(std::io::BufWriter::new(file)))))
+  }
+
+  fn write_event(&self, event: &Event) {
+    let mut writer = self.writer.lock();
+    // ignore errors because an editor closing the stream
+    // should not cause the tests to fail
+    let _ = serde_json::to_writer(
+      &mut *writer,
+      &VersionedEvent {
+        schema_version: EVENT_SCHEMA_VERSION,
+        event,
+      },
+    );
+    let _ = writer.write_all(b"\n");
+    let _ = writer.flush();
+  }
+}
+
+#[derive(Serialize)]
+struct VersionedEvent<'a> {
+  schema_version: u32,
+  #[serde(flatten)]
+  event: &'a Event<'a>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Event<'a> {
+  RunStart {
+    environment: &'a RunEnvironment,
+  },
+  CategoryStart {
+    name: &'a str,
+    path: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<&'a str>,
+  },
+  CategoryEnd {
+    name: &'a str,
+    path: PathBuf,
+  },
+  TestStart {
+    #[serde(flatten)]
+    test: TestInfo<'a>,
+  },
+  TestEnd {
+    #[serde(flatten)]
+    test: TestInfo<'a>,
+    duration_ms: u64,
+    #[serde(flatten)]
+    result: ResultInfo<'a>,
+  },
+  CategoryTimings {
+    categories: Vec<CategoryTimingInfo<'a>>,
+  },
+  RunAborted {
+    not_run: usize,
+  },
+  RunEnd {
+    total: usize,
+    failed: usize,
+  },
+}
+
+#[derive(Serialize)]
+struct CategoryTimingInfo<'a> {
+  name: &'a str,
+  path: PathBuf,
+  test_count: usize,
+  total_ms: u64,
+  average_ms: u64,
+}
+
+#[derive(Serialize)]
+struct TestInfo<'a> {
+  name: &'a str,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  alias: Option<&'a str>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  display_name: Option<&'a str>,
+  path: PathBuf,
+  line: Option<u32>,
+  column: Option<u32>,
+}
+
+impl<'a> TestInfo<'a> {
+  fn new<TData>(test: &'a CollectedTest<TData>) -> Self {
+    Self {
+      name: &test.name,
+      alias: test.alias.as_deref(),
+      display_name: test.display_name.as_deref(),
+      path: absolute_path(&test.path),
+      line: test.line_and_column.map(|(line, _)| line),
+      column: test.line_and_column.map(|(_, column)| column),
+    }
+  }
+}
+
+#[derive(Serialize)]
+struct ResultInfo<'a> {
+  result: &'static str,
+  /// Why the test was ignored without running it.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  ignore_reason: Option<&'a str>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  output: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  fields: Option<&'a BTreeMap<String, FailureValue>>,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  sub_tests: Vec<SubTestInfo<'a>>,
+}
+
+impl<'a> ResultInfo<'a> {
+  fn new(result: &'a TestResult) -> Self {
+    let kind = if result.is_failed() {
+      "failed"
+    } else if matches!(result, TestResult::Ignored) {
+      "ignored"
+    } else {
+      "passed"
+    };
+    Self {
+      result: kind,
+      ignore_reason: None,
+      output: match result {
+        TestResult::Failed { output }
+        | TestResult::FailedWithFields { output, .. } => {
+          Some(String::from_utf8_lossy(output).into_owned())
+        }
+        _ => None,
+      },
+      fields: match result {
+        TestResult::FailedWithFields { fields, .. } if !fields.is_empty() => {
+          Some(fields)
+        }
+        _ => None,
+      },
+      sub_tests: match result {
+        TestResult::SubTests(sub_tests) => {
+          sub_tests.iter().map(SubTestInfo::new).collect()
+        }
+        _ => Vec::new(),
+      },
+    }
+  }
+}
+
+#[derive(Serialize)]
+struct SubTestInfo<'a> {
+  name: &'a str,
+  #[serde(flatten)]
+  result: ResultInfo<'a>,
+}
+
+impl<'a> SubTestInfo<'a> {
+  fn new(sub_test: &'a SubTestResult) -> Self {
+    Self {
+      name: &sub_test.name,
+      result: ResultInfo::new(&sub_test.result),
+    }
+  }
+}
+
+fn absolute_path(path: &Path) -> PathBuf {
+  std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+impl<TData> Reporter<TData> for EventStreamReporter {
+  fn report_run_environment(&self, environment: &RunEnvironment) {
+    self.write_event(&Event::RunStart { environment });
+  }
+
+  fn report_category_start(
+    &self,
+    category: &CollectedTestCategory<TData>,
+    _context: &ReporterContext,
+  ) {
+    self.write_event(&Event::CategoryStart {
+      name: &category.name,
+      path: absolute_path(&category.path),
+      description: category.description.as_deref(),
+    });
+  }
+
+  fn report_category_end(
+    &self,
+    category: &CollectedTestCategory<TData>,
+    _context: &ReporterContext,
+  ) {
+    self.write_event(&Event::CategoryEnd {
+      name: &category.name,
+      path: absolute_path(&category.path),
+    });
+  }
+
+  fn report_test_start(
+    &self,
+    test: &CollectedTest<TData>,
+    _context: &ReporterContext,
+  ) {
+    self.write_event(&Event::TestStart {
+      test: TestInfo::new(test),
+    });
+  }
+
+  fn report_test_end(
+    &self,
+    test: &CollectedTest<TData>,
+    duration: Duration,
+    result: &TestResult,
+    _context: &ReporterContext,
+  ) {
+    self.write_event(&Event::TestEnd {
+      test: TestInfo::new(test),
+      duration_ms: duration.as_millis() as u64,
+      result: ResultInfo::new(result),
+    });
+  }
+
+  fn report_test_ignored(
+    &self,
+    test: &CollectedTest<TData>,
+    reason: &str,
+    _context: &ReporterContext,
+  ) {
+    self.write_event(&Event::TestEnd {
+      test: TestInfo::new(test),
+      duration_ms: 0,
+      result: ResultInfo {
+        ignore_reason: Some(reason),
+        ..ResultInfo::new(&TestResult::Ignored)
+      },
+    });
+  }
+
+  fn report_long_running_test(&self, _test_name: &str, _elapsed: Duration) {}
+
+  fn report_category_timings(&self, timings: &[CategoryTiming]) {
+    self.write_event(&Event::CategoryTimings {
+      categories: timings
+        .iter()
+        .map(|timing| CategoryTimingInfo {
+          name: &timing.name,
+          path: absolute_path(&timing.path),
+          test_count: timing.test_count,
+          total_ms: timing.total.as_millis() as u64,
+          average_ms: timing.average().as_millis() as u64,
+        })
+        .collect(),
+    });
+  }
+
+  fn report_run_aborted(&self, not_run: usize) {
+    self.write_event(&Event::RunAborted { not_run });
+  }
+
+  fn report_failures(
+    &self,
+    failures: &[ReporterFailure<TData>],
+    total_tests: usize,
+  ) {
+    self.write_event(&Event::RunEnd {
+      total: total_tests,
+      failed: failures.len(),
+    });
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::sync::Arc;
+
+  use super::*;
+
+  #[derive(Clone, Default)]
+  struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+  impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+      self.0.lock().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+      Ok(())
+    }
+  }
+
+  #[test]
+  fn writes_test_events() {
+    let buffer = SharedBuffer::default();
+    let reporter = EventStreamReporter::new(Box::new(buffer.clone()));
+    let mut test = CollectedTest::new("specs::test", "/specs/test.md", ());
+    test.line_and_column = Some((3, 5));
+    test.alias = Some("spec-42".to_string());
+    let context = ReporterContext {
+      is_parallel: false,
+      category_name: "specs".to_string(),
+      category_test_count: 1,
+      category_total_test_count: 1,
+      run_test_count: 1,
+      verbosity: 0,
+    };
+    reporter.report_test_start(&test, &context);
+    reporter.report_test_end(
+      &test,
+      Duration::from_millis(12),
+      &TestResult::SubTests(vec![SubTestResult {
+        name: "step".to_string(),
+        result: TestResult::failed(b"error".to_vec())
+          .with_field("exit_code", 1),
+      }]),
+      &context,
+    );
+    let path = serde_json::to_string(&absolute_path(&test.path)).unwrap();
+    assert_eq!(
+      String::from_utf8(buffer.0.lock().clone()).unwrap(),
+      format!(
+        concat!(
+          "{{\"schema_version\":1,\"type\":\"test_start\",\"name\":\"specs::test\",\"alias\":\"spec-42\",\"path\":{0},\"line\":3,\"column\":5}}\n",
+          "{{\"schema_version\":1,\"type\":\"test_end\",\"name\":\"specs::test\",\"alias\":\"spec-42\",\"path\":{0},\"line\":3,\"column\":5,",
+          "\"duration_ms\":12,\"result\":\"failed\",\"sub_tests\":[",
+          "{{\"name\":\"step\",\"result\":\"failed\",\"output\":\"error\",\"fields\":{{\"exit_code\":1}}}}]}}\n",
+        ),
+        path
+      )
+    );
+
+    buffer.0.lock().clear();
+    reporter.report_test_ignored(&test, "requires network", &context);
+    let event: serde_json::Value =
+      serde_json::from_slice(&buffer.0.lock()).unwrap();
+    assert_eq!(event["result"], "ignored");
+    assert_eq!(event["ignore_reason"], "requires network");
+  }
+
+  #[test]
+  fn writes_display_name() {
+    let buffer = SharedBuffer::default();
+    let reporter = EventStreamReporter::new(Box::new(buffer.clone()));
+    let mut test =
+      CollectedTest::new("specs::my_test", "/specs/my-test.md", ());
+    let context = ReporterContext {
+      is_parallel: false,
+      category_name: "specs".to_string(),
+      category_test_count: 1,
+      category_total_test_count: 1,
+      run_test_count: 1,
+      verbosity: 0,
+    };
+    reporter.report_test_start(&test, &context);
+    let event: serde_json::Value =
+      serde_json::from_slice(&buffer.0.lock()).unwrap();
+    assert_eq!(event["name"], "specs::my_test");
+    assert!(event.get("display_name").is_none());
+
+    buffer.0.lock().clear();
+    test.display_name = Some("my-test".to_string());
+    reporter.report_test_start(&test, &context);
+    let event: serde_json::Value =
+      serde_json::from_slice(&buffer.0.lock()).unwrap();
+    assert_eq!(event["name"], "specs::my_test");
+    assert_eq!(event["display_name"], "my-test");
+  }
+
+  #[test]
+  fn schema_version_matches() {
+    let schema: serde_json::Value = serde_json::from_str(EVENT_SCHEMA).unwrap();
+    assert_eq!(
+      schema["properties"]["schema_version"]["const"],
+      EVENT_SCHEMA_VERSION
+    );
+  }
+}
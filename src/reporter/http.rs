@@ -0,0 +1,487 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::net::TcpStream;
+use std::net::ToSocketAddrs;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use std::time::Instant;
+
+use crossbeam_channel::Receiver;
+use crossbeam_channel::RecvTimeoutError;
+use crossbeam_channel::Sender;
+use deno_terminal::colors;
+use parking_lot::Mutex;
+
+use crate::collection::CollectedTest;
+use crate::collection::CollectedTestCategory;
+use crate::RetryBackoff;
+use crate::RunEnvironment;
+use crate::TestResult;
+
+use super::CategoryTiming;
+use super::EventStreamReporter;
+use super::Reporter;
+use super::ReporterContext;
+use super::ReporterFailure;
+
+/// Settings of the `HttpEventReporter`.
+#[derive(Debug, Clone)]
+pub struct HttpEventOptions {
+  /// URL to POST the events to (ex. `http://localhost:8080/events`).
+  pub endpoint: String,
+  /// Headers sent with each request (ex. an `Authorization` header).
+  pub headers: Vec<(String, String)>,
+  /// Maximum number of events sent in a request.
+  pub batch_size: usize,
+  /// How long an event may wait for more events before it's sent.
+  pub flush_interval: Duration,
+  /// Number of times a failed request is retried before its events are
+  /// dropped.
+  pub max_retries: usize,
+  pub backoff: RetryBackoff,
+  /// Timeout of connecting and of reading and writing each request.
+  pub timeout: Duration,
+}
+
+impl HttpEventOptions {
+  pub fn new(endpoint: impl Into<String>) -> Self {
+    Self {
+      endpoint: endpoint.into(),
+      headers: Vec::new(),
+      batch_size: 100,
+      flush_interval: Duration::from_secs(1),
+      max_retries: 3,
+      backoff: RetryBackoff::Exponential {
+        initial: Duration::from_millis(500),
+        max: Duration::from_secs(10),
+      },
+      timeout: Duration::from_secs(10),
+    }
+  }
+}
+
+enum Message {
+  Event(Vec<u8>),
+  Finish,
+}
+
+/// Reporter that POSTs the events of the `EventStreamReporter` to an HTTP
+/// endpoint as JSON arrays, which allows live dashboards of long runs.
+///
+/// The events are sent in batches from a background thread so a slow
+/// endpoint doesn't slow down the run. Failed requests are retried and
+/// their events dropped with a warning once the retries are exhausted,
+/// which keeps an unreachable endpoint from failing the run. The
+/// remaining events are sent before the run ends.
+///
+/// Only `http://` endpoints are supported (ex. a collector on the CI
+/// machine), which avoids depending on a TLS implementation.
+///
+/// This reporter is enabled in addition to the configured reporter when
+/// the `FILE_TEST_RUNNER_EVENTS_URL` environment variable is set.
+pub struct HttpEventReporter {
+  events: EventStreamReporter,
+  sender: Sender<Message>,
+  thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl HttpEventReporter {
+  pub fn new(options: HttpEventOptions) -> Result<Self, anyhow::Error> {
+    let endpoint = Endpoint::parse(&options.endpoint)?;
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let thread = std::thread::Builder::new()
+      .name("file_test_runner_http_events".to_string())
+      .spawn(move || send_batches(&receiver, &endpoint, &options))?;
+    Ok(Self {
+      events: EventStreamReporter::new(Box::new(EventSink {
+        sender: sender.clone(),
+        line: Vec::new(),
+      })),
+      sender,
+      thread: Mutex::new(Some(thread)),
+    })
+  }
+
+  /// Creates the reporter from the `FILE_TEST_RUNNER_EVENTS_URL`
+  /// environment variable when it is set.
+  pub fn from_env() -> Result<Option<Self>, anyhow::Error> {
+    let Some(endpoint) = std::env::var("FILE_TEST_RUNNER_EVENTS_URL")
+      .ok()
+      .filter(|v| !v.is_empty())
+    else {
+      return Ok(None);
+    };
+    Self::new(HttpEventOptions::new(endpoint)).map(Some)
+  }
+
+  /// Sends the remaining events and waits for them to be delivered.
+  fn finish(&self) {
+    let _ = self.sender.send(Message::Finish);
+    if let Some(thread) = self.thread.lock().take() {
+      let _ = thread.join();
+    }
+  }
+}
+
+impl<TData> Reporter<TData> for HttpEventReporter {
+  fn report_run_environment(&self, environment: &RunEnvironment) {
+    Reporter::<TData>::report_run_environment(&self.events, environment);
+  }
+
+  fn report_category_start(
+    &self,
+    category: &CollectedTestCategory<TData>,
+    context: &ReporterContext,
+  ) {
+    self.events.report_category_start(category, context);
+  }
+
+  fn report_category_end(
+    &self,
+    category: &CollectedTestCategory<TData>,
+    context: &ReporterContext,
+  ) {
+    self.events.report_category_end(category, context);
+  }
+
+  fn report_test_start(
+    &self,
+    test: &CollectedTest<TData>,
+    context: &ReporterContext,
+  ) {
+    self.events.report_test_start(test, context);
+  }
+
+  fn report_test_end(
+    &self,
+    test: &CollectedTest<TData>,
+    duration: Duration,
+    result: &TestResult,
+    context: &ReporterContext,
+  ) {
+    self.events.report_test_end(test, duration, result, context);
+  }
+
+  fn report_test_ignored(
+    &self,
+    test: &CollectedTest<TData>,
+    reason: &str,
+    context: &ReporterContext,
+  ) {
+    self.events.report_test_ignored(test, reason, context);
+  }
+
+  fn report_long_running_test(&self, _test_name: &str, _elapsed: Duration) {}
+
+  fn report_category_timings(&self, timings: &[CategoryTiming]) {
+    Reporter::<TData>::report_category_timings(&self.events, timings);
+  }
+
+  fn report_run_aborted(&self, not_run: usize) {
+    Reporter::<TData>::report_run_aborted(&self.events, not_run);
+  }
+
+  fn report_failures(
+    &self,
+    failures: &[ReporterFailure<TData>],
+    total_tests: usize,
+  ) {
+    self.events.report_failures(failures, total_tests);
+    self.finish();
+  }
+}
+
+/// Sends each line written by the `EventStreamReporter` as an event.
+struct EventSink {
+  sender: Sender<Message>,
+  line: Vec<u8>,
+}
+
+impl Write for EventSink {
+  fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    for byte in buf {
+      if *byte == b'\n' {
+        let event = std::mem::take(&mut self.line);
+        let _ = self.sender.send(Message::Event(event));
+      } else {
+        self.line.push(*byte);
+      }
+    }
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> std::io::Result<()> {
+    Ok(())
+  }
+}
+
+fn send_batches(
+  receiver: &Receiver<Message>,
+  endpoint: &Endpoint,
+  options: &HttpEventOptions,
+) {
+  let mut batch = Vec::new();
+  let mut batch_deadline = None;
+  loop {
+    let message = match batch_deadline {
+      Some(deadline) => receiver.recv_deadline(deadline),
+      None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+    };
+    let finished = match message {
+      Ok(Message::Event(event)) => {
+        batch.push(event);
+        batch_deadline
+          .get_or_insert_with(|| Instant::now() + options.flush_interval);
+        if batch.len() < options.batch_size {
+          continue;
+        }
+        false
+      }
+      Ok(Message::Finish) | Err(RecvTimeoutError::Disconnected) => true,
+      Err(RecvTimeoutError::Timeout) => false,
+    };
+    if !batch.is_empty() {
+      post_with_retries(endpoint, options, &std::mem::take(&mut batch));
+    }
+    batch_deadline = None;
+    if finished {
+      return;
+    }
+  }
+}
+
+fn post_with_retries(
+  endpoint: &Endpoint,
+  options: &HttpEventOptions,
+  events: &[Vec<u8>],
+) {
+  let mut body = Vec::with_capacity(events.iter().map(|e| e.len() + 1).sum());
+  body.push(b'[');
+  for (index, event) in events.iter().enumerate() {
+    if index > 0 {
+      body.push(b',');
+    }
+    body.extend_from_slice(event);
+  }
+  body.push(b']');
+
+  let mut retry = 0;
+  loop {
+    let Err(err) = endpoint.post(&options.headers, &body, options.timeout)
+    else {
+      return;
+    };
+    if retry >= options.max_retries {
+      eprintln!(
+        "{}: failed to send {} events to '{}': {:#}",
+        colors::yellow_bold("warning"),
+        events.len(),
+        endpoint.url,
+        err
+      );
+      return;
+    }
+    std::thread::sleep(options.backoff.delay(retry));
+    retry += 1;
+  }
+}
+
+struct Endpoint {
+  url: String,
+  /// Host and port to send in the `Host` header.
+  authority: String,
+  host: String,
+  port: u16,
+  path: String,
+}
+
+impl Endpoint {
+  fn parse(url: &str) -> Result<Self, anyhow::Error> {
+    let Some(rest) = url.strip_prefix("http://") else {
+      anyhow::bail!(
+        "Unsupported events endpoint '{}', expected an http:// URL.",
+        url
+      );
+    };
+    let (authority, path) = match rest.find('/') {
+      Some(index) => (&rest[..index], &rest[index..]),
+      None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+      Some((host, port)) if !port.contains(']') => {
+        let port = port.parse().map_err(|_| {
+          anyhow::anyhow!("Invalid port in events endpoint '{}'.", url)
+        })?;
+        (host, port)
+      }
+      _ => (authority, 80),
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.is_empty() {
+      anyhow::bail!("Missing host in events endpoint '{}'.", url);
+    }
+    Ok(Self {
+      url: url.to_string(),
+      authority: authority.to_string(),
+      host: host.to_string(),
+      port,
+      path: path.to_string(),
+    })
+  }
+
+  fn post(
+    &self,
+    headers: &[(String, String)],
+    body: &[u8],
+    timeout: Duration,
+  ) -> std::io::Result<()> {
+    let address = (self.host.as_str(), self.port)
+      .to_socket_addrs()?
+      .next()
+      .ok_or_else(|| {
+        std::io::Error::new(
+          std::io::ErrorKind::NotFound,
+          format!("failed to resolve '{}'", self.host),
+        )
+      })?;
+    let mut stream = TcpStream::connect_timeout(&address, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let mut request = format!(
+      "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+      self.path,
+      self.authority,
+      body.len()
+    );
+    for (name, value) in headers {
+      request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes())?;
+    stream.write_all(body)?;
+
+    let mut status_line = String::new();
+    BufReader::new(stream).read_line(&mut status_line)?;
+    let status = status_line
+      .split_whitespace()
+      .nth(1)
+      .and_then(|status| status.parse::<u16>().ok());
+    match status {
+      Some(status) if (200..300).contains(&status) => Ok(()),
+      _ => Err(std::io::Error::other(format!(
+        "unexpected response '{}'",
+        status_line.trim()
+      ))),
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::io::Read;
+  use std::net::TcpListener;
+  use std::sync::Arc;
+
+  use super::*;
+  use crate::testing::CategoryBuilder;
+  use crate::RunOptions;
+
+  /// Accepts requests, failing the first one, and returns their bodies.
+  fn serve(listener: TcpListener, count: usize) -> Vec<String> {
+    let mut bodies = Vec::new();
+    for (index, stream) in listener.incoming().take(count).enumerate() {
+      let mut reader = BufReader::new(stream.unwrap());
+      let mut content_length = 0;
+      loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if line == "\r\n" {
+          break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length: ") {
+          content_length = value.trim().parse().unwrap();
+        }
+      }
+      let mut body = vec![0; content_length];
+      reader.read_exact(&mut body).unwrap();
+      let status = if index == 0 {
+        "503 Unavailable"
+      } else {
+        "200 OK"
+      };
+      write!(reader.get_mut(), "HTTP/1.1 {}\r\n\r\n", status).unwrap();
+      bodies.push(String::from_utf8(body).unwrap());
+    }
+    bodies
+  }
+
+  #[test]
+  fn posts_batched_events() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = std::thread::spawn(move || serve(listener, 4));
+    let category = CategoryBuilder::<()>::new("specs")
+      .test("a")
+      .test("b")
+      .build();
+    let reporter = HttpEventReporter::new(HttpEventOptions {
+      batch_size: 4,
+      // only send full batches until the run ends
+      flush_interval: Duration::from_secs(60),
+      backoff: RetryBackoff::None,
+      ..HttpEventOptions::new(format!("http://127.0.0.1:{}/events", port))
+    })
+    .unwrap();
+    crate::run_tests(
+      &category,
+      RunOptions {
+        deterministic: true,
+        reporter: Arc::new(reporter),
+        ..Default::default()
+      },
+      |_| TestResult::Passed,
+    );
+
+    let bodies = server.join().unwrap();
+    // the first batch is retried after the server failed it
+    assert_eq!(bodies[0], bodies[1]);
+    let events = bodies[1..]
+      .iter()
+      .flat_map(|body| {
+        serde_json::from_str::<Vec<serde_json::Value>>(body).unwrap()
+      })
+      .map(|event| event["type"].as_str().unwrap().to_string())
+      .collect::<Vec<_>>();
+    assert_eq!(
+      events,
+      vec![
+        "run_start",
+        "category_start",
+        "test_start",
+        "test_end",
+        "test_start",
+        "test_end",
+        "category_end",
+        "category_timings",
+        "run_end",
+      ]
+    );
+  }
+
+  #[test]
+  fn parses_endpoints() {
+    let endpoint = Endpoint::parse("http://localhost:8080/api/events").unwrap();
+    assert_eq!(endpoint.host, "localhost");
+    assert_eq!(endpoint.port, 8080);
+    assert_eq!(endpoint.path, "/api/events");
+    let endpoint = Endpoint::parse("http://[::1]").unwrap();
+    assert_eq!(endpoint.host, "::1");
+    assert_eq!(endpoint.port, 80);
+    assert_eq!(endpoint.path, "/");
+    assert!(Endpoint::parse("https://example.com").is_err());
+    assert!(Endpoint::parse("http://example.com:port").is_err());
+  }
+}
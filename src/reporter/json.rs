@@ -0,0 +1,276 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::time::Duration;
+use std::time::Instant;
+
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use crate::collection::CollectStats;
+use crate::collection::CollectedTest;
+use crate::collection::CollectedTestCategory;
+use crate::TestResult;
+
+use super::Reporter;
+use super::ReporterContext;
+use super::ReporterFailure;
+use super::ReporterOutput;
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum LibtestEvent<'a> {
+  Suite(SuiteEvent),
+  Test(TestEvent<'a>),
+}
+
+#[derive(Serialize)]
+struct SuiteEvent {
+  event: &'static str,
+  #[serde(flatten)]
+  counts: SuiteCounts,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum SuiteCounts {
+  Started {
+    test_count: usize,
+  },
+  Ended {
+    passed: usize,
+    failed: usize,
+    ignored: usize,
+    measured: usize,
+    filtered_out: usize,
+    exec_time: f64,
+  },
+}
+
+#[derive(Serialize)]
+struct TestEvent<'a> {
+  event: &'static str,
+  name: &'a str,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  exec_time: Option<f64>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  stdout: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  message: Option<&'a str>,
+}
+
+impl<'a> TestEvent<'a> {
+  fn new(event: &'static str, name: &'a str) -> Self {
+    Self {
+      event,
+      name,
+      exec_time: None,
+      stdout: None,
+      message: None,
+    }
+  }
+}
+
+#[derive(Default)]
+struct SuiteState {
+  started: Option<Instant>,
+  passed: usize,
+  failed: usize,
+  ignored: usize,
+  filtered_out: usize,
+}
+
+/// Reporter that writes the newline delimited JSON events of
+/// `cargo test -- --format json`, which allows tools that parse libtest's
+/// output (ex. IDE test explorers) to consume the results.
+///
+/// Tests are reported by their fully resolved name and sub tests are
+/// reported as part of their parent test. Defaults to writing to stdout.
+pub struct JsonReporter {
+  output: ReporterOutput,
+  state: Mutex<SuiteState>,
+}
+
+impl Default for JsonReporter {
+  fn default() -> Self {
+    Self::new(ReporterOutput::Stdout)
+  }
+}
+
+impl JsonReporter {
+  pub fn new(output: ReporterOutput) -> Self {
+    Self {
+      output,
+      state: Default::default(),
+    }
+  }
+
+  fn write_event(&self, event: &LibtestEvent) {
+    writeln!(self.output, "{}", serde_json::to_string(event).unwrap());
+  }
+}
+
+impl<TData> Reporter<TData> for JsonReporter {
+  fn report_collect_stats(&self, stats: &CollectStats) {
+    self.state.lock().filtered_out = stats.filtered_out;
+  }
+
+  fn report_category_start(
+    &self,
+    _category: &CollectedTestCategory<TData>,
+    context: &ReporterContext,
+  ) {
+    let mut state = self.state.lock();
+    if state.started.is_none() {
+      state.started = Some(Instant::now());
+      self.write_event(&LibtestEvent::Suite(SuiteEvent {
+        event: "started",
+        counts: SuiteCounts::Started {
+          test_count: context.run_test_count,
+        },
+      }));
+    }
+  }
+
+  fn report_category_end(
+    &self,
+    _category: &CollectedTestCategory<TData>,
+    _context: &ReporterContext,
+  ) {
+  }
+
+  fn report_test_start(
+    &self,
+    test: &CollectedTest<TData>,
+    _context: &ReporterContext,
+  ) {
+    self
+      .write_event(&LibtestEvent::Test(TestEvent::new("started", &test.name)));
+  }
+
+  fn report_test_end(
+    &self,
+    test: &CollectedTest<TData>,
+    duration: Duration,
+    result: &TestResult,
+    _context: &ReporterContext,
+  ) {
+    let mut state = self.state.lock();
+    let event = if result.is_failed() {
+      state.failed += 1;
+      TestEvent {
+        exec_time: Some(duration.as_secs_f64()),
+        stdout: Some(
+          String::from_utf8_lossy(&result.failure_output()).into_owned(),
+        ),
+        ..TestEvent::new("failed", &test.name)
+      }
+    } else if matches!(result, TestResult::Ignored) {
+      state.ignored += 1;
+      TestEvent::new("ignored", &test.name)
+    } else {
+      state.passed += 1;
+      TestEvent {
+        exec_time: Some(duration.as_secs_f64()),
+        ..TestEvent::new("ok", &test.name)
+      }
+    };
+    self.write_event(&LibtestEvent::Test(event));
+  }
+
+  fn report_test_ignored(
+    &self,
+    test: &CollectedTest<TData>,
+    reason: &str,
+    _context: &ReporterContext,
+  ) {
+    self.state.lock().ignored += 1;
+    self.write_event(&LibtestEvent::Test(TestEvent {
+      message: Some(reason),
+      ..TestEvent::new("ignored", &test.name)
+    }));
+  }
+
+  fn report_long_running_test(&self, test_name: &str, _elapsed: Duration) {
+    self.write_event(&LibtestEvent::Test(TestEvent::new("timeout", test_name)));
+  }
+
+  fn report_failures(
+    &self,
+    _failures: &[ReporterFailure<TData>],
+    _total_tests: usize,
+  ) {
+    let state = self.state.lock();
+    self.write_event(&LibtestEvent::Suite(SuiteEvent {
+      event: if state.failed > 0 { "failed" } else { "ok" },
+      counts: SuiteCounts::Ended {
+        passed: state.passed,
+        failed: state.failed,
+        ignored: state.ignored,
+        measured: 0,
+        filtered_out: state.filtered_out,
+        exec_time: state
+          .started
+          .map(|started| started.elapsed().as_secs_f64())
+          .unwrap_or_default(),
+      },
+    }));
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::sync::Arc;
+
+  use super::*;
+  use crate::testing::CategoryBuilder;
+  use crate::RunOptions;
+
+  #[test]
+  fn writes_libtest_events() {
+    let category = CategoryBuilder::<()>::new("specs")
+      .test("a")
+      .test("b")
+      .test_with("c", (), |mut test| {
+        test.ignored = Some("requires network".to_string());
+        test
+      })
+      .build();
+    let (output, buffer) = ReporterOutput::buffer();
+    crate::run_tests(
+      &category,
+      RunOptions {
+        deterministic: true,
+        reporter: Arc::new(JsonReporter::new(output)),
+        on_failure: crate::OnFailure::Return,
+        ..Default::default()
+      },
+      |test| match test.name.as_str() {
+        "specs::a" => TestResult::Passed,
+        _ => TestResult::failed(b"boom".to_vec()),
+      },
+    );
+    let events = buffer
+      .text()
+      .lines()
+      .map(|line| serde_json::from_str(line).unwrap())
+      .collect::<Vec<serde_json::Value>>();
+    assert_eq!(
+      events[..6],
+      [
+        serde_json::json!({ "type": "suite", "event": "started", "test_count": 3 }),
+        serde_json::json!({ "type": "test", "event": "started", "name": "specs::a" }),
+        serde_json::json!({ "type": "test", "event": "ok", "name": "specs::a", "exec_time": 0.0 }),
+        serde_json::json!({ "type": "test", "event": "started", "name": "specs::b" }),
+        serde_json::json!({ "type": "test", "event": "failed", "name": "specs::b", "exec_time": 0.0, "stdout": "boom" }),
+        serde_json::json!({ "type": "test", "event": "ignored", "name": "specs::c", "message": "requires network" }),
+      ]
+    );
+    let end = &events[6];
+    assert_eq!(end["type"], "suite");
+    assert_eq!(end["event"], "failed");
+    assert_eq!(end["passed"], 1);
+    assert_eq!(end["failed"], 1);
+    assert_eq!(end["ignored"], 1);
+    assert_eq!(events.len(), 7);
+  }
+}
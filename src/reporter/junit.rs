@@ -0,0 +1,312 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::fmt::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+use crate::collection::CollectedTest;
+use crate::collection::CollectedTestCategory;
+use crate::exit_code::exit_with_internal_error;
+use crate::PathedIoError;
+use crate::RunEnvironment;
+use crate::TestResult;
+
+use super::Reporter;
+use super::ReporterContext;
+use super::ReporterFailure;
+
+struct JunitTestRecord {
+  category_name: String,
+  test_name: String,
+  duration: Duration,
+  result: TestResult,
+  /// Why the test was ignored without running it.
+  ignore_reason: Option<String>,
+}
+
+/// Reporter that writes the results to a JUnit XML file at the end of the
+/// run, where each category is a test suite. This is the schema CI
+/// dashboards and Bazel's `XML_OUTPUT_FILE` expect.
+///
+/// Enable it via `RunOptions::junit_output`.
+pub struct JunitReporter {
+  path: PathBuf,
+  environment: Mutex<Option<RunEnvironment>>,
+  records: Mutex<Vec<JunitTestRecord>>,
+}
+
+impl JunitReporter {
+  /// Creates the reporter, which writes the file once the run ends.
+  pub fn new(path: PathBuf) -> Self {
+    Self {
+      path,
+      environment: Default::default(),
+      records: Default::default(),
+    }
+  }
+}
+
+impl<TData> Reporter<TData> for JunitReporter {
+  fn report_run_environment(&self, environment: &RunEnvironment) {
+    *self.environment.lock() = Some(environment.clone());
+  }
+
+  fn report_category_start(
+    &self,
+    _category: &CollectedTestCategory<TData>,
+    _context: &ReporterContext,
+  ) {
+  }
+
+  fn report_category_end(
+    &self,
+    _category: &CollectedTestCategory<TData>,
+    _context: &ReporterContext,
+  ) {
+  }
+
+  fn report_test_start(
+    &self,
+    _test: &CollectedTest<TData>,
+    _context: &ReporterContext,
+  ) {
+  }
+
+  fn report_test_end(
+    &self,
+    test: &CollectedTest<TData>,
+    duration: Duration,
+    result: &TestResult,
+    context: &ReporterContext,
+  ) {
+    self.records.lock().push(JunitTestRecord {
+      category_name: context.category_name.clone(),
+      test_name: test.name.clone(),
+      duration,
+      result: result.clone(),
+      ignore_reason: None,
+    });
+  }
+
+  fn report_test_ignored(
+    &self,
+    test: &CollectedTest<TData>,
+    reason: &str,
+    context: &ReporterContext,
+  ) {
+    self.records.lock().push(JunitTestRecord {
+      category_name: context.category_name.clone(),
+      test_name: test.name.clone(),
+      duration: Duration::ZERO,
+      result: TestResult::Ignored,
+      ignore_reason: Some(reason.to_string()),
+    });
+  }
+
+  fn report_long_running_test(&self, _test_name: &str, _elapsed: Duration) {}
+
+  fn report_failures(
+    &self,
+    _failures: &[ReporterFailure<TData>],
+    _total_tests: usize,
+  ) {
+    if let Err(err) = write_junit_xml(
+      &self.path,
+      &self.records.lock(),
+      self.environment.lock().as_ref(),
+    ) {
+      exit_with_internal_error(err);
+    }
+  }
+}
+
+fn write_junit_xml(
+  path: &Path,
+  records: &[JunitTestRecord],
+  environment: Option<&RunEnvironment>,
+) -> Result<(), PathedIoError> {
+  std::fs::write(path, build_junit_xml(records, environment))
+    .map_err(|err| PathedIoError::new(path, err))
+}
+
+fn build_junit_xml(
+  records: &[JunitTestRecord],
+  environment: Option<&RunEnvironment>,
+) -> String {
+  // group by category while maintaining the order tests were run in
+  let mut suites: Vec<(&str, Vec<&JunitTestRecord>)> = Vec::new();
+  for record in records {
+    match suites
+      .iter_mut()
+      .find(|(name, _)| *name == record.category_name)
+    {
+      Some((_, suite)) => suite.push(record),
+      None => suites.push((&record.category_name, vec![record])),
+    }
+  }
+
+  let mut text = String::new();
+  text.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+  writeln!(
+    text,
+    "<testsuites tests=\"{}\" failures=\"{}\" time=\"{:.3}\">",
+    records.len(),
+    records.iter().filter(|r| r.result.is_failed()).count(),
+    records
+      .iter()
+      .map(|r| r.duration)
+      .sum::<Duration>()
+      .as_secs_f64(),
+  )
+  .unwrap();
+  for (suite_name, suite) in suites {
+    writeln!(
+      text,
+      "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{:.3}\">",
+      escape_xml(suite_name),
+      suite.len(),
+      suite.iter().filter(|r| r.result.is_failed()).count(),
+      suite
+        .iter()
+        .filter(|r| matches!(r.result, TestResult::Ignored))
+        .count(),
+      suite.iter().map(|r| r.duration).sum::<Duration>().as_secs_f64(),
+    )
+    .unwrap();
+    if let Some(environment) = environment {
+      text.push_str("    <properties>\n");
+      for (name, value) in environment.properties() {
+        writeln!(
+          text,
+          "      <property name=\"{}\" value=\"{}\" />",
+          escape_xml(&name),
+          escape_xml(&value)
+        )
+        .unwrap();
+      }
+      text.push_str("    </properties>\n");
+    }
+    for record in suite {
+      write!(
+        text,
+        "    <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\"",
+        escape_xml(&record.test_name),
+        escape_xml(suite_name),
+        record.duration.as_secs_f64(),
+      )
+      .unwrap();
+      if matches!(record.result, TestResult::Ignored) {
+        match &record.ignore_reason {
+          Some(reason) => writeln!(
+            text,
+            ">\n      <skipped message=\"{}\" />\n    </testcase>",
+            escape_xml(reason)
+          )
+          .unwrap(),
+          None => text.push_str(">\n      <skipped />\n    </testcase>\n"),
+        }
+      } else if record.result.is_failed() {
+        writeln!(
+          text,
+          ">\n      <failure message=\"failed\">{}</failure>\n    </testcase>",
+          escape_xml(&String::from_utf8_lossy(&record.result.failure_output()))
+        )
+        .unwrap();
+      } else {
+        text.push_str(" />\n");
+      }
+    }
+    text.push_str("  </testsuite>\n");
+  }
+  text.push_str("</testsuites>\n");
+  text
+}
+
+fn escape_xml(text: &str) -> String {
+  let mut result = String::with_capacity(text.len());
+  for c in text.chars() {
+    match c {
+      '&' => result.push_str("&amp;"),
+      '<' => result.push_str("&lt;"),
+      '>' => result.push_str("&gt;"),
+      '"' => result.push_str("&quot;"),
+      '\'' => result.push_str("&apos;"),
+      // strip characters that are not allowed in xml 1.0
+      '\t' | '\n' | '\r' => result.push(c),
+      c if (c as u32) < 0x20 => {}
+      c => result.push(c),
+    }
+  }
+  result
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn writes_junit_xml() {
+    let records = vec![
+      JunitTestRecord {
+        category_name: "specs".to_string(),
+        test_name: "specs::a".to_string(),
+        duration: Duration::from_millis(1500),
+        result: TestResult::Passed,
+        ignore_reason: None,
+      },
+      JunitTestRecord {
+        category_name: "specs".to_string(),
+        test_name: "specs::b".to_string(),
+        duration: Duration::from_millis(0),
+        result: TestResult::failed(b"expected <1> & got \"2\"".to_vec()),
+        ignore_reason: None,
+      },
+      JunitTestRecord {
+        category_name: "specs::sub".to_string(),
+        test_name: "specs::sub::c".to_string(),
+        duration: Duration::from_millis(0),
+        result: TestResult::Ignored,
+        ignore_reason: Some("requires network".to_string()),
+      },
+    ];
+    assert_eq!(
+      build_junit_xml(&records, None),
+      r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites tests="3" failures="1" time="1.500">
+  <testsuite name="specs" tests="2" failures="1" skipped="0" time="1.500">
+    <testcase name="specs::a" classname="specs" time="1.500" />
+    <testcase name="specs::b" classname="specs" time="0.000">
+      <failure message="failed">expected &lt;1&gt; &amp; got &quot;2&quot;</failure>
+    </testcase>
+  </testsuite>
+  <testsuite name="specs::sub" tests="1" failures="0" skipped="1" time="0.000">
+    <testcase name="specs::sub::c" classname="specs::sub" time="0.000">
+      <skipped message="requires network" />
+    </testcase>
+  </testsuite>
+</testsuites>
+"#
+    );
+    let environment = RunEnvironment {
+      os: "linux".to_string(),
+      arch: "x86_64".to_string(),
+      cpu_count: 2,
+      parallelism: 1,
+      git_commit: None,
+      env_vars: Default::default(),
+    };
+    assert!(build_junit_xml(&records[..1], Some(&environment)).contains(
+      r#"  <testsuite name="specs" tests="1" failures="0" skipped="0" time="1.500">
+    <properties>
+      <property name="os" value="linux" />
+      <property name="arch" value="x86_64" />
+      <property name="cpu_count" value="2" />
+      <property name="parallelism" value="1" />
+    </properties>
+    <testcase name="specs::a""#
+    ));
+  }
+}
@@ -0,0 +1,866 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::collections::BTreeMap;
+use std::io::BufRead;
+use std::io::Write;
+use std::sync::Arc;
+use std::time::Duration;
+
+use deno_terminal::colors;
+
+use crate::collection::CollectProgress;
+use crate::collection::CollectStats;
+use crate::collection::CollectWarning;
+use crate::collection::CollectedTest;
+use crate::collection::CollectedTestCategory;
+use crate::history::BaselineComparison;
+use crate::FailureValue;
+use crate::SubTestResult;
+use crate::TestResult;
+
+use super::OutputReview;
+use super::OverBudgetCategory;
+use super::QuarantineUsage;
+use super::Reporter;
+use super::ReporterContext;
+use super::ReporterFailure;
+use super::ReporterOutput;
+use super::StalledTest;
+use super::TimingRegression;
+
+/// Formats the block shown for a failed test at the end of the run.
+pub type FormatFailureFunc<TData> =
+  Arc<dyn Fn(&ReporterFailure<TData>) -> String + Send + Sync>;
+
+/// Reporter that logs the results in a human readable format to stderr.
+pub struct LogReporter<TData = ()> {
+  /// Where to write the output (ex. an `OutputBuffer` to capture it).
+  /// Defaults to stderr.
+  pub output: ReporterOutput,
+  /// Formats the block shown for each failed test, which allows
+  /// following an organization's conventions (ex. including a command to
+  /// reproduce the failure). Defaults to `format_failure`.
+  pub format_failure: Option<FormatFailureFunc<TData>>,
+  /// Group the names of the failed tests at the end of the run by their
+  /// category along with the number of failures in each category.
+  pub group_failures_by_category: bool,
+  /// Only print the blocks of the first failed tests, which prevents
+  /// catastrophic runs from printing megabytes of output. The other
+  /// failed tests are still listed by name.
+  pub max_printed_failures: Option<usize>,
+  /// Don't print the blocks of the failed tests at the end of the run and
+  /// only list their names and locations, for when the `fail` lines and
+  /// a JSON or JUnit artifact are the source of truth.
+  pub failure_names_only: bool,
+}
+
+impl<TData> Default for LogReporter<TData> {
+  fn default() -> Self {
+    Self {
+      output: ReporterOutput::default(),
+      format_failure: None,
+      group_failures_by_category: false,
+      max_printed_failures: None,
+      failure_names_only: false,
+    }
+  }
+}
+
+impl<TData> Clone for LogReporter<TData> {
+  fn clone(&self) -> Self {
+    Self {
+      output: self.output.clone(),
+      format_failure: self.format_failure.clone(),
+      group_failures_by_category: self.group_failures_by_category,
+      max_printed_failures: self.max_printed_failures,
+      failure_names_only: self.failure_names_only,
+    }
+  }
+}
+
+impl<TData> std::fmt::Debug for LogReporter<TData> {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("LogReporter")
+      .field("output", &self.output)
+      .field("format_failure", &self.format_failure.is_some())
+      .field(
+        "group_failures_by_category",
+        &self.group_failures_by_category,
+      )
+      .field("max_printed_failures", &self.max_printed_failures)
+      .field("failure_names_only", &self.failure_names_only)
+      .finish()
+  }
+}
+
+/// Formats the default block shown for a failed test, which has its
+/// name, output, and location.
+pub fn format_failure<TData>(failure: &ReporterFailure<TData>) -> String {
+  format!(
+    "---- {} ----\n{}\n{}Test file: {}",
+    failure.test.label(),
+    String::from_utf8_lossy(&failure.output),
+    format_failure_fields(&failure.fields),
+    test_location(&failure.test)
+  )
+}
+
+/// Formats the fields of a failure as a table with aligned values.
+fn format_failure_fields(fields: &BTreeMap<String, FailureValue>) -> String {
+  let width = fields.keys().map(|name| name.len()).max().unwrap_or(0);
+  let mut text = String::new();
+  for (name, value) in fields {
+    text.push_str(&format!("{:width$}  {}\n", name, value, width = width));
+  }
+  text
+}
+
+fn test_location<TData>(test: &CollectedTest<TData>) -> String {
+  match test.line_and_column {
+    Some((line, column)) => {
+      format!("{}:{}:{}", test.path.display(), line, column)
+    }
+    None => test.path.display().to_string(),
+  }
+}
+
+impl<TData> Reporter<TData> for LogReporter<TData> {
+  fn report_collect_progress(&self, progress: &CollectProgress) {
+    writeln!(
+      self.output,
+      "  {} tests... {} found in {} directories",
+      colors::green_bold("Collecting"),
+      progress.tests_found,
+      progress.directories_scanned
+    );
+  }
+
+  fn report_collect_warnings(&self, warnings: &[CollectWarning]) {
+    for warning in warnings {
+      writeln!(
+        self.output,
+        "{}: {}",
+        colors::yellow_bold("warning"),
+        warning
+      );
+    }
+  }
+
+  fn report_collect_stats(&self, stats: &CollectStats) {
+    match &stats.filter {
+      Some(filter) => writeln!(
+        self.output,
+        "    {} {} of {} tests (filter: {})",
+        colors::green_bold("Selected"),
+        stats.selected(),
+        stats.total,
+        filter
+      ),
+      None => {
+        writeln!(
+          self.output,
+          "    {} {} tests",
+          colors::green_bold("Selected"),
+          stats.total
+        )
+      }
+    }
+  }
+
+  fn report_category_start(
+    &self,
+    category: &CollectedTestCategory<TData>,
+    context: &ReporterContext,
+  ) {
+    writeln!(self.output);
+    writeln!(
+      self.output,
+      "     {} {} ({} {})",
+      colors::green_bold("Running"),
+      category.name,
+      context.category_test_count,
+      if context.category_test_count == 1 {
+        "test"
+      } else {
+        "tests"
+      }
+    );
+    if let Some(description) = &category.description {
+      writeln!(self.output, "             {}", colors::gray(description));
+    }
+    writeln!(self.output);
+  }
+
+  fn report_category_end(
+    &self,
+    _category: &CollectedTestCategory<TData>,
+    _context: &ReporterContext,
+  ) {
+  }
+
+  fn report_test_start(
+    &self,
+    test: &CollectedTest<TData>,
+    context: &ReporterContext,
+  ) {
+    if !context.is_parallel {
+      write!(self.output, "test {} ... ", test.label());
+    }
+  }
+
+  fn report_test_end(
+    &self,
+    test: &CollectedTest<TData>,
+    duration: Duration,
+    result: &TestResult,
+    context: &ReporterContext,
+  ) {
+    let runner_output = build_end_test_message(result, duration);
+    if context.is_parallel {
+      write!(self.output, "test {} ... {}", test.label(), runner_output);
+    } else {
+      write!(self.output, "{}", runner_output);
+    }
+    if context.verbosity > 0 {
+      writeln!(self.output, "    {}", colors::gray(test.path.display()));
+    }
+  }
+
+  fn report_test_cached(
+    &self,
+    test: &CollectedTest<TData>,
+    _context: &ReporterContext,
+  ) {
+    writeln!(
+      self.output,
+      "test {} ... {}",
+      test.label(),
+      colors::gray("cached")
+    );
+  }
+
+  fn report_test_ignored(
+    &self,
+    test: &CollectedTest<TData>,
+    reason: &str,
+    _context: &ReporterContext,
+  ) {
+    writeln!(
+      self.output,
+      "test {} ... {} ({})",
+      test.label(),
+      colors::gray("ignored"),
+      reason
+    );
+  }
+
+  fn report_long_running_test(&self, test_name: &str, elapsed: Duration) {
+    writeln!(
+      self.output,
+      "test {} has been running for more than {} seconds",
+      test_name,
+      elapsed.as_secs()
+    );
+  }
+
+  fn report_stalled_run(&self, stalled_for: Duration, tests: &[StalledTest]) {
+    writeln!(
+      self.output,
+      "{}: no tests have completed in the last {} seconds. Pending tests:",
+      colors::yellow_bold("warning"),
+      stalled_for.as_secs()
+    );
+    for test in tests {
+      writeln!(
+        self.output,
+        "    {} ({} seconds) {}",
+        test.name,
+        test.elapsed.as_secs(),
+        test.path.display()
+      );
+    }
+  }
+
+  fn report_over_budget_categories(&self, categories: &[OverBudgetCategory]) {
+    writeln!(self.output);
+    writeln!(self.output, "categories over their time budget:");
+    for category in categories {
+      writeln!(
+        self.output,
+        "    {} took {}ms ({}ms budget)",
+        category.name,
+        category.elapsed.as_millis(),
+        category.budget.as_millis()
+      );
+    }
+  }
+
+  fn report_timing_regressions(&self, regressions: &[TimingRegression]) {
+    writeln!(self.output);
+    writeln!(self.output, "tests slower than in the baseline:");
+    for regression in regressions {
+      writeln!(
+        self.output,
+        "    {} took {}ms ({}ms in the baseline)",
+        regression.name,
+        regression.elapsed.as_millis(),
+        regression.baseline.as_millis()
+      );
+    }
+  }
+
+  fn report_quarantine(&self, usage: &[QuarantineUsage]) {
+    writeln!(self.output);
+    writeln!(self.output, "quarantine:");
+    for usage in usage {
+      match usage.absorbed_failures.len() {
+        0 => writeln!(
+          self.output,
+          "    {} {}",
+          usage.pattern,
+          colors::gray("(unused)")
+        ),
+        count => writeln!(
+          self.output,
+          "    {} absorbed {} {}",
+          usage.pattern,
+          count,
+          if count == 1 { "failure" } else { "failures" }
+        ),
+      }
+    }
+  }
+
+  fn review_output(&self, review: &OutputReview) -> bool {
+    self.review_output_with(std::io::stdin().lock(), review)
+  }
+
+  fn report_run_aborted(&self, not_run: usize) {
+    writeln!(self.output);
+    writeln!(
+      self.output,
+      "run aborted early ({} {} not run)",
+      not_run,
+      if not_run == 1 { "test" } else { "tests" }
+    );
+  }
+
+  fn report_failures(
+    &self,
+    failures: &[ReporterFailure<TData>],
+    total_tests: usize,
+  ) {
+    writeln!(self.output);
+    if !failures.is_empty() {
+      if !self.failure_names_only {
+        self.print_failure_blocks(failures);
+      }
+      writeln!(self.output, "failures:");
+      write!(
+        self.output,
+        "{}",
+        format_failure_names(
+          failures,
+          self.group_failures_by_category,
+          self.failure_names_only
+        )
+      );
+      writeln!(self.output);
+    } else {
+      writeln!(self.output, "{} tests passed", total_tests);
+      writeln!(self.output);
+    }
+  }
+
+  fn report_baseline_comparison(&self, comparison: &BaselineComparison) {
+    writeln!(self.output, "compared to the baseline:");
+    writeln!(
+      self.output,
+      "    {}",
+      colors::red_bold(format!(
+        "{} new failures",
+        comparison.new_failures.len()
+      ))
+    );
+    for name in &comparison.new_failures {
+      writeln!(self.output, "        {}", name);
+    }
+    writeln!(
+      self.output,
+      "    {} still failing",
+      comparison.still_failing.len()
+    );
+    writeln!(
+      self.output,
+      "    {}",
+      colors::green_bold(format!("{} fixed", comparison.fixed.len()))
+    );
+    for name in &comparison.fixed {
+      writeln!(self.output, "        {}", name);
+    }
+    writeln!(self.output);
+  }
+}
+
+impl<TData> LogReporter<TData> {
+  /// Shows the mismatch and reads whether to accept the actual output.
+  fn review_output_with(
+    &self,
+    mut input: impl BufRead,
+    review: &OutputReview,
+  ) -> bool {
+    writeln!(
+      self.output,
+      "\n{} {} ({})",
+      colors::yellow_bold("Output mismatch"),
+      review.test_name,
+      review.source
+    );
+    writeln!(
+      self.output,
+      "{}\n{}",
+      colors::red_bold("EXPECTED"),
+      review.expected
+    );
+    writeln!(
+      self.output,
+      "{}\n{}",
+      colors::green_bold("ACTUAL"),
+      review.actual
+    );
+    write!(
+      self.output,
+      "Accept the actual output? This replaces any wildcards. [y/N] "
+    );
+    let _ = self.output.clone().flush();
+    let mut answer = String::new();
+    input.read_line(&mut answer).is_ok()
+      && matches!(answer.trim(), "y" | "Y" | "yes")
+  }
+
+  fn print_failure_blocks(&self, failures: &[ReporterFailure<TData>]) {
+    writeln!(self.output, "spec failures:");
+    writeln!(self.output);
+    let max_printed = self.max_printed_failures.unwrap_or(usize::MAX);
+    for failure in failures.iter().take(max_printed) {
+      let block = match &self.format_failure {
+        Some(format_failure) => format_failure(failure),
+        None => format_failure(failure),
+      };
+      writeln!(self.output, "{}", block);
+      writeln!(self.output);
+    }
+    if failures.len() > max_printed {
+      writeln!(
+        self.output,
+        "... {} more failures were not printed in full. {}",
+        failures.len() - max_printed,
+        failures_file_hint()
+      );
+      writeln!(self.output);
+    }
+  }
+}
+
+/// Gets where the output of all the failures can be found.
+fn failures_file_hint() -> String {
+  let path = std::env::var("FILE_TEST_RUNNER_EVENTS")
+    .ok()
+    .or_else(|| std::env::var("XML_OUTPUT_FILE").ok())
+    .filter(|path| !path.is_empty());
+  match path {
+    Some(path) => format!("See {} for their output.", path),
+    None => "Set FILE_TEST_RUNNER_EVENTS to a file path to write their output \
+      to that file."
+      .to_string(),
+  }
+}
+
+fn format_failure_names<TData>(
+  failures: &[ReporterFailure<TData>],
+  group_by_category: bool,
+  with_location: bool,
+) -> String {
+  let entry = |failure: &ReporterFailure<TData>| {
+    if with_location {
+      format!("{} ({})", failure.test.name, test_location(&failure.test))
+    } else {
+      failure.test.name.clone()
+    }
+  };
+  let mut text = String::new();
+  if !group_by_category {
+    for failure in failures {
+      text.push_str(&format!("    {}\n", entry(failure)));
+    }
+    return text;
+  }
+  let mut categories: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+  for failure in failures {
+    let name = failure.test.name.as_str();
+    let category = name.rsplit_once("::").map(|(c, _)| c).unwrap_or("");
+    categories.entry(category).or_default().push(entry(failure));
+  }
+  for (category, names) in categories {
+    text.push_str(&format!(
+      "    {} ({} {})\n",
+      category,
+      names.len(),
+      if names.len() == 1 {
+        "failure"
+      } else {
+        "failures"
+      }
+    ));
+    for name in names {
+      text.push_str(&format!("        {}\n", name));
+    }
+  }
+  text
+}
+
+fn build_end_test_message(result: &TestResult, duration: Duration) -> String {
+  fn output_sub_tests(
+    indent: &str,
+    sub_tests: &[SubTestResult],
+    runner_output: &mut String,
+  ) {
+    for sub_test in sub_tests {
+      match &sub_test.result {
+        TestResult::Passed => {
+          runner_output.push_str(&format!(
+            "{}{} {}\n",
+            indent,
+            sub_test.name,
+            colors::green_bold("ok"),
+          ));
+        }
+        TestResult::Ignored => {
+          runner_output.push_str(&format!(
+            "{}{} {}\n",
+            indent,
+            sub_test.name,
+            colors::gray("ignored"),
+          ));
+        }
+        TestResult::Failed { .. } | TestResult::FailedWithFields { .. } => {
+          runner_output.push_str(&format!(
+            "{}{} {}\n",
+            indent,
+            sub_test.name,
+            colors::red_bold("fail")
+          ));
+        }
+        TestResult::SubTests(sub_tests) => {
+          runner_output.push_str(&format!("{}{}\n", indent, sub_test.name));
+          if sub_tests.is_empty() {
+            runner_output.push_str(&format!(
+              "{}  {}\n",
+              indent,
+              colors::gray("<no sub-tests>")
+            ));
+          } else {
+            output_sub_tests(
+              &format!("{}  ", indent),
+              sub_tests,
+              runner_output,
+            );
+          }
+        }
+      }
+    }
+  }
+
+  let mut runner_output = String::new();
+  let duration_display = colors::gray(format!("({}ms)", duration.as_millis()));
+  match result {
+    TestResult::Passed => {
+      runner_output.push_str(&format!(
+        "{} {}\n",
+        colors::green_bold("ok"),
+        duration_display
+      ));
+    }
+    TestResult::Ignored => {
+      runner_output.push_str(&format!("{}\n", colors::gray("ignored")));
+    }
+    TestResult::Failed { .. } | TestResult::FailedWithFields { .. } => {
+      runner_output.push_str(&format!(
+        "{} {}\n",
+        colors::red_bold("fail"),
+        duration_display
+      ));
+    }
+    TestResult::SubTests(sub_tests) => {
+      runner_output.push_str(&format!("{}\n", duration_display));
+      output_sub_tests("  ", sub_tests, &mut runner_output);
+    }
+  }
+
+  runner_output
+}
+
+#[cfg(test)]
+mod test {
+  use deno_terminal::colors;
+
+  use super::*;
+
+  #[test]
+  fn test_format_failure() {
+    let mut test = CollectedTest::new("specs::a", "specs/a.json", ());
+    test.line_and_column = Some((2, 5));
+    let failure = ReporterFailure {
+      test,
+      output: b"error".to_vec(),
+      fields: Default::default(),
+    };
+    assert_eq!(
+      format_failure(&failure),
+      "---- specs::a ----\nerror\nTest file: specs/a.json:2:5"
+    );
+    let failure = ReporterFailure {
+      fields: TestResult::failed(Vec::new())
+        .with_field("exit_code", 1)
+        .with_field("expected_file", "specs/a.out")
+        .failure_fields(),
+      ..failure
+    };
+    assert_eq!(
+      format_failure(&failure),
+      concat!(
+        "---- specs::a ----\nerror\n",
+        "exit_code      1\n",
+        "expected_file  specs/a.out\n",
+        "Test file: specs/a.json:2:5",
+      )
+    );
+    let mut test = failure.test;
+    test.display_name = Some("A test".to_string());
+    let failure = ReporterFailure {
+      test,
+      fields: Default::default(),
+      ..failure
+    };
+    assert_eq!(
+      format_failure(&failure),
+      "---- A test ----\nerror\nTest file: specs/a.json:2:5"
+    );
+  }
+
+  #[test]
+  fn test_format_failure_names() {
+    let failures = ["specs::lsp::b", "specs::npm::a", "specs::lsp::a"]
+      .into_iter()
+      .map(|name| ReporterFailure {
+        test: CollectedTest::new(name, name, ()),
+        output: Vec::new(),
+        fields: Default::default(),
+      })
+      .collect::<Vec<_>>();
+    assert_eq!(
+      format_failure_names(&failures, false, false),
+      "    specs::lsp::b\n    specs::npm::a\n    specs::lsp::a\n"
+    );
+    assert_eq!(
+      format_failure_names(&failures, false, true),
+      concat!(
+        "    specs::lsp::b (specs::lsp::b)\n",
+        "    specs::npm::a (specs::npm::a)\n",
+        "    specs::lsp::a (specs::lsp::a)\n",
+      )
+    );
+    assert_eq!(
+      format_failure_names(&failures, true, false),
+      concat!(
+        "    specs::lsp (2 failures)\n",
+        "        specs::lsp::b\n",
+        "        specs::lsp::a\n",
+        "    specs::npm (1 failure)\n",
+        "        specs::npm::a\n",
+      )
+    );
+  }
+
+  #[test]
+  fn writes_to_output() {
+    let (output, buffer) = ReporterOutput::buffer();
+    let reporter = LogReporter::<()> {
+      output,
+      ..Default::default()
+    };
+    reporter.report_run_aborted(2);
+    reporter.report_failures(&[], 3);
+    assert_eq!(
+      buffer.text(),
+      "\nrun aborted early (2 tests not run)\n\n3 tests passed\n\n"
+    );
+  }
+
+  #[test]
+  fn max_printed_failures() {
+    let (output, buffer) = ReporterOutput::buffer();
+    let reporter = LogReporter::<()> {
+      output,
+      max_printed_failures: Some(1),
+      ..Default::default()
+    };
+    let failures = ["specs::a", "specs::b", "specs::c"]
+      .into_iter()
+      .map(|name| ReporterFailure {
+        test: CollectedTest::new(name, name, ()),
+        output: b"error".to_vec(),
+        fields: Default::default(),
+      })
+      .collect::<Vec<_>>();
+    reporter.report_failures(&failures, 3);
+    let text = buffer.text();
+    assert!(text.contains("---- specs::a ----"), "{}", text);
+    assert!(!text.contains("---- specs::b ----"), "{}", text);
+    assert!(
+      text.contains("... 2 more failures were not printed in full."),
+      "{}",
+      text
+    );
+    // the others are still listed by name
+    assert!(
+      text.ends_with("failures:\n    specs::a\n    specs::b\n    specs::c\n\n"),
+      "{}",
+      text
+    );
+  }
+
+  #[test]
+  fn failure_names_only() {
+    let (output, buffer) = ReporterOutput::buffer();
+    let reporter = LogReporter::<()> {
+      output,
+      failure_names_only: true,
+      ..Default::default()
+    };
+    let mut test = CollectedTest::new("specs::a", "specs/a.json", ());
+    test.line_and_column = Some((2, 5));
+    reporter.report_failures(
+      &[ReporterFailure {
+        test,
+        output: b"error".to_vec(),
+        fields: Default::default(),
+      }],
+      1,
+    );
+    assert_eq!(
+      buffer.text(),
+      "\nfailures:\n    specs::a (specs/a.json:2:5)\n\n"
+    );
+  }
+
+  #[test]
+  fn review_output() {
+    let (output, buffer) = ReporterOutput::buffer();
+    let reporter = LogReporter::<()> {
+      output,
+      ..Default::default()
+    };
+    let review = OutputReview {
+      test_name: "specs::a".to_string(),
+      source: "test.out".to_string(),
+      expected: "hello".to_string(),
+      actual: "world".to_string(),
+    };
+    assert!(reporter.review_output_with("y\n".as_bytes(), &review));
+    let text = buffer.text();
+    assert!(text.contains("specs::a (test.out)"));
+    assert!(text.contains("hello"));
+    assert!(text.contains("world"));
+    for answer in ["n\n", "\n", ""] {
+      assert!(!reporter.review_output_with(answer.as_bytes(), &review));
+    }
+  }
+
+  #[test]
+  fn test_build_end_test_message_passed() {
+    assert_eq!(
+      build_end_test_message(
+        &super::TestResult::Passed,
+        std::time::Duration::from_millis(100),
+      ),
+      format!("{} {}\n", colors::green_bold("ok"), colors::gray("(100ms)"))
+    );
+  }
+
+  #[test]
+  fn test_build_end_test_message_failed() {
+    let result = super::TestResult::failed(b"error".to_vec());
+    let message =
+      build_end_test_message(&result, std::time::Duration::from_millis(100));
+    let failure_output = result.failure_output();
+    assert_eq!(
+      message,
+      format!("{} {}\n", colors::red_bold("fail"), colors::gray("(100ms)"))
+    );
+    assert_eq!(failure_output, b"error");
+  }
+
+  #[test]
+  fn test_build_end_test_message_ignored() {
+    assert_eq!(
+      build_end_test_message(
+        &super::TestResult::Ignored,
+        std::time::Duration::from_millis(10),
+      ),
+      format!("{}\n", colors::gray("ignored"))
+    );
+  }
+
+  #[test]
+  fn test_build_end_test_message_sub_tests() {
+    let result = super::TestResult::SubTests(vec![
+      super::SubTestResult {
+        name: "step1".to_string(),
+        result: super::TestResult::Passed,
+      },
+      super::SubTestResult {
+        name: "step2".to_string(),
+        result: super::TestResult::failed(b"error1".to_vec()),
+      },
+      super::SubTestResult {
+        name: "step3".to_string(),
+        result: super::TestResult::failed(b"error2".to_vec()),
+      },
+      super::SubTestResult {
+        name: "step4".to_string(),
+        result: super::TestResult::SubTests(vec![
+          super::SubTestResult {
+            name: "sub-step1".to_string(),
+            result: super::TestResult::Passed,
+          },
+          super::SubTestResult {
+            name: "sub-step2".to_string(),
+            result: super::TestResult::failed(b"error3".to_vec()),
+          },
+        ]),
+      },
+    ]);
+    let message =
+      build_end_test_message(&result, std::time::Duration::from_millis(10));
+    let failure_output = result.failure_output();
+
+    assert_eq!(
+      message,
+      format!(
+        "{}\n  step1 {}\n  step2 {}\n  step3 {}\n  step4\n    sub-step1 {}\n    sub-step2 {}\n",
+        colors::gray("(10ms)"),
+        colors::green_bold("ok"),
+        colors::red_bold("fail"),
+        colors::red_bold("fail"),
+        colors::green_bold("ok"),
+        colors::red_bold("fail"),
+      )
+    );
+
+    assert_eq!(
+      String::from_utf8(failure_output).unwrap(),
+      "error1\nerror2\nerror3"
+    );
+  }
+}
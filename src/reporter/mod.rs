@@ -0,0 +1,558 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::collection::CollectProgress;
+use crate::collection::CollectStats;
+use crate::collection::CollectWarning;
+use crate::collection::CollectedTest;
+use crate::collection::CollectedTestCategory;
+use crate::history::BaselineComparison;
+use crate::FailureValue;
+use crate::RunEnvironment;
+use crate::TestResult;
+
+mod events;
+mod http;
+mod json;
+mod junit;
+mod log;
+mod output;
+
+pub use events::*;
+pub use http::*;
+pub use json::*;
+pub use junit::*;
+pub use log::*;
+pub use output::*;
+
+#[derive(Debug, Clone)]
+pub struct ReporterContext {
+  /// Whether the tests are being run in parallel, which may include tests
+  /// from other categories.
+  pub is_parallel: bool,
+  /// Name of the category of the test or category being reported.
+  pub category_name: String,
+  /// Number of tests in the category, excluding the tests of its sub
+  /// categories, which are reported separately.
+  pub category_test_count: usize,
+  /// Number of tests in the category including its sub categories.
+  pub category_total_test_count: usize,
+  /// Number of tests in the run, which is the same for every category.
+  pub run_test_count: usize,
+  /// Level of extra diagnostics to print. See `RunOptions::verbosity`.
+  pub verbosity: u8,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReporterFailure<TData = ()> {
+  pub test: CollectedTest<TData>,
+  /// Captured output of the failed test.
+  pub output: Vec<u8>,
+  /// Structured fields of the failure. See `TestResult::with_field`.
+  pub fields: BTreeMap<String, FailureValue>,
+}
+
+/// Time spent running the tests of a category, including the tests
+/// of its sub categories.
+#[derive(Debug, Clone)]
+pub struct CategoryTiming {
+  pub name: String,
+  pub path: PathBuf,
+  /// Number of tests that were run, excluding cached and ignored tests.
+  pub test_count: usize,
+  /// Sum of the durations of the tests that were run. This may exceed
+  /// the wall-clock time when tests are run in parallel.
+  pub total: Duration,
+}
+
+impl CategoryTiming {
+  /// Average duration of the tests that were run.
+  pub fn average(&self) -> Duration {
+    self.total / self.test_count.max(1) as u32
+  }
+}
+
+/// Category that took longer to run than its budget.
+#[derive(Debug, Clone)]
+pub struct OverBudgetCategory {
+  pub name: String,
+  /// Wall-clock time taken to run the category and its sub categories.
+  pub elapsed: Duration,
+  pub budget: Duration,
+}
+
+/// Test that took longer to run than in the baseline run.
+#[derive(Debug, Clone)]
+pub struct TimingRegression {
+  pub name: String,
+  pub elapsed: Duration,
+  /// Time taken to run the test in the baseline run.
+  pub baseline: Duration,
+}
+
+/// Failures absorbed by a pattern of `RunOptions::quarantine`.
+#[derive(Debug, Clone)]
+pub struct QuarantineUsage {
+  pub pattern: String,
+  /// Names of the failed tests the pattern matched, where no failures
+  /// means the pattern may be unused and could be removed.
+  pub absorbed_failures: Vec<String>,
+}
+
+/// Test that was still pending when the run stalled.
+#[derive(Debug, Clone)]
+pub struct StalledTest {
+  pub name: String,
+  pub path: PathBuf,
+  /// Time since the test was queued to run.
+  pub elapsed: Duration,
+}
+
+/// Mismatched output of a test that can be accepted when running with
+/// `--interactive-update`.
+#[derive(Debug, Clone)]
+pub struct OutputReview {
+  pub test_name: String,
+  /// Where the expected output is from (ex. the path of the `.out` file).
+  pub source: String,
+  pub expected: String,
+  pub actual: String,
+}
+
+/// Receives events from the runner in order to display or record the
+/// results of a test run.
+pub trait Reporter<TData = ()>: Send + Sync {
+  /// Called periodically while collecting tests when using
+  /// `collect_and_run_tests`.
+  fn report_collect_progress(&self, _progress: &CollectProgress) {}
+  /// Called with the non-fatal problems found while collecting tests
+  /// when using `collect_and_run_tests`.
+  fn report_collect_warnings(&self, _warnings: &[CollectWarning]) {}
+  /// Called after collecting and filtering the tests when using
+  /// `collect_and_run_tests`.
+  fn report_collect_stats(&self, _stats: &CollectStats) {}
+  /// Called before any tests are run with the conditions of the run.
+  fn report_run_environment(&self, _environment: &RunEnvironment) {}
+  fn report_category_start(
+    &self,
+    category: &CollectedTestCategory<TData>,
+    context: &ReporterContext,
+  );
+  fn report_category_end(
+    &self,
+    category: &CollectedTestCategory<TData>,
+    context: &ReporterContext,
+  );
+  /// Called when a test starts running. When running in parallel, this
+  /// is called when the test is queued to a worker.
+  fn report_test_start(
+    &self,
+    test: &CollectedTest<TData>,
+    context: &ReporterContext,
+  );
+  fn report_test_end(
+    &self,
+    test: &CollectedTest<TData>,
+    duration: Duration,
+    result: &TestResult,
+    context: &ReporterContext,
+  );
+  /// Called instead of starting the test when it was skipped because its
+  /// input hasn't changed since it last passed.
+  ///
+  /// By default, this reports the test as passed.
+  fn report_test_cached(
+    &self,
+    test: &CollectedTest<TData>,
+    context: &ReporterContext,
+  ) {
+    self.report_test_end(test, Duration::ZERO, &TestResult::Passed, context);
+  }
+  /// Called instead of starting the test when it was ignored by the
+  /// runner (ex. because of `CollectedTest::ignore_on`).
+  ///
+  /// By default, this reports the test as ignored.
+  fn report_test_ignored(
+    &self,
+    test: &CollectedTest<TData>,
+    _reason: &str,
+    context: &ReporterContext,
+  ) {
+    self.report_test_end(test, Duration::ZERO, &TestResult::Ignored, context);
+  }
+  /// Called when a test has been running for longer than its timeout.
+  fn report_long_running_test(&self, test_name: &str, elapsed: Duration);
+  /// Called when no test has completed for longer than
+  /// `RunOptions::stall_timeout`, with the tests that are still pending
+  /// ordered from longest running.
+  fn report_stalled_run(&self, _stalled_for: Duration, _tests: &[StalledTest]) {
+  }
+  /// Called at the end of the run, before the failures are reported, when
+  /// categories exceeded their time budget (see `RunOptions::category_budget`).
+  fn report_over_budget_categories(&self, _categories: &[OverBudgetCategory]) {}
+  /// Called at the end of the run, before the failures are reported, with
+  /// the timing of each category that ran tests. Sub categories are
+  /// provided before their parent.
+  fn report_category_timings(&self, _timings: &[CategoryTiming]) {}
+  /// Called at the end of the run, before the failures are reported, when
+  /// tests slowed down compared to the baseline beyond the threshold (see
+  /// `RunOptions::timing_threshold`).
+  fn report_timing_regressions(&self, _regressions: &[TimingRegression]) {}
+  /// Called at the end of the run, before the failures are reported, with
+  /// the failures absorbed by each pattern of `RunOptions::quarantine`.
+  fn report_quarantine(&self, _usage: &[QuarantineUsage]) {}
+  /// Called while a test is running when its output doesn't match the
+  /// expected output file and `--interactive-update` was provided, returning
+  /// whether to accept the actual output. Reviews are done one at a time and
+  /// don't count towards the test's timeout.
+  ///
+  /// By default, this rejects the actual output.
+  fn review_output(&self, _review: &OutputReview) -> bool {
+    false
+  }
+  /// Called at the end of the run, before the failures are reported, when
+  /// the run ended before all the tests were run (ex. because of
+  /// `RunOptions::max_failures`).
+  fn report_run_aborted(&self, _not_run: usize) {}
+  /// Called once at the end of the run, even when there are no failures.
+  fn report_failures(
+    &self,
+    failures: &[ReporterFailure<TData>],
+    total_tests: usize,
+  );
+  /// Called at the end of the run, after the failures are reported, when
+  /// comparing the run against a baseline (see `RunOptions::baseline`).
+  fn report_baseline_comparison(&self, _comparison: &BaselineComparison) {}
+}
+
+/// Sends the events to several reporters in order (ex. a `LogReporter`
+/// for the console along with a `JunitReporter` for CI).
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use file_test_runner::reporter::JunitReporter;
+/// use file_test_runner::reporter::LogReporter;
+/// use file_test_runner::reporter::MultiReporter;
+///
+/// let reporter = MultiReporter::<()>::new(vec![
+///   Arc::new(LogReporter::default()),
+///   Arc::new(JunitReporter::new("junit.xml".into())),
+/// ]);
+/// ```
+pub struct MultiReporter<TData = ()>(pub Vec<Arc<dyn Reporter<TData>>>);
+
+impl<TData> MultiReporter<TData> {
+  pub fn new(reporters: Vec<Arc<dyn Reporter<TData>>>) -> Self {
+    Self(reporters)
+  }
+}
+
+impl<TData> Reporter<TData> for MultiReporter<TData> {
+  fn report_collect_progress(&self, progress: &CollectProgress) {
+    for reporter in &self.0 {
+      reporter.report_collect_progress(progress);
+    }
+  }
+
+  fn report_collect_warnings(&self, warnings: &[CollectWarning]) {
+    for reporter in &self.0 {
+      reporter.report_collect_warnings(warnings);
+    }
+  }
+
+  fn report_collect_stats(&self, stats: &CollectStats) {
+    for reporter in &self.0 {
+      reporter.report_collect_stats(stats);
+    }
+  }
+
+  fn report_run_environment(&self, environment: &RunEnvironment) {
+    for reporter in &self.0 {
+      reporter.report_run_environment(environment);
+    }
+  }
+
+  fn report_category_start(
+    &self,
+    category: &CollectedTestCategory<TData>,
+    context: &ReporterContext,
+  ) {
+    for reporter in &self.0 {
+      reporter.report_category_start(category, context);
+    }
+  }
+
+  fn report_category_end(
+    &self,
+    category: &CollectedTestCategory<TData>,
+    context: &ReporterContext,
+  ) {
+    for reporter in &self.0 {
+      reporter.report_category_end(category, context);
+    }
+  }
+
+  fn report_test_start(
+    &self,
+    test: &CollectedTest<TData>,
+    context: &ReporterContext,
+  ) {
+    for reporter in &self.0 {
+      reporter.report_test_start(test, context);
+    }
+  }
+
+  fn report_test_end(
+    &self,
+    test: &CollectedTest<TData>,
+    duration: Duration,
+    result: &TestResult,
+    context: &ReporterContext,
+  ) {
+    for reporter in &self.0 {
+      reporter.report_test_end(test, duration, result, context);
+    }
+  }
+
+  fn report_test_cached(
+    &self,
+    test: &CollectedTest<TData>,
+    context: &ReporterContext,
+  ) {
+    for reporter in &self.0 {
+      reporter.report_test_cached(test, context);
+    }
+  }
+
+  fn report_test_ignored(
+    &self,
+    test: &CollectedTest<TData>,
+    reason: &str,
+    context: &ReporterContext,
+  ) {
+    for reporter in &self.0 {
+      reporter.report_test_ignored(test, reason, context);
+    }
+  }
+
+  fn report_long_running_test(&self, test_name: &str, elapsed: Duration) {
+    for reporter in &self.0 {
+      reporter.report_long_running_test(test_name, elapsed);
+    }
+  }
+
+  fn report_stalled_run(&self, stalled_for: Duration, tests: &[StalledTest]) {
+    for reporter in &self.0 {
+      reporter.report_stalled_run(stalled_for, tests);
+    }
+  }
+
+  fn report_over_budget_categories(&self, categories: &[OverBudgetCategory]) {
+    for reporter in &self.0 {
+      reporter.report_over_budget_categories(categories);
+    }
+  }
+
+  fn report_category_timings(&self, timings: &[CategoryTiming]) {
+    for reporter in &self.0 {
+      reporter.report_category_timings(timings);
+    }
+  }
+
+  fn report_timing_regressions(&self, regressions: &[TimingRegression]) {
+    for reporter in &self.0 {
+      reporter.report_timing_regressions(regressions);
+    }
+  }
+
+  fn report_quarantine(&self, usage: &[QuarantineUsage]) {
+    for reporter in &self.0 {
+      reporter.report_quarantine(usage);
+    }
+  }
+
+  fn review_output(&self, review: &OutputReview) -> bool {
+    // only the first reporter that reviews the output prompts for it
+    self.0.iter().any(|reporter| reporter.review_output(review))
+  }
+
+  fn report_run_aborted(&self, not_run: usize) {
+    for reporter in &self.0 {
+      reporter.report_run_aborted(not_run);
+    }
+  }
+
+  fn report_failures(
+    &self,
+    failures: &[ReporterFailure<TData>],
+    total_tests: usize,
+  ) {
+    for reporter in &self.0 {
+      reporter.report_failures(failures, total_tests);
+    }
+  }
+
+  fn report_baseline_comparison(&self, comparison: &BaselineComparison) {
+    for reporter in &self.0 {
+      reporter.report_baseline_comparison(comparison);
+    }
+  }
+}
+
+/// Reports the durations as zero and drops the reports that depend on
+/// timing so the output is the same across runs. See
+/// `RunOptions::deterministic`.
+pub(crate) struct DeterministicReporter<TData>(pub Arc<dyn Reporter<TData>>);
+
+impl<TData> Reporter<TData> for DeterministicReporter<TData> {
+  fn report_collect_progress(&self, progress: &CollectProgress) {
+    self.0.report_collect_progress(progress);
+  }
+
+  fn report_collect_warnings(&self, warnings: &[CollectWarning]) {
+    self.0.report_collect_warnings(warnings);
+  }
+
+  fn report_collect_stats(&self, stats: &CollectStats) {
+    self.0.report_collect_stats(stats);
+  }
+
+  fn report_run_environment(&self, environment: &RunEnvironment) {
+    self.0.report_run_environment(environment);
+  }
+
+  fn report_category_start(
+    &self,
+    category: &CollectedTestCategory<TData>,
+    context: &ReporterContext,
+  ) {
+    self.0.report_category_start(category, context);
+  }
+
+  fn report_category_end(
+    &self,
+    category: &CollectedTestCategory<TData>,
+    context: &ReporterContext,
+  ) {
+    self.0.report_category_end(category, context);
+  }
+
+  fn report_test_start(
+    &self,
+    test: &CollectedTest<TData>,
+    context: &ReporterContext,
+  ) {
+    self.0.report_test_start(test, context);
+  }
+
+  fn report_test_end(
+    &self,
+    test: &CollectedTest<TData>,
+    _duration: Duration,
+    result: &TestResult,
+    context: &ReporterContext,
+  ) {
+    self
+      .0
+      .report_test_end(test, Duration::ZERO, result, context);
+  }
+
+  fn report_test_cached(
+    &self,
+    test: &CollectedTest<TData>,
+    context: &ReporterContext,
+  ) {
+    self.0.report_test_cached(test, context);
+  }
+
+  fn report_test_ignored(
+    &self,
+    test: &CollectedTest<TData>,
+    reason: &str,
+    context: &ReporterContext,
+  ) {
+    self.0.report_test_ignored(test, reason, context);
+  }
+
+  fn report_long_running_test(&self, _test_name: &str, _elapsed: Duration) {}
+
+  fn report_stalled_run(&self, _stalled_for: Duration, _tests: &[StalledTest]) {
+  }
+
+  fn report_over_budget_categories(&self, categories: &[OverBudgetCategory]) {
+    self.0.report_over_budget_categories(categories);
+  }
+
+  fn report_category_timings(&self, timings: &[CategoryTiming]) {
+    let timings = timings
+      .iter()
+      .map(|timing| CategoryTiming {
+        total: Duration::ZERO,
+        ..timing.clone()
+      })
+      .collect::<Vec<_>>();
+    self.0.report_category_timings(&timings);
+  }
+
+  fn report_timing_regressions(&self, regressions: &[TimingRegression]) {
+    self.0.report_timing_regressions(regressions);
+  }
+
+  fn report_quarantine(&self, usage: &[QuarantineUsage]) {
+    self.0.report_quarantine(usage);
+  }
+
+  fn review_output(&self, review: &OutputReview) -> bool {
+    self.0.review_output(review)
+  }
+
+  fn report_run_aborted(&self, not_run: usize) {
+    self.0.report_run_aborted(not_run);
+  }
+
+  fn report_failures(
+    &self,
+    failures: &[ReporterFailure<TData>],
+    total_tests: usize,
+  ) {
+    self.0.report_failures(failures, total_tests);
+  }
+
+  fn report_baseline_comparison(&self, comparison: &BaselineComparison) {
+    self.0.report_baseline_comparison(comparison);
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::testing::CategoryBuilder;
+  use crate::testing::FakeReporter;
+  use crate::RunOptions;
+
+  #[test]
+  fn multi_reporter_sends_events_to_each_reporter() {
+    let category = CategoryBuilder::<()>::new("specs").test("a").build();
+    let first = Arc::new(FakeReporter::default());
+    let second = Arc::new(FakeReporter::default());
+    crate::run_tests(
+      &category,
+      RunOptions {
+        parallel: false,
+        reporter: Arc::new(MultiReporter::new(vec![
+          first.clone(),
+          second.clone(),
+        ])),
+        ..Default::default()
+      },
+      |_| TestResult::Passed,
+    );
+    let events = first.events();
+    assert!(events.contains(&"test_end specs::a passed".to_string()));
+    assert_eq!(events, second.events());
+  }
+}
@@ -0,0 +1,111 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+
+use crate::PathedIoError;
+
+/// Where a reporter writes its output.
+///
+/// Write errors are ignored so a closed stream doesn't fail the run.
+#[derive(Clone, Default)]
+pub enum ReporterOutput {
+  #[default]
+  Stderr,
+  Stdout,
+  /// Writes to a file, an in-memory buffer (see `OutputBuffer`), or
+  /// anything else.
+  Writer(Arc<Mutex<Box<dyn Write + Send>>>),
+}
+
+impl ReporterOutput {
+  pub fn writer(writer: impl Write + Send + 'static) -> Self {
+    Self::Writer(Arc::new(Mutex::new(Box::new(writer))))
+  }
+
+  /// Creates or truncates the file to write to.
+  pub fn file(path: &Path) -> Result<Self, PathedIoError> {
+    let file = std::fs::File::create(path)
+      .map_err(|err| PathedIoError::new(path, err))?;
+    Ok(Self::writer(file))
+  }
+
+  /// Creates an output that writes to memory, along with the buffer to
+  /// read what was written from.
+  pub fn buffer() -> (Self, OutputBuffer) {
+    let buffer = OutputBuffer::default();
+    (Self::writer(buffer.clone()), buffer)
+  }
+
+  /// Writes the formatted text, which allows using `write!` and
+  /// `writeln!` with the output.
+  pub fn write_fmt(&self, args: std::fmt::Arguments) {
+    match self {
+      // use the macros so the output is captured in `cargo test`
+      Self::Stderr => eprint!("{}", args),
+      Self::Stdout => print!("{}", args),
+      Self::Writer(writer) => {
+        let mut writer = writer.lock();
+        let _ = writer.write_fmt(args);
+        let _ = writer.flush();
+      }
+    }
+  }
+}
+
+impl Write for ReporterOutput {
+  fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    match self {
+      Self::Stderr => std::io::stderr().write(buf),
+      Self::Stdout => std::io::stdout().write(buf),
+      Self::Writer(writer) => writer.lock().write(buf),
+    }
+  }
+
+  fn flush(&mut self) -> std::io::Result<()> {
+    match self {
+      Self::Stderr => std::io::stderr().flush(),
+      Self::Stdout => std::io::stdout().flush(),
+      Self::Writer(writer) => writer.lock().flush(),
+    }
+  }
+}
+
+impl std::fmt::Debug for ReporterOutput {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Stderr => f.write_str("Stderr"),
+      Self::Stdout => f.write_str("Stdout"),
+      Self::Writer(_) => f.write_str("Writer"),
+    }
+  }
+}
+
+/// In-memory output of a reporter, whose clones share the written bytes.
+#[derive(Debug, Clone, Default)]
+pub struct OutputBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl OutputBuffer {
+  pub fn bytes(&self) -> Vec<u8> {
+    self.0.lock().clone()
+  }
+
+  /// Gets the written text, replacing invalid UTF-8.
+  pub fn text(&self) -> String {
+    String::from_utf8_lossy(&self.0.lock()).into_owned()
+  }
+}
+
+impl Write for OutputBuffer {
+  fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    self.0.lock().extend_from_slice(buf);
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> std::io::Result<()> {
+    Ok(())
+  }
+}
@@ -1,48 +1,256 @@
 // Copyright 2018-2024 the Deno authors. MIT license.
 
 use core::panic;
+use std::cell::Cell;
 use std::cell::RefCell;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::path::Path;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::time::Duration;
 use std::time::Instant;
 
-use deno_terminal::colors;
 use parking_lot::Mutex;
+use serde::Deserialize;
+use serde::Serialize;
 
+use crate::assertions;
+use crate::bazel::BazelEnv;
+use crate::cache::ResultCache;
+use crate::capabilities::Capabilities;
+use crate::capabilities::EvaluatedCapabilities;
 use crate::collection::CollectedCategoryOrTest;
 use crate::collection::CollectedTest;
 use crate::collection::CollectedTestCategory;
+use crate::collection::TestPriority;
+use crate::environment::RunEnvironment;
+use crate::exit_code::exit_with_internal_error;
+use crate::exit_code::RunExitCode;
+use crate::fixtures::Fixtures;
+use crate::fixtures::FixturesTeardownGuard;
+use crate::fixtures::WorkerFixture;
+use crate::handle::RunControl;
+use crate::history::BaselineComparison;
+use crate::history::HistoryReporter;
+use crate::history::HistoryRun;
+use crate::history::TestHistory;
+use crate::history::TimingThreshold;
+use crate::hooks::TestHooks;
+use crate::middleware::FnMiddleware;
+use crate::middleware::NextFunc;
+use crate::middleware::RunMiddleware;
+use crate::quarantine::Quarantine;
+use crate::reporter::CategoryTiming;
+use crate::reporter::DeterministicReporter;
+use crate::reporter::EventStreamReporter;
+use crate::reporter::HttpEventReporter;
+use crate::reporter::JunitReporter;
+use crate::reporter::LogReporter;
+use crate::reporter::MultiReporter;
+use crate::reporter::OutputReview;
+use crate::reporter::OverBudgetCategory;
+use crate::reporter::QuarantineUsage;
+use crate::reporter::Reporter;
+use crate::reporter::ReporterContext;
+use crate::reporter::ReporterFailure;
+use crate::reporter::StalledTest;
+use crate::seed;
+use crate::stacks;
+use crate::state::StateStore;
+use crate::timeouts::TimeoutOverrides;
+use crate::timeouts::Timeouts;
+use crate::timeouts::DEFAULT_POLL_INTERVAL;
+use crate::timeouts::DEFAULT_TIMEOUT;
+use crate::verbosity;
+use crate::worker::WorkerHandle;
+use crate::worker::WorkerOptions;
+use crate::worker::WorkerOutput;
+use crate::worker::WorkerProcess;
 
 type RunTestFunc<TData> =
   Arc<dyn (Fn(&CollectedTest<TData>) -> TestResult) + Send + Sync>;
-
-struct Failure<TData> {
-  test: CollectedTest<TData>,
-  output: Vec<u8>,
-}
+/// Gets the additional files a test reads. See `RunOptions::input_files`.
+pub type InputFilesFunc<TData> =
+  Arc<dyn (Fn(&CollectedTest<TData>) -> Vec<PathBuf>) + Send + Sync>;
+/// Gets the wall-clock budget of a category. See `RunOptions::category_budget`.
+pub type CategoryBudgetFunc<TData> = Arc<
+  dyn (Fn(&CollectedTestCategory<TData>) -> Option<Duration>) + Send + Sync,
+>;
+type PoolWorkerFunc<TData> =
+  Box<dyn (FnMut(&CollectedTest<TData>) -> TestResult) + Send>;
 
 struct Context<TData: Clone + Send + 'static> {
   thread_pool_runner: Option<ThreadPoolTestRunner<TData>>,
-  failures: Vec<Failure<TData>>,
-  run_test: RunTestFunc<TData>,
+  failures: Vec<ReporterFailure<TData>>,
+  /// Runs tests on the current thread.
+  run_test: PoolWorkerFunc<TData>,
+  reporter: Arc<dyn Reporter<TData>>,
+  cache: Option<ResultCache>,
+  input_files: Option<InputFilesFunc<TData>>,
+  control: Option<Arc<RunControl>>,
+  max_failures: Option<usize>,
+  verbosity: u8,
+  deterministic: bool,
+  capabilities: EvaluatedCapabilities,
+  deadline: Option<RunDeadline>,
+  quarantine: Quarantine,
+  /// Names of the failed tests absorbed by each quarantine pattern.
+  quarantined: Vec<Vec<String>>,
+  /// Number of tests that weren't run because the run ended early.
+  not_run: usize,
+  category_budget: Option<CategoryBudgetFunc<TData>>,
+  over_budget: Vec<OverBudgetCategory>,
+  category_timings: Vec<CategoryTiming>,
+  /// Durations of the tests that passed when comparing against a
+  /// baseline.
+  passed_tests: Option<HashMap<String, Duration>>,
+}
+
+/// Time the run must finish by, along with the expected duration of each
+/// test from the test history.
+struct RunDeadline {
+  at: Instant,
+  expected_durations: HashMap<String, Duration>,
+}
+
+/// Reason the tests that weren't started because of the deadline are
+/// reported as ignored with.
+const MISSED_DEADLINE_REASON: &str = "predicted to miss the deadline";
+
+impl RunDeadline {
+  /// Whether the test is predicted to not finish before the deadline,
+  /// where tests without a recorded duration are expected to be quick.
+  /// This is always the case once the deadline has passed.
+  fn is_missed_by(&self, test_name: &str) -> bool {
+    let expected = self
+      .expected_durations
+      .get(test_name)
+      .copied()
+      .unwrap_or_default();
+    Instant::now() + expected >= self.at
+  }
+}
+
+impl<TData: Clone + Send + 'static> Context<TData> {
+  fn is_cancelled(&self) -> bool {
+    self.control.as_ref().is_some_and(|c| c.is_cancelled())
+  }
+
+  /// Whether no more tests should be dispatched because the run was
+  /// cancelled or reached `RunOptions::max_failures`.
+  ///
+  /// Tests are still visited after the deadline so that each one is
+  /// reported by name.
+  fn is_ending_early(&self) -> bool {
+    self.is_cancelled()
+      || self
+        .max_failures
+        .is_some_and(|max| self.failures.len() >= max)
+  }
+
+  fn is_paused(&self) -> bool {
+    self.control.as_ref().is_some_and(|c| c.is_paused())
+  }
+
+  /// Gets the maximum number of tests to run at the same time.
+  fn parallelism(&self, pool_size: usize) -> usize {
+    if self.deterministic {
+      return 1;
+    }
+    match &self.control {
+      Some(control) => control.parallelism().clamp(1, pool_size),
+      None => pool_size,
+    }
+  }
+
+  /// Blocks while the run is paused, returning false when the
+  /// run was cancelled.
+  fn wait_while_paused(&self) -> bool {
+    self.control.as_ref().is_none_or(|c| c.wait_while_paused())
+  }
 }
 
 static GLOBAL_PANIC_HOOK_COUNT: Mutex<usize> = Mutex::new(0);
+static BACKTRACE_STYLE: Mutex<Option<BacktraceStyle>> = Mutex::new(None);
+
+/// Sets the backtrace style and verbosity of a run, restoring the
+/// previous values when dropped so they don't leak into later runs.
+struct RunSettingsGuard {
+  backtrace_style: Option<BacktraceStyle>,
+  verbosity: u8,
+}
+
+impl RunSettingsGuard {
+  fn install(backtrace_style: BacktraceStyle, verbosity: u8) -> Self {
+    let guard = Self {
+      backtrace_style: BACKTRACE_STYLE.lock().replace(backtrace_style),
+      verbosity: verbosity::verbosity(),
+    };
+    verbosity::set_verbosity(verbosity);
+    guard
+  }
+}
+
+impl Drop for RunSettingsGuard {
+  fn drop(&mut self) {
+    *BACKTRACE_STYLE.lock() = self.backtrace_style;
+    verbosity::set_verbosity(self.verbosity);
+  }
+}
 
 type PanicHook = Box<dyn Fn(&std::panic::PanicHookInfo) + Sync + Send>;
 
+/// Asks the reporter of the run whether to accept the actual output.
+type OutputReviewFunc = Rc<dyn Fn(&OutputReview) -> bool>;
+
 thread_local! {
   static LOCAL_PANIC_HOOK: RefCell<Option<PanicHook>> = RefCell::new(None);
+  static OUTPUT_REVIEWER: RefCell<Option<OutputReviewFunc>> =
+    const { RefCell::new(None) };
+}
+
+/// Asks the reporter of the run whether to accept the mismatched output of
+/// the test running on the current thread. Rejects it outside of a run.
+pub(crate) fn review_output(review: &OutputReview) -> bool {
+  let reviewer = OUTPUT_REVIEWER.with(|reviewer| reviewer.borrow().clone());
+  reviewer.is_some_and(|reviewer| reviewer(review))
+}
+
+/// Runs the test with its output reviews sent to `review`, returning the
+/// result along with the time taken excluding the time spent reviewing.
+fn run_with_reviewer(
+  run_test: impl FnOnce() -> TestResult,
+  review: impl Fn(&OutputReview) -> bool + 'static,
+) -> (Duration, TestResult) {
+  let review_time = Rc::new(Cell::new(Duration::ZERO));
+  let reviewer: OutputReviewFunc = Rc::new({
+    let review_time = review_time.clone();
+    move |output_review| {
+      let start = Instant::now();
+      let accepted = review(output_review);
+      review_time.set(review_time.get() + start.elapsed());
+      accepted
+    }
+  });
+  OUTPUT_REVIEWER.with(|current| *current.borrow_mut() = Some(reviewer));
+  let start = Instant::now();
+  let result = run_test();
+  OUTPUT_REVIEWER.with(|current| *current.borrow_mut() = None);
+  (start.elapsed().saturating_sub(review_time.get()), result)
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SubTestResult {
   pub name: String,
   pub result: TestResult,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum TestResult {
   /// Test passed.
   Passed,
@@ -50,21 +258,192 @@ pub enum TestResult {
   Ignored,
   /// Test failed, returning the captured output of the test.
   Failed { output: Vec<u8> },
+  /// Test failed, returning the captured output of the test along with
+  /// structured fields describing the failure (ex. `exit_code`), which
+  /// reporters show separately from the output. See
+  /// `TestResult::with_field`.
+  FailedWithFields {
+    output: Vec<u8>,
+    fields: BTreeMap<String, FailureValue>,
+  },
   /// Multiple sub tests were run.
   SubTests(Vec<SubTestResult>),
 }
 
+/// Value of a field of a failed test. See `TestResult::with_field`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum FailureValue {
+  Bool(bool),
+  Integer(i64),
+  String(String),
+}
+
+impl std::fmt::Display for FailureValue {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      FailureValue::Bool(value) => write!(f, "{}", value),
+      FailureValue::Integer(value) => write!(f, "{}", value),
+      FailureValue::String(value) => write!(f, "{}", value),
+    }
+  }
+}
+
+impl From<bool> for FailureValue {
+  fn from(value: bool) -> Self {
+    Self::Bool(value)
+  }
+}
+
+impl From<i32> for FailureValue {
+  fn from(value: i32) -> Self {
+    Self::Integer(value as i64)
+  }
+}
+
+impl From<i64> for FailureValue {
+  fn from(value: i64) -> Self {
+    Self::Integer(value)
+  }
+}
+
+impl From<&str> for FailureValue {
+  fn from(value: &str) -> Self {
+    Self::String(value.to_string())
+  }
+}
+
+impl From<String> for FailureValue {
+  fn from(value: String) -> Self {
+    Self::String(value)
+  }
+}
+
+impl From<&Path> for FailureValue {
+  fn from(value: &Path) -> Self {
+    Self::String(value.display().to_string())
+  }
+}
+
 impl TestResult {
+  /// Creates a failed result with the captured output of the test.
+  pub fn failed(output: impl Into<Vec<u8>>) -> Self {
+    TestResult::Failed {
+      output: output.into(),
+    }
+  }
+
+  /// Adds a structured field to a failed test's result (ex.
+  /// `.with_field("exit_code", 1)`), which does nothing when the
+  /// test didn't fail.
+  pub fn with_field(
+    self,
+    name: impl Into<String>,
+    value: impl Into<FailureValue>,
+  ) -> Self {
+    match self {
+      TestResult::Failed { output } => TestResult::FailedWithFields {
+        output,
+        fields: BTreeMap::from([(name.into(), value.into())]),
+      },
+      TestResult::FailedWithFields { output, mut fields } => {
+        fields.insert(name.into(), value.into());
+        TestResult::FailedWithFields { output, fields }
+      }
+      result => result,
+    }
+  }
+
+  /// Gets the output of a failed test, which is `None` for results that
+  /// aren't a failure of the test itself (ex. failed sub tests).
+  pub(crate) fn output_mut(&mut self) -> Option<&mut Vec<u8>> {
+    match self {
+      TestResult::Failed { output }
+      | TestResult::FailedWithFields { output, .. } => Some(output),
+      TestResult::Passed | TestResult::Ignored | TestResult::SubTests(_) => {
+        None
+      }
+    }
+  }
+
   pub fn is_failed(&self) -> bool {
     match self {
       TestResult::Passed | TestResult::Ignored => false,
-      TestResult::Failed { .. } => true,
+      TestResult::Failed { .. } | TestResult::FailedWithFields { .. } => true,
       TestResult::SubTests(sub_tests) => {
         sub_tests.iter().any(|s| s.result.is_failed())
       }
     }
   }
 
+  /// Gets the captured output of the test and any failed sub tests.
+  pub(crate) fn failure_output(&self) -> Vec<u8> {
+    fn collect_sub_tests(sub_tests: &[SubTestResult], output: &mut Vec<u8>) {
+      for sub_test in sub_tests {
+        match &sub_test.result {
+          TestResult::Passed | TestResult::Ignored => {}
+          TestResult::Failed { output: sub_output }
+          | TestResult::FailedWithFields {
+            output: sub_output, ..
+          } => {
+            if !output.is_empty() {
+              output.push(b'\n');
+            }
+            output.extend(sub_output);
+          }
+          TestResult::SubTests(sub_tests) => {
+            collect_sub_tests(sub_tests, output);
+          }
+        }
+      }
+    }
+
+    match self {
+      TestResult::Passed | TestResult::Ignored => Vec::new(),
+      TestResult::Failed { output }
+      | TestResult::FailedWithFields { output, .. } => output.clone(),
+      TestResult::SubTests(sub_tests) => {
+        let mut output = Vec::new();
+        collect_sub_tests(sub_tests, &mut output);
+        output
+      }
+    }
+  }
+
+  /// Gets the structured fields of the test and any failed sub tests, where
+  /// the fields of sub tests are prefixed with the sub test's name.
+  pub(crate) fn failure_fields(&self) -> BTreeMap<String, FailureValue> {
+    fn collect_fields(
+      prefix: &str,
+      result: &TestResult,
+      fields: &mut BTreeMap<String, FailureValue>,
+    ) {
+      match result {
+        TestResult::Passed
+        | TestResult::Ignored
+        | TestResult::Failed { .. } => {}
+        TestResult::FailedWithFields {
+          fields: result_fields,
+          ..
+        } => {
+          for (name, value) in result_fields {
+            fields.insert(format!("{}{}", prefix, name), value.clone());
+          }
+        }
+        TestResult::SubTests(sub_tests) => {
+          for sub_test in sub_tests {
+            let prefix = format!("{}{}::", prefix, sub_test.name);
+            collect_fields(&prefix, &sub_test.result, fields);
+          }
+        }
+      }
+    }
+
+    let mut fields = BTreeMap::new();
+    collect_fields("", self, &mut fields);
+    fields
+  }
+
   /// Allows using a closure that may panic, capturing the panic message and
   /// returning it as a TestResult::Failed.
   ///
@@ -139,48 +518,657 @@ impl TestResult {
       drop(hook_count); // explicit for clarity, drop after taking the hook
     }
 
-    result.unwrap_or_else(|_| TestResult::Failed {
-      output: panic_message.lock().clone(),
-    })
+    result.unwrap_or_else(|_| TestResult::failed(panic_message.lock().clone()))
+  }
+}
+
+/// How backtraces are included in the output of the panics captured by
+/// `TestResult::from_maybe_panic`.
+///
+/// This can be overridden by setting the `FILE_TEST_RUNNER_BACKTRACE`
+/// environment variable to `short`, `full`, or `off`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BacktraceStyle {
+  /// Captured when enabled via `RUST_BACKTRACE`, with the frames of the
+  /// panic machinery removed from the start.
+  #[default]
+  Short,
+  /// Always captured and never trimmed, which is useful when the trimming
+  /// misfires (ex. with custom panic hooks).
+  Full,
+  /// Never included.
+  Off,
+}
+
+impl BacktraceStyle {
+  pub fn parse(text: &str) -> Option<Self> {
+    match text.to_lowercase().as_str() {
+      "short" => Some(Self::Short),
+      "full" => Some(Self::Full),
+      "off" | "none" | "0" => Some(Self::Off),
+      _ => None,
+    }
+  }
+
+  fn current() -> Self {
+    std::env::var("FILE_TEST_RUNNER_BACKTRACE")
+      .ok()
+      .and_then(|value| Self::parse(&value))
+      .or(*BACKTRACE_STYLE.lock())
+      .unwrap_or_default()
   }
 }
 
 fn capture_backtrace() -> Option<String> {
-  let backtrace = std::backtrace::Backtrace::capture();
+  let style = BacktraceStyle::current();
+  let backtrace = match style {
+    BacktraceStyle::Short => std::backtrace::Backtrace::capture(),
+    BacktraceStyle::Full => std::backtrace::Backtrace::force_capture(),
+    BacktraceStyle::Off => return None,
+  };
   if backtrace.status() != std::backtrace::BacktraceStatus::Captured {
     return None;
   }
-  let text = format!("{}", backtrace);
+  Some(format_backtrace(format!("{}", backtrace), style))
+}
+
+fn format_backtrace(text: String, style: BacktraceStyle) -> String {
+  if style == BacktraceStyle::Full {
+    return text;
+  }
   // strip the code in this crate from the start of the backtrace
   let lines = text.lines().collect::<Vec<_>>();
   let last_position = lines
     .iter()
     .position(|line| line.contains("core::panicking::panic_fmt"));
-  Some(match last_position {
+  match last_position {
     Some(position) => lines[position + 2..].join("\n"),
     None => text,
-  })
+  }
 }
 
-#[derive(Debug, Clone)]
-pub struct RunOptions {
+#[derive(Clone)]
+pub struct RunOptions<TData = ()> {
   /// Whether to run tests in parallel. By default, this will parallelize the
   /// tests across all available threads, minus one.
   ///
   /// This can be overridden by setting the `FILE_TEST_RUNNER_PARALLELISM`
   /// environment variable to the desired number of parallel threads.
   pub parallel: bool,
+  /// Run the tests one at a time ordered by name and report their
+  /// durations as zero, so the output of the `reporter` is the same across
+  /// runs (ex. for snapshot testing a custom reporter). Long running and
+  /// stalled tests aren't reported to the `reporter`.
+  ///
+  /// Tests still run on the threads or worker processes of the pool when
+  /// `parallel` or `workers` are set, but only one is dispatched at a time.
+  pub deterministic: bool,
+  /// Hooks to call around the run and each test.
+  pub hooks: Option<Arc<dyn TestHooks<TData>>>,
+  /// Middleware that wraps the running of each test, outermost last.
+  /// See `RunOptions::wrap`.
+  pub middleware: Vec<Arc<dyn RunMiddleware<TData>>>,
+  /// Reporter to use for displaying the results of the run.
+  ///
+  /// Defaults to the `LogReporter`.
+  pub reporter: Arc<dyn Reporter<TData>>,
+  /// Only run the tests with these fully resolved names, which allows
+  /// external schedulers to decide which tests to run. This is applied
+  /// before Bazel's test sharding. Exits with `RunExitCode::InternalError`
+  /// if a test can't be found.
+  pub only: Option<Vec<String>>,
+  /// Run the tests in worker processes instead of on threads in the
+  /// current process. The `parallel` option is ignored when set.
+  pub workers: Option<WorkerOptions>,
+  /// Print what tests in worker processes write to stdout as it arrives
+  /// instead of only including it in the output of failed tests. Each
+  /// line is prefixed with the test's name when there are several
+  /// workers. Tests run on threads always print directly.
+  ///
+  /// Defaults to whether `--nocapture` was passed to the test binary.
+  pub no_capture: bool,
+  /// Maximum number of bytes of output captured from each test in a
+  /// worker process. Once exceeded, the middle of the output is dropped
+  /// while its head and tail are kept.
+  pub max_output_size: Option<usize>,
+  /// How backtraces are included in the output of tests that panicked.
+  pub backtrace_style: BacktraceStyle,
+  /// Fixture set up once per worker thread or process. Tests access it
+  /// via `WorkerFixture::with`.
+  pub worker_fixture: Option<WorkerFixture>,
+  /// Directory where state is persisted across runs, such as the test
+  /// history and result cache.
+  pub state: StateStore,
+  /// Names of environment variables recorded in the `RunEnvironment` in
+  /// addition to `CI`, `RUST_BACKTRACE`, `RUST_LOG`, and the ones
+  /// starting with `FILE_TEST_RUNNER_`.
+  pub recorded_env_vars: Vec<String>,
+  /// Append the results of the run to the test history, which can be
+  /// used to find flaky tests. See `file_test_runner::history::TestHistory`.
+  pub record_history: bool,
+  /// Write the results to a JUnit XML file at this path at the end of the
+  /// run (ex. for a CI test results dashboard). See `JunitReporter`.
+  pub junit_output: Option<PathBuf>,
+  /// Previous run to classify the failures against as new, still failing,
+  /// or fixed (see `HistoryRun::load_last`). Defaults to the last run of
+  /// the history file at the `FILE_TEST_RUNNER_BASELINE` environment
+  /// variable when set.
+  pub baseline: Option<HistoryRun>,
+  /// Report the tests that slowed down beyond this threshold compared to
+  /// the `baseline`.
+  pub timing_threshold: Option<TimingThreshold>,
+  /// Fail the run when tests slowed down beyond the `timing_threshold`.
+  pub fail_on_timing_regression: bool,
+  /// Skip tests whose file hasn't changed since they last passed and
+  /// report them as cached. Changing the test binary invalidates the cache.
+  pub cache_results: bool,
+  /// Gets the additional files or directories a test reads (ex. shared
+  /// fixtures), which are hashed along with the test file when caching
+  /// results so the test runs again when they change.
+  pub input_files: Option<InputFilesFunc<TData>>,
+  /// Seed of the run that the seed of each test is derived from (see
+  /// `file_test_runner::test_seed`). Defaults to the value of the
+  /// `FILE_TEST_RUNNER_SEED` environment variable or zero.
+  pub seed: Option<u64>,
+  /// Duration after which a running test is reported as long running.
+  /// Defaults to 60 seconds.
+  pub timeout: Option<Duration>,
+  /// Timeouts of the tests matching a pattern, which take precedence
+  /// over `timeout`.
+  pub timeout_overrides: TimeoutOverrides,
+  /// Fail tests that are still running this long after exceeding their
+  /// timeout. Worker processes running the test are killed and restarted,
+  /// while tests running on threads can't be stopped, so they're left
+  /// running in the background and a new thread takes their place so the
+  /// run keeps its parallelism. Defaults to only reporting long running
+  /// tests.
+  pub kill_after: Option<Duration>,
+  /// How often running tests are checked for exceeding their timeout,
+  /// which is also how often long running tests are reported to the
+  /// reporter. Defaults to 1 second.
+  pub watchdog_interval: Option<Duration>,
+  /// Report the pending tests along with their elapsed time and path
+  /// when no test has completed for this long, which helps diagnose a
+  /// run that is stuck before CI kills it.
+  pub stall_timeout: Option<Duration>,
+  /// Include the stacks of the process running a test in its output when
+  /// it's failed via `kill_after`, and print the stacks of the running
+  /// tests when the process receives SIGQUIT. This uses gdb or lldb and
+  /// is only supported on Unix.
+  pub dump_stacks: bool,
+  /// Gets the wall-clock budget for running a category, including its
+  /// sub categories. Categories that exceed their budget are reported at
+  /// the end of the run.
+  pub category_budget: Option<CategoryBudgetFunc<TData>>,
+  /// Fail the run when a category exceeds its budget.
+  pub fail_over_budget: bool,
+  /// Stop running tests once this many tests failed (ex. `Some(1)` to fail
+  /// fast). Tests that are already running finish, while the remaining
+  /// tests of all the categories are reported as not run.
+  pub max_failures: Option<usize>,
+  /// Fail the run when no tests match the filter. This is off by default
+  /// because `cargo test <filter>` passes the filter to every test binary.
+  pub fail_when_no_tests_match: bool,
+  /// Patterns of the tests whose failures don't fail the run, which are
+  /// reported along with the failures each pattern absorbed.
+  pub quarantine: Quarantine,
+  /// Wall-clock time the run must finish within, measured from when it
+  /// starts (ex. to get partial results before a CI job's time limit).
+  /// Tests predicted to not finish in time from their last duration in
+  /// the test history aren't started and are reported as ignored, as are
+  /// the remaining tests once the deadline passes. The run fails with
+  /// `RunExitCode::TestsNotRun` when any test wasn't run. Defaults to the
+  /// `FILE_TEST_RUNNER_DEADLINE` environment variable in seconds when set.
+  pub deadline: Option<Duration>,
+  /// Capabilities the tests may require via `CollectedTest::requires`,
+  /// where tests whose requirements aren't met are reported as ignored.
+  pub capabilities: Capabilities,
+  /// Level of extra diagnostics reporters and tests print (see
+  /// `file_test_runner::verbosity`). Defaults to the number of `-v` or
+  /// `--verbose` flags or the `FILE_TEST_RUNNER_VERBOSE` environment
+  /// variable.
+  pub verbosity: u8,
+  /// What `run_tests` does when the run fails. Defaults to panicking.
+  pub on_failure: OnFailure,
+}
+
+impl<TData> std::fmt::Debug for RunOptions<TData> {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("RunOptions")
+      .field("parallel", &self.parallel)
+      .field("deterministic", &self.deterministic)
+      .field("hooks", &self.hooks.is_some())
+      .field("middleware", &self.middleware.len())
+      .field("only", &self.only)
+      .field("workers", &self.workers)
+      .field("no_capture", &self.no_capture)
+      .field("max_output_size", &self.max_output_size)
+      .field("backtrace_style", &self.backtrace_style)
+      .field("worker_fixture", &self.worker_fixture)
+      .field("state", &self.state)
+      .field("recorded_env_vars", &self.recorded_env_vars)
+      .field("record_history", &self.record_history)
+      .field("junit_output", &self.junit_output)
+      .field("baseline", &self.baseline)
+      .field("timing_threshold", &self.timing_threshold)
+      .field("fail_on_timing_regression", &self.fail_on_timing_regression)
+      .field("cache_results", &self.cache_results)
+      .field("input_files", &self.input_files.is_some())
+      .field("seed", &self.seed)
+      .field("timeout", &self.timeout)
+      .field("timeout_overrides", &self.timeout_overrides)
+      .field("kill_after", &self.kill_after)
+      .field("watchdog_interval", &self.watchdog_interval)
+      .field("stall_timeout", &self.stall_timeout)
+      .field("dump_stacks", &self.dump_stacks)
+      .field("category_budget", &self.category_budget.is_some())
+      .field("fail_over_budget", &self.fail_over_budget)
+      .field("max_failures", &self.max_failures)
+      .field("fail_when_no_tests_match", &self.fail_when_no_tests_match)
+      .field("quarantine", &self.quarantine)
+      .field("deadline", &self.deadline)
+      .field("capabilities", &self.capabilities)
+      .field("verbosity", &self.verbosity)
+      .field("on_failure", &self.on_failure)
+      .finish_non_exhaustive()
+  }
+}
+
+impl<TData: 'static> Default for RunOptions<TData> {
+  fn default() -> Self {
+    Self {
+      parallel: false,
+      deterministic: false,
+      hooks: None,
+      middleware: Vec::new(),
+      reporter: Arc::new(LogReporter::default()),
+      only: None,
+      workers: None,
+      no_capture: std::env::args().any(|arg| arg == "--nocapture"),
+      max_output_size: None,
+      backtrace_style: BacktraceStyle::default(),
+      worker_fixture: None,
+      state: StateStore::default(),
+      recorded_env_vars: Vec::new(),
+      record_history: false,
+      junit_output: None,
+      baseline: None,
+      timing_threshold: None,
+      fail_on_timing_regression: false,
+      cache_results: false,
+      input_files: None,
+      seed: None,
+      timeout: None,
+      timeout_overrides: TimeoutOverrides::default(),
+      kill_after: None,
+      watchdog_interval: None,
+      stall_timeout: None,
+      dump_stacks: false,
+      category_budget: None,
+      fail_over_budget: false,
+      max_failures: None,
+      fail_when_no_tests_match: false,
+      quarantine: Quarantine::default(),
+      deadline: None,
+      capabilities: Capabilities::default(),
+      verbosity: verbosity::verbosity_from_env(),
+      on_failure: OnFailure::default(),
+    }
+  }
+}
+
+impl<TData> RunOptions<TData> {
+  /// Wraps the running of each test with the provided closure, which
+  /// calls `next` to run the test (ex. `options.wrap(|next, test| next(test))`).
+  ///
+  /// Each call wraps the previous ones, so the last middleware added is
+  /// the outermost. Hooks are called within all the middleware.
+  pub fn wrap(
+    self,
+    middleware: impl Fn(NextFunc<'_, TData>, &CollectedTest<TData>) -> TestResult
+      + Send
+      + Sync
+      + 'static,
+  ) -> Self
+  where
+    TData: 'static,
+  {
+    self.with_middleware(FnMiddleware(middleware))
+  }
+
+  /// Wraps the running of each test with the provided middleware.
+  pub fn with_middleware(
+    mut self,
+    middleware: impl RunMiddleware<TData> + 'static,
+  ) -> Self {
+    self.middleware.push(Arc::new(middleware));
+    self
+  }
 }
 
+/// What to do at the end of `run_tests` when tests failed, or when
+/// enabled, categories exceeded their budget or no tests matched the
+/// filter.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnFailure {
+  /// Panic with the number of failures.
+  #[default]
+  Panic,
+  /// Exit the process with the `RunExitCode` for the failure, which
+  /// avoids the extra panic output in CI logs.
+  Exit,
+  /// Return the summary from `run_tests` so the caller can run follow-up
+  /// logic before deciding how to fail.
+  Return,
+}
+
+/// Runs the tests in the provided category.
+///
+/// When run via `bazel test`, this honors Bazel's test sharding
+/// environment variables and writes the results to `XML_OUTPUT_FILE`.
+///
+/// Setting the `FILE_TEST_RUNNER_EVENTS` environment variable to a file
+/// path will additionally write a stream of events to that file. See
+/// `EventStreamReporter` for more details.
+///
+/// When `--list` is passed to the test binary, the tests are printed to
+/// stdout instead of being run. Add `--format json` to print them as a
+/// JSON array of objects with the name, path, line, and column.
+///
+/// Failures are handled according to `RunOptions::on_failure`.
 pub fn run_tests<TData: Clone + Send + 'static>(
   category: &CollectedTestCategory<TData>,
-  options: RunOptions,
+  options: RunOptions<TData>,
   run_test: impl (Fn(&CollectedTest<TData>) -> TestResult) + Send + Sync + 'static,
-) {
+) -> RunSummary {
+  if let Some(format) = crate::list::list_format_from_args() {
+    if !crate::worker::is_worker_process() {
+      print!("{}", crate::list::list_tests(category, format));
+      return RunSummary::default();
+    }
+  }
+  let fail_over_budget = options.fail_over_budget;
+  let fail_on_timing_regression = options.fail_on_timing_regression;
+  let fail_when_no_tests_match = options.fail_when_no_tests_match;
+  let on_failure = options.on_failure;
+  let summary = run_tests_inner(category, options, run_test, None);
+  let failure = run_failure(
+    &summary,
+    fail_over_budget,
+    fail_on_timing_regression,
+    fail_when_no_tests_match,
+  );
+  if let Some((exit_code, failure)) = failure {
+    match on_failure {
+      OnFailure::Panic => panic!("{}", failure),
+      OnFailure::Exit => {
+        eprintln!("{}", failure);
+        exit_code.exit()
+      }
+      OnFailure::Return => {}
+    }
+  }
+  summary
+}
+
+/// Gets the exit code and message of a run that should fail.
+fn run_failure(
+  summary: &RunSummary,
+  fail_over_budget: bool,
+  fail_on_timing_regression: bool,
+  fail_when_no_tests_match: bool,
+) -> Option<(RunExitCode, String)> {
+  if !summary.failed_tests.is_empty() {
+    Some((
+      RunExitCode::TestsFailed,
+      format!(
+        "{} failed of {}",
+        summary.failed_tests.len(),
+        summary.total_tests
+      ),
+    ))
+  } else if summary.not_run > 0 {
+    Some((
+      RunExitCode::TestsNotRun,
+      format!(
+        "{} of {} tests were not run before the deadline",
+        summary.not_run, summary.total_tests
+      ),
+    ))
+  } else if fail_over_budget && !summary.over_budget_categories.is_empty() {
+    Some((
+      RunExitCode::TestsFailed,
+      format!(
+        "{} categories exceeded their time budget",
+        summary.over_budget_categories.len()
+      ),
+    ))
+  } else if fail_on_timing_regression && !summary.timing_regressions.is_empty()
+  {
+    Some((
+      RunExitCode::TestsFailed,
+      format!(
+        "{} tests were slower than in the baseline",
+        summary.timing_regressions.len()
+      ),
+    ))
+  } else if fail_when_no_tests_match
+    && summary.total_tests == 0
+    && !crate::worker::is_worker_process()
+  {
+    Some((
+      RunExitCode::NoTestsMatched,
+      "No tests matched the filter".to_string(),
+    ))
+  } else {
+    None
+  }
+}
+
+/// Runs the test with the provided fully resolved name on the current
+/// thread without reporting it, which is useful for tools that want to
+/// run tests one at a time.
+///
+/// Panics are captured as failures and a failure is returned when the
+/// test can't be found.
+pub fn run_single_test<TData>(
+  category: &CollectedTestCategory<TData>,
+  name: &str,
+  run_test: &dyn Fn(&CollectedTest<TData>) -> TestResult,
+) -> TestResult {
+  match category.find_test(name) {
+    Some(test) => TestResult::from_maybe_panic_or_result(
+      std::panic::AssertUnwindSafe(|| run_test(test)),
+    ),
+    None => {
+      TestResult::failed(format!("Test '{}' was not found.", name).into_bytes())
+    }
+  }
+}
+
+/// Outcome of a run.
+#[derive(Debug, Clone, Default)]
+pub struct RunSummary {
+  pub total_tests: usize,
+  /// Names of the tests that failed.
+  pub failed_tests: Vec<String>,
+  /// Whether the run was cancelled before all the tests were run.
+  pub cancelled: bool,
+  /// Number of tests that weren't run because the run was cancelled,
+  /// reached `RunOptions::max_failures`, or would have missed
+  /// `RunOptions::deadline`.
+  pub not_run: usize,
+  /// Conditions the tests were run in.
+  pub environment: Option<RunEnvironment>,
+  /// Names of the categories that exceeded their time budget.
+  pub over_budget_categories: Vec<String>,
+  /// Failures classified against `RunOptions::baseline`.
+  pub baseline: Option<BaselineComparison>,
+  /// Names of the tests that slowed down beyond
+  /// `RunOptions::timing_threshold`.
+  pub timing_regressions: Vec<String>,
+  /// Names of the failed tests that didn't fail the run because they
+  /// matched `RunOptions::quarantine`.
+  pub quarantined_failures: Vec<String>,
+}
+
+pub(crate) fn run_tests_inner<TData: Clone + Send + 'static>(
+  category: &CollectedTestCategory<TData>,
+  options: RunOptions<TData>,
+  run_test: impl (Fn(&CollectedTest<TData>) -> TestResult) + Send + Sync + 'static,
+  control: Option<Arc<RunControl>>,
+) -> RunSummary {
+  let _settings_guard =
+    RunSettingsGuard::install(options.backtrace_style, options.verbosity);
+  // a panic that escapes the closure would otherwise take down the worker
+  // thread or process, so capture it as a failure
+  let run_test = move |test: &CollectedTest<TData>| {
+    assertions::with_test_assertions(|| {
+      TestResult::from_maybe_panic_or_result(std::panic::AssertUnwindSafe(
+        || run_test(test),
+      ))
+    })
+  };
+  let mut run_test: RunTestFunc<TData> = match options.hooks.clone() {
+    Some(hooks) => Arc::new(move |test| {
+      hooks.before_test(test);
+      let mut result = run_test(test);
+      hooks.after_test(test, &mut result);
+      result
+    }),
+    None => Arc::new(run_test),
+  };
+  for middleware in &options.middleware {
+    let middleware = middleware.clone();
+    let next = run_test;
+    run_test = Arc::new(move |test| middleware.run(&*next, test));
+  }
+  let run_seed = seed::resolve_run_seed(options.seed);
+  let run_test = {
+    let next = run_test;
+    Arc::new(move |test: &CollectedTest<TData>| {
+      let seed = seed::derive_test_seed(run_seed, &test.name);
+      let (mut result, used) = seed::with_test_seed(seed, || next(test));
+      if let (true, Some(output)) = (used, result.output_mut()) {
+        output.extend(format!("\nTest seed: {}\n", seed).into_bytes());
+      }
+      if used {
+        // seeds use the full range of a u64, so keep them exact
+        result = result.with_field("seed", seed.to_string());
+      }
+      result
+    })
+  };
+
+  let fixtures = Arc::new(Fixtures::default());
+  Fixtures::set_current(Some(fixtures.clone()));
+  let fixtures_guard = FixturesTeardownGuard(fixtures.clone());
+
+  if crate::worker::is_worker_process() {
+    if let Some(hooks) = &options.hooks {
+      hooks.on_run_start(category);
+    }
+    let fixture_guard = options.worker_fixture.as_ref().map(|f| f.install());
+    crate::worker::run_worker(category, &*run_test);
+    drop(fixture_guard);
+    if let Some(hooks) = &options.hooks {
+      hooks.on_run_end(category);
+    }
+    drop(fixtures_guard);
+    return RunSummary::default();
+  }
+
+  let bazel_env = BazelEnv::from_env();
+  if let Err(err) = bazel_env.touch_shard_status_file() {
+    exit_with_internal_error(err);
+  }
+  // filter before sharding so the shards split the selected tests
+  let only_category;
+  let category = match &options.only {
+    Some(names) => {
+      only_category = match filter_only(category, names) {
+        Ok(category) => category,
+        Err(err) => exit_with_internal_error(err),
+      };
+      &only_category
+    }
+    None => category,
+  };
+
+  let sharded_category;
+  let category = match &bazel_env.shard {
+    Some(shard) => {
+      sharded_category = shard.apply(category);
+      &sharded_category
+    }
+    None => category,
+  };
+
   let total_tests = category.test_count();
   if total_tests == 0 {
-    return; // no tests to run because they were filtered out
+    // no tests to run because they were filtered out
+    return RunSummary::default();
+  }
+
+  let deadline = options
+    .deadline
+    .or_else(|| {
+      let seconds = std::env::var("FILE_TEST_RUNNER_DEADLINE").ok()?;
+      match seconds.parse() {
+        Ok(seconds) => Some(Duration::from_secs(seconds)),
+        Err(_) => exit_with_internal_error(format!(
+          "Invalid FILE_TEST_RUNNER_DEADLINE '{}', expected a number of seconds.",
+          seconds
+        )),
+      }
+    })
+    .map(|deadline| RunDeadline {
+      at: Instant::now() + deadline,
+      expected_durations: match TestHistory::load_from_store(&options.state) {
+        Ok(history) => history.last_durations(),
+        Err(err) => exit_with_internal_error(err),
+      },
+    });
+
+  let baseline = options.baseline.or_else(|| {
+    let path = std::env::var_os("FILE_TEST_RUNNER_BASELINE")
+      .filter(|v| !v.is_empty())?;
+    match HistoryRun::load_last(Path::new(&path)) {
+      Ok(baseline) => baseline,
+      Err(err) => exit_with_internal_error(err),
+    }
+  });
+
+  let reporter: Arc<dyn Reporter<TData>> = if options.deterministic {
+    Arc::new(DeterministicReporter(options.reporter))
+  } else {
+    options.reporter
+  };
+  let mut reporters = vec![reporter];
+  match EventStreamReporter::from_env() {
+    Ok(Some(reporter)) => reporters.push(Arc::new(reporter)),
+    Ok(None) => {}
+    Err(err) => exit_with_internal_error(err),
+  }
+  match HttpEventReporter::from_env() {
+    Ok(Some(reporter)) => reporters.push(Arc::new(reporter)),
+    Ok(None) => {}
+    Err(err) => exit_with_internal_error(err),
+  }
+  if options.record_history {
+    reporters.push(Arc::new(HistoryReporter::new(options.state.clone())));
   }
+  for path in [&bazel_env.xml_output_file, &options.junit_output]
+    .into_iter()
+    .flatten()
+  {
+    reporters.push(Arc::new(JunitReporter::new(path.clone())));
+  }
+  let reporter: Arc<dyn Reporter<TData>> = if reporters.len() == 1 {
+    reporters.remove(0)
+  } else {
+    Arc::new(MultiReporter(reporters))
+  };
 
   let parallelism = if options.parallel {
     std::cmp::max(
@@ -198,411 +1186,2541 @@ pub fn run_tests<TData: Clone + Send + 'static>(
   } else {
     1
   };
-  let run_test = Arc::new(run_test);
-  let thread_pool_runner = if parallelism > 1 {
-    Some(ThreadPoolTestRunner::new(parallelism, run_test.clone()))
+  let environment = RunEnvironment::capture(
+    options
+      .workers
+      .as_ref()
+      .map(|workers| workers.commands.len())
+      .unwrap_or(parallelism),
+    &options.recorded_env_vars,
+  );
+  reporter.report_run_environment(&environment);
+  if let Some(hooks) = &options.hooks {
+    hooks.on_run_start(category);
+  }
+  let timeouts = Timeouts {
+    default: options.timeout.unwrap_or(DEFAULT_TIMEOUT),
+    overrides: options.timeout_overrides,
+    kill_after: options.kill_after,
+    poll_interval: options.watchdog_interval.unwrap_or(DEFAULT_POLL_INTERVAL),
+    stall_after: options.stall_timeout,
+    dump_stacks: options.dump_stacks,
+  };
+  let thread_pool_runner = if let Some(workers) = options.workers {
+    let commands = workers.commands;
+    let cpu_affinity = workers.cpu_affinity.unwrap_or_default();
+    let limits = workers.limits;
+    let retry_crashed_tests = workers.retry_crashed_tests;
+    let max_output_size = options.max_output_size;
+    let output_mode = if !options.no_capture {
+      WorkerOutput::Capture
+    } else if commands.len() > 1 {
+      WorkerOutput::StreamPrefixed
+    } else {
+      WorkerOutput::Stream
+    };
+    Some(ThreadPoolTestRunner::new(
+      commands.len(),
+      move |index| {
+        let command = commands[index].clone();
+        let cpu_core = (!cpu_affinity.is_empty())
+          .then(|| cpu_affinity[index % cpu_affinity.len()]);
+        let limits = limits.clone();
+        let handle = Arc::new(Mutex::new(None::<WorkerHandle>));
+        let spawn = {
+          let handle = handle.clone();
+          move || {
+            let worker = WorkerProcess::spawn(
+              &command,
+              cpu_core,
+              &limits,
+              output_mode,
+              max_output_size,
+            );
+            *handle.lock() = worker.as_ref().ok().map(|w| w.handle());
+            worker.map_err(|err| (command.join(" "), err))
+          }
+        };
+        let mut worker = spawn();
+        PoolWorker {
+          run: Box::new(move |test| {
+            let run_test = |worker: &mut Result<WorkerProcess, _>| match worker
+            {
+              Ok(worker) => worker.run_test(test),
+              Err((command, err)) => TestResult::failed(format!(
+                "Failed to spawn worker '{}': {:#}",
+                command, err
+              )),
+            };
+            let mut result = run_test(&mut worker);
+            if retry_crashed_tests
+              && worker.as_mut().is_ok_and(|worker| worker.has_crashed())
+            {
+              worker = spawn();
+              result = run_test(&mut worker);
+            }
+            // restart workers that crashed or were killed
+            if worker.as_mut().is_ok_and(|worker| worker.has_exited()) {
+              worker = spawn();
+            }
+            result
+          }),
+          kill: Some(Box::new({
+            let handle = handle.clone();
+            move || {
+              if let Some(handle) = &*handle.lock() {
+                handle.kill();
+              }
+            }
+          })),
+          process_id: Some(Box::new({
+            let handle = handle.clone();
+            move || handle.lock().as_ref().map(|handle| handle.id())
+          })),
+          output: Some(Box::new(move || {
+            handle
+              .lock()
+              .as_ref()
+              .map(|handle| handle.output())
+              .unwrap_or_default()
+          })),
+        }
+      },
+      reporter.clone(),
+      timeouts,
+    ))
+  } else if parallelism > 1 {
+    let run_test = run_test.clone();
+    let fixtures = fixtures.clone();
+    let worker_fixture = options.worker_fixture.clone();
+    Some(ThreadPoolTestRunner::new(
+      parallelism,
+      move |_| PoolWorker {
+        run: worker_run_func(
+          run_test.clone(),
+          fixtures.clone(),
+          worker_fixture.clone(),
+        ),
+        kill: None,
+        process_id: None,
+        output: None,
+      },
+      reporter.clone(),
+      timeouts,
+    ))
   } else {
     None
   };
   let mut context = Context {
     thread_pool_runner,
     failures: Vec::new(),
-    run_test,
+    run_test: worker_run_func(
+      run_test,
+      fixtures.clone(),
+      options.worker_fixture.clone(),
+    ),
+    reporter,
+    cache: options
+      .cache_results
+      .then(|| ResultCache::load(options.state.clone())),
+    input_files: options.input_files,
+    control,
+    max_failures: options.max_failures,
+    verbosity: options.verbosity,
+    deterministic: options.deterministic,
+    capabilities: options.capabilities.evaluate(category),
+    deadline,
+    quarantined: vec![Vec::new(); options.quarantine.patterns().count()],
+    quarantine: options.quarantine,
+    not_run: 0,
+    category_budget: options.category_budget,
+    over_budget: Vec::new(),
+    category_timings: Vec::new(),
+    passed_tests: baseline.as_ref().map(|_| HashMap::new()),
   };
   run_category(category, &mut context);
-
-  eprintln!();
-  if !context.failures.is_empty() {
-    eprintln!("spec failures:");
-    eprintln!();
-    for failure in &context.failures {
-      eprintln!("---- {} ----", failure.test.name);
-      eprintln!("{}", String::from_utf8_lossy(&failure.output));
-      eprintln!("Test file: {}", failure.test.path.display());
-      eprintln!();
-    }
-    eprintln!("failures:");
-    for failure in &context.failures {
-      eprintln!("    {}", failure.test.name);
-    }
-    eprintln!();
-    panic!("{} failed of {}", context.failures.len(), total_tests);
-  } else {
-    eprintln!("{} tests passed", total_tests);
+  let Context {
+    thread_pool_runner,
+    failures,
+    run_test,
+    reporter,
+    cache,
+    input_files: _,
+    control,
+    max_failures: _,
+    verbosity: _,
+    deterministic: _,
+    capabilities: _,
+    deadline: _,
+    quarantine,
+    quarantined,
+    not_run,
+    category_budget: _,
+    over_budget,
+    category_timings,
+    passed_tests,
+  } = context;
+  // wait for the workers to finish so their fixtures are torn down
+  drop(thread_pool_runner);
+  drop(run_test);
+  if let Some(hooks) = &options.hooks {
+    hooks.on_run_end(category);
   }
-  eprintln!();
-}
+  drop(fixtures_guard);
 
-fn run_category<TData: Clone + Send>(
-  category: &CollectedTestCategory<TData>,
-  context: &mut Context<TData>,
-) {
-  let mut tests = Vec::new();
-  let mut categories = Vec::new();
-  for child in &category.children {
-    match child {
-      CollectedCategoryOrTest::Category(c) => {
-        categories.push(c);
-      }
-      CollectedCategoryOrTest::Test(t) => {
-        tests.push(t);
-      }
+  if let Some(cache) = &cache {
+    if let Err(err) = cache.save() {
+      exit_with_internal_error(err);
     }
   }
 
-  if !tests.is_empty() {
-    run_tests_for_category(category, &tests, context);
+  if not_run > 0 {
+    reporter.report_run_aborted(not_run);
   }
-
-  for category in categories {
-    run_category(category, context);
+  if !category_timings.is_empty() {
+    reporter.report_category_timings(&category_timings);
+  }
+  if !over_budget.is_empty() {
+    reporter.report_over_budget_categories(&over_budget);
+  }
+  let passed_tests = passed_tests.unwrap_or_default();
+  let timing_regressions = match (&baseline, options.timing_threshold) {
+    (Some(baseline), Some(threshold)) => {
+      baseline.timing_regressions(&passed_tests, threshold)
+    }
+    _ => Vec::new(),
+  };
+  if !timing_regressions.is_empty() {
+    reporter.report_timing_regressions(&timing_regressions);
+  }
+  let quarantine_usage = quarantine
+    .patterns()
+    .zip(quarantined)
+    .map(|(pattern, absorbed_failures)| QuarantineUsage {
+      pattern: pattern.to_string(),
+      absorbed_failures,
+    })
+    .collect::<Vec<_>>();
+  if !quarantine_usage.is_empty() {
+    reporter.report_quarantine(&quarantine_usage);
+  }
+  reporter.report_failures(&failures, total_tests);
+  let failed_tests = failures
+    .into_iter()
+    .map(|f| f.test.name)
+    .collect::<Vec<_>>();
+  let baseline = baseline.map(|baseline| {
+    let passed_tests = passed_tests.keys().cloned().collect();
+    let comparison = baseline.compare(&failed_tests, &passed_tests);
+    reporter.report_baseline_comparison(&comparison);
+    comparison
+  });
+  RunSummary {
+    total_tests,
+    failed_tests,
+    cancelled: control.is_some_and(|c| c.is_cancelled()),
+    not_run,
+    environment: Some(environment),
+    over_budget_categories: over_budget.into_iter().map(|c| c.name).collect(),
+    baseline,
+    timing_regressions: timing_regressions
+      .into_iter()
+      .map(|r| r.name)
+      .collect(),
+    quarantined_failures: quarantine_usage
+      .into_iter()
+      .flat_map(|usage| usage.absorbed_failures)
+      .collect(),
   }
 }
 
-fn run_tests_for_category<TData: Clone + Send>(
+/// Keeps only the tests with the provided names, erroring with the names
+/// that weren't found.
+fn filter_only<TData: Clone>(
   category: &CollectedTestCategory<TData>,
-  tests: &[&CollectedTest<TData>],
-  context: &mut Context<TData>,
-) {
-  if tests.is_empty() {
-    return; // ignore empty categories if they exist for some reason
+  names: &[String],
+) -> Result<CollectedTestCategory<TData>, String> {
+  let names = names.iter().map(|n| n.as_str()).collect::<HashSet<_>>();
+  let mut remaining = names.clone();
+  let mut category = category.clone();
+  category.retain_tests(&mut |test| {
+    remaining.remove(test.name.as_str());
+    names.contains(test.name.as_str())
+  });
+  if !remaining.is_empty() {
+    let mut remaining = remaining.into_iter().collect::<Vec<_>>();
+    remaining.sort();
+    return Err(format!("Tests not found: {}", remaining.join(", ")));
   }
+  Ok(category)
+}
 
-  eprintln!();
-  eprintln!("     {} {}", colors::green_bold("Running"), category.name);
-  eprintln!();
-
-  if let Some(runner) = context
-    .thread_pool_runner
-    .as_ref()
-    .filter(|_| tests.len() > 1)
-  {
-    let mut test_iterator = tests.iter();
-    let mut pending = tests.len();
-    let mut thread_pool_pending = runner.size;
-    while pending > 0 {
-      while thread_pool_pending > 0 {
-        if let Some(test) = test_iterator.next() {
-          runner.queue_test((*test).clone());
-          thread_pool_pending -= 1;
-        } else {
-          break;
-        }
-      }
-      let (test, duration, result) = runner.receive_result();
-      let is_failure = result.is_failed();
-      let (runner_output, failure_output) =
-        build_end_test_message(result, duration);
-      eprint!("test {} ... {}", test.name, runner_output);
-      if is_failure {
-        context.failures.push(Failure {
-          test,
-          output: failure_output,
-        });
-      }
-
-      pending -= 1;
-      thread_pool_pending += 1;
-    }
-  } else {
-    for test in tests {
-      eprint!("test {} ... ", test.name);
-      let start = Instant::now();
-      let result = (context.run_test)(test);
-      let is_failure = result.is_failed();
-      let (runner_output, failure_output) =
-        build_end_test_message(result, start.elapsed());
-      eprint!("{}", runner_output);
-      if is_failure {
-        context.failures.push(Failure {
-          test: (*test).clone(),
-          output: failure_output,
-        });
-      }
+/// Creates the function a worker uses to run tests, which sets up the
+/// fixtures on the worker's thread before running its first test.
+fn worker_run_func<TData: 'static>(
+  run_test: RunTestFunc<TData>,
+  fixtures: Arc<Fixtures>,
+  worker_fixture: Option<WorkerFixture>,
+) -> PoolWorkerFunc<TData> {
+  // the worker fixture guard is `Some(None)` once initialized
+  // when there is no worker fixture
+  let mut worker_fixture_guard = None;
+  Box::new(move |test| {
+    if worker_fixture_guard.is_none() {
+      Fixtures::set_current(Some(fixtures.clone()));
+      worker_fixture_guard = Some(worker_fixture.as_ref().map(|f| f.install()));
     }
-  }
+    (run_test)(test)
+  })
 }
 
-fn build_end_test_message(
-  result: TestResult,
-  duration: Duration,
-) -> (String, Vec<u8>) {
-  fn output_sub_tests(
-    indent: &str,
-    sub_tests: &[SubTestResult],
-    runner_output: &mut String,
-    failure_output: &mut Vec<u8>,
-  ) {
-    for sub_test in sub_tests {
-      match &sub_test.result {
-        TestResult::Passed => {
-          runner_output.push_str(&format!(
-            "{}{} {}\n",
-            indent,
-            sub_test.name,
-            colors::green_bold("ok"),
-          ));
-        }
-        TestResult::Ignored => {
-          runner_output.push_str(&format!(
-            "{}{} {}\n",
-            indent,
-            sub_test.name,
-            colors::gray("ignored"),
-          ));
+/// Tests of a category, excluding the tests of its sub categories.
+struct CategorySegment<'a, TData> {
+  category: &'a CollectedTestCategory<TData>,
+  tests: Vec<&'a CollectedTest<TData>>,
+  /// Index of the segment of the parent category.
+  parent: Option<usize>,
+  reporter_context: ReporterContext,
+  budget: Option<Duration>,
+  /// Index of the next test to dispatch.
+  next_test: usize,
+  /// Number of dispatched tests that haven't finished.
+  running: usize,
+  /// Fixtures of the category, which are set once it starts.
+  fixtures: Option<Arc<Fixtures>>,
+  started: Option<Instant>,
+  ended: Option<Instant>,
+  total: Duration,
+  test_count: usize,
+}
+
+impl<TData> CategorySegment<'_, TData> {
+  fn is_finished(&self) -> bool {
+    self.next_test == self.tests.len() && self.running == 0
+  }
+
+  fn next_priority(&self) -> Option<TestPriority> {
+    self.tests.get(self.next_test).map(|test| test.priority)
+  }
+}
+
+/// Finds the segment of the next test to dispatch, where the tests of
+/// every segment are dispatched in order of priority and then in the
+/// order of the segments.
+fn next_segment<TData>(
+  segments: &[CategorySegment<TData>],
+  current: &mut usize,
+  priority: &mut TestPriority,
+) -> Option<usize> {
+  loop {
+    if let Some(index) = (*current..segments.len())
+      .find(|index| segments[*index].next_priority() == Some(*priority))
+    {
+      *current = index;
+      return Some(index);
+    }
+    *priority = priority.lower()?;
+    *current = 0;
+  }
+}
+
+/// Flattens the categories into segments in the order they're run.
+fn collect_segments<'a, TData: Clone + Send>(
+  category: &'a CollectedTestCategory<TData>,
+  parent: Option<usize>,
+  is_parallel: bool,
+  run_test_count: usize,
+  context: &Context<TData>,
+  segments: &mut Vec<CategorySegment<'a, TData>>,
+) {
+  let index = segments.len();
+  let mut tests = category
+    .children
+    .iter()
+    .filter_map(|child| match child {
+      CollectedCategoryOrTest::Test(test) => Some(test),
+      CollectedCategoryOrTest::Category(_) => None,
+    })
+    .collect::<Vec<_>>();
+  let mut sub_categories = category
+    .children
+    .iter()
+    .filter_map(|child| match child {
+      CollectedCategoryOrTest::Category(category) => Some(category),
+      CollectedCategoryOrTest::Test(_) => None,
+    })
+    .collect::<Vec<_>>();
+  if context.deterministic {
+    tests.sort_by(|a, b| a.name.cmp(&b.name));
+    sub_categories.sort_by(|a, b| a.name.cmp(&b.name));
+  }
+  tests.sort_by_key(|test| test.priority);
+  let reporter_context = ReporterContext {
+    is_parallel,
+    category_name: category.name.clone(),
+    category_test_count: tests.len(),
+    category_total_test_count: category.test_count(),
+    run_test_count,
+    verbosity: context.verbosity,
+  };
+  segments.push(CategorySegment {
+    category,
+    tests,
+    parent,
+    reporter_context,
+    budget: context
+      .category_budget
+      .as_ref()
+      .and_then(|budget| budget(category)),
+    next_test: 0,
+    running: 0,
+    fixtures: None,
+    started: None,
+    ended: None,
+    total: Duration::ZERO,
+    test_count: 0,
+  });
+  for sub_category in sub_categories {
+    collect_segments(
+      sub_category,
+      Some(index),
+      is_parallel,
+      run_test_count,
+      context,
+      segments,
+    );
+  }
+}
+
+/// Runs all the tests in the category and its sub categories.
+///
+/// The tests are dispatched from a single queue in order of their
+/// priority and then of their categories, so the tests of the next category start running while
+/// the last tests of the previous one finish instead of leaving the
+/// pool idle at each category boundary.
+fn run_category<TData: Clone + Send + 'static>(
+  category: &CollectedTestCategory<TData>,
+  context: &mut Context<TData>,
+) {
+  // take the pool out of the context while running so the
+  // context can be mutated as results are received
+  let pool = context.thread_pool_runner.take();
+  let is_parallel = !context.deterministic
+    && pool.as_ref().is_some_and(|runner| runner.size > 1);
+  let mut segments = Vec::new();
+  collect_segments(
+    category,
+    None,
+    is_parallel,
+    category.test_count(),
+    context,
+    &mut segments,
+  );
+
+  // segment of each running test
+  let mut pending = HashMap::<usize, usize>::new();
+  let mut current = 0;
+  let mut priority = TestPriority::High;
+  loop {
+    let parallelism = pool
+      .as_ref()
+      .map(|runner| context.parallelism(runner.size))
+      .unwrap_or(1);
+    while pending.len() < parallelism && !context.is_ending_early() {
+      if context.is_paused() {
+        // receive the results of running tests before waiting
+        if !pending.is_empty() {
+          break;
         }
-        TestResult::Failed { output } => {
-          runner_output.push_str(&format!(
-            "{}{} {}\n",
-            indent,
-            sub_test.name,
-            colors::red_bold("fail")
-          ));
-          if !failure_output.is_empty() {
-            failure_output.push(b'\n');
-          }
-          failure_output.extend(output);
+        context.wait_while_paused();
+        continue;
+      }
+      let Some(index) = next_segment(&segments, &mut current, &mut priority)
+      else {
+        break;
+      };
+      let segment = &mut segments[index];
+      if segment.started.is_none() {
+        segment.started = Some(Instant::now());
+        segment.fixtures = Some(Arc::new(Fixtures::default()));
+        context
+          .reporter
+          .report_category_start(segment.category, &segment.reporter_context);
+      }
+      let test = segment.tests[segment.next_test];
+      segment.next_test += 1;
+      if try_skip_test(context, test, &segment.reporter_context) {
+        end_segment_if_finished(&mut segments[index], context);
+        continue;
+      }
+      if context
+        .deadline
+        .as_ref()
+        .is_some_and(|deadline| deadline.is_missed_by(&test.name))
+      {
+        // don't start a test that would be killed by the time limit
+        context.reporter.report_test_ignored(
+          test,
+          MISSED_DEADLINE_REASON,
+          &segment.reporter_context,
+        );
+        context.not_run += 1;
+        end_segment_if_finished(&mut segments[index], context);
+        continue;
+      }
+      context
+        .reporter
+        .report_test_start(test, &segment.reporter_context);
+      let fixtures = segment.fixtures.clone().unwrap();
+      match &pool {
+        Some(runner) => {
+          segment.running += 1;
+          let id = runner.queue_test(test.clone(), fixtures);
+          pending.insert(id, index);
         }
-        TestResult::SubTests(sub_tests) => {
-          runner_output.push_str(&format!("{}{}\n", indent, sub_test.name));
-          if sub_tests.is_empty() {
-            runner_output.push_str(&format!(
-              "{}  {}\n",
-              indent,
-              colors::gray("<no sub-tests>")
-            ));
-          } else {
-            output_sub_tests(
-              &format!("{}  ", indent),
-              sub_tests,
-              runner_output,
-              failure_output,
-            );
-          }
+        None => {
+          Fixtures::set_current_category(Some(fixtures));
+          let reporter = context.reporter.clone();
+          let (duration, result) = run_with_reviewer(
+            || (context.run_test)(test),
+            move |review| reporter.review_output(review),
+          );
+          Fixtures::set_current_category(None);
+          on_segment_test_result(
+            &mut segments[index],
+            context,
+            test,
+            duration,
+            &result,
+          );
         }
       }
     }
+    let Some(runner) = pool.as_ref().filter(|_| !pending.is_empty()) else {
+      break;
+    };
+    let (id, test, duration, result) = runner.receive_result();
+    let index = pending.remove(&id).unwrap();
+    let segment = &mut segments[index];
+    segment.running -= 1;
+    on_segment_test_result(segment, context, &test, duration, &result);
   }
+  context.thread_pool_runner = pool;
 
-  let mut runner_output = String::new();
-  let duration_display = colors::gray(format!("({}ms)", duration.as_millis()));
-  let mut failure_output = Vec::new();
-  match result {
-    TestResult::Passed => {
-      runner_output.push_str(&format!(
-        "{} {}\n",
-        colors::green_bold("ok"),
-        duration_display
-      ));
+  context.not_run += segments
+    .iter()
+    .map(|segment| segment.tests.len() - segment.next_test)
+    .sum::<usize>();
+  // end the categories that were started before the run ended early
+  for segment in &mut segments {
+    if segment.started.is_some() && segment.ended.is_none() {
+      segment.next_test = segment.tests.len();
+      end_segment_if_finished(segment, context);
     }
-    TestResult::Ignored => {
-      runner_output.push_str(&format!("{}\n", colors::gray("ignored")));
+  }
+
+  report_segment_totals(&segments, context);
+}
+
+fn on_segment_test_result<TData: Clone + Send>(
+  segment: &mut CategorySegment<TData>,
+  context: &mut Context<TData>,
+  test: &CollectedTest<TData>,
+  duration: Duration,
+  result: &TestResult,
+) {
+  segment.total += duration;
+  segment.test_count += 1;
+  on_test_result(context, test, duration, result, &segment.reporter_context);
+  end_segment_if_finished(segment, context);
+}
+
+fn end_segment_if_finished<TData: Clone + Send>(
+  segment: &mut CategorySegment<TData>,
+  context: &mut Context<TData>,
+) {
+  if !segment.is_finished() || segment.ended.is_some() {
+    return;
+  }
+  segment.ended = Some(Instant::now());
+  if let Some(fixtures) = segment.fixtures.take() {
+    fixtures.teardown();
+  }
+  context
+    .reporter
+    .report_category_end(segment.category, &segment.reporter_context);
+}
+
+/// Records the timing and budget of each category, including the tests
+/// of its sub categories, with sub categories before their parent.
+fn report_segment_totals<TData: Clone + Send>(
+  segments: &[CategorySegment<TData>],
+  context: &mut Context<TData>,
+) {
+  #[derive(Clone, Copy)]
+  struct Totals {
+    total: Duration,
+    test_count: usize,
+    started: Option<Instant>,
+    ended: Option<Instant>,
+  }
+
+  let mut totals = segments
+    .iter()
+    .map(|segment| Totals {
+      total: segment.total,
+      test_count: segment.test_count,
+      started: segment.started,
+      ended: segment.ended,
+    })
+    .collect::<Vec<_>>();
+  let mut children = vec![Vec::new(); segments.len()];
+  for (index, segment) in segments.iter().enumerate() {
+    if let Some(parent) = segment.parent {
+      children[parent].push(index);
     }
-    TestResult::Failed { output } => {
-      runner_output.push_str(&format!(
-        "{} {}\n",
-        colors::red_bold("fail"),
-        duration_display
-      ));
-      failure_output = output;
-    }
-    TestResult::SubTests(sub_tests) => {
-      runner_output.push_str(&format!("{}\n", duration_display));
-      output_sub_tests(
-        "  ",
-        &sub_tests,
-        &mut runner_output,
-        &mut failure_output,
-      );
+  }
+  // sub categories come after their parent, so roll them up in reverse
+  for index in (0..segments.len()).rev() {
+    let Some(parent) = segments[index].parent else {
+      continue;
+    };
+    let child = totals[index];
+    let parent = &mut totals[parent];
+    parent.total += child.total;
+    parent.test_count += child.test_count;
+    parent.started = match (parent.started, child.started) {
+      (Some(a), Some(b)) => Some(a.min(b)),
+      (a, b) => a.or(b),
+    };
+    parent.ended = parent.ended.max(child.ended);
+  }
+
+  fn visit<TData: Clone + Send>(
+    index: usize,
+    segments: &[CategorySegment<TData>],
+    children: &[Vec<usize>],
+    totals: &[Totals],
+    context: &mut Context<TData>,
+  ) {
+    for child in &children[index] {
+      visit(*child, segments, children, totals, context);
+    }
+    let segment = &segments[index];
+    let totals = &totals[index];
+    if totals.test_count > 0 {
+      context.category_timings.push(CategoryTiming {
+        name: segment.category.name.clone(),
+        path: segment.category.path.clone(),
+        test_count: totals.test_count,
+        total: totals.total,
+      });
+    }
+    if let (Some(budget), Some(started), Some(ended)) =
+      (segment.budget, totals.started, totals.ended)
+    {
+      let elapsed = ended - started;
+      if elapsed > budget {
+        context.over_budget.push(OverBudgetCategory {
+          name: segment.category.name.clone(),
+          elapsed,
+          budget,
+        });
+      }
+    }
+  }
+
+  if !segments.is_empty() {
+    visit(0, segments, &children, &totals, context);
+  }
+}
+
+/// Reports the test and returns true when it doesn't need to be run.
+fn try_skip_test<TData: Clone + Send>(
+  context: &mut Context<TData>,
+  test: &CollectedTest<TData>,
+  reporter_context: &ReporterContext,
+) -> bool {
+  let reason = test
+    .ignore_reason()
+    .or_else(|| context.capabilities.ignore_reason(test));
+  if let Some(reason) = reason {
+    context
+      .reporter
+      .report_test_ignored(test, &reason, reporter_context);
+    true
+  } else if let Some(cache) = &mut context.cache {
+    let input_files = match &context.input_files {
+      Some(input_files) => input_files(test),
+      None => Vec::new(),
+    };
+    if !cache.is_cached(test, &input_files) {
+      return false;
+    }
+    context.reporter.report_test_cached(test, reporter_context);
+    true
+  } else {
+    false
+  }
+}
+
+fn on_test_result<TData: Clone + Send>(
+  context: &mut Context<TData>,
+  test: &CollectedTest<TData>,
+  duration: Duration,
+  result: &TestResult,
+  reporter_context: &ReporterContext,
+) {
+  context
+    .reporter
+    .report_test_end(test, duration, result, reporter_context);
+  if let Some(cache) = &mut context.cache {
+    cache.record_result(test, result);
+  }
+  let quarantined = result
+    .is_failed()
+    .then(|| context.quarantine.find(&test.name))
+    .flatten();
+  if let Some(index) = quarantined {
+    context.quarantined[index].push(test.name.clone());
+  } else if result.is_failed() {
+    context.failures.push(ReporterFailure {
+      test: test.clone(),
+      output: result.failure_output(),
+      fields: result.failure_fields(),
+    });
+  } else if !matches!(result, TestResult::Ignored) {
+    if let Some(passed_tests) = &mut context.passed_tests {
+      passed_tests.insert(test.name.clone(), duration);
     }
   }
+}
 
-  (runner_output, failure_output)
+/// Kills whatever is running a test on a thread of the pool.
+type PoolKillFunc = Box<dyn Fn() + Send + Sync>;
+/// Gets the id of the process running the tests of a thread of the pool.
+type PoolProcessIdFunc = Box<dyn Fn() -> Option<u32> + Send + Sync>;
+/// Gets the output the test running on a thread of the pool has
+/// printed so far.
+type PoolOutputFunc = Box<dyn Fn() -> Vec<u8> + Send + Sync>;
+
+struct PoolWorker<TData> {
+  run: PoolWorkerFunc<TData>,
+  kill: Option<PoolKillFunc>,
+  /// Defaults to the current process.
+  process_id: Option<PoolProcessIdFunc>,
+  /// Not set when the output isn't captured.
+  output: Option<PoolOutputFunc>,
 }
 
-#[derive(Default)]
-struct PendingTests {
-  finished: bool,
-  pending: HashMap<String, Instant>,
+struct PendingTest<TData> {
+  test: CollectedTest<TData>,
+  start: Instant,
+  timeout: Duration,
+  /// Index of the thread running the test once it has started.
+  thread_index: Option<usize>,
+  reported: bool,
+  /// Whether the output of the test is being reviewed, which doesn't
+  /// count towards its timeout.
+  is_reviewing: bool,
+}
+
+struct PendingTests<TData> {
+  /// Pending tests by the id they were queued with, since test names
+  /// aren't guaranteed to be unique.
+  pending: HashMap<usize, PendingTest<TData>>,
+  /// Tests that were failed for running too long whose results
+  /// are discarded when they eventually finish.
+  timed_out: HashSet<usize>,
+  /// Threads stuck running a test that was failed for running too long,
+  /// which exit once the test finishes.
+  abandoned_threads: HashSet<usize>,
+  /// Number of abandoned threads that haven't been replaced yet.
+  unreplaced_threads: usize,
+  /// Id of the test each thread is running.
+  thread_tests: HashMap<usize, usize>,
+  /// When a test last completed or, if none were pending, was queued.
+  last_progress: Instant,
+  stall_reported: bool,
+}
+
+impl<TData> Default for PendingTests<TData> {
+  fn default() -> Self {
+    Self {
+      pending: HashMap::new(),
+      timed_out: HashSet::new(),
+      abandoned_threads: HashSet::new(),
+      unreplaced_threads: 0,
+      thread_tests: HashMap::new(),
+      last_progress: Instant::now(),
+      stall_reported: false,
+    }
+  }
+}
+
+impl<TData> PendingTests<TData> {
+  fn start_review(&mut self, id: usize) {
+    if let Some(pending) = self.pending.get_mut(&id) {
+      pending.is_reviewing = true;
+    }
+  }
+
+  /// Excludes the time spent reviewing from the test's running time.
+  fn end_review(&mut self, id: usize, elapsed: Duration) {
+    if let Some(pending) = self.pending.get_mut(&id) {
+      pending.is_reviewing = false;
+      pending.start += elapsed;
+    }
+    self.last_progress += elapsed;
+  }
 }
 
+/// Id of the queued test along with the test to run and the fixtures of
+/// its category.
+type PoolTask<TData> = (usize, CollectedTest<TData>, Arc<Fixtures>);
+type PoolResult<TData> = (usize, CollectedTest<TData>, Duration, TestResult);
+/// Output to review on the main thread along with where to send whether
+/// it was accepted.
+type PoolReview = (OutputReview, crossbeam_channel::Sender<bool>);
+
 struct ThreadPoolTestRunner<TData: Send + 'static> {
   size: usize,
-  sender: crossbeam_channel::Sender<CollectedTest<TData>>,
-  receiver:
-    crossbeam_channel::Receiver<(CollectedTest<TData>, Duration, TestResult)>,
-  pending_tests: Arc<Mutex<PendingTests>>,
+  sender: Option<crossbeam_channel::Sender<PoolTask<TData>>>,
+  receiver: crossbeam_channel::Receiver<PoolResult<TData>>,
+  /// Results of the tests that were failed for running too long.
+  timeout_receiver: crossbeam_channel::Receiver<PoolResult<TData>>,
+  /// Reviews are done on the main thread so the prompts are serialized
+  /// with the other output of the reporter.
+  review_receiver: crossbeam_channel::Receiver<PoolReview>,
+  reporter: Arc<dyn Reporter<TData>>,
+  pending_tests: Arc<Mutex<PendingTests<TData>>>,
+  /// Id of the next queued test.
+  next_id: AtomicUsize,
+  /// Threads by their index, including the ones that were replaced.
+  threads: Mutex<Vec<std::thread::JoinHandle<()>>>,
+  /// Creates the worker of the thread with the provided index.
+  create_worker: Box<dyn Fn(usize) -> PoolWorker<TData>>,
+  thread_channels: PoolThreadChannels<TData>,
+  /// Thread that checks for long running tests, which runs outside the
+  /// pool so that the pool's size equals the test parallelism.
+  watchdog:
+    Option<(crossbeam_channel::Sender<()>, std::thread::JoinHandle<()>)>,
+  timeouts: Timeouts,
 }
 
-impl<TData: Send + 'static> ThreadPoolTestRunner<TData> {
-  pub fn new(size: usize, run_test: RunTestFunc<TData>) -> Self {
+impl<TData: Clone + Send + 'static> ThreadPoolTestRunner<TData> {
+  pub fn new(
+    size: usize,
+    create_worker: impl Fn(usize) -> PoolWorker<TData> + 'static,
+    reporter: Arc<dyn Reporter<TData>>,
+    timeouts: Timeouts,
+  ) -> Self {
     let pending_tests = Arc::new(Mutex::new(PendingTests::default()));
-    let send_channel = crossbeam_channel::bounded::<CollectedTest<TData>>(size);
-    let receive_channel = crossbeam_channel::unbounded::<(
-      CollectedTest<TData>,
-      Duration,
-      TestResult,
-    )>();
-    for _ in 0..size {
-      let receiver = send_channel.1.clone();
-      let sender = receive_channel.0.clone();
-      let run_test = run_test.clone();
-      std::thread::spawn(move || {
-        let run_test = &run_test;
-        while let Ok(value) = receiver.recv() {
-          let start = Instant::now();
-          let result = (run_test)(&value);
-          sender.send((value, start.elapsed(), result)).unwrap();
-        }
-      });
+    let send_channel = crossbeam_channel::bounded::<PoolTask<TData>>(size);
+    let receive_channel = crossbeam_channel::unbounded::<PoolResult<TData>>();
+    let timeout_channel = crossbeam_channel::unbounded::<PoolResult<TData>>();
+    let review_channel = crossbeam_channel::unbounded::<PoolReview>();
+    let thread_channels = PoolThreadChannels {
+      receiver: send_channel.1,
+      sender: receive_channel.0,
+      review_sender: review_channel.0,
+    };
+    let mut threads = Vec::with_capacity(size);
+    let mut kill_funcs = Vec::with_capacity(size);
+    let mut process_id_funcs = Vec::with_capacity(size);
+    let mut output_funcs = Vec::with_capacity(size);
+    for index in 0..size {
+      let PoolWorker {
+        run,
+        kill,
+        process_id,
+        output,
+      } = create_worker(index);
+      kill_funcs.push(kill);
+      process_id_funcs.push(process_id);
+      output_funcs.push(output);
+      threads.push(spawn_pool_thread(
+        index,
+        run,
+        thread_channels.clone(),
+        pending_tests.clone(),
+      ));
     }
 
-    // thread that checks for any long running tests
-    std::thread::spawn({
-      let pending_tests = pending_tests.clone();
-      move || loop {
-        std::thread::sleep(std::time::Duration::from_secs(1));
-        let mut data = pending_tests.lock();
-        if data.finished {
-          break;
-        }
-        let mut long_tests = Vec::new();
-        for (key, value) in &data.pending {
-          if value.elapsed().as_secs() > 60 {
-            long_tests.push(key.clone());
+    // dedicated thread that checks for any long running tests, which
+    // is stopped by dropping the sender when the pool is dropped
+    let (watchdog_stop_sender, watchdog_stop_receiver) =
+      crossbeam_channel::bounded::<()>(0);
+    if timeouts.dump_stacks {
+      stacks::listen_for_sigquit();
+    }
+    let watchdog = std::thread::Builder::new()
+      .name("file_test_runner_watchdog".to_string())
+      .spawn({
+        let pending_tests = pending_tests.clone();
+        let reporter = reporter.clone();
+        let timeouts = timeouts.clone();
+        let timeout_sender = timeout_channel.0;
+        move || {
+          let process_id = |thread_index: usize| {
+            process_id_funcs
+              .get(thread_index)
+              .and_then(|process_id| process_id.as_ref()?())
+              .unwrap_or_else(std::process::id)
+          };
+          while watchdog_stop_receiver
+            .recv_timeout(timeouts.poll_interval)
+            .is_err_and(|err| err.is_timeout())
+          {
+            check_pending_tests(
+              &pending_tests,
+              &kill_funcs,
+              &output_funcs,
+              &process_id,
+              &*reporter,
+              &timeouts,
+              &timeout_sender,
+            );
+            if let Some(stall_after) = timeouts.stall_after {
+              check_stalled_run(&pending_tests, &*reporter, stall_after);
+            }
+            if timeouts.dump_stacks && stacks::take_sigquit() {
+              dump_pending_stacks(&pending_tests, &process_id);
+            }
           }
         }
-        for test in long_tests {
-          eprintln!("test {} has been running for more than 60 seconds", test);
-          data.pending.remove(&test);
-        }
-      }
-    });
+      })
+      .expect("failed to spawn watchdog thread");
 
     ThreadPoolTestRunner {
       size,
-      sender: send_channel.0,
+      sender: Some(send_channel.0),
       receiver: receive_channel.1,
+      timeout_receiver: timeout_channel.1,
+      review_receiver: review_channel.1,
+      reporter,
       pending_tests,
+      next_id: AtomicUsize::new(0),
+      threads: Mutex::new(threads),
+      create_worker: Box::new(create_worker),
+      thread_channels,
+      watchdog: Some((watchdog_stop_sender, watchdog)),
+      timeouts,
     }
   }
 
-  pub fn queue_test(&self, test: CollectedTest<TData>) {
+  /// Queues the test to run with the fixtures of its category, returning
+  /// the id its result is received with.
+  pub fn queue_test(
+    &self,
+    test: CollectedTest<TData>,
+    category_fixtures: Arc<Fixtures>,
+  ) -> usize {
+    let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+    let pending = PendingTest {
+      test: test.clone(),
+      start: Instant::now(),
+      timeout: self.timeouts.get(&test.name, test.settings.timeout),
+      thread_index: None,
+      reported: false,
+      is_reviewing: false,
+    };
+    let mut pending_tests = self.pending_tests.lock();
+    if pending_tests.pending.is_empty() {
+      // don't count the time spent without any tests to run
+      pending_tests.last_progress = Instant::now();
+    }
+    pending_tests.pending.insert(id, pending);
+    drop(pending_tests);
     self
-      .pending_tests
-      .lock()
-      .pending
-      .insert(test.name.clone(), Instant::now());
-    self.sender.send(test).unwrap()
+      .sender
+      .as_ref()
+      .unwrap()
+      .send((id, test, category_fixtures))
+      .unwrap();
+    id
   }
 
-  pub fn receive_result(&self) -> (CollectedTest<TData>, Duration, TestResult) {
-    let data = self.receiver.recv().unwrap();
-    self.pending_tests.lock().pending.remove(&data.0.name);
-    data
+  pub fn receive_result(&self) -> PoolResult<TData> {
+    loop {
+      crossbeam_channel::select! {
+        recv(self.receiver) -> data => {
+          let data = data.unwrap();
+          let mut pending_tests = self.pending_tests.lock();
+          // discard the results of tests that were already failed
+          if pending_tests.timed_out.remove(&data.0) {
+            continue;
+          }
+          pending_tests.pending.remove(&data.0);
+          return data;
+        }
+        recv(self.timeout_receiver) -> data => {
+          self.replace_abandoned_threads();
+          return data.unwrap();
+        }
+        recv(self.review_receiver) -> data => {
+          let (review, answer_sender) = data.unwrap();
+          let _ = answer_sender.send(self.reporter.review_output(&review));
+        }
+      }
+    }
+  }
+}
+
+impl<TData: Clone + Send + 'static> ThreadPoolTestRunner<TData> {
+  /// Spawns a thread in place of each thread left running a test that
+  /// was failed for running too long, so the pool keeps its size.
+  fn replace_abandoned_threads(&self) {
+    let count =
+      std::mem::take(&mut self.pending_tests.lock().unreplaced_threads);
+    let mut threads = self.threads.lock();
+    for _ in 0..count {
+      let index = threads.len();
+      // only threads that can't be killed are abandoned, so the worker
+      // has no other functions for the watchdog to use
+      let worker = (self.create_worker)(index);
+      threads.push(spawn_pool_thread(
+        index,
+        worker.run,
+        self.thread_channels.clone(),
+        self.pending_tests.clone(),
+      ));
+    }
+  }
+}
+
+/// Channels used by the threads of the pool.
+struct PoolThreadChannels<TData> {
+  receiver: crossbeam_channel::Receiver<PoolTask<TData>>,
+  sender: crossbeam_channel::Sender<PoolResult<TData>>,
+  review_sender: crossbeam_channel::Sender<PoolReview>,
+}
+
+impl<TData> Clone for PoolThreadChannels<TData> {
+  fn clone(&self) -> Self {
+    Self {
+      receiver: self.receiver.clone(),
+      sender: self.sender.clone(),
+      review_sender: self.review_sender.clone(),
+    }
+  }
+}
+
+/// Spawns a thread of the pool that runs the queued tests until the
+/// pool is dropped or the thread is abandoned.
+fn spawn_pool_thread<TData: Clone + Send + 'static>(
+  index: usize,
+  mut run_test: PoolWorkerFunc<TData>,
+  channels: PoolThreadChannels<TData>,
+  pending_tests: Arc<Mutex<PendingTests<TData>>>,
+) -> std::thread::JoinHandle<()> {
+  std::thread::Builder::new()
+    .name(format!("file_test_runner_{}", index))
+    .spawn(move || {
+      while let Ok((id, value, category_fixtures)) = channels.receiver.recv() {
+        {
+          let mut data = pending_tests.lock();
+          if let Some(pending) = data.pending.get_mut(&id) {
+            pending.thread_index = Some(index);
+          }
+          data.thread_tests.insert(index, id);
+        }
+        Fixtures::set_current_category(Some(category_fixtures));
+        let (duration, result) = run_with_reviewer(|| (run_test)(&value), {
+          let pending_tests = pending_tests.clone();
+          let review_sender = channels.review_sender.clone();
+          move |review| {
+            pending_tests.lock().start_review(id);
+            let start = Instant::now();
+            let (answer_sender, answer_receiver) =
+              crossbeam_channel::bounded(1);
+            let accepted =
+              review_sender.send((review.clone(), answer_sender)).is_ok()
+                && answer_receiver.recv().unwrap_or(false);
+            pending_tests.lock().end_review(id, start.elapsed());
+            accepted
+          }
+        });
+        Fixtures::set_current_category(None);
+        let was_abandoned = {
+          let mut data = pending_tests.lock();
+          data.thread_tests.remove(&index);
+          data.last_progress = Instant::now();
+          data.stall_reported = false;
+          data.abandoned_threads.remove(&index)
+        };
+        channels.sender.send((id, value, duration, result)).unwrap();
+        if was_abandoned {
+          // another thread took this thread's place in the pool
+          break;
+        }
+      }
+    })
+    .expect("failed to spawn test thread")
+}
+
+/// Reports the tests that have been running longer than their timeout
+/// and fails the ones that exceeded it by more than `kill_after`.
+fn check_pending_tests<TData>(
+  pending_tests: &Mutex<PendingTests<TData>>,
+  kill_funcs: &[Option<PoolKillFunc>],
+  output_funcs: &[Option<PoolOutputFunc>],
+  process_id: &dyn Fn(usize) -> u32,
+  reporter: &dyn Reporter<TData>,
+  timeouts: &Timeouts,
+  timeout_sender: &crossbeam_channel::Sender<PoolResult<TData>>,
+) {
+  let mut long_running_tests = Vec::new();
+  let mut killed_tests = Vec::new();
+  {
+    let mut data = pending_tests.lock();
+    let mut killed_ids = Vec::new();
+    for (id, pending) in &mut data.pending {
+      let elapsed = pending.start.elapsed();
+      if pending.is_reviewing || elapsed <= pending.timeout {
+        continue;
+      }
+      if !pending.reported {
+        pending.reported = true;
+        long_running_tests.push((pending.test.name.clone(), elapsed));
+      }
+      if let Some(kill_after) = timeouts.kill_after {
+        if elapsed > pending.timeout + kill_after {
+          killed_ids.push(*id);
+        }
+      }
+    }
+    for id in killed_ids {
+      // the result of the test is discarded once it eventually finishes
+      let pending = data.pending.remove(&id).unwrap();
+      data.timed_out.insert(id);
+      killed_tests.push((id, pending));
+    }
+  }
+
+  // the lock isn't held while reporting or while the debugger runs, so
+  // the other threads can keep starting and finishing tests
+  for (name, elapsed) in long_running_tests {
+    reporter.report_long_running_test(&name, elapsed);
+  }
+  for (id, pending) in killed_tests {
+    let elapsed = pending.start.elapsed();
+    let mut output = Vec::new();
+    let mut stacks = None;
+    if let Some(thread_index) = pending.thread_index {
+      if timeouts.dump_stacks {
+        stacks = Some(stacks::stacks_output(process_id(thread_index)));
+      }
+      // briefly lock to check the thread didn't move on to another test
+      // that would be killed instead
+      let mut data = pending_tests.lock();
+      if data.thread_tests.get(&thread_index) == Some(&id) {
+        if let Some(Some(get_output)) = output_funcs.get(thread_index) {
+          output = get_output();
+        }
+        match kill_funcs.get(thread_index) {
+          Some(Some(kill)) => kill(),
+          _ => {
+            data.abandoned_threads.insert(thread_index);
+            data.unreplaced_threads += 1;
+          }
+        }
+      }
+    }
+    // include the partial output because the last lines are
+    // usually the best clue of where the test got stuck
+    if !output.is_empty() && !output.ends_with(b"\n") {
+      output.push(b'\n');
+    }
+    output.extend(
+      format!(
+        "Test was failed after running for {} seconds, which exceeded its timeout of {} seconds.",
+        elapsed.as_secs(),
+        pending.timeout.as_secs(),
+      )
+      .into_bytes(),
+    );
+    if let Some(stacks) = stacks {
+      output.extend(format!("\n\n{}", stacks).into_bytes());
+    }
+    let result = TestResult::failed(output);
+    let _ = timeout_sender.send((id, pending.test, elapsed, result));
+  }
+}
+
+/// Prints the stacks of the processes running the pending tests.
+fn dump_pending_stacks<TData>(
+  pending_tests: &Mutex<PendingTests<TData>>,
+  process_id: &dyn Fn(usize) -> u32,
+) {
+  let mut processes = BTreeMap::<u32, Vec<String>>::new();
+  for pending in pending_tests.lock().pending.values() {
+    if let Some(thread_index) = pending.thread_index {
+      processes
+        .entry(process_id(thread_index))
+        .or_default()
+        .push(pending.test.name.clone());
+    }
+  }
+  // the lock isn't held while the debugger runs
+  for (pid, mut names) in processes {
+    names.sort();
+    eprintln!("---- stacks of {} ----", names.join(", "));
+    eprintln!("{}", stacks::stacks_output(pid));
+  }
+}
+
+/// Reports the pending tests once when no test has completed for
+/// longer than `stall_after`.
+fn check_stalled_run<TData>(
+  pending_tests: &Mutex<PendingTests<TData>>,
+  reporter: &dyn Reporter<TData>,
+  stall_after: Duration,
+) {
+  let mut data = pending_tests.lock();
+  let stalled_for = data.last_progress.elapsed();
+  if data.stall_reported
+    || data.pending.is_empty()
+    || data.pending.values().any(|pending| pending.is_reviewing)
+    || stalled_for < stall_after
+  {
+    return;
+  }
+  data.stall_reported = true;
+  let mut tests = data
+    .pending
+    .values()
+    .map(|pending| StalledTest {
+      name: pending.test.name.clone(),
+      path: pending.test.path.clone(),
+      elapsed: pending.start.elapsed(),
+    })
+    .collect::<Vec<_>>();
+  tests.sort_by_key(|test| std::cmp::Reverse(test.elapsed));
+  reporter.report_stalled_run(stalled_for, &tests);
+}
+
+impl<TData: Send + 'static> Drop for ThreadPoolTestRunner<TData> {
+  fn drop(&mut self) {
+    if let Some((stop_sender, watchdog)) = self.watchdog.take() {
+      drop(stop_sender);
+      let _ = watchdog.join();
+    }
+    if self.timeouts.dump_stacks {
+      stacks::stop_listening_for_sigquit();
+    }
+    let abandoned_threads = self.pending_tests.lock().abandoned_threads.clone();
+    // closing the channel causes the threads to exit
+    drop(self.sender.take());
+    for (index, thread) in self.threads.get_mut().drain(..).enumerate() {
+      // don't wait on threads stuck running a test that timed out
+      if !abandoned_threads.contains(&index) {
+        let _ = thread.join();
+      }
+    }
   }
 }
 
 #[cfg(test)]
 mod test {
-  use deno_terminal::colors;
+  use std::path::PathBuf;
 
   use super::*;
+  use crate::testing::CategoryBuilder;
+  use crate::testing::FakeReporter;
+
+  #[test]
+  fn only() {
+    let test = |name: &str| {
+      CollectedCategoryOrTest::Test(CollectedTest::new(name, name, ()))
+    };
+    let category = CollectedTestCategory {
+      name: "specs".to_string(),
+      path: PathBuf::from("specs"),
+      description: None,
+      children: vec![
+        test("specs::a"),
+        CollectedCategoryOrTest::Category(CollectedTestCategory {
+          name: "specs::sub".to_string(),
+          path: PathBuf::from("sub"),
+          description: None,
+          children: vec![test("specs::sub::b"), test("specs::sub::c")],
+        }),
+        CollectedCategoryOrTest::Category(CollectedTestCategory {
+          name: "specs::other".to_string(),
+          path: PathBuf::from("other"),
+          description: None,
+          children: vec![test("specs::other::d")],
+        }),
+      ],
+    };
+    let ran = Arc::new(Mutex::new(Vec::new()));
+    run_tests(
+      &category,
+      RunOptions {
+        only: Some(vec!["specs::sub::c".to_string(), "specs::a".to_string()]),
+        ..Default::default()
+      },
+      {
+        let ran = ran.clone();
+        move |test| {
+          ran.lock().push(test.name.clone());
+          TestResult::Passed
+        }
+      },
+    );
+    assert_eq!(*ran.lock(), vec!["specs::a", "specs::sub::c"]);
+
+    let filtered =
+      filter_only(&category, &["specs::sub::b".to_string()]).unwrap();
+    assert_eq!(filtered.children.len(), 1);
+    let result = filter_only(
+      &category,
+      &["specs::missing".to_string(), "specs::a".to_string()],
+    );
+    assert_eq!(result.err().unwrap(), "Tests not found: specs::missing");
+  }
+
+  #[test]
+  fn failure_fields() {
+    assert!(matches!(
+      TestResult::Passed.with_field("exit_code", 1),
+      TestResult::Passed
+    ));
+    let result = TestResult::failed("error")
+      .with_field("exit_code", 1)
+      .with_field("timed_out", true)
+      .with_field("expected_file", "specs/a.out");
+    let result = TestResult::SubTests(vec![
+      SubTestResult {
+        name: "step".to_string(),
+        result,
+      },
+      SubTestResult {
+        name: "other".to_string(),
+        result: TestResult::failed("error"),
+      },
+    ]);
+    assert_eq!(
+      result.failure_fields(),
+      BTreeMap::from([
+        ("step::exit_code".to_string(), FailureValue::Integer(1)),
+        (
+          "step::expected_file".to_string(),
+          FailureValue::String("specs/a.out".to_string())
+        ),
+        ("step::timed_out".to_string(), FailureValue::Bool(true)),
+      ])
+    );
+    // fields survive being sent by worker processes
+    let json = serde_json::to_string(&result).unwrap();
+    let result: TestResult = serde_json::from_str(&json).unwrap();
+    assert_eq!(result.failure_fields().len(), 3);
+  }
 
   #[test]
-  fn test_build_end_test_message_passed() {
+  fn exit_codes() {
+    let exit_code = |summary: RunSummary| {
+      run_failure(&summary, true, true, true).map(|(code, _)| code.code())
+    };
+    assert_eq!(
+      exit_code(RunSummary {
+        total_tests: 1,
+        ..Default::default()
+      }),
+      None
+    );
+    assert_eq!(
+      exit_code(RunSummary {
+        total_tests: 2,
+        failed_tests: vec!["specs::a".to_string()],
+        not_run: 1,
+        ..Default::default()
+      }),
+      Some(1)
+    );
+    assert_eq!(
+      exit_code(RunSummary {
+        total_tests: 2,
+        over_budget_categories: vec!["specs".to_string()],
+        ..Default::default()
+      }),
+      Some(1)
+    );
     assert_eq!(
-      build_end_test_message(
-        super::TestResult::Passed,
-        std::time::Duration::from_millis(100),
+      exit_code(RunSummary {
+        total_tests: 2,
+        not_run: 1,
+        ..Default::default()
+      }),
+      Some(5)
+    );
+    assert_eq!(exit_code(RunSummary::default()), Some(2));
+    assert_eq!(run_failure(&RunSummary::default(), true, true, false), None);
+  }
+
+  #[test]
+  fn single_test() {
+    let category = CollectedTestCategory {
+      name: "specs".to_string(),
+      path: PathBuf::from("specs"),
+      description: None,
+      children: vec![CollectedCategoryOrTest::Test(CollectedTest::new(
+        "specs::a",
+        "a",
+        (),
+      ))],
+    };
+    let run_test = |test: &CollectedTest| {
+      if test.name == "specs::a" {
+        std::panic!("failed");
+      }
+      TestResult::Passed
+    };
+    let TestResult::Failed { output, .. } =
+      run_single_test(&category, "specs::a", &run_test)
+    else {
+      unreachable!();
+    };
+    assert!(String::from_utf8(output).unwrap().contains("failed"));
+    let TestResult::Failed { output, .. } =
+      run_single_test(&category, "specs::b", &run_test)
+    else {
+      unreachable!();
+    };
+    assert_eq!(output, b"Test 'specs::b' was not found.");
+  }
+
+  #[test]
+  fn on_failure() {
+    let category = CollectedTestCategory {
+      name: "specs".to_string(),
+      path: PathBuf::from("specs"),
+      description: None,
+      children: ["specs::a", "specs::b"]
+        .into_iter()
+        .map(|name| {
+          CollectedCategoryOrTest::Test(CollectedTest::new(name, name, ()))
+        })
+        .collect(),
+    };
+    let run_test = |test: &CollectedTest| {
+      if test.name == "specs::b" {
+        TestResult::failed(b"failed".to_vec())
+      } else {
+        TestResult::Passed
+      }
+    };
+    let summary = run_tests(
+      &category,
+      RunOptions {
+        on_failure: OnFailure::Return,
+        ..Default::default()
+      },
+      run_test,
+    );
+    assert_eq!(summary.total_tests, 2);
+    assert_eq!(summary.failed_tests, vec!["specs::b"]);
+
+    // panics outside of `TestResult::from_maybe_panic` are failures
+    let summary = run_tests(
+      &category,
+      RunOptions {
+        on_failure: OnFailure::Return,
+        ..Default::default()
+      },
+      |test| {
+        if test.name == "specs::a" {
+          std::panic!("failed");
+        }
+        TestResult::Passed
+      },
+    );
+    assert_eq!(summary.failed_tests, vec!["specs::a"]);
+
+    let result = std::panic::catch_unwind(|| {
+      run_tests(&category, RunOptions::default(), run_test)
+    });
+    assert!(result.is_err());
+
+    let empty = CollectedTestCategory {
+      name: "specs".to_string(),
+      path: PathBuf::from("specs"),
+      description: None,
+      children: Vec::new(),
+    };
+    run_tests(&empty, RunOptions::default(), run_test);
+    let result = std::panic::catch_unwind(|| {
+      run_tests(
+        &empty,
+        RunOptions {
+          fail_when_no_tests_match: true,
+          ..Default::default()
+        },
+        run_test,
       )
-      .0,
-      format!("{} {}\n", colors::green_bold("ok"), colors::gray("(100ms)"))
+    });
+    assert!(result.is_err());
+  }
+
+  #[test]
+  fn captures_panics() {
+    let category = CategoryBuilder::<()>::new("specs")
+      .test("a")
+      .test("b")
+      .test("c")
+      .build();
+    for parallel in [false, true] {
+      let reporter = Arc::new(FakeReporter::default());
+      let summary = run_tests(
+        &category,
+        RunOptions {
+          parallel,
+          on_failure: OnFailure::Return,
+          reporter: reporter.clone(),
+          ..Default::default()
+        },
+        |test| {
+          if test.name == "specs::b" {
+            std::panic!("unexpected");
+          }
+          TestResult::Passed
+        },
+      );
+      // the panic doesn't stop the other tests from running
+      assert_eq!(summary.failed_tests, vec!["specs::b"]);
+      let mut events = reporter.take_events();
+      events.retain(|event| event.starts_with("test_end"));
+      events.sort();
+      assert_eq!(
+        events,
+        vec![
+          "test_end specs::a passed",
+          "test_end specs::b failed",
+          "test_end specs::c passed",
+        ]
+      );
+    }
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn retries_crashed_tests() {
+    let marker = std::env::temp_dir()
+      .join(format!("file_test_runner_crash_{}", std::process::id()));
+    // crashes the first time it's spawned
+    let script = format!(
+      concat!(
+        "read request\n",
+        "if [ ! -e '{}' ]; then touch '{}'; kill -9 $$; fi\n",
+        "printf '\\036file_test_runner:\\n' >&2\n",
+        "printf '\\036file_test_runner:",
+        r#"{{"type":"result","name":"specs::a","result":"Passed"}}"#,
+        "\\n'\n",
+        "read request\n",
+      ),
+      marker.display(),
+      marker.display(),
     );
+    let category = CategoryBuilder::<()>::new("specs").test("a").build();
+    let run = |retry_crashed_tests| {
+      run_tests(
+        &category,
+        RunOptions {
+          workers: Some(WorkerOptions {
+            commands: vec![vec![
+              "sh".to_string(),
+              "-c".to_string(),
+              script.clone(),
+            ]],
+            retry_crashed_tests,
+            ..Default::default()
+          }),
+          on_failure: OnFailure::Return,
+          reporter: Arc::new(FakeReporter::default()),
+          ..Default::default()
+        },
+        |_| unreachable!(),
+      )
+    };
+    assert_eq!(run(false).failed_tests, vec!["specs::a"]);
+    std::fs::remove_file(&marker).unwrap();
+    assert!(run(true).failed_tests.is_empty());
+    std::fs::remove_file(&marker).unwrap();
   }
 
   #[test]
-  fn test_build_end_test_message_failed() {
-    let (message, failure_output) = build_end_test_message(
-      super::TestResult::Failed {
-        output: b"error".to_vec(),
+  fn max_failures() {
+    let mut category = CollectedTestCategory {
+      name: "specs".to_string(),
+      path: PathBuf::from("specs"),
+      description: None,
+      children: Vec::new(),
+    };
+    for name in ["specs::a", "specs::b", "specs::c", "specs::sub::d"] {
+      category.push_test(CollectedTest::new(name, name, ()));
+    }
+    let summary = run_tests(
+      &category,
+      RunOptions {
+        max_failures: Some(2),
+        on_failure: OnFailure::Return,
+        ..Default::default()
+      },
+      |test| {
+        if test.name == "specs::c" {
+          TestResult::Passed
+        } else {
+          TestResult::failed(Vec::new())
+        }
       },
-      std::time::Duration::from_millis(100),
     );
+    assert_eq!(summary.failed_tests, vec!["specs::a", "specs::b"]);
+    // the tests of the following categories aren't run either
+    assert_eq!(summary.not_run, 2);
+  }
+
+  #[test]
+  fn deterministic() {
+    let category = CategoryBuilder::<()>::new("specs")
+      .category("b", |b| b.test("d").test("c"))
+      .test("z")
+      .category("a", |a| a.test("e"))
+      .test("y")
+      .build();
+    let run = || {
+      let reporter = Arc::new(FakeReporter::default());
+      run_tests(
+        &category,
+        RunOptions {
+          parallel: true,
+          deterministic: true,
+          reporter: reporter.clone(),
+          ..Default::default()
+        },
+        |_| TestResult::Passed,
+      );
+      reporter.take_events()
+    };
+    let events = run();
     assert_eq!(
-      message,
-      format!("{} {}\n", colors::red_bold("fail"), colors::gray("(100ms)"))
+      events,
+      vec![
+        "category_start specs (2/5 tests)",
+        "test_start specs::y",
+        "test_end specs::y passed",
+        "test_start specs::z",
+        "test_end specs::z passed",
+        "category_end specs",
+        "category_start specs::a (1/1 tests)",
+        "test_start specs::a::e",
+        "test_end specs::a::e passed",
+        "category_end specs::a",
+        "category_start specs::b (2/2 tests)",
+        "test_start specs::b::c",
+        "test_end specs::b::c passed",
+        "test_start specs::b::d",
+        "test_end specs::b::d passed",
+        "category_end specs::b",
+        "failures 0/5 []",
+      ]
     );
-    assert_eq!(failure_output, b"error");
+    assert_eq!(run(), events);
   }
 
   #[test]
-  fn test_build_end_test_message_ignored() {
+  fn requires() {
+    let requires = |name: &str| {
+      let requires = vec![name.to_string()];
+      move |mut test: CollectedTest| {
+        test.settings.requires = requires;
+        test
+      }
+    };
+    let category = CategoryBuilder::<()>::new("specs")
+      .test_with("docker", (), requires("docker"))
+      .test_with("node", (), requires("node"))
+      .build();
+    let reporter = Arc::new(FakeReporter::default());
+    let summary = run_tests(
+      &category,
+      RunOptions {
+        deterministic: true,
+        reporter: reporter.clone(),
+        capabilities: Capabilities::default()
+          .probe("docker", || false)
+          .available("node"),
+        ..Default::default()
+      },
+      |test| {
+        assert_eq!(test.name, "specs::node");
+        TestResult::Passed
+      },
+    );
+    assert!(summary.failed_tests.is_empty());
     assert_eq!(
-      build_end_test_message(
-        super::TestResult::Ignored,
-        std::time::Duration::from_millis(10),
-      )
-      .0,
-      format!("{}\n", colors::gray("ignored"))
+      reporter.take_events(),
+      vec![
+        "category_start specs (2/2 tests)",
+        "test_ignored specs::docker: requires: docker",
+        "test_start specs::node",
+        "test_end specs::node passed",
+        "category_end specs",
+        "failures 0/2 []",
+      ]
+    );
+  }
+
+  #[test]
+  fn ignored_at_collection() {
+    let category = CategoryBuilder::<()>::new("specs")
+      .test_with("network", (), |mut test| {
+        test.ignored = Some("requires network".to_string());
+        test
+      })
+      .test("local")
+      .build();
+    let reporter = Arc::new(FakeReporter::default());
+    run_tests(
+      &category,
+      RunOptions {
+        deterministic: true,
+        reporter: reporter.clone(),
+        ..Default::default()
+      },
+      |test| {
+        assert_eq!(test.name, "specs::local");
+        TestResult::Passed
+      },
+    );
+    assert_eq!(
+      reporter.take_events(),
+      vec![
+        "category_start specs (2/2 tests)",
+        "test_start specs::local",
+        "test_end specs::local passed",
+        "test_ignored specs::network: requires network",
+        "category_end specs",
+        "failures 0/2 []",
+      ]
+    );
+  }
+
+  #[test]
+  fn ignore_on() {
+    let ignore_on = |platform: &str| {
+      let ignore_on = vec![platform.to_string()];
+      move |mut test: CollectedTest| {
+        test.settings.ignore_on = ignore_on;
+        test
+      }
+    };
+    let category = CategoryBuilder::<()>::new("specs")
+      .test_with("current", (), ignore_on(std::env::consts::OS))
+      .test_with("other", (), ignore_on("other_os"))
+      .build();
+    let reporter = Arc::new(FakeReporter::default());
+    run_tests(
+      &category,
+      RunOptions {
+        deterministic: true,
+        reporter: reporter.clone(),
+        ..Default::default()
+      },
+      |test| {
+        assert_eq!(test.name, "specs::other");
+        TestResult::Passed
+      },
+    );
+    assert_eq!(
+      reporter.take_events(),
+      vec![
+        "category_start specs (2/2 tests)".to_string(),
+        format!(
+          "test_ignored specs::current: ignore_on: {}",
+          std::env::consts::OS
+        ),
+        "test_start specs::other".to_string(),
+        "test_end specs::other passed".to_string(),
+        "category_end specs".to_string(),
+        "failures 0/2 []".to_string(),
+      ]
+    );
+  }
+
+  #[test]
+  fn deadline() {
+    let dir = std::env::temp_dir()
+      .join(format!("file_test_runner_deadline_{}", std::process::id()));
+    let state = StateStore::new(&dir);
+    let record = |name: &str, duration_ms| crate::history::HistoryTestRecord {
+      name: name.to_string(),
+      outcome: crate::history::HistoryOutcome::Passed,
+      duration_ms,
+    };
+    TestHistory::append(
+      &state,
+      &HistoryRun {
+        timestamp_ms: 0,
+        tests: vec![record("specs::a", 10), record("specs::b", 600_000)],
+      },
+    )
+    .unwrap();
+    let category = CategoryBuilder::<()>::new("specs")
+      .test("a")
+      .test("b")
+      .test("c")
+      .build();
+    let reporter = Arc::new(FakeReporter::default());
+    let summary = run_tests(
+      &category,
+      RunOptions {
+        deterministic: true,
+        reporter: reporter.clone(),
+        state,
+        deadline: Some(Duration::from_secs(60)),
+        on_failure: OnFailure::Return,
+        ..Default::default()
+      },
+      |test| {
+        assert_ne!(test.name, "specs::b");
+        TestResult::Passed
+      },
+    );
+    assert_eq!(summary.not_run, 1);
+    assert!(summary.failed_tests.is_empty());
+    assert_eq!(
+      reporter.take_events(),
+      vec![
+        "category_start specs (3/3 tests)",
+        "test_start specs::a",
+        "test_end specs::a passed",
+        "test_ignored specs::b: predicted to miss the deadline",
+        "test_start specs::c",
+        "test_end specs::c passed",
+        "category_end specs",
+        "run_aborted 1",
+        "failures 0/3 []",
+      ]
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn quarantine() {
+    let category = CategoryBuilder::<()>::new("specs")
+      .category("node_compat", |b| b.test("a").test("b"))
+      .test("c")
+      .test("d")
+      .build();
+    let mut quarantine = Quarantine::default();
+    quarantine.add("specs::node_compat::*").unwrap();
+    quarantine.add("specs::c").unwrap();
+    let reporter = Arc::new(FakeReporter::default());
+    let summary = run_tests(
+      &category,
+      RunOptions {
+        deterministic: true,
+        reporter: reporter.clone(),
+        quarantine,
+        on_failure: OnFailure::Return,
+        ..Default::default()
+      },
+      |test| match test.name.as_str() {
+        "specs::c" => TestResult::Passed,
+        _ => TestResult::failed(Vec::new()),
+      },
+    );
+    assert_eq!(summary.failed_tests, vec!["specs::d"]);
+    assert_eq!(
+      summary.quarantined_failures,
+      vec!["specs::node_compat::a", "specs::node_compat::b"]
+    );
+    let events = reporter.take_events();
+    assert_eq!(
+      events[events.len() - 3..],
+      [
+        "quarantine specs::node_compat::* [specs::node_compat::a, specs::node_compat::b]",
+        "quarantine specs::c []",
+        "failures 1/4 [specs::d]",
+      ]
     );
   }
 
   #[test]
-  fn test_build_end_test_message_sub_tests() {
-    let (message, failure_output) = build_end_test_message(
-      super::TestResult::SubTests(vec![
-        super::SubTestResult {
-          name: "step1".to_string(),
-          result: super::TestResult::Passed,
+  fn priority() {
+    let with_priority = |priority| {
+      move |mut test: CollectedTest| {
+        test.priority = priority;
+        test
+      }
+    };
+    let category = CategoryBuilder::<()>::new("specs")
+      .category("a", |a| {
+        a.test("normal").test_with(
+          "high",
+          (),
+          with_priority(TestPriority::High),
+        )
+      })
+      .category("b", |b| {
+        b.test_with("low", (), with_priority(TestPriority::Low))
+          .test_with("high", (), with_priority(TestPriority::High))
+      })
+      .build();
+    let run = |max_failures| {
+      let reporter = Arc::new(FakeReporter::default());
+      let summary = run_tests(
+        &category,
+        RunOptions {
+          reporter: reporter.clone(),
+          max_failures,
+          on_failure: OnFailure::Return,
+          ..Default::default()
         },
-        super::SubTestResult {
-          name: "step2".to_string(),
-          result: super::TestResult::Failed {
-            output: b"error1".to_vec(),
-          },
+        |test| {
+          if test.name == "specs::b::high" {
+            TestResult::failed(Vec::new())
+          } else {
+            TestResult::Passed
+          }
+        },
+      );
+      let started = reporter
+        .events()
+        .into_iter()
+        .filter_map(|event| {
+          event
+            .strip_prefix("test_start ")
+            .map(|name| name.to_string())
+        })
+        .collect::<Vec<_>>();
+      (started, summary)
+    };
+    let (started, _) = run(None);
+    assert_eq!(
+      started,
+      vec![
+        "specs::a::high",
+        "specs::b::high",
+        "specs::a::normal",
+        "specs::b::low"
+      ]
+    );
+    // failing fast on a high priority test skips the rest
+    let (started, summary) = run(Some(1));
+    assert_eq!(started, vec!["specs::a::high", "specs::b::high"]);
+    assert_eq!(summary.not_run, 2);
+  }
+
+  #[test]
+  fn backtrace_style() {
+    assert_eq!(BacktraceStyle::parse("Full"), Some(BacktraceStyle::Full));
+    assert_eq!(BacktraceStyle::parse("0"), Some(BacktraceStyle::Off));
+    assert_eq!(BacktraceStyle::parse("long"), None);
+    let text = [
+      "   0: std::backtrace::Backtrace::capture",
+      "   1: core::panicking::panic_fmt",
+      "             at /rustc/src/panicking.rs:72:14",
+      "   2: specs::run_test",
+    ]
+    .join("\n");
+    assert_eq!(
+      format_backtrace(text.clone(), BacktraceStyle::Short),
+      "   2: specs::run_test"
+    );
+    assert_eq!(format_backtrace(text.clone(), BacktraceStyle::Full), text);
+  }
+
+  #[test]
+  fn kills_after_timeout() {
+    let runner = ThreadPoolTestRunner::new(
+      1,
+      |_| PoolWorker {
+        run: Box::new(|_: &CollectedTest| {
+          std::thread::sleep(Duration::from_secs(10));
+          TestResult::Passed
+        }),
+        kill: None,
+        process_id: None,
+        output: Some(Box::new(|| b"connecting to server".to_vec())),
+      },
+      Arc::new(LogReporter::default()),
+      Timeouts {
+        default: Duration::ZERO,
+        overrides: Default::default(),
+        kill_after: Some(Duration::ZERO),
+        poll_interval: Duration::from_millis(10),
+        stall_after: None,
+        dump_stacks: false,
+      },
+    );
+    runner.queue_test(
+      CollectedTest::new("specs::slow", "slow", ()),
+      Arc::new(Fixtures::default()),
+    );
+    let (_, test, duration, result) = runner.receive_result();
+    assert_eq!(test.name, "specs::slow");
+    assert!(duration < Duration::from_secs(10));
+    let TestResult::Failed { output, .. } = result else {
+      std::panic!("expected failure");
+    };
+    let output = String::from_utf8(output).unwrap();
+    assert!(
+      output.starts_with("connecting to server\nTest was failed"),
+      "{}",
+      output
+    );
+    assert!(output.contains("exceeded its timeout"), "{}", output);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn watchdog_interval() {
+    // timeouts are only tracked when running tests in parallel or in worker
+    // processes, so use a worker that's slow to respond
+    let script = concat!(
+      "read request\n",
+      "sleep 0.3\n",
+      "printf '\\036file_test_runner:\\n' >&2\n",
+      "printf '\\036file_test_runner:",
+      r#"{"type":"result","name":"specs::slow","result":"Passed"}"#,
+      "\\n'\n",
+      "read request\n",
+    );
+    let category = CategoryBuilder::<()>::new("specs").test("slow").build();
+    let run = |watchdog_interval| {
+      let reporter = Arc::new(FakeReporter::default());
+      run_tests(
+        &category,
+        RunOptions {
+          workers: Some(WorkerOptions {
+            commands: vec![["sh", "-c", script].map(String::from).to_vec()],
+            ..Default::default()
+          }),
+          reporter: reporter.clone(),
+          timeout: Some(Duration::from_millis(20)),
+          watchdog_interval,
+          ..Default::default()
         },
-        super::SubTestResult {
-          name: "step3".to_string(),
-          result: super::TestResult::Failed {
-            output: b"error2".to_vec(),
-          },
+        |_| unreachable!(),
+      );
+      reporter
+        .take_events()
+        .into_iter()
+        .filter(|event| event.starts_with("long_running"))
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(
+      run(Some(Duration::from_millis(10))),
+      vec!["long_running specs::slow"]
+    );
+    // checked too rarely by default to notice the test
+    assert_eq!(run(None), Vec::<String>::new());
+  }
+
+  #[test]
+  fn watchdog_runs_on_its_own_thread() {
+    let create_runner = |poll_interval| {
+      let reporter = Arc::new(FakeReporter::default());
+      let runner = ThreadPoolTestRunner::new(
+        1,
+        |_| PoolWorker {
+          run: Box::new(|_: &CollectedTest| {
+            std::thread::sleep(Duration::from_millis(300));
+            TestResult::Passed
+          }),
+          kill: None,
+          process_id: None,
+          output: None,
         },
-        super::SubTestResult {
-          name: "step4".to_string(),
-          result: super::TestResult::SubTests(vec![
-            super::SubTestResult {
-              name: "sub-step1".to_string(),
-              result: super::TestResult::Passed,
-            },
-            super::SubTestResult {
-              name: "sub-step2".to_string(),
-              result: super::TestResult::Failed {
-                output: b"error3".to_vec(),
-              },
-            },
-          ]),
+        reporter.clone(),
+        Timeouts {
+          default: Duration::from_millis(50),
+          overrides: Default::default(),
+          kill_after: None,
+          poll_interval,
+          stall_after: None,
+          dump_stacks: false,
         },
-      ]),
-      std::time::Duration::from_millis(10),
+      );
+      (runner, reporter)
+    };
+
+    // the only thread of the pool is busy running the test and no result
+    // is being received, yet the long running test is still reported
+    let (runner, reporter) = create_runner(Duration::from_millis(10));
+    runner.queue_test(
+      CollectedTest::new("specs::slow", "slow", ()),
+      Arc::new(Fixtures::default()),
     );
+    let start = Instant::now();
+    while reporter.events().is_empty() {
+      assert!(start.elapsed() < Duration::from_secs(5));
+      std::thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(reporter.events(), vec!["long_running specs::slow"]);
+    drop(runner);
 
-    assert_eq!(
-      message,
-      format!(
-        "{}\n  step1 {}\n  step2 {}\n  step3 {}\n  step4\n    sub-step1 {}\n    sub-step2 {}\n",
-        colors::gray("(10ms)"),
-        colors::green_bold("ok"),
-        colors::red_bold("fail"),
-        colors::red_bold("fail"),
-        colors::green_bold("ok"),
-        colors::red_bold("fail"),
+    // dropping the pool stops the watchdog without waiting for its interval
+    let (runner, _) = create_runner(Duration::from_secs(60));
+    let start = Instant::now();
+    drop(runner);
+    assert!(start.elapsed() < Duration::from_secs(5));
+  }
+
+  #[test]
+  fn replaces_abandoned_threads() {
+    let running = Arc::new(AtomicUsize::new(0));
+    let runner = ThreadPoolTestRunner::new(
+      2,
+      {
+        let running = running.clone();
+        move |_| PoolWorker {
+          run: Box::new({
+            let running = running.clone();
+            move |test: &CollectedTest| {
+              if test.name == "specs::stuck" {
+                std::thread::sleep(Duration::from_secs(2));
+                return TestResult::Passed;
+              }
+              // only passes when both tests run at the same time
+              running.fetch_add(1, Ordering::SeqCst);
+              let start = Instant::now();
+              while running.load(Ordering::SeqCst) < 2 {
+                if start.elapsed() > Duration::from_secs(1) {
+                  return TestResult::failed("ran alone");
+                }
+                std::thread::sleep(Duration::from_millis(10));
+              }
+              TestResult::Passed
+            }
+          }),
+          kill: None,
+          process_id: None,
+          output: None,
+        }
+      },
+      Arc::new(FakeReporter::default()),
+      Timeouts {
+        default: Duration::ZERO,
+        overrides: Default::default(),
+        kill_after: Some(Duration::ZERO),
+        poll_interval: Duration::from_millis(10),
+        stall_after: None,
+        dump_stacks: false,
+      },
+    );
+    let queue = |name: &str| {
+      let mut test = CollectedTest::new(name, name, ());
+      test.settings.timeout = Some(Duration::from_secs(10));
+      runner.queue_test(test, Arc::new(Fixtures::default()))
+    };
+    runner.queue_test(
+      CollectedTest::new("specs::stuck", "stuck", ()),
+      Arc::new(Fixtures::default()),
+    );
+    let (_, test, _, result) = runner.receive_result();
+    assert_eq!(test.name, "specs::stuck");
+    assert!(result.is_failed());
+    // the stuck test is still running, so both of these only run at the
+    // same time when its thread was replaced
+    queue("specs::a");
+    queue("specs::b");
+    for _ in 0..2 {
+      let (_, test, _, result) = runner.receive_result();
+      assert!(matches!(result, TestResult::Passed), "{}", test.name);
+    }
+  }
+
+  #[test]
+  fn duplicate_test_names() {
+    let runner = ThreadPoolTestRunner::new(
+      2,
+      |_| PoolWorker {
+        run: Box::new(|test: &CollectedTest<usize>| {
+          TestResult::failed(test.data.to_string())
+        }),
+        kill: None,
+        process_id: None,
+        output: None,
+      },
+      Arc::new(FakeReporter::default()),
+      Timeouts {
+        default: DEFAULT_TIMEOUT,
+        overrides: Default::default(),
+        kill_after: None,
+        poll_interval: Duration::from_millis(10),
+        stall_after: None,
+        dump_stacks: false,
+      },
+    );
+    let ids = [1, 2].map(|data| {
+      runner.queue_test(
+        CollectedTest::new("specs::same", "same", data),
+        Arc::new(Fixtures::default()),
       )
+    });
+    assert_ne!(ids[0], ids[1]);
+    let mut results = [runner.receive_result(), runner.receive_result()]
+      .map(|(id, test, _, result)| (id, test.data, result.failure_output()));
+    results.sort_by_key(|(id, _, _)| *id);
+    assert_eq!(
+      results,
+      [(ids[0], 1, b"1".to_vec()), (ids[1], 2, b"2".to_vec())]
     );
+  }
 
+  #[test]
+  fn reports_stalled_run() {
+    let reporter = Arc::new(FakeReporter::default());
+    let runner = ThreadPoolTestRunner::new(
+      1,
+      |_| PoolWorker {
+        run: Box::new(|_: &CollectedTest| {
+          std::thread::sleep(Duration::from_millis(200));
+          TestResult::Passed
+        }),
+        kill: None,
+        process_id: None,
+        output: None,
+      },
+      reporter.clone(),
+      Timeouts {
+        default: DEFAULT_TIMEOUT,
+        overrides: Default::default(),
+        kill_after: None,
+        poll_interval: Duration::from_millis(10),
+        stall_after: Some(Duration::from_millis(50)),
+        dump_stacks: false,
+      },
+    );
+    runner.queue_test(
+      CollectedTest::new("specs::stuck", "stuck", ()),
+      Arc::new(Fixtures::default()),
+    );
+    let (_, test, _, result) = runner.receive_result();
+    assert_eq!(test.name, "specs::stuck");
+    assert!(!result.is_failed());
+    drop(runner);
+    // only reported once per stall
+    assert_eq!(reporter.events(), vec!["stalled specs::stuck (stuck)"]);
+  }
+
+  #[test]
+  fn excludes_review_time_from_timeout() {
+    let reporter = Arc::new(FakeReporter::with_review(|review| {
+      std::thread::sleep(Duration::from_millis(300));
+      review.actual == "accepted"
+    }));
+    let runner = ThreadPoolTestRunner::new(
+      1,
+      |_| PoolWorker {
+        run: Box::new(|test: &CollectedTest| {
+          let accepted = review_output(&OutputReview {
+            test_name: test.name.clone(),
+            source: "specs/review.out".to_string(),
+            expected: "expected".to_string(),
+            actual: "accepted".to_string(),
+          });
+          if accepted {
+            TestResult::Passed
+          } else {
+            TestResult::failed("rejected")
+          }
+        }),
+        kill: None,
+        process_id: None,
+        output: None,
+      },
+      reporter.clone(),
+      Timeouts {
+        default: Duration::from_millis(50),
+        overrides: Default::default(),
+        kill_after: Some(Duration::from_millis(50)),
+        poll_interval: Duration::from_millis(10),
+        stall_after: None,
+        dump_stacks: false,
+      },
+    );
+    runner.queue_test(
+      CollectedTest::new("specs::review", "review", ()),
+      Arc::new(Fixtures::default()),
+    );
+    let (_, test, duration, result) = runner.receive_result();
+    assert_eq!(test.name, "specs::review");
+    assert!(matches!(result, TestResult::Passed), "{:?}", result);
+    assert!(duration < Duration::from_millis(300));
+    assert_eq!(
+      reporter.take_events(),
+      vec!["review specs::review specs/review.out"]
+    );
+    // rejected outside of a run
+    assert!(!review_output(&OutputReview {
+      test_name: "specs::review".to_string(),
+      source: "specs/review.out".to_string(),
+      expected: "expected".to_string(),
+      actual: "accepted".to_string(),
+    }));
+  }
+
+  #[test]
+  fn category_budgets() {
+    let test = |name: &str| {
+      CollectedCategoryOrTest::Test(CollectedTest::new(name, name, ()))
+    };
+    let category = CollectedTestCategory {
+      name: "specs".to_string(),
+      path: PathBuf::from("specs"),
+      description: None,
+      children: vec![
+        test("specs::a"),
+        CollectedCategoryOrTest::Category(CollectedTestCategory {
+          name: "specs::slow".to_string(),
+          path: PathBuf::from("specs/slow"),
+          description: None,
+          children: vec![test("specs::slow::b")],
+        }),
+      ],
+    };
+    let summary = run_tests_inner(
+      &category,
+      RunOptions {
+        category_budget: Some(Arc::new(|category| {
+          Some(Duration::from_millis(match category.name.as_str() {
+            "specs" => 1000,
+            _ => 5,
+          }))
+        })),
+        ..Default::default()
+      },
+      |test| {
+        if test.name == "specs::slow::b" {
+          std::thread::sleep(Duration::from_millis(10));
+        }
+        TestResult::Passed
+      },
+      None,
+    );
+    assert!(summary.failed_tests.is_empty());
+    assert_eq!(summary.over_budget_categories, vec!["specs::slow"]);
+  }
+
+  #[test]
+  fn category_test_counts() {
+    let category = CategoryBuilder::<()>::new("specs")
+      .test("a")
+      .category("lsp", |lsp| {
+        lsp
+          .test("b")
+          .test("c")
+          .category("completions", |b| b.test("d"))
+      })
+      .category("empty", |b| b)
+      .build();
+    let reporter = Arc::new(FakeReporter::default());
+    run_tests_inner(
+      &category,
+      RunOptions {
+        deterministic: true,
+        reporter: reporter.clone(),
+        ..Default::default()
+      },
+      |_| TestResult::Passed,
+      None,
+    );
+    let starts = reporter
+      .take_events()
+      .into_iter()
+      .filter(|event| event.starts_with("category_start"))
+      .collect::<Vec<_>>();
     assert_eq!(
-      String::from_utf8(failure_output).unwrap(),
-      "error1\nerror2\nerror3"
+      starts,
+      [
+        "category_start specs (1/4 tests)",
+        "category_start specs::lsp (2/3 tests)",
+        "category_start specs::lsp::completions (1/1 tests)",
+      ]
+    );
+  }
+
+  #[test]
+  fn category_timings() {
+    let category = CategoryBuilder::<()>::new("specs")
+      .test("a")
+      .category("lsp", |lsp| lsp.test("b").test("c"))
+      .build();
+    let reporter = Arc::new(FakeReporter::default());
+    run_tests_inner(
+      &category,
+      RunOptions {
+        reporter: reporter.clone(),
+        ..Default::default()
+      },
+      |test| {
+        if test.name.starts_with("specs::lsp::") {
+          std::thread::sleep(Duration::from_millis(10));
+        }
+        TestResult::Passed
+      },
+      None,
+    );
+    let timings = reporter.category_timings();
+    let names = timings
+      .iter()
+      .map(|t| (t.name.as_str(), t.test_count))
+      .collect::<Vec<_>>();
+    assert_eq!(names, vec![("specs::lsp", 2), ("specs", 3)]);
+    assert!(timings[0].total >= Duration::from_millis(20));
+    assert!(timings[0].average() >= Duration::from_millis(10));
+  }
+
+  #[test]
+  fn caches_with_input_files() {
+    let dir = std::env::temp_dir().join(format!(
+      "file_test_runner_input_files_{}",
+      std::process::id()
+    ));
+    let fixtures = dir.join("fixtures");
+    std::fs::create_dir_all(&fixtures).unwrap();
+    std::fs::write(dir.join("a.txt"), "a").unwrap();
+    std::fs::write(fixtures.join("shared.json"), "{}").unwrap();
+    let category = CollectedTestCategory {
+      name: "specs".to_string(),
+      path: dir.clone(),
+      description: None,
+      children: vec![CollectedCategoryOrTest::Test(CollectedTest::new(
+        "specs::a",
+        dir.join("a.txt"),
+        (),
+      ))],
+    };
+    let run = || {
+      let reporter = Arc::new(FakeReporter::default());
+      run_tests(
+        &category,
+        RunOptions {
+          deterministic: true,
+          reporter: reporter.clone(),
+          state: StateStore::new(dir.join("state")),
+          cache_results: true,
+          input_files: Some(Arc::new(|test| {
+            vec![test.path.with_file_name("fixtures")]
+          })),
+          ..Default::default()
+        },
+        |_| TestResult::Passed,
+      );
+      reporter
+        .take_events()
+        .into_iter()
+        .filter(|event| event.contains("specs::a"))
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(run(), ["test_start specs::a", "test_end specs::a passed"]);
+    assert_eq!(run(), ["test_cached specs::a"]);
+    std::fs::write(fixtures.join("shared.json"), "[]").unwrap();
+    assert_eq!(run(), ["test_start specs::a", "test_end specs::a passed"]);
+    assert_eq!(run(), ["test_cached specs::a"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn overlaps_categories() {
+    let test = |name: &str| {
+      CollectedCategoryOrTest::Test(CollectedTest::new(name, name, ()))
+    };
+    let category = |name: &str, children| {
+      CollectedCategoryOrTest::Category(CollectedTestCategory {
+        name: name.to_string(),
+        path: PathBuf::from(name),
+        description: None,
+        children,
+      })
+    };
+    let root = CollectedTestCategory {
+      name: "specs".to_string(),
+      path: PathBuf::from("specs"),
+      description: None,
+      children: vec![
+        category("specs::a", vec![test("specs::a::slow")]),
+        category("specs::b", vec![test("specs::b::fast")]),
+      ],
+    };
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let run_test: RunTestFunc<()> = Arc::new({
+      let events = events.clone();
+      move |test| {
+        let category = test.name.rsplit_once("::").unwrap().0.to_string();
+        let value =
+          Fixtures::current_category().get_or_init(|| category.clone());
+        assert_eq!(*value, category);
+        events.lock().push(format!("start {}", test.name));
+        if test.name.ends_with("slow") {
+          std::thread::sleep(Duration::from_millis(500));
+        }
+        events.lock().push(format!("end {}", test.name));
+        TestResult::Passed
+      }
+    });
+    let fixtures = Arc::new(Fixtures::default());
+    let mut context = Context {
+      thread_pool_runner: Some(ThreadPoolTestRunner::new(
+        2,
+        {
+          let run_test = run_test.clone();
+          let fixtures = fixtures.clone();
+          move |_| PoolWorker {
+            run: worker_run_func(run_test.clone(), fixtures.clone(), None),
+            kill: None,
+            process_id: None,
+            output: None,
+          }
+        },
+        Arc::new(LogReporter::default()),
+        Timeouts {
+          default: DEFAULT_TIMEOUT,
+          overrides: Default::default(),
+          kill_after: None,
+          poll_interval: DEFAULT_POLL_INTERVAL,
+          stall_after: None,
+          dump_stacks: false,
+        },
+      )),
+      failures: Vec::new(),
+      run_test: worker_run_func(run_test, fixtures, None),
+      reporter: Arc::new(LogReporter::default()),
+      cache: None,
+      input_files: None,
+      control: None,
+      max_failures: None,
+      verbosity: 0,
+      deterministic: false,
+      capabilities: Default::default(),
+      deadline: None,
+      quarantine: Default::default(),
+      quarantined: Vec::new(),
+      not_run: 0,
+      category_budget: None,
+      over_budget: Vec::new(),
+      category_timings: Vec::new(),
+      passed_tests: None,
+    };
+    run_category(&root, &mut context);
+    assert!(context.failures.is_empty());
+    // the next category starts without waiting for the previous to finish
+    let events = events.lock();
+    let position = |event: &str| events.iter().position(|e| e == event);
+    assert!(
+      position("end specs::b::fast") < position("end specs::a::slow"),
+      "{:?}",
+      events
     );
+    let timings = context
+      .category_timings
+      .iter()
+      .map(|t| t.name.as_str())
+      .collect::<Vec<_>>();
+    assert_eq!(timings, vec!["specs::a", "specs::b", "specs"]);
   }
 }
@@ -0,0 +1,146 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::cell::Cell;
+
+use crate::cache::Fnv1a;
+use crate::exit_code::exit_with_internal_error;
+
+/// Environment variable to set the seed of the run, which the seed of
+/// each test is derived from. See `RunOptions::seed`.
+pub const SEED_ENV_VAR: &str = "FILE_TEST_RUNNER_SEED";
+/// Environment variable with the test's seed that's set on commands
+/// created via `CollectedTest::command`.
+pub const TEST_SEED_ENV_VAR: &str = "FILE_TEST_RUNNER_TEST_SEED";
+
+#[derive(Clone, Copy)]
+struct CurrentSeed {
+  seed: u64,
+  /// Whether the seed was accessed by the test.
+  used: bool,
+}
+
+thread_local! {
+  static CURRENT_SEED: Cell<Option<CurrentSeed>> = const { Cell::new(None) };
+}
+
+/// Gets the seed of the test running on the current thread, which is
+/// derived from the test's name and the seed of the run, so it's the
+/// same across runs unless the run's seed changes.
+///
+/// The seed is included in the output of the test when it fails.
+///
+/// Panics when not called from a test.
+pub fn test_seed() -> u64 {
+  current_seed().expect("The test seed can only be accessed in a test.")
+}
+
+/// Gets the seed of the current test, marking it as used.
+pub(crate) fn current_seed() -> Option<u64> {
+  CURRENT_SEED.with(|current| {
+    let mut value = current.get()?;
+    value.used = true;
+    current.set(Some(value));
+    Some(value.seed)
+  })
+}
+
+/// Gets the seed of the run from the options or environment variable.
+pub(crate) fn resolve_run_seed(seed: Option<u64>) -> u64 {
+  seed
+    .or_else(|| {
+      let seed = std::env::var(SEED_ENV_VAR).ok()?;
+      match seed.parse() {
+        Ok(seed) => Some(seed),
+        Err(_) => exit_with_internal_error(format!(
+          "Invalid {} '{}', expected a number.",
+          SEED_ENV_VAR, seed
+        )),
+      }
+    })
+    .unwrap_or(0)
+}
+
+pub(crate) fn derive_test_seed(run_seed: u64, test_name: &str) -> u64 {
+  let mut hasher = Fnv1a::default();
+  hasher.write(&run_seed.to_le_bytes());
+  hasher.write(test_name.as_bytes());
+  hasher.0
+}
+
+/// Runs the function with the seed set for the current thread, returning
+/// whether the seed was used.
+pub(crate) fn with_test_seed<R>(
+  seed: u64,
+  func: impl FnOnce() -> R,
+) -> (R, bool) {
+  let previous = CURRENT_SEED
+    .with(|current| current.replace(Some(CurrentSeed { seed, used: false })));
+  let result = func();
+  let current = CURRENT_SEED.with(|current| current.replace(previous));
+  (result, current.is_some_and(|current| current.used))
+}
+
+#[cfg(test)]
+mod test {
+  use std::path::PathBuf;
+  use std::sync::Arc;
+
+  use parking_lot::Mutex;
+
+  use super::*;
+  use crate::collection::CollectedCategoryOrTest;
+  use crate::collection::CollectedTest;
+  use crate::collection::CollectedTestCategory;
+  use crate::run_tests_inner;
+  use crate::RunOptions;
+  use crate::TestResult;
+
+  #[test]
+  fn test_seeds() {
+    let category = CollectedTestCategory {
+      name: "specs".to_string(),
+      path: PathBuf::from("specs"),
+      description: None,
+      children: ["specs::a", "specs::b"]
+        .into_iter()
+        .map(|name| {
+          CollectedCategoryOrTest::Test(CollectedTest::new(name, name, ()))
+        })
+        .collect(),
+    };
+    let run = |seed| {
+      let seeds = Arc::new(Mutex::new(Vec::new()));
+      let summary = run_tests_inner(
+        &category,
+        RunOptions {
+          seed: Some(seed),
+          ..Default::default()
+        },
+        {
+          let seeds = seeds.clone();
+          move |test| {
+            seeds.lock().push(test_seed());
+            if test.name == "specs::b" {
+              TestResult::failed(Vec::new())
+            } else {
+              TestResult::Passed
+            }
+          }
+        },
+        None,
+      );
+      assert_eq!(summary.failed_tests, vec!["specs::b".to_string()]);
+      let seeds = seeds.lock().clone();
+      seeds
+    };
+    let seeds = run(1);
+    assert_ne!(seeds[0], seeds[1]);
+    assert_eq!(seeds[1], derive_test_seed(1, "specs::b"));
+    assert_eq!(run(1), seeds);
+    assert_ne!(run(2), seeds);
+
+    // only reported as used when accessed
+    assert!(!with_test_seed(5, || {}).1);
+    assert_eq!(with_test_seed(5, test_seed), (5, true));
+  }
+}
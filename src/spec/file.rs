@@ -0,0 +1,402 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::collection::CollectedTest;
+use crate::PathedIoError;
+use crate::SubTestResult;
+use crate::TestResult;
+
+use super::check_or_review_output;
+use super::resolve_path;
+use super::ExpectedExitCode;
+use super::ExpectedOutput;
+use super::SpecCommand;
+use super::SpecStdin;
+
+/// Number of times a step marked as flaky is attempted.
+const FLAKY_ATTEMPTS: usize = 3;
+
+/// Declarative spec file consisting of ordered steps.
+///
+/// ```json
+/// {
+///   "envs": { "NO_COLOR": "1" },
+///   "steps": [
+///     { "args": "run main.ts", "outputFile": "main.out" },
+///     { "args": ["eval", "Deno.exit(2)"], "output": "", "exitCode": 2 }
+///   ]
+/// }
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct SpecFile {
+  /// Environment variables for all the steps.
+  #[serde(default)]
+  pub envs: HashMap<String, String>,
+  pub steps: Vec<SpecStep>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct SpecStep {
+  /// Name of the sub test. Defaults to the arguments.
+  #[serde(default)]
+  pub name: Option<String>,
+  /// Arguments as a list or as a string split on whitespace. These are
+  /// appended to `SpecFileOptions::command_prefix`.
+  pub args: SpecStepArgs,
+  /// Directory to run in relative to the spec file's directory.
+  #[serde(default)]
+  pub cwd: Option<PathBuf>,
+  #[serde(default)]
+  pub envs: HashMap<String, String>,
+  /// Expected output text, which may contain wildcards. The output is not
+  /// checked when neither this nor `output_file` is provided.
+  #[serde(default)]
+  pub output: Option<String>,
+  /// Path of a file with the expected output relative to the spec file's
+  /// directory, which may contain wildcards.
+  #[serde(default)]
+  pub output_file: Option<PathBuf>,
+  /// Text to write to the step's stdin. No input is provided when neither
+  /// this nor `stdin_file` is provided.
+  #[serde(default)]
+  pub stdin: Option<String>,
+  /// Path of a file relative to the spec file's directory whose contents are
+  /// written to the step's stdin.
+  #[serde(default)]
+  pub stdin_file: Option<PathBuf>,
+  /// Expected exit code as a number or `"nonzero"`. Defaults to 0.
+  #[serde(default)]
+  pub exit_code: ExpectedExitCode,
+  /// Retry the step when it fails.
+  #[serde(default)]
+  pub flaky: bool,
+  /// Number of times to run the step, which must pass every time.
+  #[serde(default)]
+  pub repeat: Option<usize>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum SpecStepArgs {
+  List(Vec<String>),
+  Text(String),
+}
+
+impl Default for SpecStepArgs {
+  fn default() -> Self {
+    Self::List(Vec::new())
+  }
+}
+
+impl SpecStepArgs {
+  pub fn to_vec(&self) -> Vec<String> {
+    match self {
+      SpecStepArgs::List(args) => args.clone(),
+      SpecStepArgs::Text(text) => {
+        text.split_whitespace().map(|s| s.to_string()).collect()
+      }
+    }
+  }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SpecFileOptions {
+  /// Program and arguments prepended to each step's arguments
+  /// (ex. `vec!["deno".into()]`).
+  pub command_prefix: Vec<String>,
+  /// Timeout of each step.
+  pub timeout: Option<Duration>,
+  /// Maximum number of bytes of output to capture from each step
+  /// (see `SpecCommand::max_output_size`).
+  pub max_output_size: Option<usize>,
+}
+
+impl SpecFile {
+  pub fn parse(text: &str) -> Result<Self, serde_json::Error> {
+    serde_json::from_str(text)
+  }
+
+  /// Runs the steps, producing a sub test for each one.
+  ///
+  /// The steps after a failing step are reported as ignored.
+  pub fn run<TData>(
+    &self,
+    test: &CollectedTest<TData>,
+    options: &SpecFileOptions,
+  ) -> TestResult {
+    let mut sub_tests = Vec::with_capacity(self.steps.len());
+    let mut failed = false;
+    for (index, step) in self.steps.iter().enumerate() {
+      let result = if failed {
+        TestResult::Ignored
+      } else {
+        self.run_step(test, step, options)
+      };
+      failed |= result.is_failed();
+      sub_tests.push(SubTestResult {
+        name: step.name.clone().unwrap_or_else(|| {
+          format!("{}: {}", index + 1, step_args_text(step))
+        }),
+        result,
+      });
+    }
+    TestResult::SubTests(sub_tests)
+  }
+
+  fn run_step<TData>(
+    &self,
+    test: &CollectedTest<TData>,
+    step: &SpecStep,
+    options: &SpecFileOptions,
+  ) -> TestResult {
+    let expected_output = match read_expected_output(test, step) {
+      Ok(expected) => expected,
+      Err(err) => {
+        return TestResult::failed(format!("{:#}", err).into_bytes());
+      }
+    };
+    let stdin = match step_stdin(step) {
+      Ok(stdin) => stdin,
+      Err(err) => {
+        return TestResult::failed(format!("{:#}", err).into_bytes());
+      }
+    };
+    let mut env_vars = self.envs.clone();
+    env_vars.extend(step.envs.clone());
+    let command = SpecCommand {
+      args: options
+        .command_prefix
+        .iter()
+        .cloned()
+        .chain(step.args.to_vec())
+        .collect(),
+      cwd: match &step.cwd {
+        Some(cwd) => Some(resolve_path(test, cwd)),
+        None => test.path.parent().map(|dir| dir.to_path_buf()),
+      },
+      env_vars,
+      timeout: options.timeout,
+      output_file: None,
+      cpu_affinity: None,
+      limits: Default::default(),
+      max_output_size: options.max_output_size,
+      stdin,
+      exit_code: step.exit_code,
+    };
+    let attempts = if step.flaky { FLAKY_ATTEMPTS } else { 1 };
+    let mut result = TestResult::Passed;
+    for _ in 0..step.repeat.unwrap_or(1) {
+      for _ in 0..attempts {
+        result = match command.spawn_and_wait(test) {
+          Ok(output) => match check_or_review_output(
+            test,
+            &output,
+            command.exit_code,
+            expected_output.as_ref(),
+            options.timeout,
+          ) {
+            Ok(()) => TestResult::Passed,
+            Err(message) => TestResult::failed(message.into_bytes()),
+          },
+          Err(err) => TestResult::failed(format!("{:#}", err).into_bytes()),
+        };
+        if !result.is_failed() {
+          break;
+        }
+      }
+      if result.is_failed() {
+        break;
+      }
+    }
+    result
+  }
+}
+
+/// Parses the test file as a `SpecFile` and runs it.
+pub fn run_spec_file<TData>(
+  test: &CollectedTest<TData>,
+  options: &SpecFileOptions,
+) -> TestResult {
+  let text = match test.content() {
+    Ok(text) => text,
+    Err(err) => {
+      return TestResult::failed(format!("{:#}", err).into_bytes());
+    }
+  };
+  match SpecFile::parse(text) {
+    Ok(spec_file) => spec_file.run(test, options),
+    Err(err) => TestResult::failed(format!(
+      "Failed to parse {}: {:#}",
+      test.path.display(),
+      err
+    )),
+  }
+}
+
+fn step_stdin(step: &SpecStep) -> Result<SpecStdin, anyhow::Error> {
+  match (&step.stdin, &step.stdin_file) {
+    (Some(_), Some(_)) => {
+      anyhow::bail!("Only one of 'stdin' and 'stdinFile' may be provided.")
+    }
+    (Some(stdin), None) => Ok(SpecStdin::Bytes(stdin.clone().into_bytes())),
+    (None, Some(stdin_file)) => Ok(SpecStdin::File(stdin_file.clone())),
+    (None, None) => Ok(SpecStdin::Null),
+  }
+}
+
+fn read_expected_output<TData>(
+  test: &CollectedTest<TData>,
+  step: &SpecStep,
+) -> Result<Option<ExpectedOutput>, anyhow::Error> {
+  match (&step.output, &step.output_file) {
+    (Some(_), Some(_)) => {
+      anyhow::bail!("Only one of 'output' and 'outputFile' may be provided.")
+    }
+    (Some(output), None) => Ok(Some(ExpectedOutput {
+      source: "the inline output".to_string(),
+      path: None,
+      text: output.clone(),
+    })),
+    (None, Some(output_file)) => {
+      let path = resolve_path(test, output_file);
+      let text = std::fs::read_to_string(&path)
+        .map_err(|err| PathedIoError::new(&path, err))?;
+      Ok(Some(ExpectedOutput {
+        source: path.display().to_string(),
+        path: Some(path),
+        text,
+      }))
+    }
+    (None, None) => Ok(None),
+  }
+}
+
+fn step_args_text(step: &SpecStep) -> String {
+  match &step.args {
+    SpecStepArgs::List(args) => args.join(" "),
+    SpecStepArgs::Text(text) => text.clone(),
+  }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+  use super::*;
+
+  #[test]
+  fn runs_steps() {
+    let dir = std::env::temp_dir()
+      .join(format!("file_test_runner_spec_file_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("a.out"), "[WILDCARD]a\n").unwrap();
+    std::fs::write(dir.join("f.stdin"), "f\n").unwrap();
+    let test = CollectedTest::new("specs::test", dir.join("__test__.json"), ());
+    let spec_file = SpecFile::parse(
+      r#"{
+        "envs": { "VALUE": "a" },
+        "steps": [
+          { "args": ["-c", "echo $VALUE"], "outputFile": "a.out" },
+          { "args": ["-c", "echo wrote a.out"], "output": "wrote a.out\n" },
+          { "name": "exit", "args": ["-c", "exit 2"], "exitCode": 2, "repeat": 2 },
+          { "args": ["-c", "cat"], "stdin": "d\n", "output": "d\n" },
+          { "args": ["-c", "cat"], "stdin": "f.stdin", "output": "f.stdin" },
+          { "args": ["-c", "cat"], "stdinFile": "f.stdin", "output": "f\n" },
+          { "name": "nonzero", "args": ["-c", "exit 3"], "exitCode": "nonzero" },
+          { "args": ["-c", "echo b"], "output": "c\n" },
+          { "args": "-c true" }
+        ]
+      }"#,
+    )
+    .unwrap();
+    let options = SpecFileOptions {
+      command_prefix: vec!["sh".to_string()],
+      timeout: None,
+      max_output_size: None,
+    };
+    let TestResult::SubTests(sub_tests) = spec_file.run(&test, &options) else {
+      unreachable!();
+    };
+    let results = sub_tests
+      .iter()
+      .map(|s| {
+        let kind = match &s.result {
+          TestResult::Passed => "passed",
+          TestResult::Ignored => "ignored",
+          _ => "failed",
+        };
+        (s.name.as_str(), kind)
+      })
+      .collect::<Vec<_>>();
+    assert_eq!(
+      results,
+      vec![
+        ("1: -c echo $VALUE", "passed"),
+        ("2: -c echo wrote a.out", "passed"),
+        ("exit", "passed"),
+        ("4: -c cat", "passed"),
+        ("5: -c cat", "passed"),
+        ("6: -c cat", "passed"),
+        ("nonzero", "passed"),
+        ("8: -c echo b", "failed"),
+        ("9: -c true", "ignored"),
+      ]
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn rejects_output_and_output_file() {
+    let test = CollectedTest::new("specs::test", "__test__.json", ());
+    let spec_file = SpecFile::parse(
+      r#"{ "steps": [{ "args": "", "output": "a", "outputFile": "a.out" }] }"#,
+    )
+    .unwrap();
+    let Err(err) = read_expected_output(&test, &spec_file.steps[0]) else {
+      unreachable!();
+    };
+    assert_eq!(
+      err.to_string(),
+      "Only one of 'output' and 'outputFile' may be provided."
+    );
+  }
+
+  #[test]
+  fn rejects_stdin_and_stdin_file() {
+    let spec_file = SpecFile::parse(
+      r#"{ "steps": [{ "args": "", "stdin": "a", "stdinFile": "a.stdin" }] }"#,
+    )
+    .unwrap();
+    let Err(err) = step_stdin(&spec_file.steps[0]) else {
+      unreachable!();
+    };
+    assert_eq!(
+      err.to_string(),
+      "Only one of 'stdin' and 'stdinFile' may be provided."
+    );
+  }
+
+  #[test]
+  fn parses_exit_codes() {
+    let exit_code = |text: &str| {
+      SpecFile::parse(&format!(
+        r#"{{ "steps": [{{ "args": "", "exitCode": {} }}] }}"#,
+        text
+      ))
+      .map(|spec_file| spec_file.steps[0].exit_code)
+    };
+    assert_eq!(exit_code("1").unwrap(), ExpectedExitCode::Code(1));
+    assert_eq!(
+      exit_code(r#""nonzero""#).unwrap(),
+      ExpectedExitCode::NonZero
+    );
+    let err = exit_code(r#""one""#).unwrap_err().to_string();
+    assert!(err.contains("\"nonzero\""), "{}", err);
+  }
+}
@@ -0,0 +1,627 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+//! Runs tests as subprocesses and compares their output against
+//! expected output files.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use parking_lot::Mutex;
+use serde::Deserialize;
+
+use crate::collection::CollectedTest;
+use crate::output::CappedOutput;
+use crate::process::set_cpu_affinity;
+use crate::process::ProcessLimits;
+use crate::PathedIoError;
+use crate::TestResult;
+
+mod file;
+mod review;
+mod wildcard;
+
+pub use file::*;
+pub use wildcard::wildcard_match;
+
+/// Command to run for a test.
+///
+/// The following are replaced in the arguments:
+///
+/// - `{test_path}` - Path of the test file.
+/// - `{test_dir}` - Directory of the test file.
+/// - `{test_name}` - Fully resolved name of the test.
+#[derive(Debug, Clone, Default)]
+pub struct SpecCommand {
+  /// Program followed by its arguments.
+  pub args: Vec<String>,
+  /// Directory to run the command in. Defaults to the current directory.
+  pub cwd: Option<PathBuf>,
+  /// Environment variables to set in addition to the test's `env_vars`.
+  pub env_vars: HashMap<String, String>,
+  /// Kill the process and fail the test after this duration.
+  pub timeout: Option<Duration>,
+  /// File with the expected combined stdout and stderr, which may contain
+  /// wildcards (see `wildcard_match`). Relative paths are resolved
+  /// against the test file's directory.
+  pub output_file: Option<PathBuf>,
+  /// CPU cores the process may run on, which reduces the variance of
+  /// timing sensitive tests. Only supported on Linux.
+  pub cpu_affinity: Option<Vec<usize>>,
+  /// Resource limits and niceness of the process.
+  pub limits: ProcessLimits,
+  /// Maximum number of bytes of output to capture. Once exceeded, the
+  /// middle of the output is dropped while its head and tail are kept.
+  pub max_output_size: Option<usize>,
+  /// Input to write to the process' stdin.
+  pub stdin: SpecStdin,
+  /// Exit code the process must exit with. Defaults to 0.
+  pub exit_code: ExpectedExitCode,
+}
+
+/// Exit code a spec's process is expected to exit with.
+///
+/// Deserializes from a number or `"nonzero"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "ExpectedExitCodeValue")]
+pub enum ExpectedExitCode {
+  Code(i32),
+  /// Any exit code other than 0 (ex. for programs that don't document
+  /// their error exit codes).
+  NonZero,
+}
+
+impl Default for ExpectedExitCode {
+  fn default() -> Self {
+    Self::Code(0)
+  }
+}
+
+impl From<i32> for ExpectedExitCode {
+  fn from(code: i32) -> Self {
+    Self::Code(code)
+  }
+}
+
+impl ExpectedExitCode {
+  pub fn matches(&self, code: Option<i32>) -> bool {
+    match self {
+      Self::Code(expected) => code == Some(*expected),
+      // killed by a signal isn't an exit code
+      Self::NonZero => code.is_some_and(|code| code != 0),
+    }
+  }
+}
+
+impl std::fmt::Display for ExpectedExitCode {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Code(code) => write!(f, "exit code {}", code),
+      Self::NonZero => f.write_str("a non-zero exit code"),
+    }
+  }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ExpectedExitCodeValue {
+  Code(i32),
+  Text(String),
+}
+
+impl TryFrom<ExpectedExitCodeValue> for ExpectedExitCode {
+  type Error = String;
+
+  fn try_from(value: ExpectedExitCodeValue) -> Result<Self, Self::Error> {
+    match value {
+      ExpectedExitCodeValue::Code(code) => Ok(Self::Code(code)),
+      ExpectedExitCodeValue::Text(text) if text == "nonzero" => {
+        Ok(Self::NonZero)
+      }
+      ExpectedExitCodeValue::Text(text) => Err(format!(
+        "expected an exit code or \"nonzero\", but got \"{}\"",
+        text
+      )),
+    }
+  }
+}
+
+/// Input written to the stdin of a `SpecCommand`'s process, which allows
+/// testing programs that read their input from stdin.
+#[derive(Debug, Clone, Default)]
+pub enum SpecStdin {
+  /// The test file with a `.stdin` extension (ex. `cat.stdin` for
+  /// `cat.sh`) when it exists, otherwise no input.
+  #[default]
+  TestFile,
+  /// No input.
+  Null,
+  /// Contents of the file. Relative paths are resolved against the test
+  /// file's directory.
+  File(PathBuf),
+  /// The bytes (ex. from a field of the test's data).
+  Bytes(Vec<u8>),
+}
+
+impl SpecStdin {
+  fn read<TData>(
+    &self,
+    test: &CollectedTest<TData>,
+  ) -> Result<Option<Vec<u8>>, PathedIoError> {
+    match self {
+      SpecStdin::TestFile => {
+        let path = test.path.with_extension("stdin");
+        match std::fs::read(&path) {
+          Ok(bytes) => Ok(Some(bytes)),
+          Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+          Err(err) => Err(PathedIoError::new(&path, err)),
+        }
+      }
+      SpecStdin::Null => Ok(None),
+      SpecStdin::File(path) => {
+        let path = resolve_path(test, path);
+        std::fs::read(&path)
+          .map(Some)
+          .map_err(|err| PathedIoError::new(&path, err))
+      }
+      SpecStdin::Bytes(bytes) => Ok(Some(bytes.clone())),
+    }
+  }
+}
+
+/// Captured result of running a `SpecCommand`.
+#[derive(Debug, Clone)]
+pub struct SpecOutput {
+  /// Interleaved stdout and stderr.
+  pub combined: Vec<u8>,
+  pub stdout: Vec<u8>,
+  pub stderr: Vec<u8>,
+  /// Exit status or `None` if the process was killed after timing out.
+  pub status: Option<ExitStatus>,
+}
+
+impl SpecCommand {
+  /// Runs the command for the test and checks its output.
+  pub fn run<TData>(&self, test: &CollectedTest<TData>) -> TestResult {
+    match self.run_inner(test) {
+      Ok(result) => result,
+      Err(err) => TestResult::failed(format!("{:#}", err).into_bytes()),
+    }
+  }
+
+  fn run_inner<TData>(
+    &self,
+    test: &CollectedTest<TData>,
+  ) -> Result<TestResult, anyhow::Error> {
+    let expected = match &self.output_file {
+      Some(output_file) => {
+        let path = resolve_path(test, output_file);
+        let text = std::fs::read_to_string(&path)
+          .map_err(|err| PathedIoError::new(&path, err))?;
+        Some(ExpectedOutput {
+          source: path.display().to_string(),
+          path: Some(path),
+          text,
+        })
+      }
+      None => None,
+    };
+    let output = self.spawn_and_wait(test)?;
+    Ok(
+      match check_or_review_output(
+        test,
+        &output,
+        self.exit_code,
+        expected.as_ref(),
+        self.timeout,
+      ) {
+        Ok(()) => TestResult::Passed,
+        Err(message) => TestResult::failed(message.into_bytes()),
+      },
+    )
+  }
+
+  /// Spawns the command for the test and waits for it to exit or time out.
+  pub fn spawn_and_wait<TData>(
+    &self,
+    test: &CollectedTest<TData>,
+  ) -> Result<SpecOutput, anyhow::Error> {
+    let args = self
+      .args
+      .iter()
+      .map(|arg| expand_arg(arg, test))
+      .collect::<Vec<_>>();
+    let Some((program, args)) = args.split_first() else {
+      anyhow::bail!("spec command was empty");
+    };
+    let stdin = self.stdin.read(test)?;
+    let mut command = test.command(program);
+    command
+      .args(args)
+      .envs(&self.env_vars)
+      .stdin(if stdin.is_some() {
+        Stdio::piped()
+      } else {
+        Stdio::null()
+      })
+      .stdout(Stdio::piped())
+      .stderr(Stdio::piped());
+    if let Some(cwd) = &self.cwd {
+      command.current_dir(cwd);
+    }
+    if let Some(cores) = &self.cpu_affinity {
+      set_cpu_affinity(&mut command, cores);
+    }
+    self.limits.apply(&mut command);
+    let mut child = command.spawn().map_err(|err| {
+      anyhow::anyhow!("failed to spawn '{}': {:#}", program, err)
+    })?;
+    if let Some(stdin) = stdin {
+      let mut writer = child.stdin.take().unwrap();
+      // write on another thread so a process that writes a lot of output
+      // before reading its input doesn't deadlock. Errors are ignored
+      // because the process may exit without reading all of its input
+      std::thread::spawn(move || {
+        let _ = writer.write_all(&stdin);
+      });
+    }
+
+    let captured =
+      Arc::new(Mutex::new(CapturedOutput::new(self.max_output_size)));
+    let (done_tx, done_rx) = crossbeam_channel::unbounded();
+    spawn_reader(child.stdout.take().unwrap(), false, &captured, &done_tx);
+    spawn_reader(child.stderr.take().unwrap(), true, &captured, &done_tx);
+
+    let start = Instant::now();
+    let status = loop {
+      if let Some(status) = child.try_wait()? {
+        break Some(status);
+      }
+      if self
+        .timeout
+        .is_some_and(|timeout| start.elapsed() >= timeout)
+      {
+        let _ = child.kill();
+        let _ = child.wait();
+        break None;
+      }
+      std::thread::sleep(Duration::from_millis(10));
+    };
+    for _ in 0..2 {
+      if status.is_some() {
+        let _ = done_rx.recv();
+      } else {
+        // processes spawned by the killed process may keep the pipes open,
+        // so only wait a short time for the remaining output
+        let _ = done_rx.recv_timeout(Duration::from_secs(1));
+      }
+    }
+    let captured = std::mem::take(&mut *captured.lock());
+    Ok(SpecOutput {
+      combined: captured.combined.into_bytes(),
+      stdout: captured.stdout.into_bytes(),
+      stderr: captured.stderr.into_bytes(),
+      status,
+    })
+  }
+}
+
+pub(crate) struct ExpectedOutput {
+  /// Where the expected output came from for display purposes.
+  pub source: String,
+  /// File the expected output was read from, which is `None` for
+  /// inline output.
+  pub path: Option<PathBuf>,
+  pub text: String,
+}
+
+/// Checks the output like `check_output`, but with `--interactive-update`
+/// allows accepting mismatched output as the new expected output.
+pub(crate) fn check_or_review_output<TData>(
+  test: &CollectedTest<TData>,
+  output: &SpecOutput,
+  expected_exit_code: ExpectedExitCode,
+  expected_output: Option<&ExpectedOutput>,
+  timeout: Option<Duration>,
+) -> Result<(), String> {
+  let result =
+    check_output(output, expected_exit_code, expected_output, timeout);
+  let Some(expected) = expected_output else {
+    return result;
+  };
+  // only the output can be accepted, so the exit code must match
+  let is_output_mismatch = result.is_err()
+    && expected_exit_code
+      .matches(output.status.and_then(|status| status.code()));
+  if !is_output_mismatch || !review::is_interactive_update() {
+    return result;
+  }
+  match review::review_output(
+    test.label(),
+    expected,
+    &String::from_utf8_lossy(&output.combined),
+  ) {
+    Ok(true) => Ok(()),
+    Ok(false) => result,
+    Err(err) => Err(format!("{:#}", err)),
+  }
+}
+
+/// Checks the exit code and output, returning the error message
+/// on failure.
+pub(crate) fn check_output(
+  output: &SpecOutput,
+  expected_exit_code: ExpectedExitCode,
+  expected_output: Option<&ExpectedOutput>,
+  timeout: Option<Duration>,
+) -> Result<(), String> {
+  let mut errors = Vec::new();
+  match output.status {
+    Some(status) if expected_exit_code.matches(status.code()) => {}
+    Some(status) => errors.push(format!(
+      "Expected {}, but got {}.",
+      expected_exit_code,
+      match status.code() {
+        Some(code) => code.to_string(),
+        // ex. "signal: 9 (SIGKILL)" on unix
+        None => status.to_string(),
+      }
+    )),
+    None => errors.push(format!(
+      "Command timed out after {:?}.",
+      timeout.unwrap_or_default()
+    )),
+  }
+  let actual = String::from_utf8_lossy(&output.combined);
+  match expected_output {
+    Some(expected) => {
+      if !wildcard_match(&expected.text, &actual) {
+        errors.push(format!(
+          "Output did not match {}.\n\nOUTPUT\n{}\nEXPECTED\n{}",
+          expected.source, actual, expected.text
+        ));
+      }
+    }
+    None => {
+      if !errors.is_empty() {
+        errors.push(format!("OUTPUT\n{}", actual));
+      }
+    }
+  }
+  if errors.is_empty() {
+    Ok(())
+  } else {
+    Err(errors.join("\n"))
+  }
+}
+
+#[derive(Default)]
+struct CapturedOutput {
+  combined: CappedOutput,
+  stdout: CappedOutput,
+  stderr: CappedOutput,
+}
+
+impl CapturedOutput {
+  fn new(max_size: Option<usize>) -> Self {
+    Self {
+      combined: CappedOutput::new(max_size),
+      stdout: CappedOutput::new(max_size),
+      stderr: CappedOutput::new(max_size),
+    }
+  }
+}
+
+fn spawn_reader(
+  mut reader: impl Read + Send + 'static,
+  is_stderr: bool,
+  captured: &Arc<Mutex<CapturedOutput>>,
+  done_tx: &crossbeam_channel::Sender<()>,
+) {
+  let captured = captured.clone();
+  let done_tx = done_tx.clone();
+  std::thread::spawn(move || {
+    let mut buf = [0; 8192];
+    loop {
+      match reader.read(&mut buf) {
+        Ok(0) | Err(_) => break,
+        Ok(count) => {
+          let mut captured = captured.lock();
+          captured.combined.extend(&buf[..count]);
+          if is_stderr {
+            captured.stderr.extend(&buf[..count]);
+          } else {
+            captured.stdout.extend(&buf[..count]);
+          }
+        }
+      }
+    }
+    let _ = done_tx.send(());
+  });
+}
+
+fn expand_arg<TData>(arg: &str, test: &CollectedTest<TData>) -> String {
+  arg
+    .replace("{test_path}", &test.path.to_string_lossy())
+    .replace(
+      "{test_dir}",
+      &test
+        .path
+        .parent()
+        .map(|p| p.to_string_lossy())
+        .unwrap_or_default(),
+    )
+    .replace("{test_name}", &test.name)
+}
+
+pub(crate) fn resolve_path<TData>(
+  test: &CollectedTest<TData>,
+  path: &Path,
+) -> PathBuf {
+  match test.path.parent() {
+    Some(dir) => dir.join(path),
+    None => path.to_path_buf(),
+  }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+  use super::*;
+
+  fn create_test(dir: &Path) -> CollectedTest {
+    let mut test = CollectedTest::new("specs::test", dir.join("test.sh"), ());
+    test
+      .env_vars
+      .insert("NAME".to_string(), "world".to_string());
+    test
+  }
+
+  #[test]
+  fn runs_and_compares_output() {
+    let dir = std::env::temp_dir()
+      .join(format!("file_test_runner_spec_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let test = create_test(&dir);
+    // stdout and stderr are read separately, so write to a single stream to
+    // keep the order of the combined output deterministic
+    std::fs::write(&test.path, "echo hello $NAME\necho error\nexit $1")
+      .unwrap();
+    std::fs::write(dir.join("test.out"), "hello [WILDLINE]\nerror\n").unwrap();
+
+    let command = SpecCommand {
+      args: vec!["sh".to_string(), "{test_path}".to_string(), "0".to_string()],
+      output_file: Some(PathBuf::from("test.out")),
+      ..Default::default()
+    };
+    assert!(!command.run(&test).is_failed());
+    let output = command.spawn_and_wait(&test).unwrap();
+    assert_eq!(output.stdout, b"hello world\nerror\n");
+    assert_eq!(output.stderr, b"");
+
+    std::fs::write(dir.join("test.out"), "hello\n").unwrap();
+    let TestResult::Failed { output, .. } = command.run(&test) else {
+      unreachable!();
+    };
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("Output did not match"), "{}", output);
+
+    let command = SpecCommand {
+      args: vec!["sh".to_string(), "{test_path}".to_string(), "2".to_string()],
+      ..Default::default()
+    };
+    let TestResult::Failed { output, .. } = command.run(&test) else {
+      unreachable!();
+    };
+    let output = String::from_utf8(output).unwrap();
+    assert!(
+      output.starts_with("Expected exit code 0, but got 2.\nOUTPUT\n"),
+      "{}",
+      output
+    );
+    for exit_code in [ExpectedExitCode::Code(2), ExpectedExitCode::NonZero] {
+      let command = SpecCommand {
+        exit_code,
+        ..command.clone()
+      };
+      assert!(!command.run(&test).is_failed());
+    }
+    let command = SpecCommand {
+      args: vec!["sh".to_string(), "{test_path}".to_string(), "0".to_string()],
+      exit_code: ExpectedExitCode::NonZero,
+      ..Default::default()
+    };
+    let TestResult::Failed { output, .. } = command.run(&test) else {
+      unreachable!();
+    };
+    let output = String::from_utf8(output).unwrap();
+    assert!(
+      output.starts_with("Expected a non-zero exit code, but got 0."),
+      "{}",
+      output
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn caps_output() {
+    let test = create_test(Path::new("."));
+    let command = SpecCommand {
+      args: vec![
+        "sh".to_string(),
+        "-c".to_string(),
+        "echo start; head -c 100000 /dev/zero | tr '\\0' a; echo; echo end"
+          .to_string(),
+      ],
+      max_output_size: Some(100),
+      ..Default::default()
+    };
+    let output = command.spawn_and_wait(&test).unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("start\n"), "{}", stdout);
+    assert!(stdout.ends_with("end\n"), "{}", stdout);
+    assert!(
+      stdout.contains("bytes of output were dropped"),
+      "{}",
+      stdout
+    );
+    assert!(stdout.len() < 200, "{}", stdout);
+  }
+
+  #[test]
+  fn writes_stdin() {
+    let dir = std::env::temp_dir().join(format!(
+      "file_test_runner_spec_stdin_{}",
+      std::process::id()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    let test = create_test(&dir);
+    let command = SpecCommand {
+      args: vec!["cat".to_string()],
+      ..Default::default()
+    };
+    assert_eq!(command.spawn_and_wait(&test).unwrap().stdout, b"");
+    std::fs::write(dir.join("test.stdin"), "from file\n").unwrap();
+    assert_eq!(
+      command.spawn_and_wait(&test).unwrap().stdout,
+      b"from file\n"
+    );
+
+    let command = SpecCommand {
+      stdin: SpecStdin::Bytes(b"from data\n".to_vec()),
+      ..command
+    };
+    assert_eq!(
+      command.spawn_and_wait(&test).unwrap().stdout,
+      b"from data\n"
+    );
+    let command = SpecCommand {
+      stdin: SpecStdin::File(PathBuf::from("missing.stdin")),
+      ..command
+    };
+    assert!(command.spawn_and_wait(&test).is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn times_out() {
+    let test = create_test(Path::new("."));
+    let command = SpecCommand {
+      args: vec!["sleep".to_string(), "10".to_string()],
+      timeout: Some(Duration::from_millis(50)),
+      ..Default::default()
+    };
+    let start = Instant::now();
+    let output = command.spawn_and_wait(&test).unwrap();
+    assert!(output.status.is_none());
+    assert!(start.elapsed() < Duration::from_secs(5));
+  }
+}
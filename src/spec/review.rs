@@ -0,0 +1,62 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::io::IsTerminal;
+
+use crate::reporter::OutputReview;
+use crate::PathedIoError;
+
+use super::ExpectedOutput;
+
+/// Gets if mismatched output should be reviewed interactively, which is
+/// enabled with `--interactive-update` when stdin is a terminal.
+///
+/// Worker processes never review because their stdin is used to
+/// receive tests from the coordinator.
+pub(crate) fn is_interactive_update() -> bool {
+  std::env::args().any(|arg| arg == "--interactive-update")
+    && std::io::stdin().is_terminal()
+    && !crate::worker::is_worker_process()
+}
+
+/// Asks the reporter of the run whether to accept the actual output of
+/// the test, writing it to the expected output file when accepted.
+pub(crate) fn review_output(
+  test_name: &str,
+  expected: &ExpectedOutput,
+  actual: &str,
+) -> Result<bool, PathedIoError> {
+  let Some(path) = &expected.path else {
+    return Ok(false);
+  };
+  let review = OutputReview {
+    test_name: test_name.to_string(),
+    source: expected.source.clone(),
+    expected: expected.text.clone(),
+    actual: actual.to_string(),
+  };
+  if !crate::runner::review_output(&review) {
+    return Ok(false);
+  }
+  std::fs::write(path, actual).map_err(|err| PathedIoError::new(path, err))?;
+  Ok(true)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn rejects_outside_run() {
+    let path = std::env::temp_dir().join(format!(
+      "file_test_runner_review_{}.out",
+      std::process::id()
+    ));
+    let expected = ExpectedOutput {
+      source: path.display().to_string(),
+      path: Some(path.clone()),
+      text: "hello".to_string(),
+    };
+    assert!(!review_output("specs::a", &expected, "world").unwrap());
+    assert!(!path.exists());
+  }
+}
@@ -0,0 +1,130 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+/// Checks if the text matches the pattern, which may contain the
+/// following wildcards:
+///
+/// - `[WILDCARD]` - Matches any text, including newlines.
+/// - `[WILDLINE]` - Matches any text up to the end of the line.
+/// - `[WILDCHAR]` - Matches a single character.
+/// - `[WILDCHARS(n)]` - Matches `n` characters.
+///
+/// Windows line endings are normalized before comparing.
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+  let pattern = pattern.replace("\r\n", "\n");
+  let text = text.replace("\r\n", "\n");
+  matches_tokens(&parse_tokens(&pattern), &text)
+}
+
+#[derive(Debug, PartialEq)]
+enum Token<'a> {
+  Text(&'a str),
+  Wildcard,
+  Wildline,
+  Wildchars(usize),
+}
+
+fn parse_tokens(pattern: &str) -> Vec<Token<'_>> {
+  let mut tokens = Vec::new();
+  let mut text_start = 0;
+  let mut index = 0;
+  while index < pattern.len() {
+    let rest = &pattern[index..];
+    let wildcard = if rest.starts_with("[WILDCARD]") {
+      Some((Token::Wildcard, "[WILDCARD]".len()))
+    } else if rest.starts_with("[WILDLINE]") {
+      Some((Token::Wildline, "[WILDLINE]".len()))
+    } else if rest.starts_with("[WILDCHAR]") {
+      Some((Token::Wildchars(1), "[WILDCHAR]".len()))
+    } else if let Some(count_text) = rest.strip_prefix("[WILDCHARS(") {
+      count_text.find(")]").and_then(|end| {
+        let count = count_text[..end].parse().ok()?;
+        Some((Token::Wildchars(count), "[WILDCHARS(".len() + end + 2))
+      })
+    } else {
+      None
+    };
+    match wildcard {
+      Some((token, len)) => {
+        if text_start < index {
+          tokens.push(Token::Text(&pattern[text_start..index]));
+        }
+        tokens.push(token);
+        index += len;
+        text_start = index;
+      }
+      None => {
+        index += rest.chars().next().unwrap().len_utf8();
+      }
+    }
+  }
+  if text_start < pattern.len() {
+    tokens.push(Token::Text(&pattern[text_start..]));
+  }
+  tokens
+}
+
+fn matches_tokens(tokens: &[Token], text: &str) -> bool {
+  let Some((token, rest)) = tokens.split_first() else {
+    return text.is_empty();
+  };
+  match token {
+    Token::Text(expected) => text
+      .strip_prefix(expected)
+      .is_some_and(|text| matches_tokens(rest, text)),
+    Token::Wildchars(count) => {
+      let mut chars = text.char_indices();
+      match chars.nth(*count) {
+        Some((index, _)) => matches_tokens(rest, &text[index..]),
+        None => text.chars().count() == *count && matches_tokens(rest, ""),
+      }
+    }
+    Token::Wildcard | Token::Wildline => {
+      let end = match token {
+        Token::Wildline => text.find('\n').unwrap_or(text.len()),
+        _ => text.len(),
+      };
+      if rest.is_empty() {
+        return end == text.len();
+      }
+      (0..=end)
+        .filter(|index| text.is_char_boundary(*index))
+        .any(|index| matches_tokens(rest, &text[index..]))
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn matches() {
+    let cases = [
+      ("hello", "hello", true),
+      ("hello", "hello!", false),
+      ("a[WILDCARD]c", "abbb\nbc", true),
+      ("a[WILDCARD]", "a\nb\nc", true),
+      ("[WILDCARD]b[WILDCARD]d", "abcbd", true),
+      ("[WILDCARD]b[WILDCARD]d", "abce", false),
+      ("a[WILDLINE]\nc", "abb\nc", true),
+      ("a[WILDLINE]c", "ab\nc", false),
+      ("a[WILDLINE]", "abb\nc", false),
+      ("a[WILDCHAR]c", "abc", true),
+      ("a[WILDCHAR]c", "abbc", false),
+      ("a[WILDCHARS(2)]d", "abcd", true),
+      ("a[WILDCHARS(3)]", "abc", false),
+      ("[WILDCHARS(2)]", "ü€", true),
+      ("a\nb", "a\r\nb", true),
+      ("[WILDCHARS(x)]", "[WILDCHARS(x)]", true),
+    ];
+    for (pattern, text, expected) in cases {
+      assert_eq!(
+        wildcard_match(pattern, text),
+        expected,
+        "pattern: {:?}, text: {:?}",
+        pattern,
+        text
+      );
+    }
+  }
+}
@@ -0,0 +1,115 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+//! Capturing the stacks of stuck tests with a debugger.
+
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+static SIGQUIT_RECEIVED: AtomicBool = AtomicBool::new(false);
+
+/// Captures the backtraces of all the threads of the process using gdb,
+/// or lldb when gdb isn't installed.
+///
+/// The debugger must be allowed to attach to the process, which may
+/// require running as root or lowering `kernel.yama.ptrace_scope`.
+pub(crate) fn dump_stacks(pid: u32) -> Result<String, anyhow::Error> {
+  #[cfg(unix)]
+  {
+    use std::process::Command;
+    use std::process::Stdio;
+
+    let pid = pid.to_string();
+    let debuggers: [(&str, [&str; 5]); 2] = [
+      ("gdb", ["-p", &pid, "-batch", "-ex", "thread apply all bt"]),
+      (
+        "lldb",
+        ["-p", &pid, "--batch", "-o", "thread backtrace all"],
+      ),
+    ];
+    let mut errors = Vec::new();
+    for (program, args) in debuggers {
+      match Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+      {
+        Ok(output) if output.status.success() => {
+          return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+        }
+        Ok(output) => errors.push(format!(
+          "{} failed ({}): {}",
+          program,
+          output.status,
+          String::from_utf8_lossy(&output.stderr).trim()
+        )),
+        Err(err) => errors.push(format!("{}: {}", program, err)),
+      }
+    }
+    anyhow::bail!("Failed to capture stacks. {}", errors.join(". "))
+  }
+  #[cfg(not(unix))]
+  {
+    let _ = pid;
+    anyhow::bail!("Capturing stacks is only supported on Unix.")
+  }
+}
+
+/// Captures the stacks for including them in the output of a test.
+pub(crate) fn stacks_output(pid: u32) -> String {
+  match dump_stacks(pid) {
+    Ok(stacks) => format!("Stacks of process {}:\n{}", pid, stacks),
+    Err(err) => format!("{:#}", err),
+  }
+}
+
+/// Starts recording when the process receives SIGQUIT instead of
+/// exiting. Does nothing on other platforms.
+pub(crate) fn listen_for_sigquit() {
+  #[cfg(unix)]
+  {
+    extern "C" fn handle_sigquit(_signal: libc::c_int) {
+      SIGQUIT_RECEIVED.store(true, Ordering::SeqCst);
+    }
+
+    // SAFETY: the handler only stores to an atomic, which is
+    // async-signal-safe
+    unsafe {
+      libc::signal(
+        libc::SIGQUIT,
+        handle_sigquit as extern "C" fn(libc::c_int) as libc::sighandler_t,
+      );
+    }
+  }
+}
+
+/// Restores the default behavior of SIGQUIT.
+pub(crate) fn stop_listening_for_sigquit() {
+  #[cfg(unix)]
+  // SAFETY: restores the default disposition
+  unsafe {
+    libc::signal(libc::SIGQUIT, libc::SIG_DFL);
+  }
+}
+
+/// Gets whether SIGQUIT was received since this was last called.
+pub(crate) fn take_sigquit() -> bool {
+  SIGQUIT_RECEIVED.swap(false, Ordering::SeqCst)
+}
+
+#[cfg(all(test, unix))]
+mod test {
+  use super::*;
+
+  #[test]
+  fn sigquit() {
+    listen_for_sigquit();
+    assert!(!take_sigquit());
+    // SAFETY: the handler was installed above
+    unsafe {
+      libc::raise(libc::SIGQUIT);
+    }
+    assert!(take_sigquit());
+    assert!(!take_sigquit());
+    stop_listening_for_sigquit();
+  }
+}
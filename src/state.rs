@@ -0,0 +1,190 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+//! Directory of files persisted across runs, such as the test history
+//! and result cache.
+
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use crate::PathedIoError;
+
+/// A file in the state directory.
+#[derive(Debug, Clone)]
+pub struct StateEntry {
+  pub name: String,
+  pub size: u64,
+  pub modified: Option<SystemTime>,
+}
+
+/// Directory that features store their files in across runs.
+///
+/// Defaults to `<target-dir>/.file_test_runner`.
+#[derive(Debug, Clone)]
+pub struct StateStore {
+  dir: PathBuf,
+}
+
+impl Default for StateStore {
+  fn default() -> Self {
+    Self::new(default_target_dir().join(".file_test_runner"))
+  }
+}
+
+impl StateStore {
+  pub fn new(dir: impl Into<PathBuf>) -> Self {
+    Self { dir: dir.into() }
+  }
+
+  pub fn dir(&self) -> &Path {
+    &self.dir
+  }
+
+  /// Gets the path of the file with the provided name.
+  pub fn path(&self, name: &str) -> PathBuf {
+    self.dir.join(name)
+  }
+
+  /// Reads the file, returning `None` if it doesn't exist.
+  pub fn read(&self, name: &str) -> Result<Option<String>, PathedIoError> {
+    let path = self.path(name);
+    match std::fs::read_to_string(&path) {
+      Ok(text) => Ok(Some(text)),
+      Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+      Err(err) => Err(PathedIoError::new(&path, err)),
+    }
+  }
+
+  /// Replaces the contents of the file.
+  ///
+  /// This writes to a temporary file first so that a killed run
+  /// doesn't leave behind a partially written file.
+  pub fn write(&self, name: &str, data: &[u8]) -> Result<(), PathedIoError> {
+    self.create_dir()?;
+    let path = self.path(name);
+    let temp_path = self.path(&format!("{}.{}.tmp", name, std::process::id()));
+    std::fs::write(&temp_path, data)
+      .map_err(|err| PathedIoError::new(&temp_path, err))?;
+    std::fs::rename(&temp_path, &path).map_err(|err| {
+      let _ = std::fs::remove_file(&temp_path);
+      PathedIoError::new(&path, err)
+    })
+  }
+
+  /// Appends to the file, creating it if it doesn't exist.
+  pub fn append(&self, name: &str, data: &[u8]) -> Result<(), PathedIoError> {
+    self.create_dir()?;
+    let path = self.path(name);
+    std::fs::OpenOptions::new()
+      .create(true)
+      .append(true)
+      .open(&path)
+      .and_then(|mut file| file.write_all(data))
+      .map_err(|err| PathedIoError::new(&path, err))
+  }
+
+  /// Gets the files in the store sorted by name.
+  pub fn entries(&self) -> Result<Vec<StateEntry>, PathedIoError> {
+    let read_dir = match std::fs::read_dir(&self.dir) {
+      Ok(read_dir) => read_dir,
+      Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+        return Ok(Vec::new());
+      }
+      Err(err) => return Err(PathedIoError::new(&self.dir, err)),
+    };
+    let mut entries = Vec::new();
+    for entry in read_dir {
+      let entry = entry.map_err(|err| PathedIoError::new(&self.dir, err))?;
+      let metadata = entry
+        .metadata()
+        .map_err(|err| PathedIoError::new(&entry.path(), err))?;
+      if !metadata.is_file() {
+        continue;
+      }
+      entries.push(StateEntry {
+        name: entry.file_name().to_string_lossy().into_owned(),
+        size: metadata.len(),
+        modified: metadata.modified().ok(),
+      });
+    }
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(entries)
+  }
+
+  /// Removes the file if it exists.
+  pub fn remove(&self, name: &str) -> Result<(), PathedIoError> {
+    let path = self.path(name);
+    match std::fs::remove_file(&path) {
+      Ok(()) => Ok(()),
+      Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+      Err(err) => Err(PathedIoError::new(&path, err)),
+    }
+  }
+
+  /// Removes all the state.
+  pub fn clear(&self) -> Result<(), PathedIoError> {
+    match std::fs::remove_dir_all(&self.dir) {
+      Ok(()) => Ok(()),
+      Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+      Err(err) => Err(PathedIoError::new(&self.dir, err)),
+    }
+  }
+
+  fn create_dir(&self) -> Result<(), PathedIoError> {
+    std::fs::create_dir_all(&self.dir)
+      .map_err(|err| PathedIoError::new(&self.dir, err))
+  }
+}
+
+fn default_target_dir() -> PathBuf {
+  if let Some(dir) = std::env::var_os("CARGO_TARGET_DIR") {
+    return PathBuf::from(dir);
+  }
+  // cargo creates a CACHEDIR.TAG file in the root of the target directory
+  if let Ok(exe) = std::env::current_exe() {
+    if let Some(dir) = exe
+      .ancestors()
+      .skip(1)
+      .find(|dir| dir.join("CACHEDIR.TAG").is_file())
+    {
+      return dir.to_path_buf();
+    }
+  }
+  PathBuf::from("target")
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn read_write_clear() {
+    let store = StateStore::new(
+      std::env::temp_dir()
+        .join(format!("file_test_runner_state_{}", std::process::id())),
+    );
+    assert_eq!(store.read("a.json").unwrap(), None);
+    assert!(store.entries().unwrap().is_empty());
+
+    store.write("a.json", b"1").unwrap();
+    store.write("a.json", b"2").unwrap();
+    store.append("b.jsonl", b"1\n").unwrap();
+    store.append("b.jsonl", b"2\n").unwrap();
+    assert_eq!(store.read("a.json").unwrap().unwrap(), "2");
+    assert_eq!(store.read("b.jsonl").unwrap().unwrap(), "1\n2\n");
+    let entries = store.entries().unwrap();
+    assert_eq!(
+      entries
+        .iter()
+        .map(|e| (e.name.as_str(), e.size))
+        .collect::<Vec<_>>(),
+      vec![("a.json", 1), ("b.jsonl", 4)]
+    );
+
+    store.remove("a.json").unwrap();
+    assert_eq!(store.read("a.json").unwrap(), None);
+    store.clear().unwrap();
+    assert!(!store.dir().exists());
+  }
+}
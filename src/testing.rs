@@ -0,0 +1,590 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+//! Utilities for unit testing custom strategies and reporters without
+//! touching the real file system or stderr.
+
+use std::collections::BTreeMap;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+use crate::collection::CollectedCategoryOrTest;
+use crate::collection::CollectedTest;
+use crate::collection::CollectedTestCategory;
+use crate::collection::EntryKind;
+use crate::collection::FileMetadata;
+use crate::collection::FileSystem;
+use crate::reporter::CategoryTiming;
+use crate::reporter::OutputReview;
+use crate::reporter::QuarantineUsage;
+use crate::reporter::Reporter;
+use crate::reporter::ReporterContext;
+use crate::reporter::ReporterFailure;
+use crate::reporter::StalledTest;
+use crate::TestResult;
+
+/// Maximum number of symlinks followed when resolving a path.
+const MAX_SYMLINK_DEPTH: usize = 40;
+
+#[derive(Debug, Clone)]
+enum InMemoryEntry {
+  File(Vec<u8>),
+  Dir,
+  Symlink(PathBuf),
+}
+
+/// File system held in memory for collecting tests in unit tests
+/// (see `file_test_runner::collection::with_file_system`).
+///
+/// ```rs
+/// let fs = InMemoryFileSystem::default()
+///   .file("specs/a.txt", "contents")
+///   .symlink("specs/linked", "specs/sub");
+/// let category = with_file_system(Arc::new(fs), || {
+///   TestPerFileCollectionStrategy::default().collect_tests(Path::new("specs"))
+/// })?;
+/// ```
+///
+/// The parent directories of the added entries are created as necessary.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryFileSystem {
+  entries: BTreeMap<PathBuf, InMemoryEntry>,
+}
+
+impl InMemoryFileSystem {
+  /// Adds a file with the contents.
+  pub fn file(
+    self,
+    path: impl AsRef<Path>,
+    contents: impl Into<Vec<u8>>,
+  ) -> Self {
+    self.with_entry(path.as_ref(), InMemoryEntry::File(contents.into()))
+  }
+
+  /// Adds an empty directory.
+  pub fn dir(self, path: impl AsRef<Path>) -> Self {
+    self.with_entry(path.as_ref(), InMemoryEntry::Dir)
+  }
+
+  /// Adds a symlink to the target, which is a path in the file system
+  /// rather than relative to the symlink.
+  pub fn symlink(
+    self,
+    path: impl AsRef<Path>,
+    target: impl AsRef<Path>,
+  ) -> Self {
+    let target = normalize(target.as_ref());
+    self.with_entry(path.as_ref(), InMemoryEntry::Symlink(target))
+  }
+
+  fn with_entry(mut self, path: &Path, entry: InMemoryEntry) -> Self {
+    let path = normalize(path);
+    for ancestor in path.ancestors().skip(1) {
+      if ancestor.as_os_str().is_empty() {
+        break;
+      }
+      self
+        .entries
+        .entry(ancestor.to_path_buf())
+        .or_insert(InMemoryEntry::Dir);
+    }
+    self.entries.insert(path, entry);
+    self
+  }
+
+  /// Resolves the symlinks in the path, optionally leaving the last
+  /// component as is.
+  fn resolve(
+    &self,
+    path: &Path,
+    follow_last: bool,
+  ) -> std::io::Result<PathBuf> {
+    let path = normalize(path);
+    let component_count = path.components().count();
+    let mut resolved = PathBuf::new();
+    let mut depth = 0;
+    for (i, component) in path.components().enumerate() {
+      resolved.push(component);
+      if i + 1 == component_count && !follow_last {
+        break;
+      }
+      while let Some(InMemoryEntry::Symlink(target)) =
+        self.entries.get(&resolved)
+      {
+        depth += 1;
+        if depth > MAX_SYMLINK_DEPTH {
+          return Err(std::io::Error::other(format!(
+            "Too many levels of symbolic links: {}",
+            path.display()
+          )));
+        }
+        resolved = target.clone();
+      }
+    }
+    if !resolved.as_os_str().is_empty() && !self.entries.contains_key(&resolved)
+    {
+      return Err(not_found(&path));
+    }
+    Ok(resolved)
+  }
+
+  fn entry(&self, path: &Path) -> std::io::Result<&InMemoryEntry> {
+    let resolved = self.resolve(path, true)?;
+    self.entries.get(&resolved).ok_or_else(|| not_found(path))
+  }
+}
+
+impl FileSystem for InMemoryFileSystem {
+  fn read_dir(&self, path: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let resolved = self.resolve(path, true)?;
+    if !resolved.as_os_str().is_empty()
+      && !matches!(self.entries.get(&resolved), Some(InMemoryEntry::Dir))
+    {
+      return Err(std::io::Error::other(format!(
+        "Not a directory: {}",
+        path.display()
+      )));
+    }
+    Ok(
+      self
+        .entries
+        .keys()
+        .filter(|entry_path| entry_path.parent() == Some(resolved.as_path()))
+        .filter_map(|entry_path| entry_path.file_name())
+        .map(|name| path.join(name))
+        .collect(),
+    )
+  }
+
+  fn entry_kind(&self, path: &Path) -> std::io::Result<EntryKind> {
+    let resolved = self.resolve(path, false)?;
+    Ok(match self.entries.get(&resolved) {
+      Some(InMemoryEntry::File(_)) => EntryKind::File,
+      Some(InMemoryEntry::Symlink(_)) => EntryKind::Symlink,
+      Some(InMemoryEntry::Dir) | None => EntryKind::Dir,
+    })
+  }
+
+  fn canonicalize(&self, path: &Path) -> std::io::Result<PathBuf> {
+    self.resolve(path, true)
+  }
+
+  fn metadata(&self, path: &Path) -> std::io::Result<FileMetadata> {
+    let size = match self.entry(path)? {
+      InMemoryEntry::File(contents) => contents.len() as u64,
+      _ => 0,
+    };
+    Ok(FileMetadata {
+      size,
+      modified: None,
+    })
+  }
+
+  fn read(&self, path: &Path) -> std::io::Result<Vec<u8>> {
+    match self.entry(path)? {
+      InMemoryEntry::File(contents) => Ok(contents.clone()),
+      _ => Err(std::io::Error::other(format!(
+        "Is a directory: {}",
+        path.display()
+      ))),
+    }
+  }
+}
+
+/// Removes the `.` and `..` components of the path.
+fn normalize(path: &Path) -> PathBuf {
+  let mut normalized = PathBuf::new();
+  for component in path.components() {
+    match component {
+      Component::CurDir => {}
+      Component::ParentDir => {
+        normalized.pop();
+      }
+      component => normalized.push(component),
+    }
+  }
+  normalized
+}
+
+fn not_found(path: &Path) -> std::io::Error {
+  std::io::Error::new(
+    std::io::ErrorKind::NotFound,
+    format!("No such file or directory: {}", path.display()),
+  )
+}
+
+/// Reporter that records the events it receives as lines
+/// (ex. `test_end specs::a failed`) for asserting on in unit tests.
+///
+/// Categories are recorded with their number of tests and the number
+/// including their sub categories (ex. `category_start specs (1/3 tests)`).
+///
+/// Durations aren't recorded so the events are the same across runs.
+/// The category timings are kept separately (see `category_timings`).
+#[derive(Default)]
+pub struct FakeReporter {
+  events: Mutex<Vec<String>>,
+  category_timings: Mutex<Vec<CategoryTiming>>,
+  review: Option<ReviewFunc>,
+}
+
+type ReviewFunc = Box<dyn Fn(&OutputReview) -> bool + Send + Sync>;
+
+impl std::fmt::Debug for FakeReporter {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("FakeReporter")
+      .field("events", &self.events)
+      .field("category_timings", &self.category_timings)
+      .field("review", &self.review.is_some())
+      .finish()
+  }
+}
+
+impl FakeReporter {
+  /// Creates a reporter that reviews output with the function, which
+  /// rejects the output by default.
+  pub fn with_review(
+    review: impl Fn(&OutputReview) -> bool + Send + Sync + 'static,
+  ) -> Self {
+    Self {
+      review: Some(Box::new(review)),
+      ..Default::default()
+    }
+  }
+
+  /// Gets the category timings reported at the end of the run.
+  pub fn category_timings(&self) -> Vec<CategoryTiming> {
+    self.category_timings.lock().clone()
+  }
+
+  /// Gets the events recorded so far.
+  pub fn events(&self) -> Vec<String> {
+    self.events.lock().clone()
+  }
+
+  /// Gets and clears the events recorded so far.
+  pub fn take_events(&self) -> Vec<String> {
+    std::mem::take(&mut *self.events.lock())
+  }
+
+  fn record(&self, event: String) {
+    self.events.lock().push(event);
+  }
+}
+
+fn result_name(result: &TestResult) -> &'static str {
+  match result {
+    TestResult::Passed => "passed",
+    TestResult::Ignored => "ignored",
+    TestResult::Failed { .. } | TestResult::FailedWithFields { .. } => "failed",
+    TestResult::SubTests(_) if result.is_failed() => "failed",
+    TestResult::SubTests(_) => "passed",
+  }
+}
+
+impl<TData> Reporter<TData> for FakeReporter {
+  fn report_category_start(
+    &self,
+    category: &CollectedTestCategory<TData>,
+    context: &ReporterContext,
+  ) {
+    self.record(format!(
+      "category_start {} ({}/{} tests)",
+      category.name,
+      context.category_test_count,
+      context.category_total_test_count
+    ));
+  }
+
+  fn report_category_end(
+    &self,
+    category: &CollectedTestCategory<TData>,
+    _context: &ReporterContext,
+  ) {
+    self.record(format!("category_end {}", category.name));
+  }
+
+  fn report_test_start(
+    &self,
+    test: &CollectedTest<TData>,
+    _context: &ReporterContext,
+  ) {
+    self.record(format!("test_start {}", test.name));
+  }
+
+  fn report_test_end(
+    &self,
+    test: &CollectedTest<TData>,
+    _duration: Duration,
+    result: &TestResult,
+    _context: &ReporterContext,
+  ) {
+    self.record(format!("test_end {} {}", test.name, result_name(result)));
+  }
+
+  fn report_test_cached(
+    &self,
+    test: &CollectedTest<TData>,
+    _context: &ReporterContext,
+  ) {
+    self.record(format!("test_cached {}", test.name));
+  }
+
+  fn report_test_ignored(
+    &self,
+    test: &CollectedTest<TData>,
+    reason: &str,
+    _context: &ReporterContext,
+  ) {
+    self.record(format!("test_ignored {}: {}", test.name, reason));
+  }
+
+  fn report_long_running_test(&self, test_name: &str, _elapsed: Duration) {
+    self.record(format!("long_running {}", test_name));
+  }
+
+  fn report_stalled_run(&self, _stalled_for: Duration, tests: &[StalledTest]) {
+    for test in tests {
+      self.record(format!("stalled {} ({})", test.name, test.path.display()));
+    }
+  }
+
+  fn report_category_timings(&self, timings: &[CategoryTiming]) {
+    self.category_timings.lock().extend(timings.iter().cloned());
+  }
+
+  fn review_output(&self, review: &OutputReview) -> bool {
+    self.record(format!("review {} {}", review.test_name, review.source));
+    self.review.as_ref().is_some_and(|func| func(review))
+  }
+
+  fn report_quarantine(&self, usage: &[QuarantineUsage]) {
+    for usage in usage {
+      self.record(format!(
+        "quarantine {} [{}]",
+        usage.pattern,
+        usage.absorbed_failures.join(", ")
+      ));
+    }
+  }
+
+  fn report_run_aborted(&self, not_run: usize) {
+    self.record(format!("run_aborted {}", not_run));
+  }
+
+  fn report_failures(
+    &self,
+    failures: &[ReporterFailure<TData>],
+    total_tests: usize,
+  ) {
+    let names = failures
+      .iter()
+      .map(|failure| failure.test.name.as_str())
+      .collect::<Vec<_>>();
+    self.record(format!(
+      "failures {}/{} [{}]",
+      failures.len(),
+      total_tests,
+      names.join(", ")
+    ));
+  }
+}
+
+/// Gets a test with the fully resolved name and no other settings.
+pub fn collected_test<TData>(
+  name: impl Into<String>,
+  data: TData,
+) -> CollectedTest<TData> {
+  let name = name.into();
+  let path = PathBuf::from(name.replace("::", "/"));
+  CollectedTest::new(name, path, data)
+}
+
+/// Builds a `CollectedTestCategory` where the names of the tests and sub
+/// categories are relative to their category.
+///
+/// ```rs
+/// let category = CategoryBuilder::<()>::new("specs")
+///   .test("a")
+///   .category("sub", |sub| sub.test("b"))
+///   .build();
+/// assert!(category.find_test("specs::sub::b").is_some());
+/// ```
+pub struct CategoryBuilder<TData = ()> {
+  category: CollectedTestCategory<TData>,
+}
+
+impl<TData> CategoryBuilder<TData> {
+  pub fn new(name: impl Into<String>) -> Self {
+    let name = name.into();
+    Self {
+      category: CollectedTestCategory {
+        path: PathBuf::from(&name),
+        name,
+        description: None,
+        children: Vec::new(),
+      },
+    }
+  }
+
+  pub fn description(mut self, description: impl Into<String>) -> Self {
+    self.category.description = Some(description.into());
+    self
+  }
+
+  /// Adds a test with the default data.
+  pub fn test(self, name: &str) -> Self
+  where
+    TData: Default,
+  {
+    self.test_with_data(name, TData::default())
+  }
+
+  pub fn test_with_data(self, name: &str, data: TData) -> Self {
+    self.test_with(name, data, |test| test)
+  }
+
+  /// Adds a test, allowing its other settings to be changed
+  /// (ex. `|mut test| { test.settings.tags.push("slow".into()); test }`).
+  pub fn test_with(
+    mut self,
+    name: &str,
+    data: TData,
+    func: impl FnOnce(CollectedTest<TData>) -> CollectedTest<TData>,
+  ) -> Self {
+    let mut test =
+      collected_test(format!("{}::{}", self.category.name, name), data);
+    test.path = self.category.path.join(name);
+    self
+      .category
+      .children
+      .push(CollectedCategoryOrTest::Test(func(test)));
+    self
+  }
+
+  /// Adds a sub category built by the function.
+  pub fn category(
+    mut self,
+    name: &str,
+    func: impl FnOnce(CategoryBuilder<TData>) -> CategoryBuilder<TData>,
+  ) -> Self {
+    let mut builder =
+      CategoryBuilder::new(format!("{}::{}", self.category.name, name));
+    builder.category.path = self.category.path.join(name);
+    let category = func(builder).build();
+    self
+      .category
+      .children
+      .push(CollectedCategoryOrTest::Category(category));
+    self
+  }
+
+  pub fn build(self) -> CollectedTestCategory<TData> {
+    self.category
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::sync::Arc;
+
+  use super::*;
+  use crate::collection::strategies::EntryFilter;
+  use crate::collection::strategies::SymlinkPolicy;
+  use crate::collection::strategies::TestCollectionStrategy;
+  use crate::collection::strategies::TestPerFileCollectionStrategy;
+  use crate::collection::with_file_system;
+  use crate::run_tests;
+  use crate::OnFailure;
+  use crate::RunOptions;
+
+  #[test]
+  fn collects_from_in_memory_file_system() {
+    let fs = InMemoryFileSystem::default()
+      .file("specs/a.txt", "a contents")
+      .file("specs/sub/b.txt", "b contents")
+      .dir("specs/empty")
+      .symlink("specs/linked", "specs/sub");
+    assert_eq!(
+      fs.canonicalize(Path::new("specs/linked/b.txt")).unwrap(),
+      PathBuf::from("specs/sub/b.txt")
+    );
+    assert_eq!(
+      fs.entry_kind(Path::new("specs/linked")).unwrap(),
+      EntryKind::Symlink
+    );
+    assert!(!fs.exists(Path::new("specs/c.txt")));
+
+    let category = with_file_system(Arc::new(fs), || {
+      TestPerFileCollectionStrategy {
+        entry_filter: EntryFilter {
+          symlinks: SymlinkPolicy::Follow,
+          ..Default::default()
+        },
+        ..Default::default()
+      }
+      .collect_tests(Path::new("specs"))
+    })
+    .unwrap();
+    assert_eq!(category.test_count(), 3);
+    assert!(category.find_test("specs::a").is_some());
+    assert!(category.find_test("specs::sub::b").is_some());
+    let test = category.find_test("specs::linked::b").unwrap();
+    assert_eq!(test.content().unwrap(), "b contents");
+  }
+
+  #[test]
+  fn symlink_cycle() {
+    let fs = InMemoryFileSystem::default()
+      .symlink("specs/a", "specs/b")
+      .symlink("specs/b", "specs/a");
+    assert!(fs.canonicalize(Path::new("specs/a")).is_err());
+  }
+
+  #[test]
+  fn records_reporter_events() {
+    let category = CategoryBuilder::<()>::new("specs")
+      .test("a")
+      .category("sub", |sub| sub.test("b"))
+      .build();
+    assert_eq!(category.test_count(), 2);
+    let test = category.find_test("specs::sub::b").unwrap();
+    assert_eq!(test.path, PathBuf::from("specs/sub/b"));
+
+    let reporter = Arc::new(FakeReporter::default());
+    run_tests(
+      &category,
+      RunOptions {
+        reporter: reporter.clone(),
+        on_failure: OnFailure::Return,
+        ..Default::default()
+      },
+      |test| {
+        if test.name == "specs::a" {
+          TestResult::Passed
+        } else {
+          TestResult::failed(Vec::new())
+        }
+      },
+    );
+    let events = reporter.take_events();
+    assert_eq!(
+      events,
+      vec![
+        "category_start specs (1/2 tests)",
+        "test_start specs::a",
+        "test_end specs::a passed",
+        "category_end specs",
+        "category_start specs::sub (1/1 tests)",
+        "test_start specs::sub::b",
+        "test_end specs::sub::b failed",
+        "category_end specs::sub",
+        "failures 1/2 [specs::sub::b]",
+      ]
+    );
+    assert!(reporter.events().is_empty());
+  }
+}
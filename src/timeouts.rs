@@ -0,0 +1,150 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::path::Path;
+use std::time::Duration;
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::PathedIoError;
+
+/// Default duration after which a running test is reported as long running.
+pub(crate) const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Default interval at which running tests are checked for timeouts.
+pub(crate) const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Timeouts for the tests whose names match a pattern, which take
+/// precedence over `RunOptions::timeout`. This allows a few slow tests
+/// to have a longer timeout without raising it for the whole suite.
+#[derive(Debug, Clone, Default)]
+pub struct TimeoutOverrides {
+  overrides: Vec<(Regex, Duration)>,
+}
+
+#[derive(Deserialize)]
+struct TimeoutOverrideFileEntry {
+  pattern: String,
+  /// Timeout in seconds.
+  timeout: u64,
+}
+
+impl TimeoutOverrides {
+  /// Loads the overrides from a JSON file of the following format, where
+  /// the timeout is in seconds:
+  ///
+  /// ```json
+  /// [{ "pattern": "^specs::npm::", "timeout": 300 }]
+  /// ```
+  pub fn from_file(path: &Path) -> Result<Self, anyhow::Error> {
+    let text = std::fs::read_to_string(path)
+      .map_err(|err| PathedIoError::new(path, err))?;
+    let entries: Vec<TimeoutOverrideFileEntry> = serde_json::from_str(&text)
+      .map_err(|err| {
+        anyhow::anyhow!("Failed to parse '{}': {:#}", path.display(), err)
+      })?;
+    let mut overrides = Self::default();
+    for entry in entries {
+      overrides.add(&entry.pattern, Duration::from_secs(entry.timeout))?;
+    }
+    Ok(overrides)
+  }
+
+  /// Sets the timeout of the tests whose name matches the regex pattern.
+  /// When multiple patterns match, the first one added is used.
+  pub fn add(
+    &mut self,
+    pattern: &str,
+    timeout: Duration,
+  ) -> Result<(), regex::Error> {
+    self.overrides.push((Regex::new(pattern)?, timeout));
+    Ok(())
+  }
+
+  /// Gets the timeout of the test with the provided name, if overridden.
+  pub fn get(&self, test_name: &str) -> Option<Duration> {
+    self
+      .overrides
+      .iter()
+      .find(|(pattern, _)| pattern.is_match(test_name))
+      .map(|(_, timeout)| *timeout)
+  }
+}
+
+/// Resolves the timeout of each test.
+#[derive(Debug, Clone)]
+pub(crate) struct Timeouts {
+  pub default: Duration,
+  pub overrides: TimeoutOverrides,
+  pub kill_after: Option<Duration>,
+  /// How often the running tests are checked.
+  pub poll_interval: Duration,
+  /// Duration without any test completing after which the run is
+  /// reported as stalled.
+  pub stall_after: Option<Duration>,
+  /// Capture the stacks of tests that are stuck.
+  pub dump_stacks: bool,
+}
+
+impl Timeouts {
+  /// Gets the timeout of a test, where the overrides take precedence
+  /// over the test's own timeout.
+  pub fn get(
+    &self,
+    test_name: &str,
+    test_timeout: Option<Duration>,
+  ) -> Duration {
+    self
+      .overrides
+      .get(test_name)
+      .or(test_timeout)
+      .unwrap_or(self.default)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn timeout_overrides() {
+    let path = std::env::temp_dir().join(format!(
+      "file_test_runner_timeouts_{}.json",
+      std::process::id()
+    ));
+    std::fs::write(
+      &path,
+      r#"[
+        { "pattern": "^specs::npm::install", "timeout": 600 },
+        { "pattern": "^specs::npm::", "timeout": 300 }
+      ]"#,
+    )
+    .unwrap();
+    let timeouts = Timeouts {
+      default: DEFAULT_TIMEOUT,
+      overrides: TimeoutOverrides::from_file(&path).unwrap(),
+      kill_after: None,
+      poll_interval: DEFAULT_POLL_INTERVAL,
+      stall_after: None,
+      dump_stacks: false,
+    };
+    assert_eq!(
+      timeouts.get("specs::npm::install::basic", None),
+      Duration::from_secs(600)
+    );
+    assert_eq!(
+      timeouts.get("specs::npm::run", Some(Duration::from_secs(5))),
+      Duration::from_secs(300)
+    );
+    assert_eq!(timeouts.get("specs::run", None), DEFAULT_TIMEOUT);
+    assert_eq!(
+      timeouts.get("specs::run", Some(Duration::from_secs(5))),
+      Duration::from_secs(5)
+    );
+
+    std::fs::write(&path, r#"[{ "pattern": "(", "timeout": 1 }]"#).unwrap();
+    assert!(TimeoutOverrides::from_file(&path).is_err());
+
+    std::fs::remove_file(&path).unwrap();
+  }
+}
@@ -0,0 +1,244 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+//! Synchronization utilities that are useful for throttling tests.
+
+use std::time::Duration;
+
+use parking_lot::Condvar;
+use parking_lot::Mutex;
+
+/// Counting semaphore, which can be used to limit how many tests
+/// access a resource at the same time.
+#[derive(Debug)]
+pub struct Semaphore {
+  total: usize,
+  available: Mutex<usize>,
+  released: Condvar,
+}
+
+impl Semaphore {
+  pub fn new(permits: usize) -> Self {
+    Self {
+      total: permits,
+      available: Mutex::new(permits),
+      released: Condvar::new(),
+    }
+  }
+
+  /// Total number of permits.
+  pub fn total_permits(&self) -> usize {
+    self.total
+  }
+
+  pub fn available_permits(&self) -> usize {
+    *self.available.lock()
+  }
+
+  /// Blocks until a permit is available and takes it. The permit must
+  /// be returned with `release()`.
+  ///
+  /// Prefer `acquire_guard()`, which releases the permit when dropped.
+  pub fn acquire(&self) {
+    self.acquire_n(1);
+  }
+
+  /// Returns a permit taken by `acquire()`.
+  pub fn release(&self) {
+    self.release_n(1);
+  }
+
+  /// Blocks until a permit is available, returning a guard that
+  /// releases it when dropped.
+  pub fn acquire_guard(&self) -> SemaphorePermit<'_> {
+    self.acquire_many(1)
+  }
+
+  /// Blocks until `count` permits are available at the same time, which
+  /// is useful for tests that need more of a resource than others.
+  ///
+  /// Panics if `count` is greater than the total number of permits
+  /// because it would never be acquired.
+  pub fn acquire_many(&self, count: usize) -> SemaphorePermit<'_> {
+    assert!(
+      count <= self.total,
+      "Cannot acquire {} permits from a semaphore with {}.",
+      count,
+      self.total
+    );
+    self.acquire_n(count);
+    SemaphorePermit {
+      semaphore: self,
+      count,
+    }
+  }
+
+  /// Takes a permit if one is available without blocking.
+  pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+    let mut available = self.available.lock();
+    if *available == 0 {
+      return None;
+    }
+    *available -= 1;
+    Some(SemaphorePermit {
+      semaphore: self,
+      count: 1,
+    })
+  }
+
+  fn acquire_n(&self, count: usize) {
+    let mut available = self.available.lock();
+    while *available < count {
+      self.released.wait(&mut available);
+    }
+    *available -= count;
+  }
+
+  fn release_n(&self, count: usize) {
+    let mut available = self.available.lock();
+    *available += count;
+    self.released.notify_all();
+  }
+}
+
+/// Permits taken from a `Semaphore`, which are released when dropped.
+#[derive(Debug)]
+pub struct SemaphorePermit<'a> {
+  semaphore: &'a Semaphore,
+  count: usize,
+}
+
+impl SemaphorePermit<'_> {
+  /// Number of permits held.
+  pub fn count(&self) -> usize {
+    self.count
+  }
+}
+
+impl Drop for SemaphorePermit<'_> {
+  fn drop(&mut self) {
+    self.semaphore.release_n(self.count);
+  }
+}
+
+/// Flag that threads can wait on until it's set (ex. to signal that a
+/// server used by the tests is ready).
+///
+/// The flag stays set until `reset()` is called, so it can be reused
+/// across phases of a run.
+#[derive(Debug, Default)]
+pub struct Notify {
+  notified: Mutex<bool>,
+  changed: Condvar,
+}
+
+impl Notify {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Sets the flag, waking all the waiting threads.
+  pub fn notify(&self) {
+    *self.notified.lock() = true;
+    self.changed.notify_all();
+  }
+
+  pub fn is_notified(&self) -> bool {
+    *self.notified.lock()
+  }
+
+  /// Clears the flag so that `wait()` blocks until the next `notify()`.
+  pub fn reset(&self) {
+    *self.notified.lock() = false;
+  }
+
+  /// Blocks until the flag is set.
+  pub fn wait(&self) {
+    let mut notified = self.notified.lock();
+    while !*notified {
+      self.changed.wait(&mut notified);
+    }
+  }
+
+  /// Blocks until the flag is set or the timeout elapses, returning
+  /// whether the flag was set.
+  pub fn wait_timeout(&self, timeout: Duration) -> bool {
+    let mut notified = self.notified.lock();
+    if !*notified {
+      self.changed.wait_while_for(&mut notified, |n| !*n, timeout);
+    }
+    *notified
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::sync::Arc;
+
+  use super::*;
+
+  #[test]
+  fn semaphore_permits() {
+    let semaphore = Semaphore::new(3);
+    let permit = semaphore.acquire_guard();
+    assert_eq!(semaphore.available_permits(), 2);
+    let many = semaphore.acquire_many(2);
+    assert_eq!(many.count(), 2);
+    assert!(semaphore.try_acquire().is_none());
+    drop(permit);
+    let permit = semaphore.try_acquire().unwrap();
+    assert_eq!(semaphore.available_permits(), 0);
+    drop(many);
+    drop(permit);
+    assert_eq!(semaphore.available_permits(), 3);
+
+    semaphore.acquire();
+    assert_eq!(semaphore.available_permits(), 2);
+    semaphore.release();
+    assert_eq!(semaphore.available_permits(), 3);
+  }
+
+  #[test]
+  fn acquire_many_waits() {
+    let semaphore = Arc::new(Semaphore::new(2));
+    let permit = semaphore.acquire_guard();
+    let (tx, rx) = crossbeam_channel::unbounded();
+    let thread = std::thread::spawn({
+      let semaphore = semaphore.clone();
+      move || {
+        let permit = semaphore.acquire_many(2);
+        tx.send(permit.count()).unwrap();
+      }
+    });
+    assert!(rx.recv_timeout(Duration::from_millis(50)).is_err());
+    drop(permit);
+    assert_eq!(rx.recv().unwrap(), 2);
+    thread.join().unwrap();
+    assert_eq!(semaphore.available_permits(), 2);
+  }
+
+  #[test]
+  #[should_panic(expected = "Cannot acquire 3 permits")]
+  fn acquire_many_more_than_total() {
+    Semaphore::new(2).acquire_many(3);
+  }
+
+  #[test]
+  fn notify_wait_and_reset() {
+    let notify = Arc::new(Notify::new());
+    assert!(!notify.wait_timeout(Duration::from_millis(10)));
+    for _ in 0..2 {
+      let thread = std::thread::spawn({
+        let notify = notify.clone();
+        move || notify.wait()
+      });
+      std::thread::sleep(Duration::from_millis(10));
+      notify.notify();
+      thread.join().unwrap();
+      assert!(notify.is_notified());
+      assert!(notify.wait_timeout(Duration::from_millis(10)));
+      // reusable after a reset
+      notify.reset();
+      assert!(!notify.is_notified());
+    }
+  }
+}
@@ -0,0 +1,61 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+use std::sync::atomic::AtomicU8;
+use std::sync::atomic::Ordering;
+
+/// Environment variable to set the verbosity of the run (ex. `2`), which
+/// is also how it's provided to worker processes.
+pub const VERBOSE_ENV_VAR: &str = "FILE_TEST_RUNNER_VERBOSE";
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(0);
+
+/// Gets the verbosity of the run, where zero is the default and each
+/// `-v` or `--verbose` passed to the test binary increases it (ex. `-vv`
+/// is 2). Tests may use it to print extra diagnostics when debugging
+/// locally.
+pub fn verbosity() -> u8 {
+  VERBOSITY.load(Ordering::Relaxed)
+}
+
+pub(crate) fn set_verbosity(level: u8) {
+  VERBOSITY.store(level, Ordering::Relaxed);
+}
+
+/// Gets the verbosity from the command line or environment variable.
+pub(crate) fn verbosity_from_env() -> u8 {
+  let from_args = parse_verbosity(std::env::args().skip(1));
+  let from_env = std::env::var(VERBOSE_ENV_VAR)
+    .ok()
+    .and_then(|value| value.parse().ok())
+    .unwrap_or(0);
+  from_args.max(from_env)
+}
+
+fn parse_verbosity(args: impl Iterator<Item = String>) -> u8 {
+  let mut level = 0u8;
+  for arg in args {
+    if arg == "--verbose" {
+      level = level.saturating_add(1);
+    } else if let Some(flags) = arg.strip_prefix('-') {
+      if !flags.is_empty() && flags.chars().all(|c| c == 'v') {
+        level = level.saturating_add(flags.len() as u8);
+      }
+    }
+  }
+  level
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn parses_verbosity() {
+    let parse =
+      |args: &[&str]| parse_verbosity(args.iter().map(|arg| arg.to_string()));
+    assert_eq!(parse(&["lsp"]), 0);
+    assert_eq!(parse(&["-v"]), 1);
+    assert_eq!(parse(&["-vv", "--verbose"]), 3);
+    assert_eq!(parse(&["--", "-"]), 0);
+  }
+}
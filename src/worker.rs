@@ -0,0 +1,644 @@
+// Copyright 2018-2024 the Deno authors. MIT license.
+
+//! Coordinator/worker mode where tests are run in separate worker
+//! processes, which may be on other machines.
+//!
+//! The coordinator sends the names of tests to run as newline delimited
+//! JSON over the worker's stdin and the worker responds with the results
+//! over stdout. Since the worker is the same test binary, it collects the
+//! same tests and so is able to look them up by name. After each test, the
+//! worker also writes a message to stderr so the coordinator knows when it
+//! has received everything the test printed there.
+
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::process::Child;
+use std::process::ChildStderr;
+use std::process::ChildStdin;
+use std::process::ChildStdout;
+use std::process::Command;
+use std::process::ExitStatus;
+use std::process::Stdio;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use parking_lot::Mutex;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::collection::CollectedCategoryOrTest;
+use crate::collection::CollectedTest;
+use crate::collection::CollectedTestCategory;
+use crate::fixtures::Fixtures;
+use crate::output::CappedOutput;
+use crate::process::set_cpu_affinity;
+use crate::process::ProcessLimits;
+use crate::TestResult;
+use crate::VERBOSE_ENV_VAR;
+
+/// Environment variable that makes `run_tests` run as a worker.
+pub const WORKER_ENV_VAR: &str = "FILE_TEST_RUNNER_WORKER";
+
+/// Prefix of protocol messages written to stdout by a worker. This
+/// distinguishes them from anything the tests themselves print.
+const MESSAGE_PREFIX: &str = "\u{1e}file_test_runner:";
+
+/// How long to wait for the stderr of a test after its result arrives.
+const STDERR_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long a worker has to exit once its stdin is closed before it's
+/// killed.
+const EXIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Options for running the tests in worker processes.
+#[derive(Debug, Clone, Default)]
+pub struct WorkerOptions {
+  /// Program and arguments used to spawn each worker. One worker is
+  /// spawned for each command.
+  ///
+  /// The command must start the same test binary with the
+  /// `FILE_TEST_RUNNER_WORKER=1` environment variable set and with the
+  /// test files available at the same relative paths (ex.
+  /// `ssh runner1 'cd repo && FILE_TEST_RUNNER_WORKER=1 ./specs-a1b2c3'`).
+  /// The environment variable is set automatically for local commands.
+  pub commands: Vec<Vec<String>>,
+  /// CPU cores to pin the spawned worker processes to, where each worker
+  /// is pinned to a single core in round-robin order. This reduces the
+  /// variance of timing sensitive tests.
+  ///
+  /// Only supported on Linux.
+  pub cpu_affinity: Option<Vec<usize>>,
+  /// Resource limits and niceness of the spawned worker processes.
+  pub limits: ProcessLimits,
+  /// Run a test whose worker crashed (ex. from a segfault) once more on
+  /// the restarted worker, which tells apart crashes caused by the state
+  /// left behind by previous tests from crashes caused by the test.
+  pub retry_crashed_tests: bool,
+}
+
+impl WorkerOptions {
+  /// Runs the tests in the provided number of worker processes on
+  /// the current machine.
+  pub fn local(count: usize) -> Self {
+    let exe = std::env::current_exe()
+      .expect("failed to get the current executable")
+      .to_string_lossy()
+      .into_owned();
+    Self {
+      commands: (0..count).map(|_| vec![exe.clone()]).collect(),
+      ..Default::default()
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum WorkerRequest {
+  Run { name: String },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum WorkerResponse {
+  Result { name: String, result: TestResult },
+}
+
+pub(crate) fn is_worker_process() -> bool {
+  std::env::var(WORKER_ENV_VAR).is_ok_and(|v| v == "1")
+}
+
+/// Runs tests requested by the coordinator until stdin is closed.
+pub(crate) fn run_worker<TData>(
+  category: &CollectedTestCategory<TData>,
+  run_test: &dyn Fn(&CollectedTest<TData>) -> TestResult,
+) {
+  let mut current_category: Option<(&str, Arc<Fixtures>)> = None;
+  for line in std::io::stdin().lock().lines() {
+    let Ok(line) = line else {
+      break;
+    };
+    if line.trim().is_empty() {
+      continue;
+    }
+    let WorkerRequest::Run { name } = match serde_json::from_str(&line) {
+      Ok(request) => request,
+      Err(err) => panic!("invalid worker request '{}': {:#}", line, err),
+    };
+    let result = match find_test_and_category(category, &name) {
+      Some((test_category, test)) => {
+        // the coordinator sends the tests in the order of their
+        // categories, so a worker never returns to a previous category
+        let is_same_category = current_category
+          .as_ref()
+          .is_some_and(|(name, _)| *name == test_category.name);
+        if !is_same_category {
+          if let Some((_, fixtures)) = current_category.take() {
+            fixtures.teardown();
+          }
+          let fixtures = Arc::new(Fixtures::default());
+          Fixtures::set_current_category(Some(fixtures.clone()));
+          current_category = Some((&test_category.name, fixtures));
+        }
+        with_env_vars(&test.env_vars, || run_test(test))
+      }
+      None => TestResult::failed(
+        format!("Test '{}' was not found in the worker.", name).into_bytes(),
+      ),
+    };
+    // lets the coordinator know it has all the stderr of the test
+    let mut stderr = std::io::stderr().lock();
+    writeln!(stderr, "{}", MESSAGE_PREFIX)
+      .and_then(|_| stderr.flush())
+      .expect("failed to write to the coordinator");
+    drop(stderr);
+    let response = WorkerResponse::Result { name, result };
+    // don't hold the lock while running tests because they may print
+    let mut stdout = std::io::stdout().lock();
+    writeln!(
+      stdout,
+      "{}{}",
+      MESSAGE_PREFIX,
+      serde_json::to_string(&response).unwrap()
+    )
+    .and_then(|_| stdout.flush())
+    .expect("failed to write to the coordinator");
+  }
+  if let Some((_, fixtures)) = current_category {
+    Fixtures::set_current_category(None);
+    fixtures.teardown();
+  }
+}
+
+fn find_test_and_category<'a, TData>(
+  category: &'a CollectedTestCategory<TData>,
+  name: &str,
+) -> Option<(&'a CollectedTestCategory<TData>, &'a CollectedTest<TData>)> {
+  category.children.iter().find_map(|child| match child {
+    CollectedCategoryOrTest::Category(c) => find_test_and_category(c, name),
+    CollectedCategoryOrTest::Test(t) => {
+      (t.name == name).then_some((category, t))
+    }
+  })
+}
+
+/// Runs the function with the environment variables set, restoring
+/// them afterwards. This is only done in worker processes because they
+/// run a single test at a time.
+fn with_env_vars<R>(
+  env_vars: &HashMap<String, String>,
+  func: impl FnOnce() -> R,
+) -> R {
+  let previous = env_vars
+    .keys()
+    .map(|key| (key, std::env::var_os(key)))
+    .collect::<Vec<_>>();
+  for (key, value) in env_vars {
+    std::env::set_var(key, value);
+  }
+  let result = func();
+  for (key, value) in previous {
+    match value {
+      Some(value) => std::env::set_var(key, value),
+      None => std::env::remove_var(key),
+    }
+  }
+  result
+}
+
+/// How the coordinator handles what the tests print to stdout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum WorkerOutput {
+  /// Include the output in the output of failed tests.
+  Capture,
+  /// Print the output as it arrives.
+  Stream,
+  /// Print each line of the output prefixed with the test's name, which
+  /// keeps the output readable when several workers print at once.
+  StreamPrefixed,
+}
+
+/// A worker process as seen by the coordinator.
+pub(crate) struct WorkerProcess {
+  command_text: String,
+  output_mode: WorkerOutput,
+  child: Arc<Mutex<Child>>,
+  /// Output printed to stdout and stderr by the running test so far.
+  output: Arc<Mutex<CappedOutput>>,
+  /// Name of the running test, used to prefix its stderr.
+  current_test: Arc<Mutex<String>>,
+  /// Receives a message once all the stderr of a test has been read,
+  /// which disconnects when the worker's stderr closes.
+  stderr_done: mpsc::Receiver<()>,
+  /// Whether the worker was killed by the coordinator (ex. because
+  /// the test timed out) rather than crashing.
+  killed: Arc<AtomicBool>,
+  max_output_size: Option<usize>,
+  stdin: Option<ChildStdin>,
+  stdout: BufReader<ChildStdout>,
+}
+
+impl WorkerProcess {
+  pub fn spawn(
+    command: &[String],
+    cpu_core: Option<usize>,
+    limits: &ProcessLimits,
+    output_mode: WorkerOutput,
+    max_output_size: Option<usize>,
+  ) -> Result<Self, std::io::Error> {
+    let Some((program, args)) = command.split_first() else {
+      return Err(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        "worker command was empty",
+      ));
+    };
+    let mut process = Command::new(program);
+    process
+      .args(args)
+      .env(WORKER_ENV_VAR, "1")
+      .env(VERBOSE_ENV_VAR, crate::verbosity().to_string())
+      .stdin(Stdio::piped())
+      .stdout(Stdio::piped())
+      .stderr(Stdio::piped());
+    if let Some(core) = cpu_core {
+      set_cpu_affinity(&mut process, &[core]);
+    }
+    limits.apply(&mut process);
+    let mut child = process.spawn()?;
+    let output = Arc::new(Mutex::new(CappedOutput::new(max_output_size)));
+    let current_test = Arc::new(Mutex::new(String::new()));
+    let stderr_done = spawn_stderr_reader(
+      child.stderr.take().unwrap(),
+      output_mode,
+      output.clone(),
+      current_test.clone(),
+    );
+    Ok(Self {
+      command_text: command.join(" "),
+      output_mode,
+      stdin: child.stdin.take(),
+      stdout: BufReader::new(child.stdout.take().unwrap()),
+      child: Arc::new(Mutex::new(child)),
+      output,
+      current_test,
+      stderr_done,
+      killed: Default::default(),
+      max_output_size,
+    })
+  }
+
+  /// Gets a handle for controlling the worker from another thread.
+  pub fn handle(&self) -> WorkerHandle {
+    WorkerHandle {
+      child: self.child.clone(),
+      output: self.output.clone(),
+      killed: self.killed.clone(),
+    }
+  }
+
+  /// Whether the worker process exited, such as from crashing or
+  /// being killed.
+  pub fn has_exited(&mut self) -> bool {
+    !matches!(self.child.lock().try_wait(), Ok(None))
+  }
+
+  /// Whether the worker process exited without being killed by the
+  /// coordinator.
+  pub fn has_crashed(&mut self) -> bool {
+    self.has_exited() && !self.killed.load(Ordering::SeqCst)
+  }
+
+  /// Waits up to the timeout for the worker to exit.
+  fn wait_for_exit(&self, timeout: Duration) -> Option<ExitStatus> {
+    let start = Instant::now();
+    loop {
+      match self.child.lock().try_wait() {
+        Ok(Some(status)) => return Some(status),
+        Ok(None) if start.elapsed() < timeout => {}
+        _ => return None,
+      }
+      std::thread::sleep(Duration::from_millis(10));
+    }
+  }
+
+  /// Runs the test in the worker.
+  ///
+  /// Anything the test printed to stdout or stderr is included in the
+  /// output of failed tests.
+  pub fn run_test<TData>(&mut self, test: &CollectedTest<TData>) -> TestResult {
+    *self.output.lock() = CappedOutput::new(self.max_output_size);
+    *self.current_test.lock() = test.name.clone();
+    let result = self.run_test_inner(test);
+    // stderr is read on another thread, so wait for the rest of it, which
+    // returns right away when the worker exited
+    let _ = self.stderr_done.recv_timeout(STDERR_TIMEOUT);
+    let mut captured = std::mem::take(&mut *self.output.lock()).into_bytes();
+    match result {
+      Ok(mut result) => {
+        if let Some(output) = result.output_mut() {
+          if !captured.is_empty() {
+            captured.append(output);
+            *output = captured;
+          }
+        }
+        result
+      }
+      Err(err) => {
+        let status = self.wait_for_exit(Duration::from_secs(1));
+        captured.extend(
+          format!(
+            "Worker '{}' failed{}: {:#}",
+            self.command_text,
+            status
+              .map(|status| format!(" ({})", status))
+              .unwrap_or_default(),
+            err
+          )
+          .into_bytes(),
+        );
+        let mut result = TestResult::failed(captured);
+        if let Some(code) = status.and_then(|status| status.code()) {
+          result = result.with_field("exit_code", code);
+        }
+        if let Some(signal) = status.and_then(exit_signal) {
+          result = result.with_field("signal", signal);
+        }
+        result
+      }
+    }
+  }
+
+  fn run_test_inner<TData>(
+    &mut self,
+    test: &CollectedTest<TData>,
+  ) -> Result<TestResult, anyhow::Error> {
+    let request = WorkerRequest::Run {
+      name: test.name.clone(),
+    };
+    let stdin = self.stdin.as_mut().unwrap();
+    writeln!(stdin, "{}", serde_json::to_string(&request)?)?;
+    stdin.flush()?;
+
+    let prefix = MESSAGE_PREFIX.as_bytes();
+    let mut line = Vec::new();
+    loop {
+      line.clear();
+      if self.stdout.read_until(b'\n', &mut line)? == 0 {
+        anyhow::bail!("worker process exited before the test completed");
+      }
+      let Some(index) = line.windows(prefix.len()).position(|w| w == prefix)
+      else {
+        self.handle_output(&test.name, &line);
+        continue;
+      };
+      self.handle_output(&test.name, &line[..index]);
+      let WorkerResponse::Result { name, result } =
+        serde_json::from_slice(line[index + prefix.len()..].trim_ascii_end())?;
+      if name != test.name {
+        anyhow::bail!(
+          "worker responded for test '{}' instead of '{}'",
+          name,
+          test.name
+        );
+      }
+      return Ok(result);
+    }
+  }
+}
+
+impl WorkerProcess {
+  fn handle_output(&self, test_name: &str, text: &[u8]) {
+    write_output(
+      self.output_mode,
+      &self.output,
+      test_name,
+      text,
+      std::io::stdout(),
+    );
+  }
+}
+
+/// Handles what a test printed to stdout or stderr, where streamed output
+/// is written to the same stream of the coordinator.
+fn write_output(
+  output_mode: WorkerOutput,
+  output: &Mutex<CappedOutput>,
+  test_name: &str,
+  text: &[u8],
+  mut stream: impl Write,
+) {
+  if text.is_empty() {
+    return;
+  }
+  match output_mode {
+    WorkerOutput::Capture => output.lock().extend(text),
+    WorkerOutput::Stream => {
+      let _ = stream.write_all(text).and_then(|_| stream.flush());
+    }
+    WorkerOutput::StreamPrefixed => {
+      // write the whole line at once so it doesn't interleave
+      let line = prefixed_line(test_name, text);
+      let _ = stream.write_all(&line).and_then(|_| stream.flush());
+    }
+  }
+}
+
+/// Reads the worker's stderr on another thread, returning a receiver that
+/// gets a message once all the stderr of the running test has been read.
+fn spawn_stderr_reader(
+  stderr: ChildStderr,
+  output_mode: WorkerOutput,
+  output: Arc<Mutex<CappedOutput>>,
+  current_test: Arc<Mutex<String>>,
+) -> mpsc::Receiver<()> {
+  let (sender, receiver) = mpsc::channel();
+  std::thread::spawn(move || {
+    let prefix = MESSAGE_PREFIX.as_bytes();
+    let mut stderr = BufReader::new(stderr);
+    let mut line = Vec::new();
+    loop {
+      line.clear();
+      if !matches!(stderr.read_until(b'\n', &mut line), Ok(1..)) {
+        break;
+      }
+      let test_name = current_test.lock().clone();
+      let index = line.windows(prefix.len()).position(|w| w == prefix);
+      let text = &line[..index.unwrap_or(line.len())];
+      write_output(output_mode, &output, &test_name, text, std::io::stderr());
+      if index.is_some() {
+        let _ = sender.send(());
+      }
+    }
+  });
+  receiver
+}
+
+#[cfg(unix)]
+fn exit_signal(status: ExitStatus) -> Option<i32> {
+  use std::os::unix::process::ExitStatusExt;
+  status.signal()
+}
+
+#[cfg(not(unix))]
+fn exit_signal(_status: ExitStatus) -> Option<i32> {
+  None
+}
+
+fn prefixed_line(test_name: &str, text: &[u8]) -> Vec<u8> {
+  let mut line = format!("[{}] ", test_name).into_bytes();
+  line.extend(text);
+  if !line.ends_with(b"\n") {
+    line.push(b'\n');
+  }
+  line
+}
+
+impl Drop for WorkerProcess {
+  fn drop(&mut self) {
+    // closing stdin signals the worker to exit, which gives it the chance
+    // to tear down its fixtures, but kill it when it doesn't so a hung
+    // worker can't hang the run
+    drop(self.stdin.take());
+    if self.wait_for_exit(EXIT_TIMEOUT).is_none() {
+      self.handle().kill();
+      self.wait_for_exit(Duration::from_secs(1));
+    }
+  }
+}
+
+/// Handle for inspecting or killing a worker process from another thread.
+pub(crate) struct WorkerHandle {
+  child: Arc<Mutex<Child>>,
+  output: Arc<Mutex<CappedOutput>>,
+  killed: Arc<AtomicBool>,
+}
+
+impl WorkerHandle {
+  /// Gets the id of the worker process.
+  pub fn id(&self) -> u32 {
+    self.child.lock().id()
+  }
+
+  /// Gets the output the running test has printed so far.
+  pub fn output(&self) -> Vec<u8> {
+    self.output.lock().to_bytes()
+  }
+
+  /// Kills the worker, which fails the test it's running.
+  pub fn kill(&self) {
+    self.killed.store(true, Ordering::SeqCst);
+    let _ = self.child.lock().kill();
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::FailureValue;
+
+  #[test]
+  fn prefixes_lines() {
+    assert_eq!(prefixed_line("specs::a", b"hello\n"), b"[specs::a] hello\n");
+    // output printed right before the result has no newline
+    assert_eq!(prefixed_line("specs::a", b"hello"), b"[specs::a] hello\n");
+  }
+
+  #[test]
+  fn sets_env_vars_while_running() {
+    let key = format!("FILE_TEST_RUNNER_ENV_TEST_{}", std::process::id());
+    let env_vars = HashMap::from([(key.clone(), "1".to_string())]);
+    let value = with_env_vars(&env_vars, || std::env::var(&key).ok());
+    assert_eq!(value.as_deref(), Some("1"));
+    // restored afterwards
+    assert!(std::env::var(&key).is_err());
+
+    let mut test = CollectedTest::new("specs::a", "a", ());
+    test.env_vars = env_vars;
+    let command = test.command("echo");
+    let envs = command.get_envs().collect::<Vec<_>>();
+    assert!(envs.contains(&(key.as_ref(), Some("1".as_ref()))));
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn gets_partial_output_of_running_test() {
+    let command =
+      ["sh", "-c", "read request\necho connecting\nexec sleep 10\n"]
+        .map(String::from);
+    let mut worker = WorkerProcess::spawn(
+      &command,
+      None,
+      &ProcessLimits::default(),
+      WorkerOutput::Capture,
+      None,
+    )
+    .unwrap();
+    let handle = worker.handle();
+    let thread = std::thread::spawn(move || {
+      worker.run_test(&CollectedTest::new("specs::stuck", "stuck", ()))
+    });
+    let start = Instant::now();
+    while handle.output() != b"connecting\n" {
+      assert!(start.elapsed() < Duration::from_secs(5));
+      std::thread::sleep(Duration::from_millis(10));
+    }
+    handle.kill();
+    let result = thread.join().unwrap();
+    let TestResult::FailedWithFields { output, .. } = result else {
+      unreachable!("{:?}", result);
+    };
+    assert!(output.starts_with(b"connecting\n"));
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn runs_tests_until_worker_crashes() {
+    let script = concat!(
+      "read request\n",
+      "echo printed\n",
+      "echo logged >&2\n",
+      "printf '\\036file_test_runner:\\n' >&2\n",
+      "printf '\\036file_test_runner:",
+      r#"{"type":"result","name":"specs::a","result":{"Failed":{"output":[]}}}"#,
+      "\\n'\n",
+      "read request\n",
+      "echo crashed >&2\n",
+      "exit 3\n",
+    );
+    let command = ["sh", "-c", script].map(String::from);
+    let mut worker = WorkerProcess::spawn(
+      &command,
+      None,
+      &ProcessLimits::default(),
+      WorkerOutput::Capture,
+      None,
+    )
+    .unwrap();
+
+    let TestResult::Failed { output } =
+      worker.run_test(&CollectedTest::new("specs::a", "a", ()))
+    else {
+      unreachable!();
+    };
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("printed\n"), "{}", output);
+    assert!(output.contains("logged\n"), "{}", output);
+    assert!(!worker.has_exited());
+
+    let TestResult::FailedWithFields { output, fields } =
+      worker.run_test(&CollectedTest::new("specs::b", "b", ()))
+    else {
+      unreachable!();
+    };
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.starts_with("crashed\n"), "{}", output);
+    assert!(output.contains("Worker 'sh -c"), "{}", output);
+    assert_eq!(fields["exit_code"], FailureValue::Integer(3));
+    assert!(worker.has_crashed());
+  }
+}